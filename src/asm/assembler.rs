@@ -0,0 +1,1860 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use crate::elf::decoder::SymbolDefinition;
+use crate::asm::{STACK_POINTER, PROGRAM_COUNTER, LINK_REGISTER};
+
+// a small two pass assembler for ARMv6-M thumb code, it understands the subset of GNU as
+// syntax used by the tests and examples (labels, .word/.thumb_func, literal pools, macros).
+// the encodings mirror what arm-none-eabi-as picks so that Opcode::from/get_operands
+// decode the output back into the same instruction.
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct AsmError{
+   pub line: usize,
+   pub msg: String
+}
+
+impl Display for AsmError{
+   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+      write!(f,"line {}: {}",self.line,self.msg)
+   }
+}
+
+macro_rules! asm_err {
+   ($line:expr, $($arg:tt)*) => {
+      Err(AsmError{line: $line, msg: format!($($arg)*)})
+   };
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum RelocationType{
+   ABS32,
+   REL32,
+   THM_CALL,
+   THM_PC8,
+   THM_JUMP11,
+   THM_JUMP8
+}
+
+impl RelocationType{
+   pub fn elf_type(&self)->u8{
+      match self{
+         RelocationType::ABS32 => 2,
+         RelocationType::REL32 => 3,
+         RelocationType::THM_CALL => 10,
+         RelocationType::THM_PC8 => 11,
+         RelocationType::THM_JUMP11 => 102,
+         RelocationType::THM_JUMP8 => 103,
+      }
+   }
+
+   pub fn from_elf_type(elf_type: u8)->Option<Self>{
+      match elf_type{
+         2 => Some(RelocationType::ABS32),
+         3 => Some(RelocationType::REL32),
+         10 => Some(RelocationType::THM_CALL),
+         11 => Some(RelocationType::THM_PC8),
+         102 => Some(RelocationType::THM_JUMP11),
+         103 => Some(RelocationType::THM_JUMP8),
+         _ => None
+      }
+   }
+}
+
+// relocations use the REL convention, the addend lives in the bytes at offset
+#[derive(Debug,Clone,PartialEq)]
+pub struct Relocation{
+   pub offset: u32,
+   pub kind: RelocationType,
+   pub symbol: String
+}
+
+#[derive(Debug,Clone)]
+pub struct AsmSection{
+   pub name: String,
+   pub bytes: Vec<u8>,
+   pub size: u32,
+   pub nobits: bool,
+   pub executable: bool,
+   pub writable: bool,
+   pub relocations: Vec<Relocation>,
+   // (offset, is_code) pairs that become the $t/$d mapping symbols
+   pub mapping: Vec<(u32,bool)>
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum SymbolLocation{
+   Section(usize),
+   Absolute,
+   Undefined
+}
+
+#[derive(Debug,Clone)]
+pub struct AsmSymbol{
+   pub name: String,
+   pub location: SymbolLocation,
+   pub value: u32,
+   pub global: bool,
+   pub func: bool
+}
+
+#[derive(Debug,Clone)]
+pub struct Assembly{
+   pub sections: Vec<AsmSection>,
+   pub symbols: Vec<AsmSymbol>
+}
+
+impl Assembly{
+   pub fn section(&self, name: &str)->Option<&AsmSection>{
+      self.sections.iter().find(|s| s.name.eq(name))
+   }
+
+   pub fn text(&self)->&[u8]{
+      match self.section(".text"){
+         Some(s) => &s.bytes[..],
+         None => &[]
+      }
+   }
+}
+
+pub fn assemble(src: &str)->Result<Assembly,AsmError>{
+   let lines = preprocess(src)?;
+   let mut asm = Assembler::create(None, HashMap::new());
+   asm.layout(&lines)?;
+   asm.encode()?;
+   return Ok(asm.finish());
+}
+
+// assembles src as if it was placed at address, used to patch instructions in a running system.
+// symbols from the loaded ELF can be referenced by name.
+pub fn assemble_at(src: &str, address: u32, symbols: &Vec<SymbolDefinition>)->Result<Vec<u8>,AsmError>{
+   let mut known = HashMap::new();
+   for sym in symbols.iter(){
+      if sym.name.ne("$t") && sym.name.ne("$d"){
+         known.insert(sym.name.clone(), sym.position as u32);
+      }
+   }
+   let lines = preprocess(src)?;
+   let mut asm = Assembler::create(Some(address), known);
+   asm.layout(&lines)?;
+   if asm.pools.iter().any(|p| !p.entries.is_empty()){
+      return asm_err!(0,"literal pools cannot be placed when patching, load the value with MOV or from a known address");
+   }
+   asm.encode()?;
+   let mut out = asm.finish();
+   if out.sections.len() > 1{
+      return asm_err!(0,"patches can only contain a single section");
+   }
+   return Ok(out.sections.swap_remove(0).bytes);
+}
+
+#[derive(Debug,Clone,PartialEq)]
+enum Value{
+   Abs(i64),
+   Rel{section: usize, offset: i64, thumb: bool},
+   Ext{symbol: String, offset: i64}
+}
+
+enum Definition{
+   Label{section: usize, offset: u32},
+   Equ{expr: String, section: usize, dot: u32}
+}
+
+enum StmtKind{
+   Instr{mnemonic: String, args: String, literal: Option<(usize,usize)>},
+   Data{width: usize, exprs: Vec<String>},
+   Bytes(Vec<u8>),
+   Fill{count: u32, size: usize, value: String},
+   Pool(usize)
+}
+
+struct Stmt{
+   line: usize,
+   section: usize,
+   offset: u32,
+   kind: StmtKind
+}
+
+struct Pool{
+   offset: u32,
+   entries: Vec<(String,usize)>
+}
+
+struct SectionState{
+   section: AsmSection,
+   offset: u32,
+   open_pool: Option<usize>,
+   last_mapping: Option<bool>
+}
+
+struct Assembler{
+   base: Option<u32>,
+   externals: HashMap<String,u32>,
+   sections: Vec<SectionState>,
+   current: usize,
+   definitions: HashMap<String,Definition>,
+   order: Vec<String>,
+   globals: Vec<String>,
+   funcs: Vec<String>,
+   pending_thumb_func: bool,
+   unified: bool,
+   stmts: Vec<Stmt>,
+   pools: Vec<Pool>,
+   undefined: Vec<String>
+}
+
+const MAX_EXPR_DEPTH: usize = 64;
+
+impl Assembler{
+   fn create(base: Option<u32>, externals: HashMap<String,u32>)->Self{
+      let mut asm = Self{
+         base,
+         externals,
+         sections: Vec::new(),
+         current: 0,
+         definitions: HashMap::new(),
+         order: Vec::new(),
+         globals: Vec::new(),
+         funcs: Vec::new(),
+         pending_thumb_func: false,
+         unified: false,
+         stmts: Vec::new(),
+         pools: Vec::new(),
+         undefined: Vec::new()
+      };
+      asm.current = asm.section_index(".text", None);
+      return asm;
+   }
+
+   fn section_index(&mut self, name: &str, flags: Option<&str>)->usize{
+      if let Some(i) = self.sections.iter().position(|s| s.section.name.eq(name)){
+         return i;
+      }
+      let (executable, writable, nobits) = match flags{
+         Some(f) => (f.contains('x'), f.contains('w'), name.starts_with(".bss")),
+         None => (
+            name.starts_with(".text"),
+            name.starts_with(".data") || name.starts_with(".bss"),
+            name.starts_with(".bss")
+         )
+      };
+      self.sections.push(SectionState{
+         section: AsmSection{
+            name: name.to_string(),
+            bytes: Vec::new(),
+            size: 0,
+            nobits,
+            executable,
+            writable,
+            relocations: Vec::new(),
+            mapping: Vec::new()
+         },
+         offset: 0,
+         open_pool: None,
+         last_mapping: None
+      });
+      return self.sections.len() - 1;
+   }
+
+   fn dot(&self)->u32{
+      self.sections[self.current].offset
+   }
+
+   fn advance(&mut self, n: u32){
+      self.sections[self.current].offset += n;
+   }
+
+   fn mark(&mut self, is_code: bool){
+      let state = &mut self.sections[self.current];
+      if state.last_mapping != Some(is_code){
+         state.section.mapping.push((state.offset,is_code));
+         state.last_mapping = Some(is_code);
+      }
+   }
+
+   fn push(&mut self, line: usize, size: u32, kind: StmtKind){
+      let offset = self.dot();
+      self.stmts.push(Stmt{line, section: self.current, offset, kind});
+      self.advance(size);
+   }
+
+   fn define(&mut self, name: &str, def: Definition, line: usize)->Result<(),AsmError>{
+      let redefinable = matches!(def,Definition::Equ{..});
+      if let Some(existing) = self.definitions.get(name){
+         if !(redefinable && matches!(existing,Definition::Equ{..})){
+            return asm_err!(line,"symbol `{}` is already defined",name);
+         }
+      }else{
+         self.order.push(name.to_string());
+      }
+      self.definitions.insert(name.to_string(), def);
+      return Ok(());
+   }
+
+   fn layout(&mut self, lines: &Vec<(usize,String)>)->Result<(),AsmError>{
+      for (line, text) in lines.iter(){
+         let line = *line;
+         let mut rest = text.trim();
+         while let Some((label,after)) = split_label(rest){
+            let offset = self.dot();
+            self.define(label, Definition::Label{section: self.current, offset}, line)?;
+            if self.pending_thumb_func{
+               self.funcs.push(label.to_string());
+               self.pending_thumb_func = false;
+            }
+            rest = after.trim();
+         }
+         if rest.is_empty(){
+            continue;
+         }
+
+         if let Some((name,expr)) = split_assignment(rest){
+            let (section, dot) = (self.current, self.dot());
+            self.define(name, Definition::Equ{expr: expr.to_string(), section, dot}, line)?;
+            continue;
+         }
+
+         let (word, args) = split_first_word(rest);
+         if word.starts_with('.'){
+            self.directive(line, &word.to_lowercase(), args)?;
+         }else{
+            self.instruction(line, word, args)?;
+         }
+      }
+
+      for i in 0 .. self.sections.len(){
+         self.current = i;
+         self.flush_pool(0)?;
+         let state = &mut self.sections[i];
+         state.section.size = state.offset;
+      }
+      return Ok(());
+   }
+
+   fn directive(&mut self, line: usize, name: &str, args: &str)->Result<(),AsmError>{
+      match name{
+         ".thumb" | ".code" | ".cpu" | ".arch" | ".fpu" | ".eabi_attribute" | ".file" | ".ident" | ".size" | ".force_thumb" => {
+            if name.eq(".code") && args.trim().ne("16"){
+               return asm_err!(line,"only thumb code (.code 16) is supported on ARMv6-M");
+            }
+         },
+         ".arm" => return asm_err!(line,"ARM state is not supported on ARMv6-M"),
+         ".syntax" => {
+            self.unified = args.trim().eq_ignore_ascii_case("unified");
+         },
+         ".thumb_func" => self.pending_thumb_func = true,
+         ".type" => {
+            let parts = split_operands(args);
+            if parts.len() == 2 && parts[1].contains("function"){
+               self.funcs.push(parts[0].trim().to_string());
+            }
+         },
+         ".global" | ".globl" | ".weak" => {
+            for sym in split_operands(args){
+               self.globals.push(sym.trim().to_string());
+            }
+         },
+         ".local" => {},
+         ".text" | ".data" | ".bss" => {
+            self.current = self.section_index(name, None);
+         },
+         ".section" => {
+            let parts = split_operands(args);
+            if parts.is_empty(){
+               return asm_err!(line,".section needs a name");
+            }
+            let flags = parts.get(1).map(|f| f.trim().trim_matches('"').to_string());
+            self.current = self.section_index(parts[0].trim(), flags.as_deref());
+         },
+         ".equ" | ".set" | ".equiv" => {
+            let parts = split_operands(args);
+            if parts.len() != 2{
+               return asm_err!(line,"expected {} <symbol>, <expression>",name);
+            }
+            let (section, dot) = (self.current, self.dot());
+            self.define(parts[0].trim(), Definition::Equ{expr: parts[1].trim().to_string(), section, dot}, line)?;
+         },
+         ".word" | ".4byte" | ".long" | ".int" => self.data(line, 4, args),
+         ".2byte" | ".hword" | ".short" | ".half" => self.data(line, 2, args),
+         ".byte" => self.data(line, 1, args),
+         ".ascii" | ".asciz" | ".string" => {
+            let mut bytes = Vec::new();
+            for part in split_operands(args){
+               let mut s = parse_string(part.trim()).ok_or(AsmError{line, msg: format!("expected a string, found {}",part)})?;
+               if name.ne(".ascii"){
+                  s.push(0);
+               }
+               bytes.append(&mut s);
+            }
+            self.mark(false);
+            let n = bytes.len() as u32;
+            self.push(line, n, StmtKind::Bytes(bytes));
+         },
+         ".align" | ".p2align" | ".balign" => {
+            let parts = split_operands(args);
+            let n = match parts.first(){
+               Some(e) => self.constant(line, e)? as u32,
+               None => 2
+            };
+            let alignment = if name.eq(".balign"){ n } else { 1_u32.checked_shl(n).unwrap_or(0) };
+            if alignment == 0 || !alignment.is_power_of_two(){
+               return asm_err!(line,"invalid alignment {}",n);
+            }
+            let fill = match parts.get(1){
+               Some(e) if !e.trim().is_empty() => self.constant(line, e)? as u8,
+               _ => 0
+            };
+            self.align(line, alignment, fill);
+         },
+         ".fill" | ".space" | ".skip" | ".zero" => {
+            let parts = split_operands(args);
+            if parts.is_empty(){
+               return asm_err!(line,"{} needs a size",name);
+            }
+            let count = self.constant(line, &parts[0])?;
+            if count < 0{
+               return asm_err!(line,"{} size cannot be negative",name);
+            }
+            let (size, value) = if name.eq(".fill"){
+               let size = match parts.get(1){
+                  Some(e) => self.constant(line, e)? as usize,
+                  None => 1
+               };
+               (size, parts.get(2).map(|v|v.trim().to_string()).unwrap_or("0".into()))
+            }else if name.eq(".zero"){
+               (1,"0".to_string())
+            }else{
+               (1,parts.get(1).map(|v|v.trim().to_string()).unwrap_or("0".into()))
+            };
+            if size > 4{
+               return asm_err!(line,".fill size greater than 4 bytes is not supported");
+            }
+            self.mark(false);
+            let total = count as u32 * size as u32;
+            self.push(line, total, StmtKind::Fill{count: count as u32, size, value});
+         },
+         ".pool" | ".ltorg" => self.flush_pool(line)?,
+         _ => return asm_err!(line,"unsupported directive {}",name)
+      }
+      return Ok(());
+   }
+
+   fn data(&mut self, line: usize, width: usize, args: &str){
+      let exprs: Vec<String> = split_operands(args).into_iter().map(|e| e.trim().to_string()).collect();
+      self.mark(false);
+      let n = (exprs.len() * width) as u32;
+      self.push(line, n, StmtKind::Data{width, exprs});
+   }
+
+   fn align(&mut self, line: usize, alignment: u32, fill: u8){
+      let dot = self.dot();
+      let padding = (alignment - (dot % alignment)) % alignment;
+      if padding > 0{
+         self.push(line, padding, StmtKind::Bytes(vec![fill;padding as usize]));
+      }
+   }
+
+   fn flush_pool(&mut self, line: usize)->Result<(),AsmError>{
+      if let Some(p) = self.sections[self.current].open_pool.take(){
+         self.align(line, 4, 0);
+         self.mark(false);
+         let offset = self.dot();
+         self.pools[p].offset = offset;
+         let size = 4 * self.pools[p].entries.len() as u32;
+         self.push(line, size, StmtKind::Pool(p));
+      }
+      return Ok(());
+   }
+
+   fn add_literal(&mut self, expr: &str, line: usize)->(usize,usize){
+      let normalised: String = expr.chars().filter(|c| !c.is_whitespace()).collect();
+      let p = match self.sections[self.current].open_pool{
+         Some(p) => p,
+         None => {
+            self.pools.push(Pool{offset: 0, entries: Vec::new()});
+            let p = self.pools.len() - 1;
+            self.sections[self.current].open_pool = Some(p);
+            p
+         }
+      };
+      match self.pools[p].entries.iter().position(|(e,_)| e.eq(&normalised)){
+         Some(i) => (p,i),
+         None => {
+            self.pools[p].entries.push((normalised,line));
+            (p,self.pools[p].entries.len() - 1)
+         }
+      }
+   }
+
+   fn instruction(&mut self, line: usize, word: &str, args: &str)->Result<(),AsmError>{
+      if self.sections[self.current].section.nobits{
+         return asm_err!(line,"instructions cannot be placed in {}",self.sections[self.current].section.name);
+      }
+      let (mnemonic, wide) = normalise_mnemonic(word);
+      let size = match mnemonic.as_str(){
+         "bl" | "dmb" | "dsb" | "isb" | "mrs" | "msr" => 4,
+         "udf" if wide => 4,
+         _ => 2
+      };
+      if self.dot() % 2 != 0{
+         return asm_err!(line,"misaligned instruction, thumb instructions must be halfword aligned");
+      }
+      self.mark(true);
+
+      let mut literal = None;
+      if mnemonic.starts_with("ldr"){
+         let operands = split_operands(args);
+         if operands.len() == 2 && operands[1].trim().starts_with('='){
+            let expr = operands[1].trim()[1..].trim().to_string();
+            let rt = parse_register(&operands[0]);
+            let fits_mov = match self.try_constant(&expr){
+               Some(v) => (0..=255).contains(&v) && matches!(rt,Some(r) if r < 8) && mnemonic.eq("ldr"),
+               None => false
+            };
+            if !fits_mov{
+               literal = Some(self.add_literal(&expr, line));
+            }
+         }
+      }
+      self.push(line, size, StmtKind::Instr{mnemonic: word.to_string(), args: args.to_string(), literal});
+      return Ok(());
+   }
+
+   fn constant(&mut self, line: usize, expr: &str)->Result<i64,AsmError>{
+      match self.eval(expr, self.current, self.dot(), false){
+         Ok(Value::Abs(v)) => Ok(v),
+         Ok(_) => asm_err!(line,"expected a constant expression: {}",expr.trim()),
+         Err(msg) => asm_err!(line,"{}",msg)
+      }
+   }
+
+   fn try_constant(&mut self, expr: &str)->Option<i64>{
+      match self.eval(expr, self.current, self.dot(), false){
+         Ok(Value::Abs(v)) => Some(v),
+         _ => None
+      }
+   }
+
+   fn eval(&self, expr: &str, section: usize, dot: u32, final_pass: bool)->Result<Value,String>{
+      let mut lookup = |name: &str, depth: usize|->Result<Value,String>{
+         self.lookup(name, section, dot, final_pass, depth)
+      };
+      return eval_expr(expr, 0, &mut lookup);
+   }
+
+   fn lookup(&self, name: &str, section: usize, dot: u32, final_pass: bool, depth: usize)->Result<Value,String>{
+      if depth > MAX_EXPR_DEPTH{
+         return Err(format!("symbol `{}` is defined in terms of itself",name));
+      }
+      if name.eq("."){
+         return Ok(Value::Rel{section, offset: dot as i64, thumb: false});
+      }
+      match self.definitions.get(name){
+         Some(Definition::Label{section, offset}) => {
+            Ok(Value::Rel{section: *section, offset: *offset as i64, thumb: self.funcs.iter().any(|f| f.eq(name))})
+         },
+         Some(Definition::Equ{expr, section, dot, ..}) => {
+            let mut lookup = |n: &str, d: usize|->Result<Value,String>{
+               self.lookup(n, *section, *dot, final_pass, d)
+            };
+            eval_expr(expr, depth + 1, &mut lookup)
+         },
+         None => {
+            if let Some(addr) = self.externals.get(name){
+               return Ok(Value::Abs(*addr as i64));
+            }
+            if final_pass && self.base.is_none(){
+               Ok(Value::Ext{symbol: name.to_string(), offset: 0})
+            }else{
+               Err(format!("undefined symbol `{}`",name))
+            }
+         }
+      }
+   }
+
+   fn encode(&mut self)->Result<(),AsmError>{
+      for state in self.sections.iter_mut(){
+         if !state.section.nobits{
+            state.section.bytes = vec![0;state.offset as usize];
+         }
+      }
+      let stmts = std::mem::take(&mut self.stmts);
+      for stmt in stmts.iter(){
+         let bytes = match &stmt.kind{
+            StmtKind::Bytes(b) => b.clone(),
+            StmtKind::Fill{count, size, value} => {
+               let v = self.absolute(stmt, value)?;
+               let mut b = Vec::new();
+               for _ in 0 .. *count{
+                  b.extend_from_slice(&(v as u32).to_le_bytes()[..*size]);
+               }
+               b
+            },
+            StmtKind::Data{width, exprs} => {
+               let mut b = Vec::new();
+               for (i,e) in exprs.iter().enumerate(){
+                  let offset = stmt.offset + (i * width) as u32;
+                  let v = self.data_value(stmt, e, offset, *width)?;
+                  b.extend_from_slice(&v.to_le_bytes()[..*width]);
+               }
+               b
+            },
+            StmtKind::Pool(p) => {
+               let mut b = Vec::new();
+               let entries = self.pools[*p].entries.clone();
+               for (i,(e,line)) in entries.iter().enumerate(){
+                  let offset = self.pools[*p].offset + 4 * i as u32;
+                  let at = Stmt{line: *line, section: stmt.section, offset, kind: StmtKind::Bytes(Vec::new())};
+                  let v = self.data_value(&at, e, offset, 4)?;
+                  b.extend_from_slice(&v.to_le_bytes());
+               }
+               b
+            },
+            StmtKind::Instr{mnemonic, args, literal} => {
+               self.encode_instruction(stmt, mnemonic, args, *literal)?
+            }
+         };
+
+         let state = &mut self.sections[stmt.section];
+         if state.section.nobits{
+            if bytes.iter().any(|b| *b != 0){
+               return asm_err!(stmt.line,"cannot place initialised data in {}",state.section.name);
+            }
+            continue;
+         }
+         let start = stmt.offset as usize;
+         state.section.bytes[start .. start + bytes.len()].copy_from_slice(&bytes);
+      }
+      self.stmts = stmts;
+      return Ok(());
+   }
+
+   fn absolute(&self, stmt: &Stmt, expr: &str)->Result<i64,AsmError>{
+      match self.eval(expr, stmt.section, stmt.offset, true){
+         Ok(Value::Abs(v)) => Ok(v),
+         Ok(_) => asm_err!(stmt.line,"expected a constant expression: {}",expr),
+         Err(msg) => asm_err!(stmt.line,"{}",msg)
+      }
+   }
+
+   fn value(&self, stmt: &Stmt, expr: &str)->Result<Value,AsmError>{
+      match self.eval(expr, stmt.section, stmt.offset, true){
+         Ok(v) => Ok(v),
+         Err(msg) => asm_err!(stmt.line,"{}",msg)
+      }
+   }
+
+   fn section_base(&self)->i64{
+      self.base.unwrap_or(0) as i64
+   }
+
+   fn relocate(&mut self, section: usize, offset: u32, kind: RelocationType, symbol: &str){
+      if !self.definitions.contains_key(symbol)
+         && !self.sections.iter().any(|s| s.section.name.eq(symbol))
+         && !self.undefined.iter().any(|u| u.eq(symbol)){
+         self.undefined.push(symbol.to_string());
+      }
+      self.sections[section].section.relocations.push(Relocation{offset, kind, symbol: symbol.to_string()});
+   }
+
+   fn data_value(&mut self, stmt: &Stmt, expr: &str, offset: u32, width: usize)->Result<u32,AsmError>{
+      match self.value(stmt, expr)?{
+         Value::Abs(v) => {
+            let max = if width == 4 { u32::MAX as i64 } else { (1_i64 << (width * 8)) - 1 };
+            let min = -(1_i64 << (width * 8 - 1));
+            if v > max || v < min{
+               return asm_err!(stmt.line,"value {:#x} does not fit in {} bytes",v,width);
+            }
+            Ok(v as u32)
+         },
+         Value::Rel{section, offset: target, thumb} => {
+            if width != 4{
+               return asm_err!(stmt.line,"addresses need a 4 byte data directive");
+            }
+            let v = (self.section_base() + target) as u32 | (thumb as u32);
+            if self.base.is_none(){
+               let name = self.sections[section].section.name.clone();
+               self.relocate(stmt.section, offset, RelocationType::ABS32, &name);
+            }
+            Ok(v)
+         },
+         Value::Ext{symbol, offset: addend} => {
+            if width != 4{
+               return asm_err!(stmt.line,"addresses need a 4 byte data directive");
+            }
+            self.relocate(stmt.section, offset, RelocationType::ABS32, &symbol);
+            Ok(addend as u32)
+         }
+      }
+   }
+
+   fn immediate(&self, stmt: &Stmt, operand: &str)->Result<i64,AsmError>{
+      let trimmed = operand.trim();
+      let expr = trimmed.strip_prefix('#').unwrap_or(trimmed);
+      return self.absolute(stmt, expr);
+   }
+
+   // offset from the pc (instruction address + 4) to target, None when the target needs a relocation
+   fn branch_offset(&mut self, stmt: &Stmt, expr: &str, kind: RelocationType)->Result<Option<i64>,AsmError>{
+      let pc = self.section_base() + stmt.offset as i64 + 4;
+      match self.value(stmt, expr)?{
+         Value::Abs(target) => {
+            if self.base.is_none(){
+               return asm_err!(stmt.line,"branch to an absolute address needs a base address");
+            }
+            Ok(Some((target & !1) - pc))
+         },
+         Value::Rel{section, offset, ..} if section == stmt.section => {
+            Ok(Some(self.section_base() + offset - pc))
+         },
+         Value::Rel{section, ..} => {
+            let name = self.sections[section].section.name.clone();
+            self.relocate(stmt.section, stmt.offset, kind, &name);
+            Ok(None)
+         },
+         Value::Ext{symbol, ..} => {
+            self.relocate(stmt.section, stmt.offset, kind, &symbol);
+            Ok(None)
+         }
+      }
+   }
+
+   fn pc_relative_word(&mut self, stmt: &Stmt, target: i64)->Result<u16,AsmError>{
+      let pc = (stmt.offset as i64 + 4) & !3;
+      let delta = target - pc;
+      if !(0 ..= 1020).contains(&delta) || delta % 4 != 0{
+         return asm_err!(stmt.line,"pc relative offset {} is out of range (0-1020, word aligned)",delta);
+      }
+      return Ok((delta >> 2) as u16);
+   }
+
+   fn local_target(&mut self, stmt: &Stmt, expr: &str)->Result<i64,AsmError>{
+      match self.value(stmt, expr)?{
+         Value::Rel{section, offset, ..} if section == stmt.section => Ok(offset),
+         Value::Abs(addr) if self.base.is_some() => Ok(addr - self.section_base()),
+         _ => asm_err!(stmt.line,"{} must be a label in the same section",expr)
+      }
+   }
+
+   fn encode_instruction(&mut self, stmt: &Stmt, word: &str, args: &str, literal: Option<(usize,usize)>)->Result<Vec<u8>,AsmError>{
+      let line = stmt.line;
+      let (full, wide) = normalise_mnemonic(word);
+      let ops: Vec<String> = split_operands(args).into_iter().map(|s| s.trim().to_string()).collect();
+      let (base, setflags) = split_flags(&full);
+
+      let reg = |i: usize|->Result<u8,AsmError>{
+         match ops.get(i){
+            Some(op) => parse_register(op).ok_or(AsmError{line, msg: format!("expected a register, found `{}`",op)}),
+            None => asm_err!(line,"{} expects more operands",word)
+         }
+      };
+      let low = |r: u8|->Result<u16,AsmError>{
+         if r < 8 { Ok(r as u16) } else { asm_err!(line,"{} only accepts r0-r7 here",word) }
+      };
+      let n_ops = ops.len();
+      let is_imm = |i: usize|->bool{
+         match ops.get(i){
+            Some(op) => parse_register(op).is_none(),
+            None => false
+         }
+      };
+
+      let hw: u16 = match base.as_str(){
+         "adc" | "and" | "eor" | "orr" | "bic" | "sbc" | "ror" | "mul" | "tst" | "cmn" | "mvn" => {
+            let op: u16 = match base.as_str(){
+               "and" => 0, "eor" => 1, "adc" => 5, "sbc" => 6, "ror" => 7, "tst" => 8,
+               "cmn" => 11, "orr" => 12, "mul" => 13, "bic" => 14, _ => 15
+            };
+            let (rdn, rm) = match n_ops{
+               2 => (reg(0)?, reg(1)?),
+               3 => {
+                  let (rd, rn, rm) = (reg(0)?, reg(1)?, reg(2)?);
+                  let commutative = matches!(base.as_str(),"and" | "eor" | "orr" | "adc" | "mul");
+                  if rd == rn && base.ne("mul"){
+                     (rd, rm)
+                  }else if rd == rm && commutative{
+                     (rd, rn)
+                  }else{
+                     return asm_err!(line,"{} needs the destination to be one of the sources",word);
+                  }
+               },
+               _ => return asm_err!(line,"{} expects 2 or 3 operands",word)
+            };
+            0x4000 | op << 6 | low(rm)? << 3 | low(rdn)?
+         },
+         "neg" | "rsb" => {
+            if base.eq("rsb") && n_ops == 3 && self.immediate(stmt, &ops[2])? != 0{
+               return asm_err!(line,"rsb only supports #0 as the immediate");
+            }
+            let rn = if n_ops >= 2 { reg(1)? } else { reg(0)? };
+            0x4240 | low(rn)? << 3 | low(reg(0)?)?
+         },
+         "lsl" | "lsr" | "asr" => {
+            let (imm_op, reg_op): (u16,u16) = match base.as_str(){
+               "lsl" => (0x0000, 2),
+               "lsr" => (0x0800, 3),
+               _ => (0x1000, 4)
+            };
+            let rd = reg(0)?;
+            let (rm, shift) = match n_ops{
+               2 if is_imm(1) => (rd, Some(&ops[1])),
+               2 => (reg(1)?, None),
+               3 if is_imm(2) => (reg(1)?, Some(&ops[2])),
+               3 => {
+                  if reg(1)? != rd{
+                     return asm_err!(line,"register shifts need the destination to be the first source");
+                  }
+                  (reg(2)?, None)
+               },
+               _ => return asm_err!(line,"{} expects 2 or 3 operands",word)
+            };
+            match shift{
+               Some(amount) => {
+                  let imm = self.immediate(stmt, amount)?;
+                  let imm5 = match (base.as_str(), imm){
+                     ("lsl", 0 ..= 31) => imm,
+                     ("lsr" | "asr", 1 ..= 31) => imm,
+                     ("lsr" | "asr", 32) => 0,
+                     _ => return asm_err!(line,"shift amount {} is out of range",imm)
+                  } as u16;
+                  imm_op | imm5 << 6 | low(rm)? << 3 | low(rd)?
+               },
+               None => 0x4000 | reg_op << 6 | low(rm)? << 3 | low(rd)?
+            }
+         },
+         "add" => self.encode_add(stmt, &ops, setflags)?,
+         "sub" => self.encode_sub(stmt, &ops)?,
+         "mov" => {
+            if n_ops != 2{
+               return asm_err!(line,"mov expects 2 operands");
+            }
+            let rd = reg(0)?;
+            if is_imm(1){
+               let imm = self.immediate(stmt, &ops[1])?;
+               if !(0 ..= 255).contains(&imm){
+                  return asm_err!(line,"mov immediate {} is out of range (0-255)",imm);
+               }
+               0x2000 | low(rd)? << 8 | imm as u16
+            }else{
+               let rm = reg(1)?;
+               if rd < 8 && rm < 8 && setflags{
+                  (rm as u16) << 3 | rd as u16
+               }else if rd < 8 && rm < 8 && !self.unified{
+                  // pre-UAL syntax moves between low registers with ADDS Rd,Rm,#0 like GNU as does
+                  0x1C00 | (rm as u16) << 3 | rd as u16
+               }else{
+                  0x4600 | ((rd as u16) & 8) << 4 | (rm as u16) << 3 | (rd as u16) & 7
+               }
+            }
+         },
+         "cmp" => {
+            let rn = reg(0)?;
+            if is_imm(1){
+               let imm = self.immediate(stmt, &ops[1])?;
+               if !(0 ..= 255).contains(&imm){
+                  return asm_err!(line,"cmp immediate {} is out of range (0-255)",imm);
+               }
+               0x2800 | low(rn)? << 8 | imm as u16
+            }else{
+               let rm = reg(1)?;
+               if rn < 8 && rm < 8{
+                  0x4280 | (rm as u16) << 3 | rn as u16
+               }else{
+                  0x4500 | ((rn as u16) & 8) << 4 | (rm as u16) << 3 | (rn as u16) & 7
+               }
+            }
+         },
+         "adr" => {
+            let target = self.local_target(stmt, &ops.get(1).cloned().unwrap_or_default())?;
+            0xA000 | low(reg(0)?)? << 8 | self.pc_relative_word(stmt, target)?
+         },
+         "ldr" | "ldrb" | "ldrh" | "ldrsb" | "ldrsh" | "str" | "strb" | "strh" => {
+            if n_ops != 2{
+               return asm_err!(line,"{} expects 2 operands",word);
+            }
+            let rt = reg(0)?;
+            let operand = ops[1].trim();
+            if let Some((p,i)) = literal{
+               let pool = &self.pools[p];
+               let target = pool.offset as i64 + 4 * i as i64;
+               0x4800 | low(rt)? << 8 | self.pc_relative_word(stmt, target)?
+            }else if let Some(expr) = operand.strip_prefix('='){
+               let imm = self.absolute(stmt, expr)?;
+               0x2000 | low(rt)? << 8 | imm as u16
+            }else if operand.starts_with('['){
+               self.encode_load_store(stmt, &base, rt, operand)?
+            }else{
+               if base.ne("ldr"){
+                  return asm_err!(line,"{} does not support pc relative addressing",word);
+               }
+               let target = self.local_target(stmt, operand)?;
+               0x4800 | low(rt)? << 8 | self.pc_relative_word(stmt, target)?
+            }
+         },
+         "ldm" | "ldmia" | "ldmfd" | "stm" | "stmia" | "stmea" => {
+            if n_ops != 2{
+               return asm_err!(line,"{} expects a base register and a register list",word);
+            }
+            let rn_op = ops[0].trim();
+            let rn = parse_register(rn_op.trim_end_matches('!')).ok_or(AsmError{line, msg: format!("expected a register, found `{}`",rn_op)})?;
+            let list = parse_register_list(&ops[1]).ok_or(AsmError{line, msg: format!("invalid register list {}",ops[1])})?;
+            if list & 0xFF00 != 0 || list == 0{
+               return asm_err!(line,"{} only supports r0-r7 in the register list",word);
+            }
+            let opcode: u16 = if base.starts_with("ldm") { 0xC800 } else { 0xC000 };
+            opcode | low(rn)? << 8 | list
+         },
+         "push" | "pop" => {
+            let list = parse_register_list(args).ok_or(AsmError{line, msg: format!("invalid register list {}",args)})?;
+            let extra = if base.eq("push") { 1 << LINK_REGISTER } else { 1 << PROGRAM_COUNTER };
+            if list & 0xFF00 & !extra != 0 || list == 0{
+               return asm_err!(line,"{} cannot use {}",word,args.trim());
+            }
+            let opcode: u16 = if base.eq("push") { 0xB400 } else { 0xBC00 };
+            opcode | ((list & extra != 0) as u16) << 8 | (list & 0xFF)
+         },
+         "rev" | "rev16" | "revsh" | "sxth" | "sxtb" | "uxth" | "uxtb" => {
+            let opcode: u16 = match base.as_str(){
+               "rev" => 0xBA00, "rev16" => 0xBA40, "revsh" => 0xBAC0,
+               "sxth" => 0xB200, "sxtb" => 0xB240, "uxth" => 0xB280, _ => 0xB2C0
+            };
+            opcode | low(reg(1)?)? << 3 | low(reg(0)?)?
+         },
+         "b" | "bal" => {
+            match self.branch_offset(stmt, &ops.first().cloned().unwrap_or_default(), RelocationType::THM_JUMP11)?{
+               Some(offset) => {
+                  if !(-2048 ..= 2046).contains(&offset){
+                     return asm_err!(line,"branch out of range ({} bytes)",offset);
+                  }
+                  0xE000 | ((offset >> 1) as u16 & 0x7FF)
+               },
+               None => 0xE7FE
+            }
+         },
+         "bx" => 0x4700 | (reg(0)? as u16) << 3,
+         "blx" => {
+            if ops.first().map(|o| parse_register(o).is_none()).unwrap_or(true){
+               return asm_err!(line,"ARMv6-M only supports BLX with a register");
+            }
+            0x4780 | (reg(0)? as u16) << 3
+         },
+         "bl" => {
+            // unresolved calls hold the -4 addend in place like GNU as
+            let offset = self.branch_offset(stmt, &ops.first().cloned().unwrap_or_default(), RelocationType::THM_CALL)?
+               .unwrap_or(-4);
+            if !(-16777216 ..= 16777214).contains(&offset){
+               return asm_err!(line,"branch out of range ({} bytes)",offset);
+            }
+            let (first, second) = encode_bl(offset as i32);
+            return Ok(into_bytes_32b(first, second));
+         },
+         "bkpt" | "svc" | "swi" | "udf" => {
+            let imm = match ops.first(){
+               Some(op) => self.immediate(stmt, op)?,
+               None => 0
+            };
+            if base.eq("udf") && wide{
+               if !(0 ..= 0xFFFF).contains(&imm){
+                  return asm_err!(line,"immediate {} is out of range (0-65535)",imm);
+               }
+               let imm = imm as u16;
+               return Ok(into_bytes_32b(0xF7F0 | imm >> 12, 0xA000 | (imm & 0xFFF)));
+            }
+            if !(0 ..= 255).contains(&imm){
+               return asm_err!(line,"immediate {} is out of range (0-255)",imm);
+            }
+            let opcode: u16 = match base.as_str(){
+               "bkpt" => 0xBE00,
+               "udf" => 0xDE00,
+               _ => 0xDF00
+            };
+            opcode | imm as u16
+         },
+         "cpsie" | "cpsid" => {
+            // f (FAULTMASK) is only executable with the ARMv7-M main extension
+            let flags = match ops.first().map(|o| o.to_lowercase()).as_deref(){
+               Some("i") => 0x2,
+               Some("f") => 0x1,
+               Some("if") => 0x3,
+               _ => return asm_err!(line,"{} expects the i, f or if flags",word)
+            };
+            (if base.eq("cpsie") { 0xB660 } else { 0xB670 }) | flags
+         },
+         "nop" => 0xBF00,
+         "yield" => 0xBF10,
+         "wfe" => 0xBF20,
+         "wfi" => 0xBF30,
+         "sev" => 0xBF40,
+         "dmb" | "dsb" | "isb" => {
+            let option = match ops.first(){
+               Some(op) => match barrier_option(op){
+                  Some(o) => o,
+                  None => self.immediate(stmt, op)? as u16 & 0xF
+               },
+               None => 0xF
+            };
+            let second: u16 = match base.as_str(){
+               "dsb" => 0x8F40,
+               "dmb" => 0x8F50,
+               _ => 0x8F60
+            };
+            return Ok(into_bytes_32b(0xF3BF, second | option));
+         },
+         "mrs" => {
+            let rd = reg(0)?;
+            let sysm = special_register(&ops.get(1).cloned().unwrap_or_default())
+               .ok_or(AsmError{line, msg: format!("unknown special register in {}",args.trim())})?;
+            return Ok(into_bytes_32b(0xF3EF, 0x8000 | (rd as u16) << 8 | sysm));
+         },
+         "msr" => {
+            let sysm = special_register(&ops.first().cloned().unwrap_or_default())
+               .ok_or(AsmError{line, msg: format!("unknown special register in {}",args.trim())})?;
+            let rn = reg(1)?;
+            return Ok(into_bytes_32b(0xF380 | rn as u16, 0x8800 | sysm));
+         },
+         _ => {
+            if let Some(cond) = condition_code(&full){
+               match self.branch_offset(stmt, &ops.first().cloned().unwrap_or_default(), RelocationType::THM_JUMP8)?{
+                  Some(offset) => {
+                     if !(-256 ..= 254).contains(&offset){
+                        return asm_err!(line,"conditional branch out of range ({} bytes)",offset);
+                     }
+                     0xD000 | cond << 8 | ((offset >> 1) as u16 & 0xFF)
+                  },
+                  None => 0xD000 | cond << 8 | 0xFE
+               }
+            }else{
+               return asm_err!(line,"unknown instruction {}",word);
+            }
+         }
+      };
+      return Ok(hw.to_le_bytes().to_vec());
+   }
+
+   fn encode_add(&mut self, stmt: &Stmt, ops: &Vec<String>, setflags: bool)->Result<u16,AsmError>{
+      let line = stmt.line;
+      let reg = |i: usize|->Result<u8,AsmError>{
+         match ops.get(i){
+            Some(op) => parse_register(op).ok_or(AsmError{line, msg: format!("expected a register, found `{}`",op)}),
+            None => asm_err!(line,"add expects more operands")
+         }
+      };
+      let (rd, rn, last) = match ops.len(){
+         2 => (reg(0)?, reg(0)?, &ops[1]),
+         3 => (reg(0)?, reg(1)?, &ops[2]),
+         _ => return asm_err!(line,"add expects 2 or 3 operands")
+      };
+      match parse_register(last){
+         Some(rm) => {
+            if ops.len() == 3 && rd < 8 && rn < 8 && rm < 8{
+               return Ok(0x1800 | (rm as u16) << 6 | (rn as u16) << 3 | rd as u16);
+            }
+            if ops.len() == 2 && rd < 8 && rm < 8 && (setflags || !self.unified){
+               return Ok(0x1800 | (rm as u16) << 6 | (rd as u16) << 3 | rd as u16);
+            }
+            if setflags{
+               return asm_err!(line,"adds only accepts r0-r7, use add for high registers");
+            }
+            let other = if rd == rn { rm } else if rd == rm { rn } else {
+               return asm_err!(line,"add with high registers needs the destination to be one of the sources");
+            };
+            return Ok(0x4400 | ((rd as u16) & 8) << 4 | (other as u16) << 3 | (rd as u16) & 7);
+         },
+         None => {
+            let imm = self.immediate(stmt, last)?;
+            if rn == STACK_POINTER || rn == PROGRAM_COUNTER{
+               if imm % 4 != 0{
+                  return asm_err!(line,"immediate {} must be a multiple of 4",imm);
+               }
+               if rd == STACK_POINTER && rn == STACK_POINTER{
+                  if !(0 ..= 508).contains(&imm){
+                     return asm_err!(line,"immediate {} is out of range (0-508)",imm);
+                  }
+                  return Ok(0xB000 | (imm >> 2) as u16);
+               }
+               if rd >= 8 || !(0 ..= 1020).contains(&imm){
+                  return asm_err!(line,"add rd, {}, #imm needs r0-r7 and an immediate in 0-1020",if rn == STACK_POINTER {"SP"} else {"PC"});
+               }
+               let opcode: u16 = if rn == STACK_POINTER { 0xA800 } else { 0xA000 };
+               return Ok(opcode | (rd as u16) << 8 | (imm >> 2) as u16);
+            }
+            if rd >= 8 || rn >= 8{
+               return asm_err!(line,"add only accepts r0-r7 with an immediate");
+            }
+            if ops.len() == 3 && (rd != rn || imm <= 7) && (0 ..= 7).contains(&imm){
+               return Ok(0x1C00 | (imm as u16) << 6 | (rn as u16) << 3 | rd as u16);
+            }
+            if rd == rn && (0 ..= 255).contains(&imm){
+               return Ok(0x3000 | (rd as u16) << 8 | imm as u16);
+            }
+            return asm_err!(line,"add immediate {} is out of range",imm);
+         }
+      }
+   }
+
+   fn encode_sub(&mut self, stmt: &Stmt, ops: &Vec<String>)->Result<u16,AsmError>{
+      let line = stmt.line;
+      let reg = |i: usize|->Result<u8,AsmError>{
+         match ops.get(i){
+            Some(op) => parse_register(op).ok_or(AsmError{line, msg: format!("expected a register, found `{}`",op)}),
+            None => asm_err!(line,"sub expects more operands")
+         }
+      };
+      let (rd, rn, last) = match ops.len(){
+         2 => (reg(0)?, reg(0)?, &ops[1]),
+         3 => (reg(0)?, reg(1)?, &ops[2]),
+         _ => return asm_err!(line,"sub expects 2 or 3 operands")
+      };
+      match parse_register(last){
+         Some(rm) => {
+            if rd >= 8 || rn >= 8 || rm >= 8{
+               return asm_err!(line,"sub only accepts r0-r7 registers");
+            }
+            return Ok(0x1A00 | (rm as u16) << 6 | (rn as u16) << 3 | rd as u16);
+         },
+         None => {
+            let imm = self.immediate(stmt, last)?;
+            if rd == STACK_POINTER && rn == STACK_POINTER{
+               if imm % 4 != 0 || !(0 ..= 508).contains(&imm){
+                  return asm_err!(line,"immediate {} must be a multiple of 4 in 0-508",imm);
+               }
+               return Ok(0xB080 | (imm >> 2) as u16);
+            }
+            if rd >= 8 || rn >= 8{
+               return asm_err!(line,"sub only accepts r0-r7 with an immediate");
+            }
+            if ops.len() == 3 && (rd != rn || imm <= 7) && (0 ..= 7).contains(&imm){
+               return Ok(0x1E00 | (imm as u16) << 6 | (rn as u16) << 3 | rd as u16);
+            }
+            if rd == rn && (0 ..= 255).contains(&imm){
+               return Ok(0x3800 | (rd as u16) << 8 | imm as u16);
+            }
+            return asm_err!(line,"sub immediate {} is out of range",imm);
+         }
+      }
+   }
+
+   fn encode_load_store(&mut self, stmt: &Stmt, base: &str, rt: u8, operand: &str)->Result<u16,AsmError>{
+      let line = stmt.line;
+      let inner = operand.trim().strip_prefix('[').and_then(|s| s.strip_suffix(']'))
+         .ok_or(AsmError{line, msg: format!("invalid memory operand {}",operand)})?;
+      let parts: Vec<String> = split_operands(inner).into_iter().map(|s| s.trim().to_string()).collect();
+      let rn = parts.first().and_then(|r| parse_register(r))
+         .ok_or(AsmError{line, msg: format!("invalid base register in {}",operand)})?;
+      if rt >= 8{
+         return asm_err!(line,"{} only accepts r0-r7 as the transfer register",base);
+      }
+      let rt = rt as u16;
+
+      if let Some(rm) = parts.get(1).and_then(|r| parse_register(r)){
+         if rn >= 8 || rm >= 8{
+            return asm_err!(line,"register offsets only accept r0-r7");
+         }
+         let opcode: u16 = match base{
+            "str" => 0x5000, "strh" => 0x5200, "strb" => 0x5400, "ldrsb" => 0x5600,
+            "ldr" => 0x5800, "ldrh" => 0x5A00, "ldrb" => 0x5C00, _ => 0x5E00
+         };
+         return Ok(opcode | (rm as u16) << 6 | (rn as u16) << 3 | rt);
+      }
+
+      let imm = match parts.get(1){
+         Some(op) => self.immediate(stmt, op)?,
+         None => 0
+      };
+      if rn == STACK_POINTER || rn == PROGRAM_COUNTER{
+         if base.ne("ldr") && !(base.eq("str") && rn == STACK_POINTER){
+            return asm_err!(line,"{} cannot use {} as a base register",base,operand);
+         }
+         if imm % 4 != 0 || !(0 ..= 1020).contains(&imm){
+            return asm_err!(line,"offset {} must be a multiple of 4 in 0-1020",imm);
+         }
+         let opcode: u16 = match (base, rn){
+            ("str", _) => 0x9000,
+            (_, STACK_POINTER) => 0x9800,
+            _ => 0x4800
+         };
+         return Ok(opcode | rt << 8 | (imm >> 2) as u16);
+      }
+      if rn >= 8{
+         return asm_err!(line,"{} only accepts r0-r7, SP or PC as a base register",base);
+      }
+      let (opcode, scale): (u16,i64) = match base{
+         "str" => (0x6000, 4),
+         "ldr" => (0x6800, 4),
+         "strb" => (0x7000, 1),
+         "ldrb" => (0x7800, 1),
+         "strh" => (0x8000, 2),
+         "ldrh" => (0x8800, 2),
+         _ => return asm_err!(line,"{} only supports register offsets",base)
+      };
+      if imm % scale != 0 || !(0 ..= 31 * scale).contains(&imm){
+         return asm_err!(line,"offset {} is out of range for {}",imm,base);
+      }
+      return Ok(opcode | ((imm / scale) as u16) << 6 | (rn as u16) << 3 | rt);
+   }
+
+   fn finish(mut self)->Assembly{
+      let mut symbols = Vec::new();
+      let order = std::mem::take(&mut self.order);
+      for name in order.iter(){
+         let global = self.globals.iter().any(|g| g.eq(name));
+         let func = self.funcs.iter().any(|f| f.eq(name));
+         match &self.definitions[name]{
+            Definition::Label{section, offset} => {
+               let value = self.base.unwrap_or(0) + offset;
+               symbols.push(AsmSymbol{
+                  name: name.clone(),
+                  location: SymbolLocation::Section(*section),
+                  value: if func { value | 1 } else { value },
+                  global,
+                  func
+               });
+            },
+            Definition::Equ{section, dot, ..} => {
+               match self.lookup(name, *section, *dot, true, 0){
+                  Ok(Value::Abs(v)) => symbols.push(AsmSymbol{
+                     name: name.clone(), location: SymbolLocation::Absolute, value: v as u32, global, func
+                  }),
+                  Ok(Value::Rel{section, offset, thumb}) => symbols.push(AsmSymbol{
+                     name: name.clone(),
+                     location: SymbolLocation::Section(section),
+                     value: (self.base.unwrap_or(0) as i64 + offset) as u32 | (thumb as u32),
+                     global,
+                     func: func || thumb
+                  }),
+                  _ => {}
+               }
+            }
+         }
+      }
+      for name in self.undefined.iter(){
+         symbols.push(AsmSymbol{name: name.clone(), location: SymbolLocation::Undefined, value: 0, global: true, func: false});
+      }
+      for name in self.globals.iter(){
+         if !symbols.iter().any(|s| s.name.eq(name)){
+            symbols.push(AsmSymbol{name: name.clone(), location: SymbolLocation::Undefined, value: 0, global: true, func: false});
+         }
+      }
+      let sections = self.sections.into_iter()
+         .filter(|s| s.section.name.eq(".text") || s.offset > 0 || !s.section.relocations.is_empty())
+         .map(|s| s.section)
+         .collect();
+      return Assembly{sections, symbols};
+   }
+}
+
+fn into_bytes_32b(first: u16, second: u16)->Vec<u8>{
+   let mut bytes = first.to_le_bytes().to_vec();
+   bytes.extend_from_slice(&second.to_le_bytes());
+   return bytes;
+}
+
+pub fn encode_bl(offset: i32)->(u16,u16){
+   let imm32 = offset as u32;
+   let s = (imm32 >> 24) & 1;
+   let i1 = (imm32 >> 23) & 1;
+   let i2 = (imm32 >> 22) & 1;
+   let j1 = (!i1 & 1) ^ s;
+   let j2 = (!i2 & 1) ^ s;
+   let imm10 = (imm32 >> 12) & 0x3FF;
+   let imm11 = (imm32 >> 1) & 0x7FF;
+   let first = 0xF000 | (s << 10) | imm10;
+   let second = 0xD000 | (j1 << 13) | (j2 << 11) | imm11;
+   return (first as u16, second as u16);
+}
+
+fn normalise_mnemonic(word: &str)->(String,bool){
+   let lower = word.to_lowercase();
+   if let Some(m) = lower.strip_suffix(".w"){
+      return (m.to_string(), true);
+   }
+   if let Some(m) = lower.strip_suffix(".n"){
+      return (m.to_string(), false);
+   }
+   return (lower, false);
+}
+
+fn split_flags(mnemonic: &str)->(String,bool){
+   const FLAG_SETTING: [&str;17] = [
+      "adc","add","and","asr","bic","eor","lsl","lsr","mov","mul","mvn","neg","orr","ror","rsb","sbc","sub"
+   ];
+   if let Some(base) = mnemonic.strip_suffix('s'){
+      if FLAG_SETTING.contains(&base){
+         return (base.to_string(), true);
+      }
+   }
+   return (mnemonic.to_string(), false);
+}
+
+fn condition_code(mnemonic: &str)->Option<u16>{
+   let cond = mnemonic.strip_prefix('b')?;
+   match cond{
+      "eq" => Some(0),
+      "ne" => Some(1),
+      "cs" | "hs" => Some(2),
+      "cc" | "lo" => Some(3),
+      "mi" => Some(4),
+      "pl" => Some(5),
+      "vs" => Some(6),
+      "vc" => Some(7),
+      "hi" => Some(8),
+      "ls" => Some(9),
+      "ge" => Some(10),
+      "lt" => Some(11),
+      "gt" => Some(12),
+      "le" => Some(13),
+      _ => None
+   }
+}
+
+fn barrier_option(op: &str)->Option<u16>{
+   match op.trim().to_lowercase().as_str(){
+      "sy" => Some(0xF),
+      "st" => Some(0xE),
+      "ish" => Some(0xB),
+      "ishst" => Some(0xA),
+      "nsh" => Some(0x7),
+      "nshst" => Some(0x6),
+      "osh" => Some(0x3),
+      "oshst" => Some(0x2),
+      _ => None
+   }
+}
+
+fn special_register(name: &str)->Option<u16>{
+   match name.trim().to_lowercase().as_str(){
+      "apsr" | "apsr_nzcvq" | "apsr_nzcv" => Some(0),
+      "iapsr" => Some(1),
+      "eapsr" => Some(2),
+      "xpsr" | "psr" => Some(3),
+      "ipsr" => Some(5),
+      "epsr" => Some(6),
+      "iepsr" => Some(7),
+      "msp" => Some(8),
+      "psp" => Some(9),
+      "primask" => Some(16),
+      "basepri" => Some(17),
+      "basepri_max" => Some(18),
+      "faultmask" => Some(19),
+      "control" => Some(20),
+      _ => None
+   }
+}
+
+pub fn parse_register(name: &str)->Option<u8>{
+   let lower = name.trim().to_lowercase();
+   match lower.as_str(){
+      "sp" => return Some(STACK_POINTER),
+      "lr" => return Some(LINK_REGISTER),
+      "pc" => return Some(PROGRAM_COUNTER),
+      "ip" => return Some(12),
+      "fp" => return Some(11),
+      "sl" => return Some(10),
+      "sb" => return Some(9),
+      _ => {}
+   }
+   let num = lower.strip_prefix('r')?;
+   match num.parse::<u8>(){
+      Ok(n) if n <= 15 && !num.starts_with('+') => Some(n),
+      _ => None
+   }
+}
+
+fn parse_register_list(list: &str)->Option<u16>{
+   let inner = list.trim().strip_prefix('{')?.strip_suffix('}')?;
+   let mut bits: u16 = 0;
+   for item in inner.split(','){
+      let item = item.trim();
+      match item.split_once('-'){
+         Some((from,to)) => {
+            let (a,b) = (parse_register(from)?, parse_register(to)?);
+            if a > b{
+               return None;
+            }
+            for r in a ..= b{
+               bits |= 1 << r;
+            }
+         },
+         None => bits |= 1 << parse_register(item)?
+      }
+   }
+   return Some(bits);
+}
+
+fn parse_string(s: &str)->Option<Vec<u8>>{
+   let inner = s.strip_prefix('"')?.strip_suffix('"')?;
+   let mut out = Vec::new();
+   let mut chars = inner.chars();
+   while let Some(c) = chars.next(){
+      if c == '\\'{
+         match chars.next()?{
+            'n' => out.push(b'\n'),
+            't' => out.push(b'\t'),
+            'r' => out.push(b'\r'),
+            '0' => out.push(0),
+            '\\' => out.push(b'\\'),
+            '"' => out.push(b'"'),
+            other => out.push(other as u8)
+         }
+      }else{
+         let mut buf = [0;4];
+         out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+      }
+   }
+   return Some(out);
+}
+
+fn is_symbol_char(c: char)->bool{
+   c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$'
+}
+
+fn split_label(text: &str)->Option<(&str,&str)>{
+   let end = text.find(|c: char| !is_symbol_char(c)).unwrap_or(text.len());
+   if end == 0{
+      return None;
+   }
+   let rest = &text[end..];
+   let after = rest.trim_start().strip_prefix(':')?;
+   return Some((&text[..end], after));
+}
+
+fn split_assignment(text: &str)->Option<(&str,&str)>{
+   let end = text.find(|c: char| !is_symbol_char(c)).unwrap_or(text.len());
+   if end == 0 || text.starts_with('.') && end == 1{
+      return None;
+   }
+   let rest = text[end..].trim_start();
+   let expr = rest.strip_prefix('=')?;
+   if expr.starts_with('='){
+      return None;
+   }
+   return Some((&text[..end], expr.trim()));
+}
+
+fn split_first_word(text: &str)->(&str,&str){
+   match text.find(char::is_whitespace){
+      Some(i) => (&text[..i], text[i..].trim()),
+      None => (text, "")
+   }
+}
+
+// splits on top level commas, ignoring commas inside brackets, braces and strings
+fn split_operands(args: &str)->Vec<String>{
+   let mut out = Vec::new();
+   if args.trim().is_empty(){
+      return out;
+   }
+   let mut depth = 0;
+   let mut in_string = false;
+   let mut current = String::new();
+   for c in args.chars(){
+      match c{
+         '"' => { in_string = !in_string; current.push(c); },
+         '[' | '{' | '(' if !in_string => { depth += 1; current.push(c); },
+         ']' | '}' | ')' if !in_string => { depth -= 1; current.push(c); },
+         ',' if depth == 0 && !in_string => {
+            out.push(current.clone());
+            current.clear();
+         },
+         _ => current.push(c)
+      }
+   }
+   out.push(current);
+   return out;
+}
+
+fn binary_op(op: &str, left: Value, right: Value)->Result<Value,String>{
+   match (op, left, right){
+      (_, Value::Abs(a), Value::Abs(b)) => {
+         let v = match op{
+            "+" => a.wrapping_add(b),
+            "-" => a.wrapping_sub(b),
+            "*" => a.wrapping_mul(b),
+            "/" => if b == 0 { return Err("division by zero".into()) } else { a / b },
+            "%" => if b == 0 { return Err("division by zero".into()) } else { a % b },
+            "<<" => a.wrapping_shl(b as u32),
+            ">>" => a.wrapping_shr(b as u32),
+            "&" => a & b,
+            "|" => a | b,
+            "^" => a ^ b,
+            _ => unreachable!()
+         };
+         Ok(Value::Abs(v))
+      },
+      ("+", Value::Rel{section, offset, thumb}, Value::Abs(b)) | ("+", Value::Abs(b), Value::Rel{section, offset, thumb}) => {
+         Ok(Value::Rel{section, offset: offset + b, thumb})
+      },
+      ("-", Value::Rel{section, offset, thumb}, Value::Abs(b)) => Ok(Value::Rel{section, offset: offset - b, thumb}),
+      ("-", Value::Rel{section: s0, offset: a, ..}, Value::Rel{section: s1, offset: b, ..}) if s0 == s1 => Ok(Value::Abs(a - b)),
+      ("+", Value::Ext{symbol, offset}, Value::Abs(b)) | ("+", Value::Abs(b), Value::Ext{symbol, offset}) => {
+         Ok(Value::Ext{symbol, offset: offset + b})
+      },
+      ("-", Value::Ext{symbol, offset}, Value::Abs(b)) => Ok(Value::Ext{symbol, offset: offset - b}),
+      _ => Err("expression cannot be resolved to a constant or a single symbol plus an offset".into())
+   }
+}
+
+type Lookup<'a> = dyn FnMut(&str,usize)->Result<Value,String> + 'a;
+
+struct ExprParser<'a,'b>{
+   chars: Vec<char>,
+   pos: usize,
+   depth: usize,
+   lookup: &'a mut Lookup<'b>
+}
+
+fn eval_expr(expr: &str, depth: usize, lookup: &mut Lookup)->Result<Value,String>{
+   let mut parser = ExprParser{chars: expr.trim().chars().collect(), pos: 0, depth, lookup};
+   if parser.chars.is_empty(){
+      return Err("missing expression".into());
+   }
+   let v = parser.parse_binary(0)?;
+   parser.skip_whitespace();
+   if parser.pos < parser.chars.len(){
+      return Err(format!("unexpected `{}` in expression {}",parser.chars[parser.pos],expr.trim()));
+   }
+   return Ok(v);
+}
+
+impl<'a,'b> ExprParser<'a,'b>{
+   fn skip_whitespace(&mut self){
+      while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace(){
+         self.pos += 1;
+      }
+   }
+
+   fn peek_operator(&mut self)->Option<(&'static str,usize)>{
+      self.skip_whitespace();
+      let rest: String = self.chars[self.pos..].iter().take(2).collect();
+      const OPERATORS: [(&str,usize);10] = [
+         ("<<",4),(">>",4),("|",1),("^",2),("&",3),("+",5),("-",5),("*",6),("/",6),("%",6)
+      ];
+      for (op,precedence) in OPERATORS{
+         if rest.starts_with(op){
+            return Some((op,precedence));
+         }
+      }
+      return None;
+   }
+
+   fn parse_binary(&mut self, min_precedence: usize)->Result<Value,String>{
+      let mut left = self.parse_unary()?;
+      while let Some((op,precedence)) = self.peek_operator(){
+         if precedence <= min_precedence{
+            break;
+         }
+         self.pos += op.len();
+         let right = self.parse_binary(precedence)?;
+         left = binary_op(op, left, right)?;
+      }
+      return Ok(left);
+   }
+
+   fn parse_unary(&mut self)->Result<Value,String>{
+      self.skip_whitespace();
+      if self.pos >= self.chars.len(){
+         return Err("unexpected end of expression".into());
+      }
+      match self.chars[self.pos]{
+         '-' => {
+            self.pos += 1;
+            match self.parse_unary()?{
+               Value::Abs(v) => Ok(Value::Abs(-v)),
+               _ => Err("cannot negate an address".into())
+            }
+         },
+         '+' => {
+            self.pos += 1;
+            self.parse_unary()
+         },
+         '~' => {
+            self.pos += 1;
+            match self.parse_unary()?{
+               Value::Abs(v) => Ok(Value::Abs(!v)),
+               _ => Err("cannot invert an address".into())
+            }
+         },
+         '(' => {
+            self.pos += 1;
+            let v = self.parse_binary(0)?;
+            self.skip_whitespace();
+            if self.pos >= self.chars.len() || self.chars[self.pos] != ')'{
+               return Err("missing `)` in expression".into());
+            }
+            self.pos += 1;
+            Ok(v)
+         },
+         '\'' => {
+            let c = *self.chars.get(self.pos + 1).ok_or("unterminated character literal")?;
+            self.pos += 2;
+            if self.chars.get(self.pos) == Some(&'\''){
+               self.pos += 1;
+            }
+            Ok(Value::Abs(c as i64))
+         },
+         c if c.is_ascii_digit() => {
+            let start = self.pos;
+            while self.pos < self.chars.len() && self.chars[self.pos].is_ascii_alphanumeric(){
+               self.pos += 1;
+            }
+            let literal: String = self.chars[start .. self.pos].iter().collect();
+            parse_number(&literal).map(Value::Abs).ok_or(format!("invalid number {}",literal))
+         },
+         c if is_symbol_char(c) => {
+            let start = self.pos;
+            while self.pos < self.chars.len() && is_symbol_char(self.chars[self.pos]){
+               self.pos += 1;
+            }
+            let name: String = self.chars[start .. self.pos].iter().collect();
+            (self.lookup)(&name, self.depth)
+         },
+         c => Err(format!("unexpected `{}` in expression",c))
+      }
+   }
+}
+
+fn parse_number(literal: &str)->Option<i64>{
+   let lower = literal.to_lowercase();
+   if let Some(hex) = lower.strip_prefix("0x"){
+      return i64::from_str_radix(hex, 16).ok();
+   }
+   if let Some(bin) = lower.strip_prefix("0b"){
+      return i64::from_str_radix(bin, 2).ok();
+   }
+   if lower.len() > 1 && lower.starts_with('0'){
+      return i64::from_str_radix(&lower[1..], 8).ok();
+   }
+   return lower.parse::<i64>().ok();
+}
+
+struct Macro{
+   params: Vec<(String,Option<String>)>,
+   body: Vec<(usize,String)>
+}
+
+// strips comments, splits ';' separated statements and expands .macro/.rept/.if blocks
+fn preprocess(src: &str)->Result<Vec<(usize,String)>,AsmError>{
+   let mut statements = Vec::new();
+   let mut in_block_comment = false;
+   for (i,raw) in src.lines().enumerate(){
+      let stripped = strip_comments(raw, &mut in_block_comment);
+      for stmt in split_statements(&stripped){
+         if !stmt.trim().is_empty(){
+            statements.push((i + 1, stmt.trim().to_string()));
+         }
+      }
+   }
+
+   let mut out = Vec::new();
+   let mut macros = HashMap::new();
+   let mut constants = HashMap::new();
+   expand(&statements, &mut out, &mut macros, &mut constants, 0)?;
+   return Ok(out);
+}
+
+fn strip_comments(line: &str, in_block_comment: &mut bool)->String{
+   let chars: Vec<char> = line.chars().collect();
+   let mut out = String::new();
+   let mut in_string = false;
+   let mut i = 0;
+   if !*in_block_comment && line.trim_start().starts_with('#'){
+      return out;
+   }
+   while i < chars.len(){
+      if *in_block_comment{
+         if chars[i] == '*' && chars.get(i + 1) == Some(&'/'){
+            *in_block_comment = false;
+            i += 2;
+         }else{
+            i += 1;
+         }
+         continue;
+      }
+      let c = chars[i];
+      if c == '"'{
+         in_string = !in_string;
+      }
+      if !in_string{
+         if c == '@' || (c == '/' && chars.get(i + 1) == Some(&'/')){
+            break;
+         }
+         if c == '/' && chars.get(i + 1) == Some(&'*'){
+            *in_block_comment = true;
+            i += 2;
+            continue;
+         }
+      }
+      out.push(c);
+      i += 1;
+   }
+   return out;
+}
+
+fn split_statements(line: &str)->Vec<String>{
+   let mut out = Vec::new();
+   let mut current = String::new();
+   let mut in_string = false;
+   for c in line.chars(){
+      if c == '"'{
+         in_string = !in_string;
+      }
+      if c == ';' && !in_string{
+         out.push(current.clone());
+         current.clear();
+      }else{
+         current.push(c);
+      }
+   }
+   out.push(current);
+   return out;
+}
+
+fn block_end(lines: &[(usize,String)], start: usize, open: &[&str], close: &str)->Result<usize,AsmError>{
+   let mut depth = 0;
+   for (i,(_,text)) in lines.iter().enumerate().skip(start){
+      let (word,_) = split_first_word(text);
+      let word = word.to_lowercase();
+      if open.contains(&word.as_str()){
+         depth += 1;
+      }else if word.eq(close){
+         depth -= 1;
+         if depth == 0{
+            return Ok(i);
+         }
+      }
+   }
+   return asm_err!(lines[start].0,"missing {}",close);
+}
+
+fn expand(
+      lines: &[(usize,String)],
+      out: &mut Vec<(usize,String)>,
+      macros: &mut HashMap<String,Macro>,
+      constants: &mut HashMap<String,i64>,
+      depth: usize
+   )->Result<bool,AsmError>{
+   if depth > MAX_EXPR_DEPTH{
+      return asm_err!(lines.first().map(|l|l.0).unwrap_or(0),"macro expansion is nested too deeply");
+   }
+   let mut i = 0;
+   while i < lines.len(){
+      let (line, text) = (&lines[i].0, &lines[i].1);
+      let (word, args) = split_first_word(text);
+      let directive = word.to_lowercase();
+      match directive.as_str(){
+         ".macro" => {
+            let end = block_end(lines, i, &[".macro"], ".endm")?;
+            let mut header = split_operands(&args.replacen(' ', ",", 1));
+            let name = header.remove(0).trim().to_string();
+            let params = header.iter()
+               .flat_map(|p| p.split_whitespace().map(|s| s.to_string()).collect::<Vec<String>>())
+               .map(|p| match p.split_once('='){
+                  Some((n,d)) => (n.trim().to_string(), Some(d.trim().to_string())),
+                  None => (p.trim().to_string(), None)
+               })
+               .filter(|(n,_)| !n.is_empty())
+               .collect();
+            macros.insert(name, Macro{params, body: lines[i + 1 .. end].to_vec()});
+            i = end + 1;
+            continue;
+         },
+         ".rept" => {
+            let end = block_end(lines, i, &[".rept"], ".endr")?;
+            let count = const_eval(args, constants).ok_or(AsmError{line: *line, msg: format!("invalid .rept count {}",args)})?;
+            for _ in 0 .. count.max(0){
+               if expand(&lines[i + 1 .. end], out, macros, constants, depth + 1)?{
+                  return Ok(true);
+               }
+            }
+            i = end + 1;
+            continue;
+         },
+         ".if" | ".ifdef" | ".ifndef" | ".ifne" | ".ifeq" => {
+            let end = block_end(lines, i, &[".if",".ifdef",".ifndef",".ifne",".ifeq"], ".endif")?;
+            let condition = match directive.as_str(){
+               ".ifdef" => constants.contains_key(args.trim()),
+               ".ifndef" => !constants.contains_key(args.trim()),
+               ".ifeq" => const_eval(args, constants).ok_or(AsmError{line: *line, msg: format!("cannot evaluate {}",args)})? == 0,
+               _ => const_eval(args, constants).ok_or(AsmError{line: *line, msg: format!("cannot evaluate {}",args)})? != 0
+            };
+            // find an .else at the same nesting level
+            let mut nesting = 0;
+            let mut else_at = None;
+            for (j,(_,t)) in lines.iter().enumerate().take(end).skip(i + 1){
+               let w = split_first_word(t).0.to_lowercase();
+               if w.starts_with(".if"){
+                  nesting += 1;
+               }else if w.eq(".endif"){
+                  nesting -= 1;
+               }else if w.eq(".else") && nesting == 0{
+                  else_at = Some(j);
+               }
+            }
+            let (start, stop) = match (condition, else_at){
+               (true, Some(e)) => (i + 1, e),
+               (true, None) => (i + 1, end),
+               (false, Some(e)) => (e + 1, end),
+               (false, None) => (end, end)
+            };
+            if expand(&lines[start .. stop], out, macros, constants, depth + 1)?{
+               return Ok(true);
+            }
+            i = end + 1;
+            continue;
+         },
+         ".end" => return Ok(true),
+         ".equ" | ".set" | ".equiv" => {
+            let parts = split_operands(args);
+            if parts.len() == 2{
+               if let Some(v) = const_eval(&parts[1], constants){
+                  constants.insert(parts[0].trim().to_string(), v);
+               }
+            }
+         },
+         _ => {}
+      }
+
+      if let Some(m) = macros.get(word){
+         let mut values: Vec<String> = split_operands(args);
+         if values.len() == 1 && m.params.len() > 1{
+            values = args.split_whitespace().map(|s| s.to_string()).collect();
+         }
+         let mut body = Vec::new();
+         for (_,t) in m.body.iter(){
+            let mut expanded = t.clone();
+            for (k,(name,default)) in m.params.iter().enumerate(){
+               let value = match values.get(k){
+                  Some(v) if !v.trim().is_empty() => v.trim().trim_matches('"').to_string(),
+                  _ => default.clone().unwrap_or_default()
+               };
+               expanded = expanded.replace(&format!("\\{}",name), &value);
+            }
+            body.push((*line, expanded.replace("\\()", "")));
+         }
+         if expand(&body, out, macros, constants, depth + 1)?{
+            return Ok(true);
+         }
+         i += 1;
+         continue;
+      }
+
+      out.push((*line, text.clone()));
+      i += 1;
+   }
+   return Ok(false);
+}
+
+fn const_eval(expr: &str, constants: &HashMap<String,i64>)->Option<i64>{
+   let mut lookup = |name: &str, _depth: usize|->Result<Value,String>{
+      match constants.get(name){
+         Some(v) => Ok(Value::Abs(*v)),
+         None => Err(format!("undefined symbol `{}`",name))
+      }
+   };
+   match eval_expr(expr, 0, &mut lookup){
+      Ok(Value::Abs(v)) => Some(v),
+      _ => None
+   }
+}
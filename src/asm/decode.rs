@@ -40,11 +40,14 @@ pub enum B16{
    BREAKPOINT,
    BR_LNK_EXCHANGE,
    BR_EXCHANGE,
+   CBZ,
+   CBNZ,
    CMP_NEG_REG,
    CMP_Imm8,
    CMP_REG_T1,
    CMP_REG_T2,
    CPS,
+   IT,
    XOR_REG,
    LDM,//load from base address sequencialy to register list
    LDR_Imm5,
@@ -135,11 +138,14 @@ impl fmt::Display for B16{
          B16::BREAKPOINT => write!(f,"BKPT"),
          B16::BR_LNK_EXCHANGE => write!(f,"BLX"),
          B16::BR_EXCHANGE => write!(f,"BX"),
+         B16::CBZ => write!(f,"CBZ"),
+         B16::CBNZ => write!(f,"CBNZ"),
          B16::CMP_NEG_REG => write!(f,"CMN"),
          B16::CMP_Imm8 => write!(f,"CMP"),
          B16::CMP_REG_T1 => write!(f,"CMP"),
          B16::CMP_REG_T2 => write!(f,"CMP"),
          B16::CPS => Ok(()),//the instruction name is known by decode_operands
+         B16::IT => Ok(()),//so are the then/else suffixes of IT
          B16::XOR_REG => write!(f,"EOR"),
          B16::LDM => write!(f,"LDM"),//load from base address sequencialy to register list
          B16::LDR_Imm5 => write!(f,"LDR"),
@@ -231,11 +237,14 @@ pub fn serialise_opcode(buffer: &mut String, opcode: &Opcode){
       Opcode::_16Bit(B16::BREAKPOINT) => buffer.push_str("BKPT"),
       Opcode::_16Bit(B16::BR_LNK_EXCHANGE) => buffer.push_str("BLX"),
       Opcode::_16Bit(B16::BR_EXCHANGE) => buffer.push_str("BX"),
+      Opcode::_16Bit(B16::CBZ) => buffer.push_str("CBZ"),
+      Opcode::_16Bit(B16::CBNZ) => buffer.push_str("CBNZ"),
       Opcode::_16Bit(B16::CMP_NEG_REG) => buffer.push_str("CMN"),
       Opcode::_16Bit(B16::CMP_Imm8) => buffer.push_str("CMP"),
       Opcode::_16Bit(B16::CMP_REG_T1) => buffer.push_str("CMP"),
       Opcode::_16Bit(B16::CMP_REG_T2) => buffer.push_str("CMP"),
       Opcode::_16Bit(B16::CPS) => {},//the instruction name is known by decode_operands
+      Opcode::_16Bit(B16::IT) => {},
       Opcode::_16Bit(B16::XOR_REG) => buffer.push_str("EOR"),
       Opcode::_16Bit(B16::LDM) => buffer.push_str("LDM"),//load from base address sequencialy to register list
       Opcode::_16Bit(B16::LDR_Imm5) => buffer.push_str("LDR"),
@@ -288,13 +297,209 @@ pub fn serialise_opcode(buffer: &mut String, opcode: &Opcode){
       Opcode::_16Bit(B16::WFE) => buffer.push_str("WFE"),
       Opcode::_16Bit(B16::WFI) => buffer.push_str("WFI"),
       Opcode::_16Bit(B16::YIELD) => buffer.push_str("YIELD"),
-      Opcode::_32Bit(B32::UNDEFINED) => buffer.push_str("UDF.W"),
-      Opcode::_32Bit(B32::BR_AND_LNK) => buffer.push_str("BL"),
-      Opcode::_32Bit(B32::DMB) => buffer.push_str("DMB"),
-      Opcode::_32Bit(B32::DSB) => buffer.push_str("DSB"),
-      Opcode::_32Bit(B32::ISB) => buffer.push_str("ISB"),
-      Opcode::_32Bit(B32::MRS) => buffer.push_str("MRS"),
-      Opcode::_32Bit(B32::MSR) => buffer.push_str("MSR")
+      Opcode::_32Bit(opcode) => serialise_b32(buffer, opcode)
+   }
+}
+
+fn serialise_b32(buffer: &mut String, opcode: &B32){
+   match opcode{
+      B32::UNDEFINED => buffer.push_str("UDF.W"),
+      B32::BR_AND_LNK => buffer.push_str("BL"),
+      B32::DMB => buffer.push_str("DMB"),
+      B32::DSB => buffer.push_str("DSB"),
+      B32::ISB => buffer.push_str("ISB"),
+      B32::MRS => buffer.push_str("MRS"),
+      B32::MSR => buffer.push_str("MSR"),
+      B32::DATA_Imm(op, setflags) | B32::DATA_REG(op, setflags) => {
+         buffer.push_str(data_op_name(op));
+         if *setflags && !op.is_comparison(){
+            buffer.push('S');
+         }
+         buffer.push_str(".W");
+      },
+      B32::ADDW => buffer.push_str("ADDW"),
+      B32::SUBW => buffer.push_str("SUBW"),
+      B32::MOVW => buffer.push_str("MOVW"),
+      B32::MOVT => buffer.push_str("MOVT"),
+      B32::SSAT => buffer.push_str("SSAT"),
+      B32::USAT => buffer.push_str("USAT"),
+      B32::SBFX => buffer.push_str("SBFX"),
+      B32::UBFX => buffer.push_str("UBFX"),
+      B32::BFI => buffer.push_str("BFI"),
+      B32::BFC => buffer.push_str("BFC"),
+      B32::LSL_W(setflags) => serialise_shift_mnemonic(buffer, "LSL", *setflags),
+      B32::LSR_W(setflags) => serialise_shift_mnemonic(buffer, "LSR", *setflags),
+      B32::ASR_W(setflags) => serialise_shift_mnemonic(buffer, "ASR", *setflags),
+      B32::ROR_W(setflags) => serialise_shift_mnemonic(buffer, "ROR", *setflags),
+      B32::SXTH_W => buffer.push_str("SXTH.W"),
+      B32::SXTB_W => buffer.push_str("SXTB.W"),
+      B32::UXTH_W => buffer.push_str("UXTH.W"),
+      B32::UXTB_W => buffer.push_str("UXTB.W"),
+      B32::REV_W => buffer.push_str("REV.W"),
+      B32::REV16_W => buffer.push_str("REV16.W"),
+      B32::REVSH_W => buffer.push_str("REVSH.W"),
+      B32::RBIT => buffer.push_str("RBIT"),
+      B32::CLZ => buffer.push_str("CLZ"),
+      B32::MUL_W => buffer.push_str("MUL.W"),
+      B32::MLA => buffer.push_str("MLA"),
+      B32::MLS => buffer.push_str("MLS"),
+      B32::SMULL => buffer.push_str("SMULL"),
+      B32::UMULL => buffer.push_str("UMULL"),
+      B32::SMLAL => buffer.push_str("SMLAL"),
+      B32::UMLAL => buffer.push_str("UMLAL"),
+      B32::SDIV => buffer.push_str("SDIV"),
+      B32::UDIV => buffer.push_str("UDIV"),
+      B32::B_W => buffer.push_str("B.W"),
+      B32::B_COND_W(cond) => {
+         buffer.push('B');
+         buffer.push_str(condition_name(*cond));
+         buffer.push_str(".W");
+      },
+      B32::NOP_W => buffer.push_str("NOP.W"),
+      B32::YIELD_W => buffer.push_str("YIELD.W"),
+      B32::WFE_W => buffer.push_str("WFE.W"),
+      B32::WFI_W => buffer.push_str("WFI.W"),
+      B32::SEV_W => buffer.push_str("SEV.W"),
+      B32::CLREX => buffer.push_str("CLREX"),
+      B32::LDM_W => buffer.push_str("LDM.W"),
+      B32::LDMDB => buffer.push_str("LDMDB"),
+      B32::STM_W => buffer.push_str("STM.W"),
+      B32::STMDB => buffer.push_str("STMDB"),
+      B32::POP_W => buffer.push_str("POP.W"),
+      B32::PUSH_W => buffer.push_str("PUSH.W"),
+      B32::LDREX => buffer.push_str("LDREX"),
+      B32::LDREXB => buffer.push_str("LDREXB"),
+      B32::LDREXH => buffer.push_str("LDREXH"),
+      B32::STREX => buffer.push_str("STREX"),
+      B32::STREXB => buffer.push_str("STREXB"),
+      B32::STREXH => buffer.push_str("STREXH"),
+      B32::LDRD => buffer.push_str("LDRD"),
+      B32::STRD => buffer.push_str("STRD"),
+      B32::TBB => buffer.push_str("TBB"),
+      B32::TBH => buffer.push_str("TBH"),
+      B32::LDA(width) => {
+         buffer.push_str("LDA");
+         buffer.push_str(width_suffix(width));
+      },
+      B32::STL(width) => {
+         buffer.push_str("STL");
+         buffer.push_str(width_suffix(width));
+      },
+      B32::LDAEX(width) => {
+         buffer.push_str("LDAEX");
+         buffer.push_str(width_suffix(width));
+      },
+      B32::STLEX(width) => {
+         buffer.push_str("STLEX");
+         buffer.push_str(width_suffix(width));
+      },
+      B32::LDR_W(width) => {
+         buffer.push_str("LDR");
+         buffer.push_str(width_suffix(width));
+         buffer.push_str(".W");
+      },
+      B32::STR_W(width) => {
+         buffer.push_str("STR");
+         buffer.push_str(width_suffix(width));
+         buffer.push_str(".W");
+      },
+      B32::PRELOAD => buffer.push_str("PLD")
+   }
+}
+
+fn serialise_shift_mnemonic(buffer: &mut String, name: &str, setflags: bool){
+   buffer.push_str(name);
+   if setflags{
+      buffer.push('S');
+   }
+   buffer.push_str(".W");
+}
+
+pub fn condition_name(cond: u8)->&'static str{
+   let table = ["EQ","NE","CS","CC","MI","PL","VS","VC","HI","LS","GE","LT","GT","LE","AL",""];
+   table[(cond & 0xF) as usize]
+}
+
+// the data processing operations shared by the modified immediate and shifted register encodings
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum DataOp{
+   AND,
+   TST,
+   BIC,
+   ORR,
+   MOV,
+   ORN,
+   MVN,
+   EOR,
+   TEQ,
+   ADD,
+   CMN,
+   ADC,
+   SBC,
+   SUB,
+   CMP,
+   RSB
+}
+
+impl DataOp{
+   // these only update the flags, they have no destination register
+   pub fn is_comparison(&self)->bool{
+      matches!(self, DataOp::TST | DataOp::TEQ | DataOp::CMN | DataOp::CMP)
+   }
+
+   // these take no first operand register
+   pub fn is_move(&self)->bool{
+      matches!(self, DataOp::MOV | DataOp::MVN)
+   }
+}
+
+fn data_op_name(op: &DataOp)->&'static str{
+   match op{
+      DataOp::AND => "AND",
+      DataOp::TST => "TST",
+      DataOp::BIC => "BIC",
+      DataOp::ORR => "ORR",
+      DataOp::MOV => "MOV",
+      DataOp::ORN => "ORN",
+      DataOp::MVN => "MVN",
+      DataOp::EOR => "EOR",
+      DataOp::TEQ => "TEQ",
+      DataOp::ADD => "ADD",
+      DataOp::CMN => "CMN",
+      DataOp::ADC => "ADC",
+      DataOp::SBC => "SBC",
+      DataOp::SUB => "SUB",
+      DataOp::CMP => "CMP",
+      DataOp::RSB => "RSB"
+   }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Width{
+   Byte,
+   SignedByte,
+   HalfWord,
+   SignedHalfWord,
+   Word
+}
+
+impl Width{
+   pub fn in_bytes(&self)->u32{
+      match self{
+         Width::Byte | Width::SignedByte => 1,
+         Width::HalfWord | Width::SignedHalfWord => 2,
+         Width::Word => 4
+      }
+   }
+}
+
+fn width_suffix(width: &Width)->&'static str{
+   match width{
+      Width::Byte => "B",
+      Width::SignedByte => "SB",
+      Width::HalfWord => "H",
+      Width::SignedHalfWord => "SH",
+      Width::Word => ""
    }
 }
 
@@ -307,19 +512,79 @@ pub enum B32{
    ISB,
    MRS,
    MSR,
+   // everything below is only executable on ARMv7-M
+   DATA_Imm(DataOp,bool),//modified immediate, bool is the S bit
+   DATA_REG(DataOp,bool),//shifted register
+   ADDW,
+   SUBW,
+   MOVW,
+   MOVT,
+   SSAT,
+   USAT,
+   SBFX,
+   UBFX,
+   BFI,
+   BFC,
+   LSL_W(bool),
+   LSR_W(bool),
+   ASR_W(bool),
+   ROR_W(bool),
+   SXTH_W,
+   SXTB_W,
+   UXTH_W,
+   UXTB_W,
+   REV_W,
+   REV16_W,
+   REVSH_W,
+   RBIT,
+   CLZ,
+   MUL_W,
+   MLA,
+   MLS,
+   SMULL,
+   UMULL,
+   SMLAL,
+   UMLAL,
+   SDIV,
+   UDIV,
+   B_W,
+   B_COND_W(u8),
+   NOP_W,
+   YIELD_W,
+   WFE_W,
+   WFI_W,
+   SEV_W,
+   CLREX,
+   LDM_W,
+   LDMDB,
+   STM_W,
+   STMDB,
+   POP_W,
+   PUSH_W,
+   LDREX,
+   LDREXB,
+   LDREXH,
+   STREX,
+   STREXB,
+   STREXH,
+   LDRD,
+   STRD,
+   TBB,
+   TBH,
+   LDA(Width),
+   STL(Width),
+   LDAEX(Width),
+   STLEX(Width),
+   LDR_W(Width),
+   STR_W(Width),
+   PRELOAD
 }
 
 impl fmt::Display for B32{
    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-      match self{
-         B32::UNDEFINED => write!(f,"UDF.W"),
-         B32::BR_AND_LNK => write!(f,"BL"),
-         B32::DMB => write!(f,"DMB"),
-         B32::DSB => write!(f,"DSB"),
-         B32::ISB => write!(f,"ISB"),
-         B32::MRS => write!(f,"MRS"),
-         B32::MSR => write!(f,"MSR")
-      }
+      let mut name = String::new();
+      serialise_b32(&mut name, self);
+      write!(f,"{}",name)
    }
 }
 
@@ -509,6 +774,8 @@ fn misc(hw: HalfWord)->Opcode{
    match code{
       0 | 1 | 2 | 3 => Opcode::_16Bit(B16::INCR_SP_BY_IMM7),
       4 | 5 | 6 | 7 => Opcode::_16Bit(B16::SUB_SP_Imm7),
+      0x08 ..=0x0F | 0x18 ..=0x1F => Opcode::_16Bit(B16::CBZ),
+      0x48 ..=0x4F | 0x58 ..=0x5F => Opcode::_16Bit(B16::CBNZ),
       16 | 17 => Opcode::_16Bit(B16::SXTH),
       18 | 19 => Opcode::_16Bit(B16::SXTB),
       0x14 | 0x15 => Opcode::_16Bit(B16::UXTH),
@@ -520,6 +787,7 @@ fn misc(hw: HalfWord)->Opcode{
       0x56 | 0x57 => Opcode::_16Bit(B16::REVSH),
       0x60 ..= 0x6F => Opcode::_16Bit(B16::POP),
       0x70 ..=0x77 => Opcode::_16Bit(B16::BREAKPOINT),
+      0x78 ..=0x7F if hw[0] & 0x0F != 0 => Opcode::_16Bit(B16::IT),
       _ => {
          println!("WARN: enrecognised misc encoding [{:#x} {:#x}]",hw[0],hw[1]);
          Opcode::_16Bit(B16::UNDEFINED)
@@ -554,7 +822,7 @@ fn shift_add_sub_mv_cmpr(hw: HalfWord)->Opcode{
    let code = (hw[1] & 0x3E) >> 1;
    match code{
       0 ..=3 => {
-         if from_arm_bytes_16b(hw) & 0x01C0 == 0{
+         if from_arm_bytes_16b(hw) & 0x07C0 == 0{
             Opcode::_16Bit(B16::MOV_REGS_T2)
          }else{
             Opcode::_16Bit(B16::LSL_Imm5)
@@ -581,14 +849,238 @@ impl From<Word> for Opcode{
 
       match (op1,op){
          (0,_) =>  panic!("cannot decode a 16bit instruction as 32bit"),
-         (1|3,_)=> Opcode::_32Bit(B32::UNDEFINED),
-         (2,0) => Opcode::_32Bit(B32::UNDEFINED), 
+         (1,_)=> Opcode::_32Bit(load_store_and_data_register(word)),
+         (2,0) => Opcode::_32Bit(data_immediate(word)), 
          (2,1) => Opcode::_32Bit(branch_and_misc(word)),
+         (3,_)=> Opcode::_32Bit(load_store_single_and_arithmetic(word)),
          _ => unreachable!(),
       }
    }
 }
 
+#[inline]
+pub fn thumb2_halves(bytes: Word)->(u32,u32){
+   let first = from_arm_bytes_16b([bytes[0],bytes[1]]) as u32;
+   let second = from_arm_bytes_16b([bytes[2],bytes[3]]) as u32;
+   (first,second)
+}
+
+fn data_op(op: u32, rn: u32, rd: u32, setflags: bool)->Option<DataOp>{
+   let test = rd == 15 && setflags;
+   match op{
+      0b0000 => Some(if test {DataOp::TST} else {DataOp::AND}),
+      0b0001 => Some(DataOp::BIC),
+      0b0010 => Some(if rn == 15 {DataOp::MOV} else {DataOp::ORR}),
+      0b0011 => Some(if rn == 15 {DataOp::MVN} else {DataOp::ORN}),
+      0b0100 => Some(if test {DataOp::TEQ} else {DataOp::EOR}),
+      0b1000 => Some(if test {DataOp::CMN} else {DataOp::ADD}),
+      0b1010 => Some(DataOp::ADC),
+      0b1011 => Some(DataOp::SBC),
+      0b1101 => Some(if test {DataOp::CMP} else {DataOp::SUB}),
+      0b1110 => Some(DataOp::RSB),
+      _ => None
+   }
+}
+
+// op1 == 0b01: load/store multiple, dual, exclusive, table branch and shifted register data processing
+fn load_store_and_data_register(bytes: Word)->B32{
+   let (hw1,hw2) = thumb2_halves(bytes);
+   let op2 = (hw1 >> 4) & 0x7F;
+   if op2 & 0x64 == 0x00{
+      load_store_multiple(hw1)
+   }else if op2 & 0x64 == 0x04{
+      load_store_dual_or_exclusive(hw1,hw2)
+   }else if op2 & 0x60 == 0x20{
+      let setflags = hw1 & 0x10 > 0;
+      match data_op((hw1 >> 5) & 0xF, hw1 & 0xF, (hw2 >> 8) & 0xF, setflags){
+         Some(op) => B32::DATA_REG(op,setflags),
+         None => B32::UNDEFINED
+      }
+   }else{
+      println!("WARN: coprocessor instructions are not supported {:?}",bytes);
+      B32::UNDEFINED
+   }
+}
+
+fn load_store_multiple(hw1: u32)->B32{
+   let write_back = hw1 & 0x20 > 0;
+   let sp_base = (hw1 & 0xF) == 13;
+   match ((hw1 >> 7) & 3, hw1 & 0x10 > 0){
+      (1,false) => B32::STM_W,
+      (1,true) => if write_back && sp_base {B32::POP_W} else {B32::LDM_W},
+      (2,false) => if write_back && sp_base {B32::PUSH_W} else {B32::STMDB},
+      (2,true) => B32::LDMDB,
+      _ => B32::UNDEFINED
+   }
+}
+
+fn load_store_dual_or_exclusive(hw1: u32, hw2: u32)->B32{
+   let op1 = (hw1 >> 7) & 3;
+   let op2 = (hw1 >> 4) & 3;
+   let op3 = (hw2 >> 4) & 0xF;
+   match (op1,op2){
+      (0,0) => B32::STREX,
+      (0,1) => B32::LDREX,
+      (0|1,2) | (2|3,0|2) => B32::STRD,
+      (0|1,3) | (2|3,1|3) => B32::LDRD,
+      (1,0) => match op3{
+         4 => B32::STREXB,
+         5 => B32::STREXH,
+         8 ..= 10 => B32::STL(acquire_release_width(op3)),
+         12 ..= 14 => B32::STLEX(acquire_release_width(op3)),
+         _ => B32::UNDEFINED
+      },
+      (1,1) => match op3{
+         0 => B32::TBB,
+         1 => B32::TBH,
+         4 => B32::LDREXB,
+         5 => B32::LDREXH,
+         8 ..= 10 => B32::LDA(acquire_release_width(op3)),
+         12 ..= 14 => B32::LDAEX(acquire_release_width(op3)),
+         _ => B32::UNDEFINED
+      },
+      _ => unreachable!()
+   }
+}
+
+// the ARMv8-M load-acquire/store-release encodings keep the size in op3[1:0]
+fn acquire_release_width(op3: u32)->Width{
+   match op3 & 3{
+      0 => Width::Byte,
+      1 => Width::HalfWord,
+      _ => Width::Word
+   }
+}
+
+// op1 == 0b10, op == 0: modified and plain binary immediates
+fn data_immediate(bytes: Word)->B32{
+   let (hw1,hw2) = thumb2_halves(bytes);
+   let rn = hw1 & 0xF;
+   if hw1 & 0x0200 == 0{
+      let setflags = hw1 & 0x10 > 0;
+      return match data_op((hw1 >> 5) & 0xF, rn, (hw2 >> 8) & 0xF, setflags){
+         Some(op) => B32::DATA_Imm(op,setflags),
+         None => B32::UNDEFINED
+      };
+   }
+
+   let shift = (((hw2 >> 12) & 7) << 2) | ((hw2 >> 6) & 3);
+   match (hw1 >> 4) & 0x1F{
+      0b00000 => B32::ADDW,
+      0b00100 => B32::MOVW,
+      0b01010 => B32::SUBW,
+      0b01100 => B32::MOVT,
+      0b10000 => B32::SSAT,
+      0b10010 => if shift == 0 {B32::UNDEFINED} else {B32::SSAT},//SSAT16 is DSP
+      0b10100 => B32::SBFX,
+      0b10110 => if rn == 15 {B32::BFC} else {B32::BFI},
+      0b11000 => B32::USAT,
+      0b11010 => if shift == 0 {B32::UNDEFINED} else {B32::USAT},//USAT16 is DSP
+      0b11100 => B32::UBFX,
+      _ => B32::UNDEFINED
+   }
+}
+
+// op1 == 0b11: single loads and stores, register data processing, multiply and divide
+fn load_store_single_and_arithmetic(bytes: Word)->B32{
+   let (hw1,hw2) = thumb2_halves(bytes);
+   let op2 = (hw1 >> 4) & 0x7F;
+   if op2 & 0x71 == 0x00{
+      let width = match (hw1 >> 5) & 3{
+         0 => Width::Byte,
+         1 => Width::HalfWord,
+         2 => Width::Word,
+         _ => return B32::UNDEFINED
+      };
+      if !is_valid_single_addressing(hw1,hw2){
+         return B32::UNDEFINED;
+      }
+      B32::STR_W(width)
+   }else if op2 & 0x67 == 0x01{
+      load_single(hw1,hw2,Width::Byte,Width::SignedByte)
+   }else if op2 & 0x67 == 0x03{
+      load_single(hw1,hw2,Width::HalfWord,Width::SignedHalfWord)
+   }else if op2 & 0x67 == 0x05{
+      if hw1 & 0x100 > 0{
+         return B32::UNDEFINED;
+      }
+      load_single(hw1,hw2,Width::Word,Width::Word)
+   }else if op2 & 0x70 == 0x20{
+      data_register(hw1,hw2)
+   }else if op2 & 0x78 == 0x30{
+      match ((hw1 >> 4) & 7, (hw2 >> 4) & 3){
+         (0,0) => if (hw2 >> 12) == 15 {B32::MUL_W} else {B32::MLA},
+         (0,1) => B32::MLS,
+         _ => B32::UNDEFINED//the rest are DSP extension multiplies
+      }
+   }else if op2 & 0x78 == 0x38{
+      match ((hw1 >> 4) & 7, (hw2 >> 4) & 0xF){
+         (0,0) => B32::SMULL,
+         (1,0xF) => B32::SDIV,
+         (2,0) => B32::UMULL,
+         (3,0xF) => B32::UDIV,
+         (4,0) => B32::SMLAL,
+         (6,0) => B32::UMLAL,
+         _ => B32::UNDEFINED
+      }
+   }else{
+      println!("WARN: coprocessor instructions are not supported {:?}",bytes);
+      B32::UNDEFINED
+   }
+}
+
+// the imm8 (P/U/W) and register offset forms share an encoding space with reserved values
+fn is_valid_single_addressing(hw1: u32, hw2: u32)->bool{
+   let imm12_form = hw1 & 0x80 > 0 || (hw1 & 0xF) == 15;
+   // the imm8 form needs P or W set, P=0 W=0 is undefined
+   let imm8_form = hw2 & 0x800 > 0 && hw2 & 0x500 > 0;
+   imm12_form || imm8_form || hw2 & 0xFC0 == 0
+}
+
+fn load_single(hw1: u32, hw2: u32, unsigned: Width, signed: Width)->B32{
+   if !is_valid_single_addressing(hw1,hw2){
+      return B32::UNDEFINED;
+   }
+   let rt = hw2 >> 12;
+   if rt == 15 && unsigned != Width::Word{
+      return B32::PRELOAD;
+   }
+   if hw1 & 0x100 > 0{
+      B32::LDR_W(signed)
+   }else{
+      B32::LDR_W(unsigned)
+   }
+}
+
+fn data_register(hw1: u32, hw2: u32)->B32{
+   if hw2 & 0xF000 != 0xF000{
+      return B32::UNDEFINED;
+   }
+   let op1 = (hw1 >> 4) & 0xF;
+   let op2 = (hw2 >> 4) & 0xF;
+   let setflags = op1 & 1 > 0;
+   let rn = hw1 & 0xF;
+   match (op1,op2){
+      (0|1,0) => B32::LSL_W(setflags),
+      (2|3,0) => B32::LSR_W(setflags),
+      (4|5,0) => B32::ASR_W(setflags),
+      (6|7,0) => B32::ROR_W(setflags),
+      (0,8 ..=11) if rn == 15 => B32::SXTH_W,
+      (1,8 ..=11) if rn == 15 => B32::UXTH_W,
+      (4,8 ..=11) if rn == 15 => B32::SXTB_W,
+      (5,8 ..=11) if rn == 15 => B32::UXTB_W,
+      (8 ..=11,8 ..=11) => match (op1 & 3, op2 & 3){
+         (1,0) => B32::REV_W,
+         (1,1) => B32::REV16_W,
+         (1,2) => B32::RBIT,
+         (1,3) => B32::REVSH_W,
+         (3,0) => B32::CLZ,
+         _ => B32::UNDEFINED
+      },
+      _ => B32::UNDEFINED//the extend and add and parallel arithmetic are DSP
+   }
+}
+
 #[inline]
 fn branch_and_misc(bytes: Word)->B32{
    let first_u16 = from_arm_bytes_16b([bytes[0],bytes[1]]);
@@ -602,9 +1094,12 @@ fn branch_and_misc(bytes: Word)->B32{
    dbg_ln!("{:x},{:x}",op2,op1);
    match (op2,op1){
       (0,0x38|0x39) => B32::MSR,
+      (0,0x3A) => hint(bytes),
       (0,0x3B) => control(bytes),
       (0,0x3E | 0x3F) => B32::MRS,
       (2,0x7F) => B32::UNDEFINED,
+      (0|2,_) if op1 & 0x38 != 0x38 => B32::B_COND_W(((op1 >> 2) & 0xF) as u8),
+      (0x1|0x3,_) => B32::B_W,
       (0x5|0x7,_) => B32::BR_AND_LNK,
       _ => {
          println!("WARN: un-recognised brach/misc encoding {:?}",bytes);
@@ -620,7 +1115,20 @@ fn control(bytes: Word)->B32{
       0x40 => B32::DSB,
       0x50 => B32::DMB,
       0x60 => B32::ISB,
-      _ => unreachable!(),
+      0x20 => B32::CLREX,
+      _ => B32::UNDEFINED,
+   }
+}
+
+#[inline]
+fn hint(bytes: Word)->B32{
+   match bytes[2]{
+      0 => B32::NOP_W,
+      1 => B32::YIELD_W,
+      2 => B32::WFE_W,
+      3 => B32::WFI_W,
+      4 => B32::SEV_W,
+      _ => B32::NOP_W,//unallocated hints execute as NOPs
    }
 }
 
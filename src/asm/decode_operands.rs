@@ -14,7 +14,7 @@ use super::{
    Word, STACK_POINTER, PROGRAM_COUNTER
 };
 
-use crate::asm::decode::{Opcode,B16,B32};
+use crate::asm::decode::{Opcode,B16,B32,DataOp,condition_name,thumb2_halves};
 use crate::binutils::{from_arm_bytes_16b, get_bitfield, BitList,sign_extend};
 
 #[allow(non_camel_case_types)]
@@ -51,9 +51,45 @@ pub enum Operands{
    Byte(Literal<8>),
    HalfWord(Literal<16>),
    Primask(bool),
+   Faultmask(bool,bool),//CPS with the F flag (ARMv7-M), the second flag is set when I is named too
    MSR(SpecialRegister,SrcRegister),
    MRS(DestRegister,SpecialRegister),
-   Nibble(Literal<4>)
+   Nibble(Literal<4>),
+   // Thumb-2 (ARMv7-M) operand shapes
+   DataImm(Option<DestRegister>,Option<SrcRegister>,u32,Option<bool>),//carry out of the immediate expansion
+   DataReg(Option<DestRegister>,Option<SrcRegister>,Register,Shift),
+   RegPairImm12(DestRegister,SrcRegister,u32),
+   DestImm16(DestRegister,u32),
+   Saturate(DestRegister,u8,Register,Shift),//saturate to n bits
+   BitRange(DestRegister,Option<SrcRegister>,u8,u8),//lsb, width
+   Extend(DestRegister,Register,u8),//rotation
+   MulAcc(DestRegister,Register,Register,Register),
+   LongMul(DestRegister,DestRegister,Register,Register),//lo, hi, n, m
+   CBZ(Register,i32),
+   IT(u8,u8),//firstcond, mask
+   RegListWb(Register,bool,BitList),
+   Memory(Register,Register,Address),//rt, rn
+   Exclusive(Option<DestRegister>,Register,Register,u32),//status, rt, rn, offset
+   Dual(Register,Register,Register,Address),//rt, rt2, rn
+   TableBranch(Register,Register,bool),//rn, rm, halfword entries
+   Preload(Register,Address)
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Shift{
+   LSL(u32),
+   LSR(u32),
+   ASR(u32),
+   ROR(u32),
+   RRX
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Address{
+   Offset(i32),
+   PreIndexed(i32),
+   PostIndexed(i32),
+   Shifted(u8,u8),//rm, left shift
 }
 
 fn fmt_branch_offset(f: &mut std::fmt::Formatter<'_>,offset: i32)->fmt::Result{
@@ -135,9 +171,17 @@ impl fmt::Display for Operands{
                 write!(f, "CPSIE i")
              }
           },
+          Operands::Faultmask(flag, primask) => {
+             write!(f, "{} {}f", if *flag {"CPSID"} else {"CPSIE"}, if *primask {"i"} else {""})
+          },
           Operands::MSR(meta, src) => write!(f, "{:?}, {}", meta, src),
           Operands::MRS(src, meta) => write!(f, "{}, {:?}", src, meta),
           Operands::Nibble(imm4) => write!(f, "{}", imm4),
+          _ => {
+             let mut text = String::new();
+             serialise_operand(&mut text, self, 0);
+             write!(f, "{}", text)
+          }
        }
     }
 }
@@ -392,6 +436,13 @@ pub fn serialise_operand(buffer: &mut String, operands: &Operands, address: u32)
             //write!(f, "CPSIE i")
          }
       },
+      Operands::Faultmask(flag, primask) => {
+         buffer.push_str(if *flag {"CPSID "} else {"CPSIE "});
+         if *primask{
+            buffer.push('i');
+         }
+         buffer.push('f');
+      },
       Operands::MSR(meta, src) => {
          serialise_special_register(buffer,meta);
          buffer.push_str(", ");
@@ -406,6 +457,193 @@ pub fn serialise_operand(buffer: &mut String, operands: &Operands, address: u32)
          buffer.push('#');
          u32_to_b10(buffer,imm4.0);
       },
+      Operands::DataImm(d, n, imm, _) => {
+         serialise_optional_registers(buffer, d.as_ref().map(|r| r.0), n.as_ref().map(|r| r.0));
+         buffer.push('#');
+         u32_to_b10(buffer,*imm);
+      },
+      Operands::DataReg(d, n, m, shift) => {
+         serialise_optional_registers(buffer, d.as_ref().map(|r| r.0), n.as_ref().map(|r| r.0));
+         serialise_register(buffer,m.0);
+         serialise_shift(buffer,shift);
+      },
+      Operands::RegPairImm12(d, n, imm12) => {
+         serialise_register(buffer,d.0);
+         buffer.push_str(", ");
+         serialise_register(buffer,n.0);
+         buffer.push_str(", #");
+         u32_to_b10(buffer,*imm12);
+      },
+      Operands::DestImm16(d, imm16) => {
+         serialise_register(buffer,d.0);
+         buffer.push_str(", #");
+         u32_to_b10(buffer,*imm16);
+      },
+      Operands::Saturate(d, bits, n, shift) => {
+         serialise_register(buffer,d.0);
+         buffer.push_str(", #");
+         u32_to_b10(buffer,*bits as u32);
+         buffer.push_str(", ");
+         serialise_register(buffer,n.0);
+         serialise_shift(buffer,shift);
+      },
+      Operands::BitRange(d, n, lsb, width) => {
+         serialise_register(buffer,d.0);
+         buffer.push_str(", ");
+         if let Some(src) = n{
+            serialise_register(buffer,src.0);
+            buffer.push_str(", ");
+         }
+         buffer.push('#');
+         u32_to_b10(buffer,*lsb as u32);
+         buffer.push_str(", #");
+         u32_to_b10(buffer,*width as u32);
+      },
+      Operands::Extend(d, m, rotation) => {
+         serialise_register(buffer,d.0);
+         buffer.push_str(", ");
+         serialise_register(buffer,m.0);
+         serialise_shift(buffer,&Shift::ROR(*rotation as u32));
+      },
+      Operands::MulAcc(d, n, m, a) => {
+         serialise_register(buffer,d.0);
+         buffer.push_str(", ");
+         serialise_register(buffer,n.0);
+         buffer.push_str(", ");
+         serialise_register(buffer,m.0);
+         buffer.push_str(", ");
+         serialise_register(buffer,a.0);
+      },
+      Operands::LongMul(lo, hi, n, m) => {
+         serialise_register(buffer,lo.0);
+         buffer.push_str(", ");
+         serialise_register(buffer,hi.0);
+         buffer.push_str(", ");
+         serialise_register(buffer,n.0);
+         buffer.push_str(", ");
+         serialise_register(buffer,m.0);
+      },
+      Operands::CBZ(n, off) => {
+         serialise_register(buffer,n.0);
+         buffer.push_str(", ");
+         serialise_branch_offset(buffer, *off);
+      },
+      Operands::IT(firstcond, mask) => {
+         buffer.push_str("IT");
+         let count = 4 - mask.trailing_zeros();
+         for i in 1 .. count{
+            let bit = (mask >> (4 - i)) & 1;
+            if bit == firstcond & 1{
+               buffer.push('T');
+            }else{
+               buffer.push('E');
+            }
+         }
+         buffer.push(' ');
+         buffer.push_str(condition_name(*firstcond));
+      },
+      Operands::RegListWb(n, write_back, list) => {
+         serialise_register(buffer,n.0);
+         if *write_back{
+            buffer.push('!');
+         }
+         buffer.push_str(", ");
+         serialise_register_list(buffer,get_set_bits(*list));
+      },
+      Operands::Memory(t, n, address) => {
+         serialise_register(buffer,t.0);
+         buffer.push_str(", ");
+         serialise_address(buffer,n.0,address);
+      },
+      Operands::Exclusive(status, t, n, imm) => {
+         if let Some(d) = status{
+            serialise_register(buffer,d.0);
+            buffer.push_str(", ");
+         }
+         serialise_register(buffer,t.0);
+         buffer.push_str(", ");
+         serialise_address(buffer,n.0,&Address::Offset(*imm as i32));
+      },
+      Operands::Dual(t, t2, n, address) => {
+         serialise_register(buffer,t.0);
+         buffer.push_str(", ");
+         serialise_register(buffer,t2.0);
+         buffer.push_str(", ");
+         serialise_address(buffer,n.0,address);
+      },
+      Operands::TableBranch(n, m, half) => {
+         buffer.push('[');
+         serialise_register(buffer,n.0);
+         buffer.push_str(", ");
+         serialise_register(buffer,m.0);
+         if *half{
+            buffer.push_str(", LSL #1");
+         }
+         buffer.push(']');
+      },
+      Operands::Preload(n, address) => {
+         serialise_address(buffer,n.0,address);
+      },
+   }
+}
+
+fn i32_to_b10(buffer: &mut String, num: i32){
+   if num.is_negative(){
+      buffer.push('-');
+   }
+   u32_to_b10(buffer,num.unsigned_abs());
+}
+
+fn serialise_optional_registers(buffer: &mut String, first: Option<u8>, second: Option<u8>){
+   for r in [first,second].into_iter().flatten(){
+      serialise_register(buffer,r);
+      buffer.push_str(", ");
+   }
+}
+
+fn serialise_shift(buffer: &mut String, shift: &Shift){
+   let (name,amount) = match shift{
+      Shift::LSL(0) | Shift::ROR(0) => return,
+      Shift::LSL(n) => ("LSL",n),
+      Shift::LSR(n) => ("LSR",n),
+      Shift::ASR(n) => ("ASR",n),
+      Shift::ROR(n) => ("ROR",n),
+      Shift::RRX => {
+         buffer.push_str(", RRX");
+         return;
+      }
+   };
+   buffer.push_str(", ");
+   buffer.push_str(name);
+   buffer.push_str(" #");
+   u32_to_b10(buffer,*amount);
+}
+
+fn serialise_address(buffer: &mut String, base: u8, address: &Address){
+   buffer.push('[');
+   serialise_register(buffer,base);
+   match address{
+      Address::Offset(0) => buffer.push(']'),
+      Address::Offset(imm) => {
+         buffer.push_str(", #");
+         i32_to_b10(buffer,*imm);
+         buffer.push(']');
+      },
+      Address::PreIndexed(imm) => {
+         buffer.push_str(", #");
+         i32_to_b10(buffer,*imm);
+         buffer.push_str("]!");
+      },
+      Address::PostIndexed(imm) => {
+         buffer.push_str("], #");
+         i32_to_b10(buffer,*imm);
+      },
+      Address::Shifted(m, shift) => {
+         buffer.push_str(", ");
+         serialise_register(buffer,*m);
+         serialise_shift(buffer,&Shift::LSL(*shift as u32));
+         buffer.push(']');
+      }
    }
 }
 
@@ -421,7 +659,11 @@ fn serialise_special_register(buffer: &mut String, reg: &SpecialRegister){
       SpecialRegister::MSP => buffer.push_str("MSP"),
       SpecialRegister::PSP => buffer.push_str("PSP"),
       SpecialRegister::PRIMASK => buffer.push_str("PRIMASK"),
-      SpecialRegister::CONTROL => buffer.push_str("CONTROL")
+      SpecialRegister::BASEPRI => buffer.push_str("BASEPRI"),
+      SpecialRegister::BASEPRI_MAX => buffer.push_str("BASEPRI_MAX"),
+      SpecialRegister::FAULTMASK => buffer.push_str("FAULTMASK"),
+      SpecialRegister::CONTROL => buffer.push_str("CONTROL"),
+      SpecialRegister::Reserved(sysm) => buffer.push_str(&format!("SYSm{}",sysm))
    }
 }
 
@@ -464,6 +706,13 @@ fn dbg_print(operands: &Operands)->String{
          fmt_register_list(registers)
       },
       Operands::Primask(flag) => if *flag {String::from("CPSID i")} else{String::from("CPSIE i")},
+      Operands::Faultmask(flag, primask) => format!("{} {}f",if *flag {"CPSID"} else {"CPSIE"},if *primask {"i"} else {""}),
+      Operands::DataImm(..) | Operands::DataReg(..) | Operands::RegPairImm12(..) |
+      Operands::DestImm16(..) | Operands::Saturate(..) | Operands::BitRange(..) |
+      Operands::Extend(..) | Operands::MulAcc(..) | Operands::LongMul(..) |
+      Operands::CBZ(..) | Operands::IT(..) | Operands::RegListWb(..) |
+      Operands::Memory(..) | Operands::Exclusive(..) | Operands::Dual(..) |
+      Operands::TableBranch(..) | Operands::Preload(..) => format!("{}",operands),
       _ => {
          let dbg_operands = format!("{:?}",operands);
          remove_everything_outside_brackets(&dbg_operands)
@@ -545,11 +794,14 @@ pub fn get_operands(code: &Opcode, hw: HalfWord)-> Option<Operands>{
             B16::BREAKPOINT => Some(get_breakpoint_operands(hw)),
             B16::BR_LNK_EXCHANGE => Some(get_br_lnk_exchange_operands(hw)),
             B16::BR_EXCHANGE => Some(get_br_exchange_operands(hw)),
+            B16::CBZ => Some(get_cbz_operands(hw)),
+            B16::CBNZ => Some(get_cbz_operands(hw)),
             B16::CMP_NEG_REG => Some(get_pure_reg_pair(hw)),
             B16::CMP_Imm8 => Some(get_cmp_imm8_operands(hw)),
             B16::CMP_REG_T1 => Some(get_cmp_reg_operands::<3>(hw)),
             B16::CMP_REG_T2 => Some(get_cmp_reg_operands::<4>(hw)),
             B16::CPS => Some(get_cps_operands(hw)),
+            B16::IT => Some(Operands::IT(hw[0] >> 4, hw[0] & 0x0F)),
             B16::XOR_REG => Some(get_def_reg_pair_as_operands(hw)),
             B16::LDM => Some(get_load_list_operands(hw)),
             B16::LDR_Imm5 => Some(get_ldr_imm5_operands(hw,4)),
@@ -624,6 +876,35 @@ pub fn get_operands_32b(code: &Opcode, bytes: Word)->Option<Operands>{
             B32::ISB => Some(get_barrier_option(bytes)),
             B32::DMB => Some(get_barrier_option(bytes)),
             B32::UNDEFINED => Some(get_undefined_32b(bytes)),
+            B32::DATA_Imm(op, _) => Some(get_data_imm_operands(op,bytes)),
+            B32::DATA_REG(op, _) => Some(get_data_reg_operands(op,bytes)),
+            B32::ADDW | B32::SUBW => Some(get_reg_pair_imm12_operands(bytes)),
+            B32::MOVW | B32::MOVT => Some(get_dest_imm16_operands(bytes)),
+            B32::SSAT => Some(get_saturate_operands(bytes,1)),
+            B32::USAT => Some(get_saturate_operands(bytes,0)),
+            B32::SBFX | B32::UBFX => Some(get_bitfield_extract_operands(bytes)),
+            B32::BFI | B32::BFC => Some(get_bitfield_insert_operands(bytes)),
+            B32::LSL_W(_) | B32::LSR_W(_) | B32::ASR_W(_) | B32::ROR_W(_) => Some(get_thumb2_register_triplet(bytes)),
+            B32::SXTH_W | B32::SXTB_W | B32::UXTH_W | B32::UXTB_W => Some(get_extend_operands(bytes)),
+            B32::REV_W | B32::REV16_W | B32::REVSH_W | B32::RBIT | B32::CLZ => Some(get_thumb2_register_pair(bytes)),
+            B32::MUL_W | B32::SDIV | B32::UDIV => Some(get_thumb2_register_triplet(bytes)),
+            B32::MLA | B32::MLS => Some(get_mul_acc_operands(bytes)),
+            B32::SMULL | B32::UMULL | B32::SMLAL | B32::UMLAL => Some(get_long_mul_operands(bytes)),
+            B32::B_W => Some(Operands::B_ALWAYS(thumb2_branch_offset(bytes))),
+            B32::B_COND_W(_) => Some(get_cond_branch_w_operands(bytes)),
+            B32::NOP_W | B32::YIELD_W | B32::WFE_W | B32::WFI_W | B32::SEV_W | B32::CLREX => None,
+            B32::LDM_W | B32::LDMDB | B32::STM_W | B32::STMDB => Some(get_reg_list_wb_operands(bytes)),
+            B32::POP_W | B32::PUSH_W => Some(Operands::RegisterList(thumb2_halves(bytes).1 as u16)),
+            B32::LDREX | B32::STREX => Some(get_exclusive_operands(bytes)),
+            B32::LDREXB | B32::LDREXH => Some(get_exclusive_narrow_operands(bytes,false)),
+            B32::STREXB | B32::STREXH => Some(get_exclusive_narrow_operands(bytes,true)),
+            B32::LDA(_) | B32::STL(_) | B32::LDAEX(_) => Some(get_exclusive_narrow_operands(bytes,false)),
+            B32::STLEX(_) => Some(get_exclusive_narrow_operands(bytes,true)),
+            B32::LDRD | B32::STRD => Some(get_dual_operands(bytes)),
+            B32::TBB => Some(get_table_branch_operands(bytes,false)),
+            B32::TBH => Some(get_table_branch_operands(bytes,true)),
+            B32::LDR_W(_) | B32::STR_W(_) => Some(get_memory_operands(bytes)),
+            B32::PRELOAD => Some(get_preload_operands(bytes)),
          }
       }
    }
@@ -750,6 +1031,11 @@ fn get_breakpoint_operands(hw: HalfWord)->Operands{
 }
 
 fn get_branch_and_lnk_operands(bytes: Word)->Operands{
+   Operands::BR_LNK(thumb2_branch_offset(bytes))
+}
+
+// the S:I1:I2:imm10:imm11 offset shared by BL and B.W
+fn thumb2_branch_offset(bytes: Word)->i32{
    //dbg_ln!("instr: [{:#x},{:#x},{:#x},{:#x}]",bytes[0],bytes[1],bytes[2],bytes[3]);
    let left_hw: [u8;2] = [bytes[0],bytes[1]];
    let native_l: u16 = from_arm_bytes_16b(left_hw);
@@ -782,7 +1068,208 @@ fn get_branch_and_lnk_operands(bytes: Word)->Operands{
    debug_assert_eq!(result % 2,0);
    debug_assert!(result >= -16777216," within limit specified in ARMv6 ISA");
    debug_assert!(result <= 16777214," within limit specified in ARMv6 ISA");
-   Operands::BR_LNK(result)
+   result
+}
+
+fn get_cond_branch_w_operands(bytes: Word)->Operands{
+   let (hw1,hw2) = thumb2_halves(bytes);
+   let sign_bit = (hw1 >> 10) & 1;
+   let imm6 = hw1 & 0x3F;
+   let j1 = (hw2 >> 13) & 1;
+   let j2 = (hw2 >> 11) & 1;
+   let imm11 = hw2 & 0x07FF;
+   let offset: Literal<21> = ((sign_bit << 20) | (j2 << 19) | (j1 << 18) | (imm6 << 12) | (imm11 << 1)).into();
+   Operands::COND_BRANCH(sign_extend(offset) + 4)
+}
+
+fn get_cbz_operands(hw: HalfWord)->Operands{
+   let native = from_arm_bytes_16b(hw) as u32;
+   let i = (native >> 9) & 1;
+   let imm5 = (native >> 3) & 0x1F;
+   let offset = (i << 6) | (imm5 << 1);
+   Operands::CBZ((hw[0] & 0x07).into(), offset as i32 + 4)
+}
+
+// ThumbExpandImm_C, the carry is only produced by the rotated forms
+pub fn thumb_expand_imm(imm12: u32)->(u32,Option<bool>){
+   let imm8 = imm12 & 0xFF;
+   if imm12 & 0xC00 == 0{
+      let value = match (imm12 >> 8) & 3{
+         0 => imm8,
+         1 => (imm8 << 16) | imm8,
+         2 => (imm8 << 24) | (imm8 << 8),
+         _ => (imm8 << 24) | (imm8 << 16) | (imm8 << 8) | imm8
+      };
+      (value,None)
+   }else{
+      let unrotated = 0x80 | (imm12 & 0x7F);
+      let value = unrotated.rotate_right(imm12 >> 7);
+      (value,Some(value & 0x80000000 > 0))
+   }
+}
+
+pub fn decode_imm_shift(shift_type: u32, imm5: u32)->Shift{
+   match (shift_type,imm5){
+      (0,n) => Shift::LSL(n),
+      (1,0) => Shift::LSR(32),
+      (1,n) => Shift::LSR(n),
+      (2,0) => Shift::ASR(32),
+      (2,n) => Shift::ASR(n),
+      (_,0) => Shift::RRX,
+      (_,n) => Shift::ROR(n)
+   }
+}
+
+#[inline]
+fn thumb2_imm12(hw1: u32, hw2: u32)->u32{
+   (((hw1 >> 10) & 1) << 11) | (((hw2 >> 12) & 7) << 8) | (hw2 & 0xFF)
+}
+
+#[inline]
+fn thumb2_imm5(hw2: u32)->u32{
+   (((hw2 >> 12) & 7) << 2) | ((hw2 >> 6) & 3)
+}
+
+fn get_data_imm_operands(op: &DataOp, bytes: Word)->Operands{
+   let (hw1,hw2) = thumb2_halves(bytes);
+   let (value,carry) = thumb_expand_imm(thumb2_imm12(hw1,hw2));
+   let dest: Option<DestRegister> = if op.is_comparison() {None} else {Some(((hw2 >> 8) & 0xF).into())};
+   let src: Option<SrcRegister> = if op.is_move() {None} else {Some((hw1 & 0xF).into())};
+   Operands::DataImm(dest,src,value,carry)
+}
+
+fn get_data_reg_operands(op: &DataOp, bytes: Word)->Operands{
+   let (hw1,hw2) = thumb2_halves(bytes);
+   let dest: Option<DestRegister> = if op.is_comparison() {None} else {Some(((hw2 >> 8) & 0xF).into())};
+   let src: Option<SrcRegister> = if op.is_move() {None} else {Some((hw1 & 0xF).into())};
+   let shift = decode_imm_shift((hw2 >> 4) & 3, thumb2_imm5(hw2));
+   Operands::DataReg(dest,src,(hw2 & 0xF).into(),shift)
+}
+
+fn get_reg_pair_imm12_operands(bytes: Word)->Operands{
+   let (hw1,hw2) = thumb2_halves(bytes);
+   Operands::RegPairImm12(((hw2 >> 8) & 0xF).into(),(hw1 & 0xF).into(),thumb2_imm12(hw1,hw2))
+}
+
+fn get_dest_imm16_operands(bytes: Word)->Operands{
+   let (hw1,hw2) = thumb2_halves(bytes);
+   let imm16 = ((hw1 & 0xF) << 12) | thumb2_imm12(hw1,hw2);
+   Operands::DestImm16(((hw2 >> 8) & 0xF).into(),imm16)
+}
+
+fn get_saturate_operands(bytes: Word, bias: u8)->Operands{
+   let (hw1,hw2) = thumb2_halves(bytes);
+   let shift = decode_imm_shift((hw1 >> 4) & 2, thumb2_imm5(hw2));
+   let bits = (hw2 & 0x1F) as u8 + bias;
+   Operands::Saturate(((hw2 >> 8) & 0xF).into(),bits,(hw1 & 0xF).into(),shift)
+}
+
+fn get_bitfield_extract_operands(bytes: Word)->Operands{
+   let (hw1,hw2) = thumb2_halves(bytes);
+   let lsb = thumb2_imm5(hw2) as u8;
+   let width = (hw2 & 0x1F) as u8 + 1;
+   Operands::BitRange(((hw2 >> 8) & 0xF).into(),Some((hw1 & 0xF).into()),lsb,width)
+}
+
+fn get_bitfield_insert_operands(bytes: Word)->Operands{
+   let (hw1,hw2) = thumb2_halves(bytes);
+   let lsb = thumb2_imm5(hw2) as u8;
+   let msb = (hw2 & 0x1F) as u8;
+   let width = (msb + 1).saturating_sub(lsb);
+   let src: Option<SrcRegister> = if hw1 & 0xF == 15 {None} else {Some((hw1 & 0xF).into())};
+   Operands::BitRange(((hw2 >> 8) & 0xF).into(),src,lsb,width)
+}
+
+fn get_thumb2_register_triplet(bytes: Word)->Operands{
+   let (hw1,hw2) = thumb2_halves(bytes);
+   Operands::RegisterTriplet(((hw2 >> 8) & 0xF).into(),(hw1 & 0xF).into(),(hw2 & 0xF).into())
+}
+
+fn get_thumb2_register_pair(bytes: Word)->Operands{
+   let (_,hw2) = thumb2_halves(bytes);
+   Operands::RegisterPair(((hw2 >> 8) & 0xF).into(),(hw2 & 0xF).into())
+}
+
+fn get_extend_operands(bytes: Word)->Operands{
+   let (_,hw2) = thumb2_halves(bytes);
+   let rotation = (((hw2 >> 4) & 3) * 8) as u8;
+   Operands::Extend(((hw2 >> 8) & 0xF).into(),(hw2 & 0xF).into(),rotation)
+}
+
+fn get_mul_acc_operands(bytes: Word)->Operands{
+   let (hw1,hw2) = thumb2_halves(bytes);
+   Operands::MulAcc(((hw2 >> 8) & 0xF).into(),(hw1 & 0xF).into(),(hw2 & 0xF).into(),(hw2 >> 12).into())
+}
+
+fn get_long_mul_operands(bytes: Word)->Operands{
+   let (hw1,hw2) = thumb2_halves(bytes);
+   Operands::LongMul((hw2 >> 12).into(),((hw2 >> 8) & 0xF).into(),(hw1 & 0xF).into(),(hw2 & 0xF).into())
+}
+
+fn get_reg_list_wb_operands(bytes: Word)->Operands{
+   let (hw1,hw2) = thumb2_halves(bytes);
+   Operands::RegListWb((hw1 & 0xF).into(),hw1 & 0x20 > 0,hw2 as u16)
+}
+
+fn get_exclusive_operands(bytes: Word)->Operands{
+   let (hw1,hw2) = thumb2_halves(bytes);
+   let status: Option<DestRegister> = if hw1 & 0x10 > 0 {None} else {Some(((hw2 >> 8) & 0xF).into())};
+   Operands::Exclusive(status,(hw2 >> 12).into(),(hw1 & 0xF).into(),(hw2 & 0xFF) << 2)
+}
+
+fn get_exclusive_narrow_operands(bytes: Word, store: bool)->Operands{
+   let (hw1,hw2) = thumb2_halves(bytes);
+   let status: Option<DestRegister> = if store {Some((hw2 & 0xF).into())} else {None};
+   Operands::Exclusive(status,(hw2 >> 12).into(),(hw1 & 0xF).into(),0)
+}
+
+fn signed_offset(add: bool, imm: u32)->i32{
+   if add {imm as i32} else {-(imm as i32)}
+}
+
+fn get_dual_operands(bytes: Word)->Operands{
+   let (hw1,hw2) = thumb2_halves(bytes);
+   let offset = signed_offset(hw1 & 0x80 > 0, (hw2 & 0xFF) << 2);
+   let address = match (hw1 & 0x100 > 0, hw1 & 0x20 > 0){
+      (true,false) => Address::Offset(offset),
+      (true,true) => Address::PreIndexed(offset),
+      _ => Address::PostIndexed(offset)
+   };
+   Operands::Dual((hw2 >> 12).into(),((hw2 >> 8) & 0xF).into(),(hw1 & 0xF).into(),address)
+}
+
+fn get_table_branch_operands(bytes: Word, half: bool)->Operands{
+   let (hw1,hw2) = thumb2_halves(bytes);
+   Operands::TableBranch((hw1 & 0xF).into(),(hw2 & 0xF).into(),half)
+}
+
+// addressing shared by the single register LDR/STR.W and PLD encodings
+fn single_address(hw1: u32, hw2: u32)->Address{
+   if hw1 & 0xF == 15{
+      return Address::Offset(signed_offset(hw1 & 0x80 > 0, hw2 & 0xFFF));
+   }
+   if hw1 & 0x80 > 0{
+      return Address::Offset((hw2 & 0xFFF) as i32);
+   }
+   if hw2 & 0x800 > 0{
+      let offset = signed_offset(hw2 & 0x200 > 0, hw2 & 0xFF);
+      return match (hw2 & 0x400 > 0, hw2 & 0x100 > 0){
+         (true,false) => Address::Offset(offset),//includes the unprivileged LDRT forms
+         (true,true) => Address::PreIndexed(offset),
+         _ => Address::PostIndexed(offset)
+      };
+   }
+   Address::Shifted((hw2 & 0xF) as u8,((hw2 >> 4) & 3) as u8)
+}
+
+fn get_memory_operands(bytes: Word)->Operands{
+   let (hw1,hw2) = thumb2_halves(bytes);
+   Operands::Memory((hw2 >> 12).into(),(hw1 & 0xF).into(),single_address(hw1,hw2))
+}
+
+fn get_preload_operands(bytes: Word)->Operands{
+   let (hw1,hw2) = thumb2_halves(bytes);
+   Operands::Preload((hw1 & 0xF).into(),single_address(hw1,hw2))
 }
 
 fn get_br_lnk_exchange_operands(hw: HalfWord)->Operands{
@@ -919,6 +1406,9 @@ fn get_low_byte(hw: HalfWord)->Operands{
 
 fn get_cps_operands(hw: HalfWord)->Operands{
    let flat = (hw[0] & 0x10) > 0;
+   if hw[0] & 0x01 > 0{
+      return Operands::Faultmask(flat, hw[0] & 0x02 > 0);
+   }
    Operands::Primask(flat)
 }
 
@@ -953,7 +1443,10 @@ fn get_special_register(byte: u8)->SpecialRegister{
       8 => SpecialRegister::MSP,
       9 => SpecialRegister::PSP,
       16 => SpecialRegister::PRIMASK,
+      17 => SpecialRegister::BASEPRI,
+      18 => SpecialRegister::BASEPRI_MAX,
+      19 => SpecialRegister::FAULTMASK,
       20 => SpecialRegister::CONTROL,
-      _ => unreachable!()
+      sysm => SpecialRegister::Reserved(sysm)
    }
 }
@@ -103,7 +103,7 @@ pub fn serialise_instruction(bfr: &mut String, addr: u32, code:&Opcode, operand:
    bfr.push_str(INDENT);
    match operand{
       Some(args)=>{
-         if *code != Opcode::_16Bit(B16::CPS){
+         if *code != Opcode::_16Bit(B16::CPS) && *code != Opcode::_16Bit(B16::IT){
             serialise_opcode(bfr, code);
             bfr.push(' ');
          }
@@ -117,7 +117,7 @@ pub fn serialise_instruction(bfr: &mut String, addr: u32, code:&Opcode, operand:
 pub fn print_instruction(addr: u32,code: &Opcode, operands: &Option<Operands>)->String{
    let instruction = match operands{
       Some(args) => {
-         if *code == Opcode::_16Bit(B16::CPS){
+         if *code == Opcode::_16Bit(B16::CPS) || *code == Opcode::_16Bit(B16::IT){
             format!("{}{:#010x}:{}{}",INDENT,addr,INDENT,pretty_print(addr,&args))
          }else{
             format!("{}{:#010x}:{}{} {}",INDENT,addr,INDENT,code,pretty_print(addr,&args))
@@ -162,7 +162,7 @@ fn symbol_aware_disassemble(
          let instruction = match operands{
             Some(args) => {
                match code{
-                  Opcode::_16Bit(B16::CPS) =>{
+                  Opcode::_16Bit(B16::CPS) | Opcode::_16Bit(B16::IT) =>{
                      format!("{}{:#010x}:{}{}",INDENT,byte_offset,INDENT,pretty_print(byte_offset as u32,&args))
                   },
                   Opcode::_16Bit(B16::B_ALWAYS)=>{
@@ -201,6 +201,22 @@ fn symbol_aware_disassemble(
                      }
                   },
                   
+                  Opcode::_32Bit(B32::B_W) | Opcode::_32Bit(B32::B_COND_W(_))=>{
+                     let offset: i32 = match args{
+                        Operands::B_ALWAYS(v) | Operands::COND_BRANCH(v) => v,
+                        _ => panic!("could not decode {} operands",code)
+                     };
+                     let target = offset_addr(byte_offset as u32, offset);
+                     match peek(symbols,target as usize){
+                        Some(ref_label) => {
+                           format!("{}{:#010x}:{}{} ({})",INDENT,byte_offset,INDENT,code,ref_label)
+                        },
+                        None => {
+                           format!("{}{:#010x}:{}{} {}    //@{:#010x}",INDENT,byte_offset,INDENT,code,pretty_print(byte_offset as u32,&args),target)
+                        },
+                     }
+                  },
+
                   Opcode::_32Bit(B32::BR_AND_LNK)=>{
                      let offset: i32 = branch_offset!(Operands::BR_LNK,args);
                      let target = offset_addr(byte_offset as u32, offset);
@@ -1,6 +1,7 @@
 pub mod interpreter;
 pub mod decode;
 pub mod decode_operands;
+pub mod assembler;
 
 use std::fmt;
 // are little endian
@@ -54,6 +54,25 @@ pub const fn from_arm_bytes_16b(hw: [u8;2])->u16{
    u16::from_le_bytes(hw)
 }
 
+// assembles a value from bytes in memory order, BE8 images store data most significant byte first
+pub fn from_data_bytes(bytes: &[u8], big_endian: bool)->u32{
+   let fold = |v: u32, b: &u8| (v << 8) | *b as u32;
+   if big_endian{
+      bytes.iter().fold(0, fold)
+   }else{
+      bytes.iter().rev().fold(0, fold)
+   }
+}
+
+// the size least significant bytes of value in memory order, the inverse of from_data_bytes
+pub fn into_data_bytes(value: u32, size: usize, big_endian: bool)->Vec<u8>{
+   let mut bytes = value.to_le_bytes()[.. size].to_vec();
+   if big_endian{
+      bytes.reverse();
+   }
+   bytes
+}
+
 #[derive(Copy,Clone)]
 pub struct  BitField<const L: u32> (pub u32);
 impl <const L: u32> From<u32> for  BitField<L>{
@@ -0,0 +1,676 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+
+use crate::binutils::from_arm_bytes;
+use crate::asm::interpreter::is_segment_mapping_symbol;
+use crate::asm::decode::{Opcode, B16, B32};
+use crate::elf::decoder::SymbolDefinition;
+use crate::json::Json;
+use crate::system::simulator::{HaltType, Machine};
+use crate::system::debug::{DebugHalt, debug_halt_msg};
+use crate::system::dump::StateDump;
+use crate::ui::console::parse_condition;
+
+// a Debug Adapter Protocol server, editors connect over a local TCP socket.
+// the disassembly is exposed as a single source so breakpoints can be set on its lines
+
+const POLL_INTERVAL: usize = 1024;
+const DISASM_SOURCE_REF: i64 = 1;
+const DISASM_SOURCE_NAME: &'static str = "disassembly";
+// variables references are core * VARS_PER_CORE + scope
+const VARS_PER_CORE: i64 = 16;
+const SCOPE_CORE_REGISTERS: i64 = 1;
+const SCOPE_SPECIAL_REGISTERS: i64 = 2;
+// upper bounds on a single readMemory / disassemble response, clients ask again for the rest
+const MAX_READ_BYTES: i64 = 1 << 16;
+const MAX_DISASM_INSTRUCTIONS: i64 = 1 << 12;
+
+enum Goal{
+   // run until a breakpoint or a pause request
+   Breakpoint,
+   // step out of a function on a core by running until PC reaches its return address with SP back at or above
+   // the SP inside the function, a recursive call reaches the same address deeper down the stack first
+   ReturnTo(usize,u32,u32),
+   // step out of a handler by running until the active exception changes
+   LeaveException(usize,u32),
+   // step over a call on a core by running until PC reaches the return address with the caller's SP
+   StepOver(usize,u32,u32)
+}
+
+pub struct DapServer{
+   machine: Machine,
+   symbols: Vec<SymbolDefinition>,
+   disasm: String,
+   // (address, text) of every instruction or data line in the disassembly, in address order
+   instructions: Vec<(u32,String)>,
+   source_breakpoints: HashMap<String,Vec<(u32,Option<String>)>>,
+   function_breakpoints: Vec<(u32,Option<String>)>,
+   instruction_breakpoints: Vec<(u32,Option<String>)>,
+   running: Option<Goal>,
+   state_dump: Option<StateDump>,
+   seq: i64,
+   quit: bool
+}
+
+pub fn read_message<R: BufRead>(reader: &mut R)->Option<Result<Json,String>>{
+   let mut content_length = None;
+   let mut has_headers = false;
+   loop{
+      let mut line = String::new();
+      match reader.read_line(&mut line){
+         Ok(0) => return None,
+         Ok(_) => {},
+         Err(e) => return Some(Err(e.to_string()))
+      }
+      let line = line.trim();
+      if line.is_empty(){
+         if has_headers{
+            break;
+         }
+         continue;
+      }
+      has_headers = true;
+      if let Some(len) = line.strip_prefix("Content-Length:"){
+         match len.trim().parse::<usize>(){
+            Ok(len) => content_length = Some(len),
+            Err(_) => return Some(Err(format!("invalid Content-Length '{}'",len.trim())))
+         }
+      }
+   }
+   let content_length = match content_length{
+      Some(len) => len,
+      None => return Some(Err("the message has no Content-Length header".into()))
+   };
+   let mut body = vec![0_u8;content_length];
+   if let Err(e) = reader.read_exact(&mut body){
+      return Some(Err(e.to_string()));
+   }
+   return Some(String::from_utf8(body).map_err(|e| e.to_string()).and_then(|s| Json::parse(&s)));
+}
+
+pub fn write_message<W: Write>(writer: &mut W, msg: &Json)->std::io::Result<()>{
+   let body = msg.to_string();
+   write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+   writer.flush()
+}
+
+pub fn base64_encode(bytes: &[u8])->String{
+   const ALPHABET: &[u8;64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+   let mut out = String::new();
+   for chunk in bytes.chunks(3){
+      let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+      let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+      for i in 0 .. 4{
+         if i <= chunk.len(){
+            out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+         }else{
+            out.push('=');
+         }
+      }
+   }
+   return out;
+}
+
+fn parse_address(reference: &str)->Option<u32>{
+   let reference = reference.trim();
+   match reference.strip_prefix("0x"){
+      Some(hex) => u32::from_str_radix(hex, 16).ok(),
+      None => reference.parse::<u32>().ok()
+   }
+}
+
+fn line_address(line: &str)->Option<(u32,&str)>{
+   let (addr, text) = line.split_once(':')?;
+   let addr = u32::from_str_radix(addr.trim().trim_start_matches("0x"), 16).ok()?;
+   Some((addr, text.trim()))
+}
+
+impl DapServer{
+   pub fn create(machine: Machine, symbols: Vec<SymbolDefinition>, disasm: String, state_dump: Option<StateDump>)->Self{
+      // labels sit on their own unindented lines, only indented lines hold instructions
+      let instructions = disasm.lines()
+         .filter(|l| l.starts_with(char::is_whitespace))
+         .filter_map(line_address)
+         .map(|(a, t)| (a, t.to_owned()))
+         .collect();
+      Self{
+         machine,
+         symbols,
+         disasm,
+         instructions,
+         source_breakpoints: HashMap::new(),
+         function_breakpoints: Vec::new(),
+         instruction_breakpoints: Vec::new(),
+         running: None,
+         state_dump,
+         seq: 0,
+         quit: false
+      }
+   }
+
+   pub fn listen(self, port: u16)->std::io::Result<()>{
+      let listener = TcpListener::bind(("127.0.0.1", port))?;
+      println!("DAP server listening on {}",listener.local_addr()?);
+      return self.accept(listener);
+   }
+
+   // serves a single client then returns
+   pub fn accept(mut self, listener: TcpListener)->std::io::Result<()>{
+      let (stream, peer) = listener.accept()?;
+      println!("DAP client connected from {}",peer);
+      let mut reader = BufReader::new(stream.try_clone()?);
+      let mut writer = stream;
+      // requests are read on their own thread so that a running simulation can be paused
+      let (sndr, rcvr) = channel();
+      std::thread::spawn(move ||{
+         while let Some(msg) = read_message(&mut reader){
+            match msg{
+               Ok(m) => if sndr.send(m).is_err(){ return; },
+               Err(e) => { println!("WARN: malformed DAP message: {}",e); return; }
+            }
+         }
+      });
+      return self.serve(rcvr, &mut writer);
+   }
+
+   pub fn serve<W: Write>(&mut self, requests: Receiver<Json>, writer: &mut W)->std::io::Result<()>{
+      while !self.quit{
+         let msg = if self.running.is_some(){
+            match requests.try_recv(){
+               Ok(m) => Some(m),
+               Err(TryRecvError::Empty) => None,
+               Err(TryRecvError::Disconnected) => return Ok(())
+            }
+         }else{
+            match requests.recv(){
+               Ok(m) => Some(m),
+               Err(_) => return Ok(())
+            }
+         };
+         if let Some(request) = msg{
+            for out in self.handle(&request){
+               self.send(writer, out)?;
+            }
+         }
+         if self.running.is_some(){
+            if let Some(event) = self.run_for(POLL_INTERVAL){
+               self.send(writer, event)?;
+            }
+         }
+      }
+      return Ok(());
+   }
+
+   fn send<W: Write>(&mut self, writer: &mut W, msg: Json)->std::io::Result<()>{
+      self.seq += 1;
+      let mut fields = vec![("seq".to_owned(), Json::from(self.seq as i64))];
+      if let Json::Object(rest) = msg{
+         fields.extend(rest);
+      }
+      write_message(writer, &Json::Object(fields))
+   }
+
+   fn response(request: &Json, body: Result<Json,String>)->Json{
+      let command = request.get("command").cloned().unwrap_or(Json::Null);
+      let request_seq = request.get("seq").cloned().unwrap_or(Json::Null);
+      match body{
+         Ok(body) => Json::object(vec![
+            ("type", "response".into()),
+            ("request_seq", request_seq),
+            ("success", true.into()),
+            ("command", command),
+            ("body", body)
+         ]),
+         Err(message) => Json::object(vec![
+            ("type", "response".into()),
+            ("request_seq", request_seq),
+            ("success", false.into()),
+            ("command", command),
+            ("message", message.into())
+         ])
+      }
+   }
+
+   fn event(name: &str, body: Json)->Json{
+      Json::object(vec![("type", "event".into()), ("event", name.into()), ("body", body)])
+   }
+
+   fn stopped(reason: &str, core: usize, description: Option<String>)->Json{
+      let mut body = vec![
+         ("reason", Json::from(reason)),
+         ("threadId", Json::from(core + 1)),
+         ("allThreadsStopped", true.into())
+      ];
+      if let Some(d) = description{
+         body.push(("description", d.into()));
+      }
+      Self::event("stopped", Json::object(body))
+   }
+
+   pub fn handle(&mut self, request: &Json)->Vec<Json>{
+      let args = request.get("arguments").cloned().unwrap_or(Json::object(vec![]));
+      let command = request.get("command").and_then(|c| c.as_str()).unwrap_or("");
+      let mut events = Vec::new();
+      let body = match command{
+         "initialize" => {
+            events.push(Self::event("initialized", Json::object(vec![])));
+            Ok(Json::object(vec![
+               ("supportsConfigurationDoneRequest", true.into()),
+               ("supportsConditionalBreakpoints", true.into()),
+               ("supportsFunctionBreakpoints", true.into()),
+               ("supportsInstructionBreakpoints", true.into()),
+               ("supportsReadMemoryRequest", true.into()),
+               ("supportsDisassembleRequest", true.into()),
+               ("supportsSteppingGranularity", true.into())
+            ]))
+         },
+         "launch" | "attach" => {
+            if args.get("stopOnEntry").and_then(|v| v.as_bool()).unwrap_or(true){
+               events.push(Self::stopped("entry", 0, None));
+            }else{
+               self.running = Some(Goal::Breakpoint);
+            }
+            Ok(Json::object(vec![]))
+         },
+         "configurationDone" => Ok(Json::object(vec![])),
+         "setBreakpoints" => self.set_breakpoints(&args),
+         "setFunctionBreakpoints" => self.set_function_breakpoints(&args),
+         "setInstructionBreakpoints" => self.set_instruction_breakpoints(&args),
+         "setExceptionBreakpoints" => Ok(Json::object(vec![])),
+         "threads" => Ok(Json::object(vec![("threads", (0 .. self.machine.n_cores())
+            .map(|core| Json::object(vec![("id", (core + 1).into()), ("name", format!("core {}",core).into())]))
+            .collect::<Vec<Json>>().into())])),
+         "continue" => {
+            self.running = Some(Goal::Breakpoint);
+            Ok(Json::object(vec![("allThreadsContinued", true.into())]))
+         },
+         "next" => {
+            let core = self.thread_core(&args);
+            match self.call_at_pc(core){
+               Some((ret, sp)) => self.running = Some(Goal::StepOver(core, ret, sp)),
+               None => events.push(self.step(core))
+            }
+            Ok(Json::object(vec![]))
+         },
+         "stepIn" => {
+            let core = self.thread_core(&args);
+            events.push(self.step(core));
+            Ok(Json::object(vec![]))
+         },
+         "stepOut" => {
+            let core = self.thread_core(&args);
+            let sys = self.machine.cores[core].lock().unwrap();
+            let lr = sys.registers.lr;
+            // an EXC_RETURN value in LR means we are in a handler
+            self.running = Some(if lr >= 0xFFFFFFF0{
+               Goal::LeaveException(core, sys.scs.vec_active())
+            }else{
+               Goal::ReturnTo(core, lr & !1, sys.get_sp())
+            });
+            Ok(Json::object(vec![]))
+         },
+         "pause" => {
+            if self.running.take().is_some(){
+               events.push(Self::stopped("pause", self.thread_core(&args), None));
+            }
+            Ok(Json::object(vec![]))
+         },
+         "stackTrace" => Ok(self.stack_trace(self.thread_core(&args))),
+         "scopes" => {
+            let core = args.get("frameId").and_then(|v| v.as_i64()).unwrap_or(0) / VARS_PER_CORE;
+            Ok(Json::object(vec![("scopes", vec![
+               Json::object(vec![
+                  ("name", "Core Registers".into()),
+                  ("variablesReference", (core * VARS_PER_CORE + SCOPE_CORE_REGISTERS).into()),
+                  ("expensive", false.into())
+               ]),
+               Json::object(vec![
+                  ("name", "Special Registers".into()),
+                  ("variablesReference", (core * VARS_PER_CORE + SCOPE_SPECIAL_REGISTERS).into()),
+                  ("expensive", false.into())
+               ])
+            ].into())]))
+         },
+         "variables" => self.variables(&args),
+         "source" => {
+            if args.get("sourceReference").and_then(|v| v.as_i64()) == Some(DISASM_SOURCE_REF){
+               Ok(Json::object(vec![("content", self.disasm.clone().into()), ("mimeType", "text/x-asm".into())]))
+            }else{
+               Err("only the disassembly source is available".into())
+            }
+         },
+         "readMemory" => self.read_memory(&args),
+         "disassemble" => self.disassemble(&args),
+         "disconnect" | "terminate" => {
+            self.running = None;
+            self.quit = true;
+            Ok(Json::object(vec![]))
+         },
+         other => Err(format!("{} is not supported",other))
+      };
+      let mut out = vec![Self::response(request, body)];
+      out.extend(events);
+      return out;
+   }
+
+   fn thread_core(&self, args: &Json)->usize{
+      let id = args.get("threadId").and_then(|v| v.as_i64()).unwrap_or(1);
+      return (id.max(1) as usize - 1).min(self.machine.n_cores() - 1);
+   }
+
+   fn step(&mut self, core: usize)->Json{
+      match self.machine.step(){
+         Ok(_) => Self::stopped("step", core, None),
+         Err(e) => self.halt_event(e, core)
+      }
+   }
+
+   // the return address and SP of a BL or BLX about to run on the core
+   fn call_at_pc(&self, core: usize)->Option<(u32,u32)>{
+      let sys = self.machine.cores[core].lock().unwrap();
+      let pc = sys.read_raw_ir();
+      let size = match sys.decode_at(pc)?.0{
+         Opcode::_32Bit(B32::BR_AND_LNK) => 4,
+         Opcode::_16Bit(B16::BR_LNK_EXCHANGE) => 2,
+         _ => return None
+      };
+      Some((pc + size, sys.get_sp()))
+   }
+
+   fn halt_event(&mut self, halt: HaltType, core: usize)->Json{
+      self.running = None;
+      if let Some(dump) = &self.state_dump{
+         if let Err(e) = dump.write(&self.machine, Some(&halt)){
+            println!("WARN: could not write {}: {}",dump.path.display(),e);
+         }
+      }
+      match halt{
+         HaltType::breakpoint(None) => Self::stopped("breakpoint", core, None),
+         HaltType::breakpoint(Some(imm)) => Self::stopped("breakpoint", core, Some(format!("BKPT #{}",imm))),
+         HaltType::usercmd => Self::stopped("pause", core, None),
+         HaltType::lockup => Self::stopped("exception", core, Some("lockup".into())),
+         HaltType::error(e) => Self::stopped("exception", core, Some(format!("{:?}",e))),
+         HaltType::debug(DebugHalt::Step) => Self::stopped("step", core, None),
+         HaltType::debug(reason) => Self::stopped("pause", core, Some(debug_halt_msg(&reason)))
+      }
+   }
+
+   // steps the machine towards the current goal, returns a stopped event once it is reached
+   fn run_for(&mut self, steps: usize)->Option<Json>{
+      for _ in 0 .. steps{
+         if let Err(e) = self.machine.step(){
+            return Some(self.halt_event(e, 0));
+         }
+         let reached = match &self.running{
+            Some(Goal::ReturnTo(core, ret, sp)) => {
+               let sys = self.machine.cores[*core].lock().unwrap();
+               if sys.read_raw_ir() == *ret && sys.get_sp() >= *sp { Some(*core) } else { None }
+            },
+            Some(Goal::LeaveException(core, exc)) => {
+               if self.machine.cores[*core].lock().unwrap().scs.vec_active() != *exc { Some(*core) } else { None }
+            },
+            // a recursive call passes the return address deeper down the stack first
+            Some(Goal::StepOver(core, ret, sp)) => {
+               let sys = self.machine.cores[*core].lock().unwrap();
+               if sys.read_raw_ir() == *ret && sys.get_sp() >= *sp { Some(*core) } else { None }
+            },
+            _ => None
+         };
+         if let Some(core) = reached{
+            self.running = None;
+            return Some(Self::stopped("step", core, None));
+         }
+         if self.machine.on_breakpoint(){
+            let core = self.machine.cores.iter().position(|c| c.lock().unwrap().on_breakpoint()).unwrap_or(0);
+            return Some(self.halt_event(HaltType::breakpoint(None), core));
+         }
+      }
+      return None;
+   }
+
+   fn apply_breakpoints(&mut self)->Result<(),String>{
+      self.machine.clear_breakpoints();
+      let all: Vec<(u32,Option<String>)> = self.source_breakpoints.values().flatten()
+         .chain(self.function_breakpoints.iter())
+         .chain(self.instruction_breakpoints.iter())
+         .cloned()
+         .collect();
+      for (addr, cond) in all{
+         match cond{
+            Some(c) => self.machine.add_conditional_breakpoint(addr, &parse_condition(&c, &self.symbols)?),
+            None => self.machine.add_breakpoint(addr)
+         }
+      }
+      return Ok(());
+   }
+
+   fn breakpoint_json(verified: bool, addr: Option<u32>, message: Option<String>, line: Option<i64>)->Json{
+      let mut fields = vec![("verified", Json::from(verified))];
+      if let Some(a) = addr{
+         fields.push(("instructionReference", format!("{:#010x}",a).into()));
+      }
+      if let Some(l) = line{
+         fields.push(("line", l.into()));
+         fields.push(("source", Self::disasm_source()));
+      }
+      if let Some(m) = message{
+         fields.push(("message", m.into()));
+      }
+      Json::object(fields)
+   }
+
+   fn disasm_source()->Json{
+      Json::object(vec![("name", DISASM_SOURCE_NAME.into()), ("sourceReference", DISASM_SOURCE_REF.into())])
+   }
+
+   // checks a condition parses before it is stored so bad conditions are reported per breakpoint
+   fn condition_of(&self, bp: &Json)->Result<Option<String>,String>{
+      match bp.get("condition").and_then(|c| c.as_str()){
+         Some(c) if !c.trim().is_empty() => parse_condition(c, &self.symbols).map(|_| Some(c.to_owned())),
+         _ => Ok(None)
+      }
+   }
+
+   fn set_breakpoints(&mut self, args: &Json)->Result<Json,String>{
+      let source = args.get("source").cloned().unwrap_or(Json::Null);
+      let is_disasm = source.get("sourceReference").and_then(|v| v.as_i64()) == Some(DISASM_SOURCE_REF)
+         || source.get("name").and_then(|v| v.as_str()) == Some(DISASM_SOURCE_NAME);
+      let key = source.get("path").or(source.get("name")).and_then(|v| v.as_str()).unwrap_or(DISASM_SOURCE_NAME).to_owned();
+      let lines: Vec<&str> = self.disasm.lines().collect();
+      let mut set = Vec::new();
+      let mut results = Vec::new();
+      for bp in args.get("breakpoints").and_then(|b| b.as_array()).cloned().unwrap_or_default(){
+         let line = bp.get("line").and_then(|l| l.as_i64()).unwrap_or(0);
+         if !is_disasm{
+            results.push(Self::breakpoint_json(false, None, Some("no line information, set breakpoints in the disassembly".into()), None));
+            continue;
+         }
+         // DAP lines start at 1, labels resolve to the first instruction after them
+         let addr = lines.iter().skip((line.max(1) - 1) as usize)
+            .filter(|l| l.starts_with(char::is_whitespace))
+            .find_map(|l| line_address(l).map(|(a,_)| a));
+         match (addr, self.condition_of(&bp)){
+            (Some(a), Ok(cond)) => {
+               set.push((a, cond));
+               let actual = lines.iter().position(|l| l.starts_with(char::is_whitespace) && line_address(l).map(|(x,_)| x) == Some(a));
+               results.push(Self::breakpoint_json(true, Some(a), None, actual.map(|n| n as i64 + 1)));
+            },
+            (None, _) => results.push(Self::breakpoint_json(false, None, Some(format!("no instruction at or after line {}",line)), None)),
+            (_, Err(e)) => results.push(Self::breakpoint_json(false, None, Some(e), None))
+         }
+      }
+      self.source_breakpoints.insert(key, set);
+      self.apply_breakpoints()?;
+      return Ok(Json::object(vec![("breakpoints", results.into())]));
+   }
+
+   fn set_function_breakpoints(&mut self, args: &Json)->Result<Json,String>{
+      let mut set = Vec::new();
+      let mut results = Vec::new();
+      for bp in args.get("breakpoints").and_then(|b| b.as_array()).cloned().unwrap_or_default(){
+         let name = bp.get("name").and_then(|n| n.as_str()).unwrap_or("");
+         let addr = self.symbols.iter()
+            .find(|s| s.name.eq(name.trim()) && !is_segment_mapping_symbol(&s.name))
+            .map(|s| s.position as u32 & !1);
+         match (addr, self.condition_of(&bp)){
+            (Some(a), Ok(cond)) => {
+               set.push((a, cond));
+               results.push(Self::breakpoint_json(true, Some(a), None, None));
+            },
+            (None, _) => results.push(Self::breakpoint_json(false, None, Some(format!("no symbol named {}",name)), None)),
+            (_, Err(e)) => results.push(Self::breakpoint_json(false, None, Some(e), None))
+         }
+      }
+      self.function_breakpoints = set;
+      self.apply_breakpoints()?;
+      return Ok(Json::object(vec![("breakpoints", results.into())]));
+   }
+
+   fn set_instruction_breakpoints(&mut self, args: &Json)->Result<Json,String>{
+      let mut set = Vec::new();
+      let mut results = Vec::new();
+      for bp in args.get("breakpoints").and_then(|b| b.as_array()).cloned().unwrap_or_default(){
+         let base = bp.get("instructionReference").and_then(|r| r.as_str()).and_then(parse_address);
+         let offset = bp.get("offset").and_then(|o| o.as_i64()).unwrap_or(0);
+         match (base, self.condition_of(&bp)){
+            (Some(b), Ok(cond)) => {
+               let a = (b as i64 + offset) as u32 & !1;
+               set.push((a, cond));
+               results.push(Self::breakpoint_json(true, Some(a), None, None));
+            },
+            (None, _) => results.push(Self::breakpoint_json(false, None, Some("invalid instruction reference".into()), None)),
+            (_, Err(e)) => results.push(Self::breakpoint_json(false, None, Some(e), None))
+         }
+      }
+      self.instruction_breakpoints = set;
+      self.apply_breakpoints()?;
+      return Ok(Json::object(vec![("breakpoints", results.into())]));
+   }
+
+   fn symbol_for(&self, addr: u32)->Option<&SymbolDefinition>{
+      self.symbols.iter()
+         .filter(|s| !s.name.is_empty() && !is_segment_mapping_symbol(&s.name) && (s.position as u32 & !1) <= addr)
+         .max_by_key(|s| s.position & !1)
+   }
+
+   fn frame(&self, id: i64, addr: u32)->Json{
+      let name = match self.symbol_for(addr){
+         Some(sym) if (sym.position as u32 & !1) == addr => sym.name.clone(),
+         Some(sym) => format!("{}+{:#x}",sym.name,addr - (sym.position as u32 & !1)),
+         None => format!("{:#010x}",addr)
+      };
+      let line = self.disasm.lines().position(|l| l.starts_with(char::is_whitespace) && line_address(l).map(|(a,_)| a) == Some(addr));
+      let mut fields = vec![
+         ("id", Json::from(id)),
+         ("name", name.into()),
+         ("instructionPointerReference", format!("{:#010x}",addr).into()),
+         ("column", 0_i64.into())
+      ];
+      match line{
+         Some(l) => {
+            fields.push(("line", (l as i64 + 1).into()));
+            fields.push(("source", Self::disasm_source()));
+         },
+         None => fields.push(("line", 0_i64.into()))
+      }
+      Json::object(fields)
+   }
+
+   // without unwind information only the current frame and the caller held in LR can be shown
+   fn stack_trace(&self, core: usize)->Json{
+      let sys = self.machine.cores[core].lock().unwrap();
+      let mut frames = vec![self.frame(core as i64 * VARS_PER_CORE, sys.read_raw_ir())];
+      let lr = sys.registers.lr;
+      if lr != 0 && lr < 0xFFFFFFF0{
+         frames.push(self.frame(core as i64 * VARS_PER_CORE + 1, lr & !1));
+      }
+      let total = frames.len();
+      Json::object(vec![("stackFrames", frames.into()), ("totalFrames", total.into())])
+   }
+
+   fn variables(&self, args: &Json)->Result<Json,String>{
+      let reference = args.get("variablesReference").and_then(|v| v.as_i64()).ok_or("missing variablesReference")?;
+      let core = (reference / VARS_PER_CORE) as usize;
+      if core >= self.machine.n_cores(){
+         return Err(format!("invalid variablesReference {}",reference));
+      }
+      let sys = self.machine.cores[core].lock().unwrap();
+      let registers: Vec<(String,u32)> = match reference % VARS_PER_CORE{
+         SCOPE_CORE_REGISTERS => {
+            let mut regs: Vec<(String,u32)> = sys.registers.generic.iter().enumerate().map(|(i,v)| (format!("r{}",i), *v)).collect();
+            regs.push(("sp".into(), sys.get_sp()));
+            regs.push(("lr".into(), sys.registers.lr));
+            regs.push(("pc".into(), sys.registers.pc as u32));
+            regs
+         },
+         SCOPE_SPECIAL_REGISTERS => vec![
+            ("xpsr".into(), from_arm_bytes(sys.xpsr)),
+            ("msp".into(), sys.registers.sp_main),
+            ("psp".into(), sys.registers.sp_process),
+            ("primask".into(), sys.primask() as u32),
+            ("basepri".into(), sys.basepri() as u32),
+            ("faultmask".into(), sys.faultmask() as u32),
+            ("control".into(), from_arm_bytes(sys.control_register))
+         ],
+         _ => return Err(format!("invalid variablesReference {}",reference))
+      };
+      let vars: Vec<Json> = registers.into_iter().map(|(name, v)| Json::object(vec![
+         ("name", name.into()),
+         ("value", format!("{:#010x}",v).into()),
+         ("type", "u32".into()),
+         ("memoryReference", format!("{:#010x}",v).into()),
+         ("variablesReference", 0_i64.into())
+      ])).collect();
+      return Ok(Json::object(vec![("variables", vars.into())]));
+   }
+
+   fn read_memory(&self, args: &Json)->Result<Json,String>{
+      let base = args.get("memoryReference").and_then(|r| r.as_str()).and_then(parse_address).ok_or("invalid memoryReference")?;
+      let offset = args.get("offset").and_then(|o| o.as_i64()).unwrap_or(0);
+      let count = args.get("count").and_then(|c| c.as_i64()).unwrap_or(0).clamp(0, MAX_READ_BYTES) as u32;
+      let addr = (base as i64).wrapping_add(offset) as u32;
+      let sys = self.machine.cores[0].lock().unwrap();
+      let data = if count == 0 { Vec::new() } else { sys.alloc.view(addr, addr.saturating_add(count - 1)) };
+      return Ok(Json::object(vec![
+         ("address", format!("{:#010x}",addr).into()),
+         ("data", base64_encode(&data).into())
+      ]));
+   }
+
+   fn disassemble(&self, args: &Json)->Result<Json,String>{
+      let base = args.get("memoryReference").and_then(|r| r.as_str()).and_then(parse_address).ok_or("invalid memoryReference")?;
+      let offset = args.get("offset").and_then(|o| o.as_i64()).unwrap_or(0);
+      let instruction_offset = args.get("instructionOffset").and_then(|o| o.as_i64()).unwrap_or(0)
+         .clamp(-(u32::MAX as i64), u32::MAX as i64);
+      let count = args.get("instructionCount").and_then(|c| c.as_i64()).unwrap_or(0).clamp(0, MAX_DISASM_INSTRUCTIONS);
+      let addr = (base as i64).wrapping_add(offset) as u32;
+      let start = self.instructions.partition_point(|(a,_)| *a < addr) as i64 + instruction_offset;
+      let mut out = Vec::new();
+      for i in start .. start + count{
+         let entry = if i >= 0 { self.instructions.get(i as usize) } else { None };
+         out.push(match entry{
+            Some((a, text)) => {
+               let mut fields = vec![
+                  ("address", Json::from(format!("{:#010x}",a))),
+                  ("instruction", text.clone().into()),
+                  ("location", Self::disasm_source())
+               ];
+               if let Some(sym) = self.symbol_for(*a).filter(|s| (s.position as u32 & !1) == *a){
+                  fields.push(("symbol", sym.name.clone().into()));
+               }
+               Json::object(fields)
+            },
+            // the protocol expects exactly instructionCount entries, pad with invalid ones
+            None => Json::object(vec![
+               ("address", format!("{:#010x}",(addr as i64 + 2 * (i - start + instruction_offset)) as u32).into()),
+               ("instruction", "??".into()),
+               ("presentationHint", "invalid".into())
+            ])
+         });
+      }
+      return Ok(Json::object(vec![("instructions", out.into())]));
+   }
+}
+
@@ -104,13 +104,47 @@ pub struct ElfHeader{
    pub section_header_table_index: ElfHalf
 }
 
+// e_flags bit set by the linker when code was converted to little endian (--be8)
+pub const EF_ARM_BE8: u32 = 0x00800000;
+pub const EF_ARM_EABIMASK: u32 = 0xFF000000;
+pub const EF_ARM_ABI_FLOAT_SOFT: u32 = 0x00000200;
+pub const EF_ARM_ABI_FLOAT_HARD: u32 = 0x00000400;
+// the newest ARM EABI version a toolchain will emit
+pub const EF_ARM_EABI_VER5: u32 = 5;
+
 impl ElfHeader{
    pub fn get_elf_endianess(&self)-> EIData{
       EIData::from(self.identity[5])
    }
+
+   // ARMv6-M and ARMv7-M only support BE8, instructions little endian and data big endian.
+   // a big endian image without EF_ARM_BE8 (BE32) still has big endian instructions and cannot run
+   pub fn is_big_endian_image(&self)->Result<bool,ElfError>{
+      match self.get_elf_endianess(){
+         EIData::Msb if to_native_endianness_32b(self, &self.cpu_flags) & EF_ARM_BE8 == 0 => Err(ElfError::Arch(
+            String::from("big endian image is not BE8, relink it with --be8")
+         )),
+         EIData::Msb => Ok(true),
+         _ => Ok(false)
+      }
+   }
+
+   // checks the EABI flags of a header get_header accepted and returns the EABI version,
+   // version 0 is what older GNU toolchains emitted before the EABI existed
+   pub fn validate_arm_eabi(&self)->Result<u32,ElfError>{
+      let flags = to_native_endianness_32b(self, &self.cpu_flags);
+      let eabi = (flags & EF_ARM_EABIMASK) >> 24;
+      if eabi > EF_ARM_EABI_VER5{
+         return Err(ElfError::Arch(format!("unknown ARM EABI version {} in flags {:#x}",eabi,flags)));
+      }
+      if flags & EF_ARM_ABI_FLOAT_SOFT > 0 && flags & EF_ARM_ABI_FLOAT_HARD > 0{
+         return Err(ElfError::Arch(format!("flags {:#x} claim both the soft and hard float ABI",flags)));
+      }
+      Ok(eabi)
+   }
 }
 
-fn to_native_endianness_16b(header: &ElfHeader, bytes: &[u8;2])->u16{
+pub fn to_native_endianness_16b(header: &ElfHeader, bytes: &[u8;2])->u16{
    match header.get_elf_endianess(){
       EIData::_None => panic!("cannot read with unknown endianness"),
       EIData::Lsb => u16::from_le_bytes(*bytes),
@@ -146,15 +180,28 @@ pub enum SectionHeaderFlag{
 #[derive(Debug)]
 pub struct SectionHeader{
    pub name: ElfWord,
-   _type: ElfWord,
-   flags: ElfWord,
-   _addr_in_memory_img: Addr,
+   pub _type: ElfWord,
+   pub flags: ElfWord,
+   pub _addr_in_memory_img: Addr,
    pub offset_of_entries_in_bytes: ElfOffset,
-   section_size_in_bytes: ElfWord,  // not header size
-   link: ElfWord,
-   info: ElfWord,
-   alignment: ElfWord,
-   entry_size: ElfWord,
+   pub section_size_in_bytes: ElfWord,  // not header size
+   pub link: ElfWord,
+   pub info: ElfWord,
+   pub alignment: ElfWord,
+   pub entry_size: ElfWord,
+}
+
+#[repr(C,packed)]
+#[derive(Debug)]
+pub struct ProgramHeader{
+   pub _type: ElfWord,
+   pub offset: ElfOffset,
+   pub virtual_addr: Addr,
+   pub physical_addr: Addr,
+   pub file_size: ElfWord,
+   pub memory_size: ElfWord,
+   pub flags: ElfWord,
+   pub alignment: ElfWord
 }
 
 
@@ -163,12 +210,12 @@ pub const SHN_ABS: u16 = 0xfff1;
 #[repr(C,packed)]
 #[derive(Debug)]
 pub struct SymbolTableEntry{
-   name_index: ElfWord,
-   value: Addr,
-   size: ElfWord,
-   info: u8,
-   other: u8,
-   header_index: ElfHalf
+   pub name_index: ElfWord,
+   pub value: Addr,
+   pub size: ElfWord,
+   pub info: u8,
+   pub other: u8,
+   pub header_index: ElfHalf
 }
 
 impl From<&SymbolTableEntry> for Option<SymbolType>{
@@ -248,6 +295,7 @@ pub enum SymbolType{
    Hiproc = 0xf
 }
 
+#[derive(Debug)]
 pub enum SymbolBinding{
    Local = 0x0,
    Global = 0x1,
@@ -321,11 +369,7 @@ pub fn load_sections(
       match load{
          Some(ld) => {
             if matches!(&sections[ld].load,LoadType::PROGBITS){
-               let section_size = to_native_endianness_32b(header, &hdr.section_size_in_bytes);
-               let offset = to_native_endianness_32b(header, &hdr.offset_of_entries_in_bytes);
-               let mut buffer = vec![0_u8;section_size as usize];
-               reader.seek(std::io::SeekFrom::Start(offset as u64))?;
-               reader.read_exact(&mut buffer)?;
+               let buffer = read_section_bytes(reader, header, hdr, &sections[ld].name)?;
                loaded.push((sections[ld].name.clone(),sections[ld].start,buffer));
             }
          },
@@ -382,18 +426,7 @@ fn section_name(
       sect_hdr: &SectionHeader,
       sh_str_table_hdr: &SectionHeader
    )->Result<String, ElfError>{
-   let offset = to_native_endianness_32b(
-      header,
-      &sh_str_table_hdr.offset_of_entries_in_bytes
-   );
-   let table_size = to_native_endianness_32b(
-      header,
-      &sh_str_table_hdr.section_size_in_bytes
-   );
-
-   let mut str_buffer = vec![0_u8;table_size as usize];
-   reader.seek(std::io::SeekFrom::Start(offset as u64))?;
-   reader.read_exact(&mut str_buffer)?;
+   let str_buffer = read_section_bytes(reader, header, sh_str_table_hdr, "section name string table")?;
    let sh_str_name = to_native_endianness_32b(header, &sh_str_table_hdr.name);
    //let mut name_map = vec![String::new();sect_hdrs.len()];
 
@@ -401,7 +434,7 @@ fn section_name(
    let name = to_native_endianness_32b(header, &sect_hdr.name);
    if name != sh_str_name{
       let mut c = name as usize;
-      while str_buffer[c] as char != '\0' && (c < str_buffer.len()){
+      while c < str_buffer.len() && str_buffer[c] as char != '\0'{
          section_name.push(str_buffer[c] as char);
          c += 1;
       }
@@ -415,24 +448,51 @@ fn section_name(
 
 
 
+// a NUL terminated string from a string table, names running past the table are cut short
+pub fn string_at(table: &[u8], index: u32)->String{
+   let tail = table.get(index as usize ..).unwrap_or(&[]);
+   let len = tail.iter().position(|b| *b == 0).unwrap_or(tail.len());
+   String::from_utf8_lossy(&tail[..len]).into_owned()
+}
+
+// offset must be inside the file and offset + size must not run past its end
+fn check_file_range(reader: &BufReader<File>, what: &str, offset: u64, size: u64)->Result<(),ElfError>{
+   let file_size = reader.get_ref().metadata()?.len();
+   if size > 0 && offset >= file_size{
+      return Err(ElfError::OffsetOutOfRange{what: what.to_string(), offset, file_size});
+   }
+   if offset + size > file_size{
+      return Err(ElfError::Truncated{what: what.to_string(), offset, size, file_size});
+   }
+   Ok(())
+}
+
+// the bytes of a section as they are in the file, NOBITS sections have none
+pub fn read_section_bytes(
+      reader: &mut BufReader<File>,
+      header: &ElfHeader,
+      sect_hdr: &SectionHeader,
+      what: &str
+   )->Result<Vec<u8>,ElfError>{
+   if has_no_bytes(header, sect_hdr){
+      return Ok(Vec::new());
+   }
+   let offset = to_native_endianness_32b(header, &sect_hdr.offset_of_entries_in_bytes) as u64;
+   let size = to_native_endianness_32b(header, &sect_hdr.section_size_in_bytes) as u64;
+   check_file_range(reader, what, offset, size)?;
+   let mut buffer = vec![0_u8;size as usize];
+   reader.seek(std::io::SeekFrom::Start(offset))?;
+   reader.read_exact(&mut buffer)?;
+   Ok(buffer)
+}
+
 pub fn get_section_names(
       reader: &mut BufReader<File>,
       header: &ElfHeader,
       sect_hdrs: &Vec<SectionHeader>
    )->Result<Vec<String>, ElfError>{
-   let sh_str_table_hdr = get_sh_string_table_header(header, sect_hdrs);
-   let offset = to_native_endianness_32b(
-      header,
-      &sh_str_table_hdr.offset_of_entries_in_bytes
-   );
-   let table_size = to_native_endianness_32b(
-      header,
-      &sh_str_table_hdr.section_size_in_bytes
-   );
-
-   let mut str_buffer = vec![0_u8;table_size as usize];
-   reader.seek(std::io::SeekFrom::Start(offset as u64))?;
-   reader.read_exact(&mut str_buffer)?;
+   let sh_str_table_hdr = get_sh_string_table_header(header, sect_hdrs)?;
+   let str_buffer = read_section_bytes(reader, header, sh_str_table_hdr, "section name string table")?;
    let sh_str_name = to_native_endianness_32b(header, &sh_str_table_hdr.name);
    let mut section_name = String::new();
    //let mut name_map = vec![String::new();sect_hdrs.len()];
@@ -444,11 +504,11 @@ pub fn get_section_names(
       );
       if name != sh_str_name{
          let mut c = name as usize;
-         while str_buffer[c] as char != '\0' && (c < str_buffer.len()){
+         while c < str_buffer.len() && str_buffer[c] as char != '\0'{
             section_name.push(str_buffer[c] as char);
             c += 1;
          }
-         println!("section hdr {} == {}",i,section_name);
+         dbg_ln!("section hdr {} == {}",i,section_name);
          //name_map.insert(i,section_name.clone());
          name_list[i].push_str(&section_name);
          section_name.clear();
@@ -457,26 +517,46 @@ pub fn get_section_names(
    Ok(name_list)
 }
 
-pub fn get_sh_string_table_header<'a>(header: &'a ElfHeader, section_headers: &'a Vec<SectionHeader>)->&'a SectionHeader{
+pub fn get_sh_string_table_header<'a>(header: &'a ElfHeader, section_headers: &'a Vec<SectionHeader>)->Result<&'a SectionHeader,ElfError>{
    let sh_name_table_index = to_native_endianness_16b(header, &header.section_header_table_index);
-   let section = &section_headers[sh_name_table_index as usize];
+   if sh_name_table_index == 0{
+      return Err(ElfError::MissingTable("section name string table"));
+   }
+   let section = section_headers.get(sh_name_table_index as usize).ok_or(ElfError::IndexOutOfRange{
+      what: "section name string table",
+      index: sh_name_table_index as u32,
+      count: section_headers.len() as u32
+   })?;
    let _type = to_native_endianness_32b(header, &section._type);
    let flags = to_native_endianness_32b(header, &section.flags);
 
-   assert_eq!(_type, SectionHeaderType::StringTable as u32);
-   assert_eq!(flags ,SectionHeaderFlag::Null as u32);
-   section
+   if _type != SectionHeaderType::StringTable as u32 || flags != SectionHeaderFlag::Null as u32{
+      return Err(ElfError::MissingTable("section name string table"));
+   }
+   Ok(section)
 }
 
 pub fn has_no_bytes(header: &ElfHeader, sect_header: &SectionHeader)->bool{
-   let flags = to_native_endianness_32b(header,&sect_header._type);
-   (flags & SectionHeaderType::NOBITS as u32) > 0
+   let _type = to_native_endianness_32b(header,&sect_header._type);
+   _type == SectionHeaderType::NOBITS as u32
 }
 
 #[derive(Debug)]
 pub enum ElfError{
    Arch(String),
    FileIO(String),
+   UnsupportedClass(u8),
+   UnsupportedMachine(u16),
+   UnsupportedVersion(u32),
+   MissingTable(&'static str),
+   MissingSection(String),
+   // a table or section that starts in the file but runs past its end
+   Truncated{what: String, offset: u64, size: u64, file_size: u64},
+   OffsetOutOfRange{what: String, offset: u64, file_size: u64},
+   IndexOutOfRange{what: &'static str, index: u32, count: u32},
+   UndefinedSymbol(String),
+   // a relocation the linker cannot apply, at section + offset
+   Relocation{section: String, offset: u32, msg: String}
 }
 
 impl From<std::io::Error> for ElfError{
@@ -489,7 +569,26 @@ impl Display for ElfError{
    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
       match self{
          ElfError::FileIO(msg) => write!(f,"{}",msg),
-         ElfError::Arch(msg) =>write!(f, "{}",msg)
+         ElfError::Arch(msg) =>write!(f, "{}",msg),
+         ElfError::UnsupportedClass(class) => {
+            let name = if *class == EIClass::_64 as u8 {"ELF64"} else {"unknown"};
+            write!(f,"unsupported ELF class {} ({}), only 32 bit ELFs are supported",class,name)
+         },
+         ElfError::UnsupportedMachine(machine) => write!(f,"unsupported machine {}, expected ARM ({})",machine,EIMachine::Arm as u16),
+         ElfError::UnsupportedVersion(version) => write!(f,"unsupported ELF version {}, expected 1 (current)",version),
+         ElfError::MissingTable(table) => write!(f,"the ELF has no {}",table),
+         ElfError::MissingSection(name) => write!(f,"the ELF has no {} section",name),
+         ElfError::Truncated{what, offset, size, file_size} => write!(
+            f,"{} at offset {:#x} ({} bytes) runs past the end of the file ({} bytes), the ELF is truncated",what,offset,size,file_size
+         ),
+         ElfError::OffsetOutOfRange{what, offset, file_size} => write!(
+            f,"{} offset {:#x} is past the end of the file ({} bytes)",what,offset,file_size
+         ),
+         ElfError::IndexOutOfRange{what, index, count} => write!(
+            f,"{} index {} is out of range, there are {} section headers",what,index,count
+         ),
+         ElfError::UndefinedSymbol(name) => write!(f,"undefined symbol {}",name),
+         ElfError::Relocation{section, offset, msg} => write!(f,"cannot relocate {}+{:#x}: {}",section,offset,msg)
       }
    }
 }
@@ -499,6 +598,7 @@ pub fn get_header(file: &Path)-> Result<(ElfHeader, BufReader<File>),ElfError>{
    const SIZE: usize = std::mem::size_of::<ElfHeader>();
    let mut source: [u8;SIZE] = [0;SIZE];
    let mut reader = BufReader::new(f);
+   check_file_range(&reader, "ELF header", 0, SIZE as u64)?;
    reader.read_exact(&mut source)?;
    let header: ElfHeader;
    unsafe {
@@ -513,7 +613,24 @@ pub fn get_header(file: &Path)-> Result<(ElfHeader, BufReader<File>),ElfError>{
    if header.get_elf_endianess() == EIData::_None{
       return Err(ElfError::Arch(String::from("could not read endianness")));
    }
-   
+
+   if EIClass::from(header.identity[4]) != EIClass::_32{
+      return Err(ElfError::UnsupportedClass(header.identity[4]));
+   }
+
+   let version = to_native_endianness_32b(&header, &header.version);
+   if header.identity[6] != EIVersion::Current as u8{
+      return Err(ElfError::UnsupportedVersion(header.identity[6] as u32));
+   }
+   if version != EIVersion::Current as u32{
+      return Err(ElfError::UnsupportedVersion(version));
+   }
+
+   let machine = to_native_endianness_16b(&header, &header.machine);
+   if machine != EIMachine::Arm as u16{
+      return Err(ElfError::UnsupportedMachine(machine));
+   }
+
    Ok((header,reader))
 }
 
@@ -527,6 +644,11 @@ pub fn get_all_section_headers(
    let mut header_meta_data_source: [u8;SIZE] = [0;SIZE];
    let num_headers = to_native_endianness_16b(header, &header.num_section_header_entries);
    dbg_ln!("headers {}",num_headers);
+   let entry_size = to_native_endianness_16b(header, &header.section_header_size_in_bytes);
+   if num_headers > 0 && entry_size as usize != SIZE{
+      return Err(ElfError::FileIO(format!("section header entries are {} bytes, expected {}",entry_size,SIZE)));
+   }
+   check_file_range(reader, "section header table", section_offset as u64, (num_headers as usize * SIZE) as u64)?;
    let mut headers: Vec<SectionHeader> = Vec::with_capacity(num_headers as usize);
    for _ in 0 .. num_headers{
       reader.read_exact(&mut header_meta_data_source)?;
@@ -540,6 +662,35 @@ pub fn get_all_section_headers(
    Ok(headers)
 } 
 
+pub fn get_program_headers(
+   reader: &mut BufReader<File>,
+   header: &ElfHeader
+)->Result<Vec<ProgramHeader>,ElfError>{
+   let num_headers = to_native_endianness_16b(header, &header.num_program_header_entries);
+   if num_headers == 0{
+      return Ok(Vec::new());
+   }
+   const SIZE: usize = std::mem::size_of::<ProgramHeader>();
+   let entry_size = to_native_endianness_16b(header, &header.program_header_entry_size_in_bytes);
+   if entry_size as usize != SIZE{
+      return Err(ElfError::FileIO(format!("program header entries are {} bytes, expected {}",entry_size,SIZE)));
+   }
+   let offset = to_native_endianness_32b(header, &header.program_header_offset);
+   check_file_range(reader, "program header table", offset as u64, (num_headers as usize * SIZE) as u64)?;
+   reader.seek(std::io::SeekFrom::Start(offset as u64))?;
+   let mut source: [u8;SIZE] = [0;SIZE];
+   let mut headers = Vec::with_capacity(num_headers as usize);
+   for _ in 0 .. num_headers{
+      reader.read_exact(&mut source)?;
+      let header: ProgramHeader;
+      unsafe {
+         header = std::mem::transmute_copy::<[u8;SIZE],ProgramHeader>(&source);
+      }
+      headers.push(header);
+   }
+   Ok(headers)
+}
+
 pub fn get_entry_point_offset(elf_header: &ElfHeader)->usize{
    let offset = to_native_endianness_32b(elf_header, &elf_header._entry_point);
    offset as usize
@@ -559,6 +710,24 @@ pub fn get_section_symbols(
       return Ok(Vec::new());
    }
 
+   let entries = get_all_symbol_entries(reader, elf_header, symtable_hdr)?;
+
+   let local_symbols: Vec<SymbolTableEntry> = entries.into_iter()
+      .filter(|e| 
+         has_a_visible_type(e) 
+         && has_any_binding(e) 
+         && to_native_endianness_32b(elf_header, &e.name_index) != 0)
+      .collect();
+
+   Ok(local_symbols)
+}
+
+// every entry of a symbol table, including the null symbol, sections and files
+pub fn get_all_symbol_entries(
+   reader: &mut BufReader<File>,
+   elf_header: &ElfHeader,
+   symtable_hdr: &SectionHeader
+   )->Result<Vec<SymbolTableEntry>, ElfError>{
    let symbol_table_offset = to_native_endianness_32b(elf_header, &symtable_hdr.offset_of_entries_in_bytes);
    let bytes = to_native_endianness_32b(elf_header, &symtable_hdr.section_size_in_bytes);
    let alignment = to_native_endianness_32b(elf_header, &symtable_hdr.alignment);
@@ -569,10 +738,13 @@ pub fn get_section_symbols(
 
    const SYMBOL_ENTRY_SIZE: usize = std::mem::size_of::<SymbolTableEntry>();
 
-   dbg_ln!("number of symbol table entries = {}/{} = {}",bytes,entry_size,bytes/entry_size);
    dbg_ln!("symbol struct entry size is {}",SYMBOL_ENTRY_SIZE);
-   assert_eq!(SYMBOL_ENTRY_SIZE,entry_size as usize);
+   if SYMBOL_ENTRY_SIZE != entry_size as usize{
+      return Err(ElfError::FileIO(format!("symbol table entries are {} bytes, expected {}",entry_size,SYMBOL_ENTRY_SIZE)));
+   }
+   dbg_ln!("number of symbol table entries = {}/{} = {}",bytes,entry_size,bytes/entry_size);
 
+   check_file_range(reader, "symbol table", symbol_table_offset as u64, bytes as u64)?;
    reader.seek(std::io::SeekFrom::Start(symbol_table_offset as u64))?;
 
    let mut entry_buffer: [u8;SYMBOL_ENTRY_SIZE] = [0;SYMBOL_ENTRY_SIZE];
@@ -587,16 +759,44 @@ pub fn get_section_symbols(
       }
       entries.push(entry);
    }
+   Ok(entries)
+}
 
-   let local_symbols: Vec<SymbolTableEntry> = entries.into_iter()
-      .filter(|e| 
-         has_a_visible_type(e) 
-         && has_any_binding(e) 
-         && to_native_endianness_32b(elf_header, &e.name_index) != 0)
-      .collect();
+// the string table a symbol table's sh_link points at
+pub fn get_symbol_string_table_hdr<'a>(
+   elf_header: &ElfHeader,
+   sect_hdrs: &'a [SectionHeader],
+   symtable_hdr: &SectionHeader
+   )->Result<&'a SectionHeader,ElfError>{
+   let link = to_native_endianness_32b(elf_header, &symtable_hdr.link);
+   let str_table_hdr = sect_hdrs.get(link as usize).ok_or(ElfError::IndexOutOfRange{
+      what: "symbol string table",
+      index: link,
+      count: sect_hdrs.len() as u32
+   })?;
+   let _type = to_native_endianness_32b(elf_header, &str_table_hdr._type);
+   if link == 0 || _type != SectionHeaderType::StringTable as u32{
+      return Err(ElfError::MissingTable("symbol string table (.strtab)"));
+   }
+   Ok(str_table_hdr)
+}
 
-   assert!(local_symbols.is_empty() == false);
-   Ok(local_symbols)
+// the visible symbols of the image, stripped images have no .symtab and load without symbols
+pub fn get_symbols(
+   reader: &mut BufReader<File>,
+   elf_header: &ElfHeader,
+   sect_hdrs: &[SectionHeader]
+   )->Result<Vec<SymbolDefinition>,ElfError>{
+   let symtab = match sect_hdrs.iter().find(|hdr| is_symbol_table_section_hdr(elf_header, hdr)){
+      Some(symtab) => symtab,
+      None => {
+         println!("no symbol table, the ELF is stripped");
+         return Ok(Vec::new());
+      }
+   };
+   let str_table_hdr = get_symbol_string_table_hdr(elf_header, sect_hdrs, symtab)?;
+   let sym_entries = get_section_symbols(reader, elf_header, symtab)?;
+   get_all_symbol_names(reader, elf_header, &sym_entries, str_table_hdr)
 }
 
 pub fn get_text_section_symbols<'a>(
@@ -684,19 +884,14 @@ fn get_matching_sym_in_place(
    sym_entries: &Vec<SymbolTableEntry>,
    str_table_hdr: &SectionHeader,
 )-> Result<Vec<String>,ElfError>{
-   let str_table_pos = to_native_endianness_32b(elf_header, &str_table_hdr.offset_of_entries_in_bytes);
-   let str_table_size = to_native_endianness_32b(elf_header, &str_table_hdr.section_size_in_bytes);
-   let mut str_buffer = vec![0u8;str_table_size as usize];
-
-   reader.seek(std::io::SeekFrom::Start(str_table_pos as u64))?;
-   reader.read_exact(&mut str_buffer)?;
+   let str_buffer = read_section_bytes(reader, elf_header, str_table_hdr, "symbol string table")?;
    
    let mut symbol_names = Vec::new();
    let mut symbol_name = String::new();
 
    for symbol in sym_entries{
       let mut index = to_native_endianness_32b(elf_header, &symbol.name_index) as usize;
-      while str_buffer[index] as char != '\0' && index < str_buffer.len(){
+      while index < str_buffer.len() && str_buffer[index] as char != '\0'{
          symbol_name.push(str_buffer[index] as char);
          index += 1;
       }
@@ -712,19 +907,14 @@ pub fn get_matching_symbol_names(
    sym_entries: &Vec<&SymbolTableEntry>,
    str_table_hdr: &SectionHeader,
 )-> Result<Vec<String>,ElfError>{
-   let str_table_pos = to_native_endianness_32b(elf_header, &str_table_hdr.offset_of_entries_in_bytes);
-   let str_table_size = to_native_endianness_32b(elf_header, &str_table_hdr.section_size_in_bytes);
-   let mut str_buffer = vec![0u8;str_table_size as usize];
-
-   reader.seek(std::io::SeekFrom::Start(str_table_pos as u64))?;
-   reader.read_exact(&mut str_buffer)?;
+   let str_buffer = read_section_bytes(reader, elf_header, str_table_hdr, "symbol string table")?;
    
    let mut symbol_names = Vec::new();
    let mut symbol_name = String::new();
 
    for symbol in sym_entries{
       let mut index = to_native_endianness_32b(elf_header, &symbol.name_index) as usize;
-      while str_buffer[index] as char != '\0'{
+      while index < str_buffer.len() && str_buffer[index] as char != '\0'{
          symbol_name.push(str_buffer[index] as char);
          index += 1;
       }
@@ -849,6 +1039,7 @@ pub fn read_text_section(
    dbg_ln!("text section is {} bytes",bytes);
    dbg_ln!("address alignment {} bytes",alignment);
    dbg_ln!("section entry size {} bytes",entry_size);
+   check_file_range(reader, ".text", text_offset as u64, bytes as u64)?;
    let mut section = vec![0u8;bytes as usize];
    reader.seek(std::io::SeekFrom::Start(text_offset as u64))?;
    reader.read_exact(&mut section)?;
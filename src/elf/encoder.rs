@@ -0,0 +1,278 @@
+use crate::asm::assembler::{Assembly, AsmSymbol, SymbolLocation};
+use crate::elf::decoder::ELF_FORMAT_HDR;
+
+// writes the output of the built-in assembler as a little endian ELF32 relocatable object,
+// laid out the same way arm-none-eabi-as lays out its objects so the decoder reads both alike
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_NOBITS: u32 = 8;
+const SHT_REL: u32 = 9;
+
+const SHF_WRITE: u32 = 0x1;
+const SHF_ALLOC: u32 = 0x2;
+const SHF_EXECINSTR: u32 = 0x4;
+const SHF_INFO_LINK: u32 = 0x40;
+
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_NOTYPE: u8 = 0;
+const STT_FUNC: u8 = 2;
+const STT_SECTION: u8 = 3;
+
+const SHN_ABS: u16 = 0xfff1;
+const EF_ARM_EABI_VER5: u32 = 0x05000000;
+
+const ELF_HEADER_SIZE: usize = 52;
+const SECTION_HEADER_SIZE: usize = 40;
+const SYMBOL_SIZE: usize = 16;
+
+struct StringTable{
+   bytes: Vec<u8>
+}
+
+impl StringTable{
+   fn new()->Self{
+      Self{bytes: vec![0]}
+   }
+
+   fn add(&mut self, s: &str)->u32{
+      if s.is_empty(){
+         return 0;
+      }
+      let index = self.bytes.len() as u32;
+      self.bytes.extend_from_slice(s.as_bytes());
+      self.bytes.push(0);
+      return index;
+   }
+}
+
+struct SectionEntry{
+   name: u32,
+   _type: u32,
+   flags: u32,
+   offset: u32,
+   size: u32,
+   link: u32,
+   info: u32,
+   alignment: u32,
+   entry_size: u32
+}
+
+struct SymbolEntry{
+   name: u32,
+   value: u32,
+   bind: u8,
+   _type: u8,
+   section_index: u16
+}
+
+fn push_symbol(
+      symbols: &mut Vec<SymbolEntry>,
+      names: &mut Vec<String>,
+      strtab: &mut StringTable,
+      sym: &AsmSymbol,
+      section_index: u16
+   ){
+   symbols.push(SymbolEntry{
+      name: strtab.add(&sym.name),
+      value: sym.value,
+      bind: if sym.global { STB_GLOBAL } else { STB_LOCAL },
+      _type: if sym.func { STT_FUNC } else { STT_NOTYPE },
+      section_index
+   });
+   names.push(sym.name.clone());
+}
+
+fn push_16(out: &mut Vec<u8>, v: u16){
+   out.extend_from_slice(&v.to_le_bytes());
+}
+
+fn push_32(out: &mut Vec<u8>, v: u32){
+   out.extend_from_slice(&v.to_le_bytes());
+}
+
+fn align_to(out: &mut Vec<u8>, alignment: usize){
+   while out.len() % alignment != 0{
+      out.push(0);
+   }
+}
+
+pub fn encode_relocatable(assembly: &Assembly)->Vec<u8>{
+   let mut strtab = StringTable::new();
+   let mut symbols = vec![SymbolEntry{name: 0, value: 0, bind: STB_LOCAL, _type: STT_NOTYPE, section_index: 0}];
+
+   // section headers: NULL, each assembled section, then its .rel section if it has relocations
+   let mut section_header_index = Vec::new();
+   let mut next_index = 1_u16;
+   for section in assembly.sections.iter(){
+      section_header_index.push(next_index);
+      next_index += if section.relocations.is_empty() { 1 } else { 2 };
+   }
+   let symtab_index = next_index as u32;
+
+   for index in section_header_index.iter(){
+      symbols.push(SymbolEntry{name: 0, value: 0, bind: STB_LOCAL, _type: STT_SECTION, section_index: *index});
+   }
+   // locals are grouped by section with the mapping symbols first, then constants, then globals
+   let mut symbol_names = vec![String::new();symbols.len()];
+   for (i,section) in assembly.sections.iter().enumerate(){
+      for (offset,is_code) in section.mapping.iter(){
+         let name = strtab.add(if *is_code {"$t"} else {"$d"});
+         symbols.push(SymbolEntry{name, value: *offset, bind: STB_LOCAL, _type: STT_NOTYPE, section_index: section_header_index[i]});
+         symbol_names.push(String::new());
+      }
+      for sym in assembly.symbols.iter().filter(|s| !s.global && s.location == SymbolLocation::Section(i)){
+         push_symbol(&mut symbols, &mut symbol_names, &mut strtab, sym, section_header_index[i]);
+      }
+   }
+   for sym in assembly.symbols.iter().filter(|s| !s.global && s.location == SymbolLocation::Absolute){
+      push_symbol(&mut symbols, &mut symbol_names, &mut strtab, sym, SHN_ABS);
+   }
+   for sym in assembly.symbols.iter().filter(|s| s.global){
+      let section_index = match sym.location{
+         SymbolLocation::Section(i) => section_header_index[i],
+         SymbolLocation::Absolute => SHN_ABS,
+         SymbolLocation::Undefined => 0
+      };
+      push_symbol(&mut symbols, &mut symbol_names, &mut strtab, sym, section_index);
+   }
+   let first_global = symbols.iter().position(|s| s.bind == STB_GLOBAL).unwrap_or(symbols.len()) as u32;
+
+   let symbol_index = |name: &str|->u32{
+      if let Some(i) = assembly.sections.iter().position(|s| s.name.eq(name)){
+         return (i + 1) as u32;
+      }
+      return symbol_names.iter().position(|n| n.eq(name)).unwrap_or(0) as u32;
+   };
+
+   let mut shstrtab = StringTable::new();
+   let mut headers = vec![SectionEntry{name: 0, _type: 0, flags: 0, offset: 0, size: 0, link: 0, info: 0, alignment: 0, entry_size: 0}];
+   let mut out = vec![0_u8;ELF_HEADER_SIZE];
+
+   for (i,section) in assembly.sections.iter().enumerate(){
+      align_to(&mut out, 4);
+      let mut flags = SHF_ALLOC;
+      if section.executable { flags |= SHF_EXECINSTR; }
+      if section.writable { flags |= SHF_WRITE; }
+      headers.push(SectionEntry{
+         name: shstrtab.add(&section.name),
+         _type: if section.nobits { SHT_NOBITS } else { SHT_PROGBITS },
+         flags,
+         offset: out.len() as u32,
+         size: section.size,
+         link: 0,
+         info: 0,
+         alignment: 4,
+         entry_size: 0
+      });
+      if !section.nobits{
+         out.extend_from_slice(&section.bytes);
+      }
+
+      if !section.relocations.is_empty(){
+         align_to(&mut out, 4);
+         let offset = out.len() as u32;
+         for reloc in section.relocations.iter(){
+            push_32(&mut out, reloc.offset);
+            push_32(&mut out, symbol_index(&reloc.symbol) << 8 | reloc.kind.elf_type() as u32);
+         }
+         headers.push(SectionEntry{
+            name: shstrtab.add(&format!(".rel{}",section.name)),
+            _type: SHT_REL,
+            flags: SHF_INFO_LINK,
+            offset,
+            size: 8 * section.relocations.len() as u32,
+            link: symtab_index,
+            info: section_header_index[i] as u32,
+            alignment: 4,
+            entry_size: 8
+         });
+      }
+   }
+
+   align_to(&mut out, 4);
+   let symtab_offset = out.len() as u32;
+   for sym in symbols.iter(){
+      push_32(&mut out, sym.name);
+      push_32(&mut out, sym.value);
+      push_32(&mut out, 0);
+      out.push(sym.bind << 4 | sym._type);
+      out.push(0);
+      push_16(&mut out, sym.section_index);
+   }
+   headers.push(SectionEntry{
+      name: shstrtab.add(".symtab"),
+      _type: SHT_SYMTAB,
+      flags: 0,
+      offset: symtab_offset,
+      size: (symbols.len() * SYMBOL_SIZE) as u32,
+      link: symtab_index + 1,
+      info: first_global,
+      alignment: 4,
+      entry_size: SYMBOL_SIZE as u32
+   });
+
+   headers.push(SectionEntry{
+      name: shstrtab.add(".strtab"),
+      _type: SHT_STRTAB,
+      flags: 0,
+      offset: out.len() as u32,
+      size: strtab.bytes.len() as u32,
+      link: 0,
+      info: 0,
+      alignment: 1,
+      entry_size: 0
+   });
+   out.extend_from_slice(&strtab.bytes);
+
+   let shstrtab_name = shstrtab.add(".shstrtab");
+   headers.push(SectionEntry{
+      name: shstrtab_name,
+      _type: SHT_STRTAB,
+      flags: 0,
+      offset: out.len() as u32,
+      size: shstrtab.bytes.len() as u32,
+      link: 0,
+      info: 0,
+      alignment: 1,
+      entry_size: 0
+   });
+   out.extend_from_slice(&shstrtab.bytes);
+
+   align_to(&mut out, 4);
+   let section_header_offset = out.len() as u32;
+   for hdr in headers.iter(){
+      push_32(&mut out, hdr.name);
+      push_32(&mut out, hdr._type);
+      push_32(&mut out, hdr.flags);
+      push_32(&mut out, 0);
+      push_32(&mut out, hdr.offset);
+      push_32(&mut out, hdr.size);
+      push_32(&mut out, hdr.link);
+      push_32(&mut out, hdr.info);
+      push_32(&mut out, hdr.alignment);
+      push_32(&mut out, hdr.entry_size);
+   }
+
+   let mut header = Vec::with_capacity(ELF_HEADER_SIZE);
+   header.extend_from_slice(&ELF_FORMAT_HDR);
+   header.extend_from_slice(&[1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+   push_16(&mut header, 1); // ET_REL
+   push_16(&mut header, 40); // EM_ARM
+   push_32(&mut header, 1);
+   push_32(&mut header, 0);
+   push_32(&mut header, 0);
+   push_32(&mut header, section_header_offset);
+   push_32(&mut header, EF_ARM_EABI_VER5);
+   push_16(&mut header, ELF_HEADER_SIZE as u16);
+   push_16(&mut header, 0);
+   push_16(&mut header, 0);
+   push_16(&mut header, SECTION_HEADER_SIZE as u16);
+   push_16(&mut header, headers.len() as u16);
+   push_16(&mut header, (headers.len() - 1) as u16);
+   out[..ELF_HEADER_SIZE].copy_from_slice(&header);
+
+   return out;
+}
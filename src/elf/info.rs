@@ -0,0 +1,383 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use crate::elf::decoder::{
+   ElfHeader,
+   ElfError,
+   EIData,
+   SectionHeader,
+   ProgramHeader,
+   SymbolTableEntry,
+   SymbolType,
+   SymbolBinding,
+   SectionHeaderType,
+   SHN_ABS,
+   EF_ARM_BE8,
+   EF_ARM_EABIMASK,
+   EF_ARM_ABI_FLOAT_SOFT,
+   EF_ARM_ABI_FLOAT_HARD,
+   get_header,
+   get_all_section_headers,
+   get_sh_string_table_header,
+   get_program_headers,
+   get_all_symbol_entries,
+   get_symbol_string_table_hdr,
+   read_section_bytes,
+   string_at,
+   is_symbol_table_section_hdr,
+   to_native_endianness_16b,
+   to_native_endianness_32b
+};
+
+const SHN_COMMON: u16 = 0xfff2;
+// 16 system exceptions + the most external interrupts any M profile core can have
+const MAX_VECTORS: u32 = 16 + 496;
+// without symbols to bound it the table is assumed to be an ARMv6-M one with 32 interrupts
+const UNBOUNDED_VECTORS: u32 = 16 + 32;
+
+// a readelf like report of an ARM ELF, used by `armageddon info <elf>`.
+// the vector table is read from vtor (0 by default) when a section of the image holds it
+pub fn describe(file: &Path, vtor: Option<u32>)->Result<String,ElfError>{
+   let (header, mut reader) = get_header(file)?;
+   let eabi = header.validate_arm_eabi()?;
+
+   let section_headers = get_all_section_headers(&mut reader, &header)?;
+   let section_names = if section_headers.is_empty(){
+      Vec::new()
+   }else{
+      let names = read_section_bytes(&mut reader, &header, get_sh_string_table_header(&header, &section_headers)?, "section name string table")?;
+      section_headers.iter().map(|hdr| string_at(&names, to_native_endianness_32b(&header, &hdr.name))).collect()
+   };
+   let program_headers = get_program_headers(&mut reader, &header)?;
+
+   let mut out = String::new();
+   describe_header(&mut out, &header, eabi);
+   describe_sections(&mut out, &header, &section_headers, &section_names);
+   describe_program_headers(&mut out, &header, &program_headers);
+
+   let mut symbols = Vec::new();
+   for (i, hdr) in section_headers.iter().enumerate(){
+      if !is_symbol_table_section_hdr(&header, hdr){
+         continue;
+      }
+      let entries = get_all_symbol_entries(&mut reader, &header, hdr)?;
+      let str_table_hdr = get_symbol_string_table_hdr(&header, &section_headers, hdr)?;
+      let str_table = read_section_bytes(&mut reader, &header, str_table_hdr, "symbol string table")?;
+      out.push_str(&format!("\nSymbol table '{}' contains {} entries:\n",section_names[i],entries.len()));
+      out.push_str("   Num:    Value  Size Type    Bind   Ndx Name\n");
+      for (n, entry) in entries.into_iter().enumerate(){
+         let mut name = string_at(&str_table, to_native_endianness_32b(&header, &entry.name_index));
+         if name.is_empty() && matches!(Into::<Option<SymbolType>>::into(&entry), Some(SymbolType::Section)){
+            let index = to_native_endianness_16b(&header, &entry.header_index) as usize;
+            name = section_names.get(index).cloned().unwrap_or_default();
+         }
+         out.push_str(&format!(
+            "{:>6}: {:08x} {:>5} {:<7} {:<6} {:>3} {}\n",
+            n,
+            to_native_endianness_32b(&header, &entry.value),
+            to_native_endianness_32b(&header, &entry.size),
+            symbol_type_name(&entry),
+            symbol_binding_name(&entry),
+            section_index_name(to_native_endianness_16b(&header, &entry.header_index)),
+            name
+         ));
+         symbols.push((name, entry));
+      }
+   }
+   if symbols.is_empty(){
+      out.push_str("\nThere are no symbols in this file.\n");
+   }
+
+   // relocatable objects have not been placed yet, every section starts at 0
+   if to_native_endianness_16b(&header, &header.e_type) == 1{
+      out.push_str("\nThere is no vector table in a relocatable file.\n");
+   }else{
+      describe_vector_table(&mut out, &mut reader, &header, &section_headers, &section_names, &symbols, vtor.unwrap_or(0))?;
+   }
+   Ok(out)
+}
+
+fn describe_header(out: &mut String, header: &ElfHeader, eabi: u32){
+   let flags = to_native_endianness_32b(header, &header.cpu_flags);
+   let data = match header.get_elf_endianess(){
+      EIData::Msb if flags & EF_ARM_BE8 > 0 => "big endian (BE8)",
+      EIData::Msb => "big endian (BE32, cannot be simulated)",
+      _ => "little endian"
+   };
+   let e_type = match to_native_endianness_16b(header, &header.e_type){
+      0 => String::from("NONE"),
+      1 => String::from("REL (relocatable file)"),
+      2 => String::from("EXEC (executable file)"),
+      3 => String::from("DYN (shared object file)"),
+      4 => String::from("CORE (core file)"),
+      t => format!("{:#x}",t)
+   };
+   let mut flag_names = vec![if eabi == 0 {String::from("GNU EABI")} else {format!("Version{} EABI",eabi)}];
+   if flags & EF_ARM_ABI_FLOAT_SOFT > 0{
+      flag_names.push(String::from("soft-float ABI"));
+   }
+   if flags & EF_ARM_ABI_FLOAT_HARD > 0{
+      flag_names.push(String::from("hard-float ABI"));
+   }
+   if flags & EF_ARM_BE8 > 0{
+      flag_names.push(String::from("BE8"));
+   }
+   let other = flags & !(EF_ARM_EABIMASK | EF_ARM_ABI_FLOAT_SOFT | EF_ARM_ABI_FLOAT_HARD | EF_ARM_BE8);
+   if other > 0{
+      flag_names.push(format!("<unknown: {:#x}>",other));
+   }
+
+   out.push_str("ELF Header:\n");
+   out.push_str("  Class:                     ELF32\n");
+   out.push_str(&format!("  Data:                      {}\n",data));
+   out.push_str(&format!("  Version:                   {}\n",header.identity[6]));
+   out.push_str(&format!("  Type:                      {}\n",e_type));
+   out.push_str("  Machine:                   ARM\n");
+   out.push_str(&format!("  Entry point address:       {:#x}\n",to_native_endianness_32b(header, &header._entry_point)));
+   out.push_str(&format!("  Flags:                     {:#x}, {}\n",flags,flag_names.join(", ")));
+   out.push_str(&format!("  Size of this header:       {} bytes\n",to_native_endianness_16b(header, &header.elf_header_size_in_bytes)));
+   out.push_str(&format!(
+      "  Program headers:           {} at offset {} ({} bytes each)\n",
+      to_native_endianness_16b(header, &header.num_program_header_entries),
+      to_native_endianness_32b(header, &header.program_header_offset),
+      to_native_endianness_16b(header, &header.program_header_entry_size_in_bytes)
+   ));
+   out.push_str(&format!(
+      "  Section headers:           {} at offset {} ({} bytes each)\n",
+      to_native_endianness_16b(header, &header.num_section_header_entries),
+      to_native_endianness_32b(header, &header.section_header_offset),
+      to_native_endianness_16b(header, &header.section_header_size_in_bytes)
+   ));
+   out.push_str(&format!("  Section name table index:  {}\n",to_native_endianness_16b(header, &header.section_header_table_index)));
+}
+
+fn describe_sections(out: &mut String, header: &ElfHeader, section_headers: &[SectionHeader], names: &[String]){
+   if section_headers.is_empty(){
+      out.push_str("\nThere are no sections in this file.\n");
+      return;
+   }
+   out.push_str("\nSection Headers:\n");
+   out.push_str("  [Nr] Name                 Type            Addr     Off    Size   Flg\n");
+   for (i, hdr) in section_headers.iter().enumerate(){
+      out.push_str(&format!(
+         "  [{:>2}] {:<20} {:<15} {:08x} {:06x} {:06x} {}\n",
+         i,
+         names[i],
+         section_type_name(to_native_endianness_32b(header, &hdr._type)),
+         to_native_endianness_32b(header, &hdr._addr_in_memory_img),
+         to_native_endianness_32b(header, &hdr.offset_of_entries_in_bytes),
+         to_native_endianness_32b(header, &hdr.section_size_in_bytes),
+         section_flag_names(to_native_endianness_32b(header, &hdr.flags))
+      ));
+   }
+   out.push_str("Key to Flags: W (write), A (alloc), X (execute), M (merge), S (strings), I (info), L (link order), G (group)\n");
+}
+
+fn describe_program_headers(out: &mut String, header: &ElfHeader, program_headers: &[ProgramHeader]){
+   if program_headers.is_empty(){
+      out.push_str("\nThere are no program headers in this file.\n");
+      return;
+   }
+   out.push_str("\nProgram Headers:\n");
+   out.push_str("  Type       Offset   VirtAddr   PhysAddr   FileSiz  MemSiz   Flg Align\n");
+   for hdr in program_headers{
+      let flags = to_native_endianness_32b(header, &hdr.flags);
+      let flag_str: String = [(4,'R'),(2,'W'),(1,'E')].iter()
+         .map(|(bit,c)| if flags & bit > 0 {*c} else {' '})
+         .collect();
+      out.push_str(&format!(
+         "  {:<10} {:#08x} {:#010x} {:#010x} {:#07x}  {:#07x}  {} {:#x}\n",
+         program_type_name(to_native_endianness_32b(header, &hdr._type)),
+         to_native_endianness_32b(header, &hdr.offset),
+         to_native_endianness_32b(header, &hdr.virtual_addr),
+         to_native_endianness_32b(header, &hdr.physical_addr),
+         to_native_endianness_32b(header, &hdr.file_size),
+         to_native_endianness_32b(header, &hdr.memory_size),
+         flag_str,
+         to_native_endianness_32b(header, &hdr.alignment)
+      ));
+   }
+}
+
+fn describe_vector_table(
+   out: &mut String,
+   reader: &mut BufReader<File>,
+   header: &ElfHeader,
+   section_headers: &[SectionHeader],
+   names: &[String],
+   symbols: &[(String, SymbolTableEntry)],
+   vtor: u32
+)->Result<(),ElfError>{
+   let holder = section_headers.iter().enumerate().find(|(_,hdr)|{
+      let _type = to_native_endianness_32b(header, &hdr._type);
+      let start = to_native_endianness_32b(header, &hdr._addr_in_memory_img);
+      let size = to_native_endianness_32b(header, &hdr.section_size_in_bytes);
+      _type == SectionHeaderType::PROGBITS as u32 && is_allocated(header, hdr) && vtor >= start && vtor < start.saturating_add(size)
+   });
+   let (i, hdr) = match holder{
+      Some(found) => found,
+      None => {
+         out.push_str(&format!("\nThere is no vector table, no section is loaded at {:#010x}.\n",vtor));
+         return Ok(());
+      }
+   };
+   let start = to_native_endianness_32b(header, &hdr._addr_in_memory_img);
+   let end = start.saturating_add(to_native_endianness_32b(header, &hdr.section_size_in_bytes));
+   let data = read_section_bytes(reader, header, hdr, &names[i])?;
+
+   // the table ends at its symbol's size if it has one, or at the first code in the section
+   let sized = symbols.iter()
+      .find(|(_,sym)| is_in_section(header, sym, i) && symbol_value(header, sym) == vtor && symbol_size(header, sym) > 0)
+      .map(|(_,sym)| vtor + symbol_size(header, sym));
+   let code = symbols.iter()
+      .filter(|(name,sym)| is_in_section(header, sym, i) && (name == "$t" || is_func(sym)))
+      .map(|(_,sym)| symbol_value(header, sym) & !1)
+      .filter(|addr| *addr > vtor)
+      .min();
+   let entries = match sized.or(code){
+      Some(table_end) => ((table_end.min(end) - vtor) / 4).min(MAX_VECTORS),
+      None => ((end - vtor) / 4).min(UNBOUNDED_VECTORS)
+   };
+
+   out.push_str(&format!("\nVector table at {:#010x} in {} ({} entries):\n",vtor,names[i],entries));
+   out.push_str("   Num Exception       Value      Symbol\n");
+   for n in 0 .. entries{
+      let offset = (vtor - start + n * 4) as usize;
+      let word = to_native_endianness_32b(header, &[data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]);
+      let symbol = if n == 0{
+         symbols.iter()
+            .find(|(name,sym)| is_label(name, sym) && symbol_value(header, sym) == word)
+            .map(|(name,_)| name.clone())
+      }else{
+         handler_symbol(header, symbols, word)
+      };
+      let mut note = symbol.unwrap_or_default();
+      if n > 0 && word != 0 && word & 1 == 0{
+         note.push_str(" (missing thumb bit, will fault)");
+      }
+      out.push_str(&format!("  {:>4} {:<15} {:#010x} {}\n",n,vector_name(n),word,note.trim()));
+   }
+   Ok(())
+}
+
+// handler addresses have the thumb bit set, function symbols usually do too
+fn handler_symbol(header: &ElfHeader, symbols: &[(String, SymbolTableEntry)], word: u32)->Option<String>{
+   if word == 0{
+      return None;
+   }
+   let named = |(name,sym): &&(String, SymbolTableEntry)| is_label(name, sym);
+   symbols.iter()
+      .filter(named)
+      .find(|(_,sym)| is_func(sym) && symbol_value(header, sym) == word)
+      .or_else(|| symbols.iter().filter(named).find(|(_,sym)| symbol_value(header, sym) & !1 == word & !1))
+      .map(|(name,_)| name.clone())
+}
+
+fn vector_name(num: u32)->String{
+   match num{
+      0 => "Initial SP".into(),
+      1 => "Reset".into(),
+      2 => "NMI".into(),
+      3 => "HardFault".into(),
+      4 => "MemManage".into(),
+      5 => "BusFault".into(),
+      6 => "UsageFault".into(),
+      11 => "SVCall".into(),
+      12 => "DebugMonitor".into(),
+      14 => "PendSV".into(),
+      15 => "SysTick".into(),
+      7 ..= 13 => "Reserved".into(),
+      _ => format!("IRQ {}",num - 16)
+   }
+}
+
+fn is_allocated(header: &ElfHeader, hdr: &SectionHeader)->bool{
+   to_native_endianness_32b(header, &hdr.flags) & 0x2 > 0
+}
+
+fn is_in_section(header: &ElfHeader, sym: &SymbolTableEntry, index: usize)->bool{
+   to_native_endianness_16b(header, &sym.header_index) as usize == index
+}
+
+// skips mapping symbols ($t, $d) and section or file names
+fn is_label(name: &str, sym: &SymbolTableEntry)->bool{
+   let named = !name.is_empty() && !name.starts_with('$');
+   named && !matches!(Into::<Option<SymbolType>>::into(sym), Some(SymbolType::Section) | Some(SymbolType::File))
+}
+
+fn is_func(sym: &SymbolTableEntry)->bool{
+   matches!(Into::<Option<SymbolType>>::into(sym), Some(SymbolType::Func))
+}
+
+fn symbol_value(header: &ElfHeader, sym: &SymbolTableEntry)->u32{
+   to_native_endianness_32b(header, &sym.value)
+}
+
+fn symbol_size(header: &ElfHeader, sym: &SymbolTableEntry)->u32{
+   to_native_endianness_32b(header, &sym.size)
+}
+
+fn symbol_type_name(sym: &SymbolTableEntry)->String{
+   match Into::<Option<SymbolType>>::into(sym){
+      Some(t) => format!("{:?}",t).to_uppercase(),
+      None => format!("<{}>",sym.info & 0xf)
+   }
+}
+
+fn symbol_binding_name(sym: &SymbolTableEntry)->String{
+   match Into::<Option<SymbolBinding>>::into(sym){
+      Some(b) => format!("{:?}",b).to_uppercase(),
+      None => format!("<{}>",sym.info >> 4)
+   }
+}
+
+fn section_index_name(index: u16)->String{
+   match index{
+      0 => "UND".into(),
+      SHN_ABS => "ABS".into(),
+      SHN_COMMON => "COM".into(),
+      i => i.to_string()
+   }
+}
+
+fn section_type_name(_type: u32)->String{
+   match _type{
+      0 => "NULL".into(),
+      1 => "PROGBITS".into(),
+      2 => "SYMTAB".into(),
+      3 => "STRTAB".into(),
+      4 => "RELA".into(),
+      5 => "HASH".into(),
+      6 => "DYNAMIC".into(),
+      7 => "NOTE".into(),
+      8 => "NOBITS".into(),
+      9 => "REL".into(),
+      11 => "DYNSYM".into(),
+      14 => "INIT_ARRAY".into(),
+      15 => "FINI_ARRAY".into(),
+      0x70000001 => "ARM_EXIDX".into(),
+      0x70000003 => "ARM_ATTRIBUTES".into(),
+      t => format!("{:#x}",t)
+   }
+}
+
+fn section_flag_names(flags: u32)->String{
+   [(0x1,'W'),(0x2,'A'),(0x4,'X'),(0x10,'M'),(0x20,'S'),(0x40,'I'),(0x80,'L'),(0x200,'G')].iter()
+      .filter(|(bit,_)| flags & bit > 0)
+      .map(|(_,c)| *c)
+      .collect()
+}
+
+fn program_type_name(_type: u32)->String{
+   match _type{
+      0 => "NULL".into(),
+      1 => "LOAD".into(),
+      2 => "DYNAMIC".into(),
+      3 => "INTERP".into(),
+      4 => "NOTE".into(),
+      6 => "PHDR".into(),
+      0x70000001 => "EXIDX".into(),
+      t => format!("{:#x}",t)
+   }
+}
@@ -0,0 +1,266 @@
+use std::fs::File;
+use std::io::BufReader;
+
+use crate::asm::assembler::{RelocationType, encode_bl};
+use crate::binutils::sign_extend_u32;
+use crate::elf::decoder::{
+   ElfHeader,
+   ElfError,
+   EIData,
+   SectionHeader,
+   SectionHeaderFlag,
+   SectionHeaderType,
+   SymbolDefinition,
+   SymbolTableEntry,
+   SHN_ABS,
+   get_section_names,
+   get_symbols,
+   get_all_symbol_entries,
+   get_symbol_string_table_hdr,
+   has_no_bytes,
+   is_symbol_table_section_hdr,
+   read_section_bytes,
+   string_at,
+   to_native_endianness_16b,
+   to_native_endianness_32b
+};
+
+// a minimal static linker, it places the sections of one relocatable object (ET_REL) in memory
+// and applies the relocations the assembler and arm-none-eabi-as emit for ARMv6-M code
+
+const ET_REL: u16 = 1;
+const SHT_RELA: u32 = 4;
+const SHT_REL: u32 = 9;
+const SHN_COMMON: u16 = 0xfff2;
+
+// code and read only data are packed from `code`, writable sections from `data`
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct MemoryMap{
+   pub code: u32,
+   pub data: u32
+}
+
+impl Default for MemoryMap{
+   fn default()->Self{
+      Self{code: 0x00000000, data: 0x20000000}
+   }
+}
+
+pub struct LinkedObject{
+   pub sections: Vec<(String, u32, Vec<u8>)>,
+   pub symbols: Vec<SymbolDefinition>,
+   pub entry_point: usize
+}
+
+pub fn is_relocatable(header: &ElfHeader)->bool{
+   to_native_endianness_16b(header, &header.e_type) == ET_REL
+}
+
+pub fn link_relocatable(
+   reader: &mut BufReader<File>,
+   header: &ElfHeader,
+   sect_hdrs: &Vec<SectionHeader>,
+   map: &MemoryMap
+   )->Result<LinkedObject,ElfError>{
+   if header.get_elf_endianess() == EIData::Msb{
+      return Err(ElfError::Arch(String::from("only little endian relocatable objects can be linked")));
+   }
+   let names = get_section_names(reader, header, sect_hdrs)?;
+   let bases = layout(header, sect_hdrs, &names, map)?;
+
+   let mut contents: Vec<Option<Vec<u8>>> = Vec::with_capacity(sect_hdrs.len());
+   for (i, hdr) in sect_hdrs.iter().enumerate(){
+      contents.push(match bases[i]{
+         Some(_) if !has_no_bytes(header, hdr) => Some(read_section_bytes(reader, header, hdr, &names[i])?),
+         _ => None
+      });
+   }
+
+   for (i, hdr) in sect_hdrs.iter().enumerate(){
+      let _type = to_native_endianness_32b(header, &hdr._type);
+      if _type != SHT_REL && _type != SHT_RELA{
+         continue;
+      }
+      let target = to_native_endianness_32b(header, &hdr.info) as usize;
+      // relocations for sections that are not loaded, e.g. debug info
+      if bases.get(target).copied().flatten().is_none() || contents[target].is_none(){
+         continue;
+      }
+      let symtab_index = to_native_endianness_32b(header, &hdr.link) as usize;
+      let symtab = sect_hdrs.get(symtab_index)
+         .filter(|symtab| is_symbol_table_section_hdr(header, symtab))
+         .ok_or(ElfError::MissingTable("symbol table (.symtab)"))?;
+      let symbols = get_all_symbol_entries(reader, header, symtab)?;
+      let str_table = read_section_bytes(reader, header, get_symbol_string_table_hdr(header, sect_hdrs, symtab)?, "symbol string table")?;
+
+      let entry_size = if _type == SHT_RELA { 12 } else { 8 };
+      let entries = read_section_bytes(reader, header, hdr, &names[i])?;
+      for entry in entries.chunks_exact(entry_size){
+         let word = |n: usize| to_native_endianness_32b(header, &[entry[n], entry[n + 1], entry[n + 2], entry[n + 3]]);
+         let offset = word(0);
+         let info = word(4);
+         let addend = if _type == SHT_RELA { Some(word(8) as i32) } else { None };
+         let symbol = symbols.get((info >> 8) as usize).ok_or(ElfError::IndexOutOfRange{
+            what: "relocation symbol",
+            index: info >> 8,
+            count: symbols.len() as u32
+         })?;
+         let s = symbol_address(header, symbol, &str_table, &bases, &names)?;
+         let p = bases[target].unwrap().wrapping_add(offset);
+         let bytes = contents[target].as_mut().unwrap();
+         apply(bytes, offset, p, s, addend, (info & 0xff) as u8).map_err(|msg| ElfError::Relocation{
+            section: names[target].clone(),
+            offset,
+            msg
+         })?;
+      }
+   }
+
+   let mut sections = Vec::new();
+   for (i, data) in contents.into_iter().enumerate(){
+      if let (Some(base), Some(data)) = (bases[i], data){
+         sections.push((names[i].clone(), base, data));
+      }
+   }
+
+   let mut symbols = get_symbols(reader, header, sect_hdrs)?;
+   for symbol in symbols.iter_mut(){
+      if let Some(Some(base)) = bases.get(symbol.section_index as usize){
+         symbol.position += *base as usize;
+      }
+   }
+   symbols.sort_unstable();
+
+   // there is no entry point before linking, run from _start if the object has one
+   let entry_point = symbols.iter()
+      .find(|s| s.name == "_start")
+      .map(|s| s.position)
+      .unwrap_or(map.code as usize);
+   Ok(LinkedObject{sections, symbols, entry_point})
+}
+
+// the address of every allocated section, in file order
+fn layout(header: &ElfHeader, sect_hdrs: &[SectionHeader], names: &[String], map: &MemoryMap)->Result<Vec<Option<u32>>,ElfError>{
+   let mut code = map.code;
+   let mut data = map.data;
+   let mut bases = Vec::with_capacity(sect_hdrs.len());
+   for (i, hdr) in sect_hdrs.iter().enumerate(){
+      let flags = to_native_endianness_32b(header, &hdr.flags);
+      let _type = to_native_endianness_32b(header, &hdr._type);
+      if flags & SectionHeaderFlag::Allocatable as u32 == 0 || _type == SectionHeaderType::NULL as u32{
+         bases.push(None);
+         continue;
+      }
+      let alignment = to_native_endianness_32b(header, &hdr.alignment).max(1);
+      let size = to_native_endianness_32b(header, &hdr.section_size_in_bytes);
+      let cursor = if flags & SectionHeaderFlag::Write as u32 > 0 { &mut data } else { &mut code };
+      // sizes and alignments come straight from the file, a crafted object must not wrap the address space
+      let base = cursor.checked_next_multiple_of(alignment).ok_or_else(|| ElfError::Arch(format!(
+         "cannot align {} to {} bytes from {:#x}, it is past the end of the address space",names[i],alignment,*cursor
+      )))?;
+      let end = base.checked_add(size).ok_or_else(|| ElfError::Arch(format!(
+         "{} ({} bytes) at {:#x} runs past the end of the address space",names[i],size,base
+      )))?;
+      println!("linking {} at {:#x} ({} bytes)",names[i],base,size);
+      bases.push(Some(base));
+      *cursor = end;
+   }
+   Ok(bases)
+}
+
+fn symbol_address(
+   header: &ElfHeader,
+   symbol: &SymbolTableEntry,
+   str_table: &[u8],
+   bases: &[Option<u32>],
+   names: &[String]
+   )->Result<u32,ElfError>{
+   let value = to_native_endianness_32b(header, &symbol.value);
+   let name = || string_at(str_table, to_native_endianness_32b(header, &symbol.name_index));
+   match to_native_endianness_16b(header, &symbol.header_index){
+      0 => Err(ElfError::UndefinedSymbol(name())),
+      SHN_ABS => Ok(value),
+      SHN_COMMON => Err(ElfError::Arch(format!("common symbol {} is not supported, define it in .bss",name()))),
+      index => match bases.get(index as usize){
+         Some(Some(base)) => Ok(base.wrapping_add(value)),
+         Some(None) => Err(ElfError::Arch(format!("symbol {} is in {} which is not loaded",name(),names[index as usize]))),
+         None => Err(ElfError::IndexOutOfRange{what: "symbol section", index: index as u32, count: bases.len() as u32})
+      }
+   }
+}
+
+// REL relocations keep the addend in the bytes being relocated, RELA ones pass it in
+fn apply(bytes: &mut [u8], offset: u32, p: u32, s: u32, addend: Option<i32>, elf_type: u8)->Result<(),String>{
+   let kind = RelocationType::from_elf_type(elf_type).ok_or(format!("unsupported relocation type {}",elf_type))?;
+   let at = offset as usize;
+   let width = match kind{
+      RelocationType::ABS32 | RelocationType::REL32 | RelocationType::THM_CALL => 4,
+      _ => 2
+   };
+   if at + width > bytes.len(){
+      return Err(format!("offset is past the end of the section ({} bytes)",bytes.len()));
+   }
+   let hw = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+   let first = hw(at);
+
+   match kind{
+      RelocationType::ABS32 | RelocationType::REL32 => {
+         let a = addend.unwrap_or(u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]) as i32);
+         let mut v = s.wrapping_add(a as u32);
+         if kind == RelocationType::REL32{
+            v = v.wrapping_sub(p);
+         }
+         bytes[at .. at + 4].copy_from_slice(&v.to_le_bytes());
+      },
+      RelocationType::THM_CALL => {
+         let second = hw(at + 2);
+         let a = addend.unwrap_or_else(|| decode_bl(first, second));
+         let offset = branch(s, a, p, -16777216, 16777214)?;
+         let (first, second) = encode_bl(offset);
+         bytes[at .. at + 2].copy_from_slice(&first.to_le_bytes());
+         bytes[at + 2 .. at + 4].copy_from_slice(&second.to_le_bytes());
+      },
+      RelocationType::THM_JUMP11 => {
+         let a = addend.unwrap_or(sign_extend_u32::<12>((first as u32 & 0x7FF) << 1) as i32);
+         let offset = branch(s, a, p, -2048, 2046)?;
+         let patched = (first & 0xF800) | ((offset >> 1) as u16 & 0x7FF);
+         bytes[at .. at + 2].copy_from_slice(&patched.to_le_bytes());
+      },
+      RelocationType::THM_JUMP8 => {
+         let a = addend.unwrap_or(sign_extend_u32::<9>((first as u32 & 0xFF) << 1) as i32);
+         let offset = branch(s, a, p, -256, 254)?;
+         let patched = (first & 0xFF00) | ((offset >> 1) as u16 & 0xFF);
+         bytes[at .. at + 2].copy_from_slice(&patched.to_le_bytes());
+      },
+      RelocationType::THM_PC8 => {
+         // the in place addend of an LDR/ADR literal is ((imm8 << 2) + 4) & 0x3FF) - 4
+         let a = addend.unwrap_or(((((first as i32 & 0xFF) << 2) + 4) & 0x3FF) - 4);
+         let delta = s as i64 + a as i64 - (p & !3) as i64;
+         if !(0 ..= 1020).contains(&delta) || delta % 4 != 0{
+            return Err(format!("pc relative offset {} is out of range (0-1020, word aligned)",delta));
+         }
+         let patched = (first & 0xFF00) | (delta >> 2) as u16;
+         bytes[at .. at + 2].copy_from_slice(&patched.to_le_bytes());
+      }
+   }
+   Ok(())
+}
+
+// S + A - P for a thumb branch, the thumb bit of S is dropped
+fn branch(s: u32, a: i32, p: u32, min: i64, max: i64)->Result<i32,String>{
+   let offset = (s as i64 + a as i64 - p as i64) & !1;
+   if !(min ..= max).contains(&offset){
+      return Err(format!("branch to {:#x} is out of range ({} bytes)",s,offset));
+   }
+   Ok(offset as i32)
+}
+
+fn decode_bl(first: u16, second: u16)->i32{
+   let s = (first as u32 >> 10) & 1;
+   let j1 = (second as u32 >> 13) & 1;
+   let j2 = (second as u32 >> 11) & 1;
+   let i1 = !(j1 ^ s) & 1;
+   let i2 = !(j2 ^ s) & 1;
+   let imm = s << 24 | i1 << 23 | i2 << 22 | (first as u32 & 0x3FF) << 12 | (second as u32 & 0x7FF) << 1;
+   sign_extend_u32::<25>(imm) as i32
+}
@@ -1 +1,4 @@
 pub mod decoder;
+pub mod encoder;
+pub mod info;
+pub mod linker;
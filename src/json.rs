@@ -0,0 +1,278 @@
+use std::fmt::{Display, Formatter, Write};
+
+// just enough JSON for the debug adapter and state dumps, objects keep their insertion order
+
+#[derive(Clone,Debug,PartialEq)]
+pub enum Json{
+   Null,
+   Bool(bool),
+   Number(f64),
+   Str(String),
+   Array(Vec<Json>),
+   Object(Vec<(String,Json)>)
+}
+
+impl Json{
+   pub fn object(fields: Vec<(&str,Json)>)->Self{
+      Json::Object(fields.into_iter().map(|(k,v)| (k.to_owned(),v)).collect())
+   }
+
+   pub fn get(&self, key: &str)->Option<&Json>{
+      match self{
+         Json::Object(fields) => fields.iter().find(|(k,_)| k.eq(key)).map(|(_,v)| v),
+         _ => None
+      }
+   }
+
+   pub fn as_str(&self)->Option<&str>{
+      match self{
+         Json::Str(s) => Some(s),
+         _ => None
+      }
+   }
+
+   pub fn as_i64(&self)->Option<i64>{
+      match self{
+         Json::Number(n) if n.fract() == 0.0 => Some(*n as i64),
+         _ => None
+      }
+   }
+
+   pub fn as_bool(&self)->Option<bool>{
+      match self{
+         Json::Bool(b) => Some(*b),
+         _ => None
+      }
+   }
+
+   pub fn as_array(&self)->Option<&Vec<Json>>{
+      match self{
+         Json::Array(a) => Some(a),
+         _ => None
+      }
+   }
+
+   pub fn parse(input: &str)->Result<Json,String>{
+      let mut parser = Parser{bytes: input.as_bytes(), pos: 0};
+      let v = parser.value()?;
+      parser.skip_whitespace();
+      if parser.pos != parser.bytes.len(){
+         return Err(format!("trailing characters at {}",parser.pos));
+      }
+      return Ok(v);
+   }
+}
+
+impl From<bool> for Json{
+   fn from(v: bool)->Self{ Json::Bool(v) }
+}
+
+impl From<u32> for Json{
+   fn from(v: u32)->Self{ Json::Number(v as f64) }
+}
+
+impl From<i64> for Json{
+   fn from(v: i64)->Self{ Json::Number(v as f64) }
+}
+
+impl From<u64> for Json{
+   fn from(v: u64)->Self{ Json::Number(v as f64) }
+}
+
+impl From<usize> for Json{
+   fn from(v: usize)->Self{ Json::Number(v as f64) }
+}
+
+impl From<&str> for Json{
+   fn from(v: &str)->Self{ Json::Str(v.to_owned()) }
+}
+
+impl From<String> for Json{
+   fn from(v: String)->Self{ Json::Str(v) }
+}
+
+impl From<Vec<Json>> for Json{
+   fn from(v: Vec<Json>)->Self{ Json::Array(v) }
+}
+
+fn write_escaped(f: &mut Formatter<'_>, s: &str)->std::fmt::Result{
+   f.write_char('"')?;
+   for c in s.chars(){
+      match c{
+         '"' => f.write_str("\\\"")?,
+         '\\' => f.write_str("\\\\")?,
+         '\n' => f.write_str("\\n")?,
+         '\r' => f.write_str("\\r")?,
+         '\t' => f.write_str("\\t")?,
+         c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+         c => f.write_char(c)?
+      }
+   }
+   f.write_char('"')
+}
+
+impl Display for Json{
+   fn fmt(&self, f: &mut Formatter<'_>)->std::fmt::Result{
+      match self{
+         Json::Null => f.write_str("null"),
+         Json::Bool(b) => write!(f, "{}", b),
+         Json::Number(n) if n.fract() == 0.0 && n.abs() < 9.0e15 => write!(f, "{}", *n as i64),
+         Json::Number(n) => write!(f, "{}", n),
+         Json::Str(s) => write_escaped(f, s),
+         Json::Array(items) => {
+            f.write_char('[')?;
+            for (i, item) in items.iter().enumerate(){
+               if i > 0{
+                  f.write_char(',')?;
+               }
+               write!(f, "{}", item)?;
+            }
+            f.write_char(']')
+         },
+         Json::Object(fields) => {
+            f.write_char('{')?;
+            for (i, (k, v)) in fields.iter().enumerate(){
+               if i > 0{
+                  f.write_char(',')?;
+               }
+               write_escaped(f, k)?;
+               write!(f, ":{}", v)?;
+            }
+            f.write_char('}')
+         }
+      }
+   }
+}
+
+struct Parser<'a>{
+   bytes: &'a [u8],
+   pos: usize
+}
+
+impl<'a> Parser<'a>{
+   fn skip_whitespace(&mut self){
+      while self.pos < self.bytes.len() && self.bytes[self.pos].is_ascii_whitespace(){
+         self.pos += 1;
+      }
+   }
+
+   fn peek(&mut self)->Option<u8>{
+      self.skip_whitespace();
+      self.bytes.get(self.pos).copied()
+   }
+
+   fn expect(&mut self, c: u8)->Result<(),String>{
+      if self.peek() == Some(c){
+         self.pos += 1;
+         return Ok(());
+      }
+      return Err(format!("expected '{}' at {}",c as char,self.pos));
+   }
+
+   fn literal(&mut self, word: &str, v: Json)->Result<Json,String>{
+      if self.bytes[self.pos ..].starts_with(word.as_bytes()){
+         self.pos += word.len();
+         return Ok(v);
+      }
+      return Err(format!("unexpected token at {}",self.pos));
+   }
+
+   fn value(&mut self)->Result<Json,String>{
+      match self.peek(){
+         Some(b'{') => self.object(),
+         Some(b'[') => self.array(),
+         Some(b'"') => self.string().map(Json::Str),
+         Some(b't') => self.literal("true", Json::Bool(true)),
+         Some(b'f') => self.literal("false", Json::Bool(false)),
+         Some(b'n') => self.literal("null", Json::Null),
+         Some(c) if c == b'-' || c.is_ascii_digit() => self.number(),
+         Some(_) => Err(format!("unexpected token at {}",self.pos)),
+         None => Err("unexpected end of input".into())
+      }
+   }
+
+   fn object(&mut self)->Result<Json,String>{
+      self.expect(b'{')?;
+      let mut fields = Vec::new();
+      if self.peek() == Some(b'}'){
+         self.pos += 1;
+         return Ok(Json::Object(fields));
+      }
+      loop{
+         self.skip_whitespace();
+         let key = self.string()?;
+         self.expect(b':')?;
+         fields.push((key, self.value()?));
+         match self.peek(){
+            Some(b',') => self.pos += 1,
+            Some(b'}') => { self.pos += 1; return Ok(Json::Object(fields)); },
+            _ => return Err(format!("expected ',' or '}}' at {}",self.pos))
+         }
+      }
+   }
+
+   fn array(&mut self)->Result<Json,String>{
+      self.expect(b'[')?;
+      let mut items = Vec::new();
+      if self.peek() == Some(b']'){
+         self.pos += 1;
+         return Ok(Json::Array(items));
+      }
+      loop{
+         items.push(self.value()?);
+         match self.peek(){
+            Some(b',') => self.pos += 1,
+            Some(b']') => { self.pos += 1; return Ok(Json::Array(items)); },
+            _ => return Err(format!("expected ',' or ']' at {}",self.pos))
+         }
+      }
+   }
+
+   fn string(&mut self)->Result<String,String>{
+      if self.bytes.get(self.pos) != Some(&b'"'){
+         return Err(format!("expected a string at {}",self.pos));
+      }
+      self.pos += 1;
+      let mut out = Vec::new();
+      loop{
+         let c = *self.bytes.get(self.pos).ok_or("unterminated string")?;
+         self.pos += 1;
+         match c{
+            b'"' => break,
+            b'\\' => {
+               let esc = *self.bytes.get(self.pos).ok_or("unterminated string")?;
+               self.pos += 1;
+               match esc{
+                  b'"' | b'\\' | b'/' => out.push(esc),
+                  b'n' => out.push(b'\n'),
+                  b'r' => out.push(b'\r'),
+                  b't' => out.push(b'\t'),
+                  b'b' => out.push(0x8),
+                  b'f' => out.push(0xC),
+                  b'u' => {
+                     let hex = self.bytes.get(self.pos .. self.pos + 4).ok_or("truncated \\u escape")?;
+                     let code = u32::from_str_radix(std::str::from_utf8(hex).map_err(|e| e.to_string())?, 16)
+                        .map_err(|e| e.to_string())?;
+                     self.pos += 4;
+                     let c = char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER);
+                     let mut buf = [0_u8;4];
+                     out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                  },
+                  _ => return Err(format!("invalid escape at {}",self.pos))
+               }
+            },
+            c => out.push(c)
+         }
+      }
+      return String::from_utf8(out).map_err(|e| e.to_string());
+   }
+
+   fn number(&mut self)->Result<Json,String>{
+      let start = self.pos;
+      while self.pos < self.bytes.len() && matches!(self.bytes[self.pos], b'-' | b'+' | b'.' | b'e' | b'E' | b'0' ..= b'9'){
+         self.pos += 1;
+      }
+      let text = std::str::from_utf8(&self.bytes[start .. self.pos]).unwrap();
+      return text.parse::<f64>().map(Json::Number).map_err(|_| format!("invalid number '{}'",text));
+   }
+}
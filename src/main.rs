@@ -5,6 +5,8 @@ mod system;
 mod binutils;
 mod log;
 mod ui;
+mod json;
+mod dap;
 
 #[cfg(test)]
 mod tests;
@@ -16,18 +18,37 @@ use iced::Application;
 use ui::parse_hex;
 
 use crate::asm::interpreter::{print_assembly, disasm_text};
-use crate::elf::decoder::{get_string_table_section_hdr, is_symbol_table_section_hdr, get_section_symbols, get_entry_point_offset, get_all_symbol_names};
+use crate::elf::decoder::get_entry_point_offset;
+use crate::elf::linker::{MemoryMap, is_relocatable, link_relocatable};
 use crate::system::System;
+use crate::system::simulator::Machine;
 use crate::ui::App;
+use crate::ui::tui::Tui;
+use crate::dap::DapServer;
+use crate::system::dump::StateDump;
+use crate::system::stimulus::Schedule;
+use crate::system::profile::Profile;
 
 struct Args{
-   pub elf: PathBuf,
+   pub elfs: Vec<PathBuf>,
+   pub boot_elf: usize,
+   pub memory_map: MemoryMap,
    pub sp_reset_val: Option<u32>,
    pub vtor_override: Option<u32>,
    pub entry_point_override: Option<u32>,
    pub opt_load_section: Option<(String,u32)>,
    pub opt_jump: Option<u32>,
-   pub manual_boot: bool
+   pub manual_boot: bool,
+   pub rp2040: bool,
+   pub profile: Profile,
+   pub tui: bool,
+   pub no_debugger: bool,
+   pub dap_port: Option<u16>,
+   // instructions per second of wall clock time, None runs flat out
+   pub clock_hz: Option<u64>,
+   pub state_dump: Option<StateDump>,
+   pub schedule_file: Option<PathBuf>,
+   pub stimuli: Vec<String>
 }
 
 #[derive(Debug)]
@@ -40,7 +61,8 @@ fn main() {
 }
 
 const HELP_MSG: &'static str =  concat!(
-   "Usage: armageddon <FILE> <OPTIONS>\n",
+   "Usage: armageddon <FILE>.. <OPTIONS>\n",
+   "       armageddon info <FILE> [--vtor=<HEX>]   print the headers, sections, symbols and vector table of an ELF\n",
    "-h,--help               show this message\n",
    "\n",
    "--manual-boot           when this flag is passed the simulator will not execute the reset handler during startup\n",
@@ -57,7 +79,40 @@ const HELP_MSG: &'static str =  concat!(
    "\n",
    "--load-mem=<FILE:HEX>   specify a raw binary file to be mapped into memory at a specific hex address\n",
    "\n",
-   "--jump-to=<HEX>  jump to an address after boot\n"
+   "--load-elf=<FILE>       load another ELF into the same memory, can be repeated and combined with ELF arguments\n",
+   "\n",
+   "--boot-elf=<FILE>       the loaded ELF that supplies the entry point and vector table, the first ELF by default.\n",
+   "                        when several ELFs are loaded VTOR points at the lowest loaded address of this ELF\n",
+   "\n",
+   "--link-map=<HEX:HEX>    where relocatable objects (.o) are linked, code and read only data are placed from the first\n",
+   "                        address and writable sections from the second, 0x0:0x20000000 by default\n",
+   "\n",
+   "--jump-to=<HEX>  jump to an address after boot\n",
+   "\n",
+   "--rp2040                simulate a dual core RP2040, core1 waits in the bootrom until it is launched\n",
+   "                        by core0 through the SIO FIFO (see multicore_launch_core1)\n",
+   "\n",
+   "--arch=<NAME>           the architecture to execute, armv6-m (default, also cortex-m0/cortex-m0+)\n",
+   "                        armv7-m (also cortex-m3) which adds the Thumb-2 instruction set\n",
+   "                        or armv8-m.base (also cortex-m23) which adds a few 32 bit instructions and the MPU\n",
+   "\n",
+   "--tui,--cli             run the debugger from a terminal prompt instead of opening a window\n",
+   "\n",
+   "--no-debugger           run as if no debugger were attached, DHCSR.C_DEBUGEN reads 0 and BKPT escalates to HardFault\n",
+   "\n",
+   "--dap=<PORT>            serve the Debug Adapter Protocol on 127.0.0.1:<PORT> instead of opening a window\n",
+   "\n",
+   "--clock-hz=<N>          run at N instructions per second of wall clock time instead of as fast as possible,\n",
+   "                        so SysTick and other timed events happen at a watchable rate\n",
+   "\n",
+   "--dump-state-on-halt=<FILE>  write the registers, exception states and memory as JSON whenever the simulator halts\n",
+   "\n",
+   "--dump-mem=<HEX:HEX,..>  inclusive memory ranges to include in state dumps, e.g. 0x20000000:0x200000FF\n",
+   "\n",
+   "--schedule=<FILE>       inject interrupts and events into core 0 from a schedule file, one stimulus per line\n",
+   "                        e.g. \"pend irq 5 at instruction 10000\" or \"trigger nmi when pc == handler_x\"\n",
+   "\n",
+   "--stimulus=<LINE;..>    add stimuli to the schedule from the command line, separated by ';'\n"
 );
 
 fn gui_diasm(){
@@ -70,11 +125,18 @@ fn gui_diasm(){
       std::process::exit(0);
    }
 
+   if args.len() > 1 && args[1] == "info"{
+      elf_info(&args);
+   }
+
    let cli_arg = parse_args(args).unwrap(); 
-   let maybe_instructions  = load_instruction_opcodes(&cli_arg.elf,cli_arg.opt_load_section.clone());
+   let maybe_instructions  = load_instruction_opcodes(&cli_arg.elfs, cli_arg.boot_elf, &cli_arg.memory_map, cli_arg.opt_load_section.clone());
    exit_on_err(&maybe_instructions);
 
    let (disasm, mut entry_point, symbol_map, mut sys) = maybe_instructions.unwrap();
+   sys.profile = cli_arg.profile;
+   println!("architecture: {}",sys.profile.name());
+   println!("data endianness: {}",if sys.is_big_endian() {"big (BE8)"} else {"little"});
    println!("sys memory image: 0 -> {} pages ",sys.alloc.pages());
    println!("{} symbols defined",symbol_map.len());
 
@@ -99,6 +161,32 @@ fn gui_diasm(){
 
    sys.scs.wfi_wake_up = false;
 
+   let mut schedule = String::new();
+   if let Some(path) = &cli_arg.schedule_file{
+      match std::fs::read_to_string(path){
+         Ok(text) => schedule.push_str(&text),
+         Err(e) => {
+            println!("could not read schedule {}: {}",path.display(),e);
+            std::process::exit(-1);
+         }
+      }
+      schedule.push('\n');
+   }
+   for line in cli_arg.stimuli.iter(){
+      schedule.push_str(line);
+      schedule.push('\n');
+   }
+   match Schedule::parse(&schedule, &symbol_map){
+      Ok(s) => {
+         println!("{} stimuli scheduled",s.stimuli.len());
+         sys.stimuli = s;
+      },
+      Err(e) => {
+         println!("invalid schedule: {}",e);
+         std::process::exit(-1);
+      }
+   }
+
    if !cli_arg.manual_boot{
       println!("system boot type: RESET");
       sys.reset();
@@ -129,7 +217,30 @@ fn gui_diasm(){
       msg.push_str(&i);
       msg.push('\n');
    }
-   let flags = (sys,entry_point,symbol_map, msg);
+   if cli_arg.rp2040 && sys.is_big_endian(){
+      println!("the RP2040 is little endian, it cannot run a BE8 image");
+      std::process::exit(-1);
+   }
+   let machine = if cli_arg.rp2040{
+      println!("machine: dual core RP2040");
+      Machine::rp2040(sys)
+   }else{
+      Machine::single(sys)
+   };
+   // every front end is a debugger unless asked to behave like a detached board
+   machine.attach_debugger(!cli_arg.no_debugger);
+   if cli_arg.tui{
+      Tui::create(machine, symbol_map, msg, cli_arg.state_dump, cli_arg.clock_hz).run();
+      return;
+   }
+   if let Some(port) = cli_arg.dap_port{
+      if let Err(e) = DapServer::create(machine, symbol_map, msg, cli_arg.state_dump).listen(port){
+         println!("DAP server failed: {}",e);
+         std::process::exit(-1);
+      }
+      return;
+   }
+   let flags = (machine,entry_point,symbol_map, msg, cli_arg.state_dump, cli_arg.clock_hz);
    App::run(iced::Settings::with_flags(flags)).unwrap();
 }
 
@@ -168,12 +279,23 @@ fn get_first_parameter_arg<'a>(args: &'a Vec<String>,name: &str)->Result<Option<
 }
 
 fn parse_args(args: Vec<String>)->Result<Args,ParseErr>{
-   if args.len()  < 2{
-      dbg_ln!("you must provide one elf file");
-      return Err(ParseErr(String::from("you must provide one elf file")));
+   // every argument that is not an option is an ELF, as is every --load-elf=
+   let mut elfs: Vec<PathBuf> = args.iter().skip(1)
+      .filter(|a| !a.starts_with('-'))
+      .map(PathBuf::from)
+      .collect();
+   elfs.extend(args.iter().filter_map(|a| a.strip_prefix("--load-elf=")).map(PathBuf::from));
+   if elfs.is_empty(){
+      dbg_ln!("you must provide at least one elf file");
+      return Err(ParseErr(String::from("you must provide at least one elf file")));
    }
-
-   let maybe_file = PathBuf::from(&args[1]);
+   let boot_elf = match get_first_parameter_arg(&args, "--boot-elf=")?{
+      Some(boot) => match elfs.iter().position(|elf| elf == Path::new(boot)){
+         Some(i) => i,
+         None => return Err(ParseErr(format!("--boot-elf={} is not one of the loaded ELF files",boot)))
+      },
+      None => 0
+   };
    let maybe_reset_val = args.iter().position(|a| a.starts_with("--sp-reset-val="));
    let mut reset_val = None;
    match maybe_reset_val{
@@ -191,10 +313,58 @@ fn parse_args(args: Vec<String>)->Result<Args,ParseErr>{
 
 
    let manual_boot = args.contains(&String::from("--manual-boot"));
+   let rp2040 = args.contains(&String::from("--rp2040"));
+   let profile = match get_first_parameter_arg(&args, "--arch=")?{
+      Some(name) => match Profile::from_name(name){
+         Some(p) => p,
+         None => return Err(ParseErr(format!("{} is not a supported architecture",name)))
+      },
+      None => Profile::default()
+   };
+   if rp2040 && profile != Profile::ArmV6M{
+      return Err(ParseErr(String::from("the RP2040 cores are ARMv6-M, --rp2040 cannot be combined with another --arch")));
+   }
+   let tui = args.contains(&String::from("--tui")) | args.contains(&String::from("--cli"));
+   let no_debugger = args.contains(&String::from("--no-debugger"));
    let maybe_vtor = get_optional_hex(&args, "--vtor=")?;
    let maybe_entry_point = get_optional_hex(&args, "--entrypoint=")?;
    let maybe_jump = get_optional_hex(&args, "--jump-to=")?;
    let maybe_load_opt = get_first_parameter_arg(&args, "--load-mem=")?;
+   let dump_ranges = match get_first_parameter_arg(&args, "--dump-mem=")?{
+      Some(ranges) => match parse_ranges(ranges){
+         Some(r) => r,
+         None => return Err(ParseErr(format!("{} is an invalid list of memory ranges",ranges)))
+      },
+      None => Vec::new()
+   };
+   let state_dump = get_first_parameter_arg(&args, "--dump-state-on-halt=")?
+      .map(|path| StateDump{path: PathBuf::from(path), ranges: dump_ranges});
+   let schedule_file = get_first_parameter_arg(&args, "--schedule=")?.map(PathBuf::from);
+   let stimuli = match get_first_parameter_arg(&args, "--stimulus=")?{
+      Some(lines) => lines.split(';').filter(|l| !l.trim().is_empty()).map(|l| l.trim().to_owned()).collect(),
+      None => Vec::new()
+   };
+   let dap_port = match get_first_parameter_arg(&args, "--dap=")?{
+      Some(port) => match port.parse::<u16>(){
+         Ok(p) => Some(p),
+         Err(_) => return Err(ParseErr(format!("{} is an invalid port",port)))
+      },
+      None => None
+   };
+   let clock_hz = match get_first_parameter_arg(&args, "--clock-hz=")?{
+      Some(hz) => match hz.parse::<u64>(){
+         Ok(n) if n > 0 => Some(n),
+         _ => return Err(ParseErr(format!("{} is an invalid clock rate, expected a number of Hz above 0",hz)))
+      },
+      None => None
+   };
+   let memory_map = match get_first_parameter_arg(&args, "--link-map=")?{
+      Some(pair) => match pair.split_once(':').and_then(|(code, data)| Some((parse_hex(code)?, parse_hex(data)?))){
+         Some((code, data)) => MemoryMap{code, data},
+         None => return Err(ParseErr(format!("{} is an invalid memory map, expected <HEX:HEX>",pair)))
+      },
+      None => MemoryMap::default()
+   };
    let load_sec_arg = match maybe_load_opt{
       Some(pair) => {
          match pair.split_once(':'){
@@ -213,16 +383,42 @@ fn parse_args(args: Vec<String>)->Result<Args,ParseErr>{
    };
 
    Ok(Args { 
-      elf: maybe_file,
+      elfs,
+      boot_elf,
+      memory_map,
       sp_reset_val: reset_val,
       vtor_override: maybe_vtor,
       entry_point_override: maybe_entry_point,
       opt_load_section: load_sec_arg,
       opt_jump: maybe_jump,
-      manual_boot
+      manual_boot,
+      rp2040,
+      profile,
+      tui,
+      no_debugger,
+      dap_port,
+      clock_hz,
+      state_dump,
+      schedule_file,
+      stimuli
    })
 }
 
+// parses start:end pairs separated by commas, e.g. 0x20000000:0x200000FF
+fn parse_ranges(input: &str)->Option<Vec<(u32,u32)>>{
+   let mut ranges = Vec::new();
+   for pair in input.split(',').filter(|p| !p.trim().is_empty()){
+      let (start, end) = pair.split_once(':')?;
+      let start = parse_hex(start.trim())?;
+      let end = parse_hex(end.trim())?;
+      if end < start{
+         return None;
+      }
+      ranges.push((start, end));
+   }
+   return Some(ranges);
+}
+
 fn cli_disasm(){
    let args: Vec<String> = std::env::args().collect();
 
@@ -232,8 +428,12 @@ fn cli_disasm(){
       std::process::exit(0);
    }
 
+   if args.len() > 1 && args[1] == "info"{
+      elf_info(&args);
+   }
+
    let cli_arg = parse_args(args).unwrap(); 
-   let maybe_instructions  = load_instruction_opcodes(&cli_arg.elf, None);
+   let maybe_instructions  = load_instruction_opcodes(&cli_arg.elfs, cli_arg.boot_elf, &cli_arg.memory_map, None);
    exit_on_err(&maybe_instructions);
 
    //let (instructions, entry_point, symbol_map) = maybe_instructions.unwrap();
@@ -273,44 +473,96 @@ fn asm_file_to_elf(path: &Path)->Result<PathBuf,std::io::Error>{
 }
 */
 
-fn load_instruction_opcodes(file: &Path, extra_sec: Option<(String,u32)>)->Result<(Vec<String>, usize, Vec<SymbolDefinition>, System),ElfError>{
+// one ELF file loaded into memory, several of them can share a system
+struct ElfImage{
+   name: String,
+   sections: Vec<(String, u32, Vec<u8>)>,
+   disasm: Vec<String>,
+   symbols: Vec<SymbolDefinition>,
+   entry_point: usize,
+   big_endian: bool
+}
+
+fn load_elf_image(file: &Path, map: &MemoryMap)->Result<ElfImage,ElfError>{
    use crate::elf::decoder::{
-      SectionHeader,
       get_header,
       get_all_section_headers,
       get_loadable_sections,
+      get_symbols,
       load_sections
    };
    let (elf_header,mut reader) = get_header(file)?;
+   let big_endian = elf_header.is_big_endian_image()?;
 
    let section_headers = get_all_section_headers(&mut reader, &elf_header)?;
    dbg_ln!("sect_hdrs {:?}",section_headers);
-   assert!(!section_headers.is_empty());
-
-   let strtab_idx = get_string_table_section_hdr(&elf_header, &section_headers).unwrap();
-   let str_table_hdr = &section_headers[strtab_idx];
-
-   let maybe_symtab: Vec<&SectionHeader> = section_headers.iter()
-      .filter(|hdr| is_symbol_table_section_hdr(&elf_header, hdr))
-      .collect();
-
-   let sym_entries = get_section_symbols(&mut reader, &elf_header, &maybe_symtab[0]).unwrap();
-
+   if section_headers.is_empty(){
+      return Err(ElfError::MissingTable("section header table"));
+   }
 
-   //let text_section_symbols = get_text_section_symbols(&elf_header, &section_headers, &sym_entries).unwrap();
-   //let names = get_matching_symbol_names(&mut reader, &elf_header, &text_section_symbols, &str_table_hdr).unwrap();
-   //let text_sect_offset_map = build_symbol_byte_offset_map(&elf_header, names, &sym_entries);
-   let symbols = get_all_symbol_names(&mut reader, &elf_header, &sym_entries, str_table_hdr).unwrap();
+   let (sections, symbols, entry_point) = if is_relocatable(&elf_header){
+      println!("linking relocatable object {}",file.display());
+      let linked = link_relocatable(&mut reader, &elf_header, &section_headers, map)?;
+      (linked.sections, linked.symbols, linked.entry_point)
+   }else{
+      //let text_section_symbols = get_text_section_symbols(&elf_header, &section_headers, &sym_entries).unwrap();
+      //let names = get_matching_symbol_names(&mut reader, &elf_header, &text_section_symbols, &str_table_hdr).unwrap();
+      //let text_sect_offset_map = build_symbol_byte_offset_map(&elf_header, names, &sym_entries);
+      let symbols = get_symbols(&mut reader, &elf_header, &section_headers)?;
 
-   let loadable = get_loadable_sections(&mut reader, &elf_header,&section_headers)?;
+      let loadable = get_loadable_sections(&mut reader, &elf_header,&section_headers)?;
 
-   let mut section_data = load_sections(&mut reader, &elf_header, &section_headers, loadable)?;
+      let sections = load_sections(&mut reader, &elf_header, &section_headers, loadable)?;
+      (sections, symbols, get_entry_point_offset(&elf_header))
+   };
 
-   let t = section_data.iter().position(|(name,_,_)|name.eq(".text")).expect("ELF file did not specify a .text section ???");
+   let t = sections.iter().position(|(name,_,_)|name.eq(".text")).ok_or(ElfError::MissingSection(String::from(".text")))?;
 
-   let (_,text_offset,text_data) = &section_data[t];
+   let (_,text_offset,text_data) = &sections[t];
    let disasm = disasm_text(text_data, *text_offset as usize, &symbols);
-   let entry_point = get_entry_point_offset(&elf_header);
+   Ok(ElfImage{
+      name: file.display().to_string(),
+      disasm,
+      symbols,
+      entry_point,
+      big_endian,
+      sections
+   })
+}
+
+// loads every ELF into one system, the boot ELF supplies the entry point
+fn load_instruction_opcodes(
+      files: &[PathBuf],
+      boot: usize,
+      map: &MemoryMap,
+      extra_sec: Option<(String,u32)>
+   )->Result<(Vec<String>, usize, Vec<SymbolDefinition>, System),ElfError>{
+   let mut images = Vec::new();
+   for file in files{
+      images.push(load_elf_image(file, map)?);
+   }
+   check_images(&images)?;
+
+   let boot_image = &images[boot];
+   let entry_point = boot_image.entry_point;
+   let big_endian = boot_image.big_endian;
+   // the vector table is assumed to be the first thing in the boot image
+   let boot_vtor = boot_image.sections.iter().map(|(_,start,_)| *start).min().unwrap_or(0);
+
+   let multiple = images.len() > 1;
+   let mut disasm = Vec::new();
+   let mut symbols = Vec::new();
+   let mut section_data = Vec::new();
+   for image in images.into_iter(){
+      if multiple{
+         let start = image.sections.iter().map(|(_,start,_)| *start).min().unwrap_or(0);
+         disasm.push(format!("\n{:#010x}: <image {}, entry point {:#x}>",start,image.name,image.entry_point));
+      }
+      disasm.extend(image.disasm);
+      symbols.extend(image.symbols);
+      section_data.extend(image.sections);
+   }
+   symbols.sort_unstable();
 
    if let Some((fpath,addr)) = extra_sec{
       let mut fhandle = std::fs::File::open(&fpath)?;
@@ -321,10 +573,55 @@ fn load_instruction_opcodes(file: &Path, extra_sec: Option<(String,u32)>)->Resul
    }
 
    let mut sys = System::with_sections(section_data);
+   sys.set_endianness(big_endian);
+   if multiple{
+      println!("booting {}",files[boot].display());
+      sys.set_vtor(boot_vtor);
+   }
    sys.set_pc(entry_point & (!1)).unwrap();
    Ok((disasm, entry_point, symbols, sys))
 }
 
+// images must agree on their byte order and must not load over each other
+fn check_images(images: &[ElfImage])->Result<(),ElfError>{
+   for (i, image) in images.iter().enumerate(){
+      for other in images[i + 1 ..].iter(){
+         if image.big_endian != other.big_endian{
+            return Err(ElfError::Arch(format!("{} and {} have different data endianness",image.name,other.name)));
+         }
+         for (name,start,data) in image.sections.iter(){
+            let end = *start as u64 + data.len() as u64;
+            let overlap = other.sections.iter().find(|(_,o_start,o_data)|{
+               (*o_start as u64) < end && (*start as u64) < *o_start as u64 + o_data.len() as u64
+            });
+            if let Some((o_name,o_start,_)) = overlap{
+               return Err(ElfError::Arch(format!(
+                  "{} of {} at {:#x} overlaps {} of {} at {:#x}",name,image.name,start,o_name,other.name,o_start
+               )));
+            }
+         }
+      }
+   }
+   Ok(())
+}
+
+// armageddon info <FILE> [--vtor=<HEX>], describes an ELF without simulating it
+fn elf_info(args: &Vec<String>){
+   let maybe_file = args.get(2).filter(|a| !a.starts_with("--"));
+   let vtor = get_optional_hex(args, "--vtor=");
+   match (maybe_file, vtor){
+      (Some(file), Ok(vtor)) => {
+         let report = elf::info::describe(Path::new(file), vtor);
+         exit_on_err(&report);
+         print!("{}",report.unwrap());
+         std::process::exit(0);
+      },
+      (None, _) => println!("missing ELF file\n{}",HELP_MSG),
+      (_, Err(ParseErr(msg))) => println!("{}",msg)
+   }
+   std::process::exit(-1);
+}
+
 fn exit_on_err<T>(maybe_err: &Result<T,ElfError>){
    match maybe_err{
       Err(e) => {println!("{}",e); std::process::exit(-1);},
@@ -0,0 +1,608 @@
+use std::num::Wrapping;
+
+use crate::asm::decode::{Opcode, instruction_size, InstructionSize, B16, B32, DataOp, Width};
+use crate::asm::decode_operands::{Operands, Shift, Address};
+use crate::binutils::{from_arm_bytes, into_arm_bytes, from_arm_bytes_16b, from_data_bytes, get_set_bits, sign_extend_u32};
+use super::registers::SpecialRegister;
+use super::instructions::{adc_flags, carry_flag, overflow_flag, condition_holds, shift_c, ConditionFlags};
+use super::{System, ArmException, MemoryMappedRegister, load_memory, write_memory, load_thumb_instr, fault_if_not_aligned, is_aligned};
+
+// ARMv7-M (Cortex-M3) execution, the 32 bit Thumb-2 instructions, IT blocks and the usage fault status bits.
+// configurable faults are never enabled in SHCSR here, every fault escalates to HardFault with HFSR.FORCED set
+
+pub const UFSR_UNDEFINSTR: u32 = 1 << 16;
+pub const UFSR_INVSTATE: u32 = 1 << 17;
+pub const UFSR_UNALIGNED: u32 = 1 << 24;
+pub const UFSR_DIVBYZERO: u32 = 1 << 25;
+pub const HFSR_FORCED: u32 = 1 << 30;
+pub const CCR_UNALIGN_TRP: u32 = 1 << 3;
+pub const CCR_DIV_0_TRP: u32 = 1 << 4;
+pub const CCR_STKALIGN: u32 = 1 << 9;
+// NONBASETHRDENA, USERSETMPEND, UNALIGN_TRP, DIV_0_TRP, BFHFNMIGN and STKALIGN
+pub const CCR_MASK: u32 = 0x31B;
+// the IT[7:0] bits are split across xPSR[26:25] and xPSR[15:10]
+pub const EPSR_IT_MASK: u32 = 0x0600FC00;
+const APSR_Q: u32 = 1 << 27;
+const APSR_NZCV: u32 = 0xF0000000;
+
+// inside an IT block the 16 bit flag setting data processing instructions leave the flags alone
+fn sets_flags_outside_it(code: &Opcode)->bool{
+   matches!(code,
+      Opcode::_16Bit(B16::ADCS) | Opcode::_16Bit(B16::ADD_Imm3) | Opcode::_16Bit(B16::ADD_Imm8)
+      | Opcode::_16Bit(B16::ADDS_REG) | Opcode::_16Bit(B16::ANDS) | Opcode::_16Bit(B16::ASRS_Imm5)
+      | Opcode::_16Bit(B16::ASRS_REG) | Opcode::_16Bit(B16::BIT_CLEAR_REGISTER) | Opcode::_16Bit(B16::XOR_REG)
+      | Opcode::_16Bit(B16::LSL_Imm5) | Opcode::_16Bit(B16::LSL_REGS) | Opcode::_16Bit(B16::LSR_Imm5)
+      | Opcode::_16Bit(B16::LSR_REGS) | Opcode::_16Bit(B16::MOV_Imm8) | Opcode::_16Bit(B16::MOV_REGS_T2)
+      | Opcode::_16Bit(B16::MUL) | Opcode::_16Bit(B16::MVN) | Opcode::_16Bit(B16::ORR)
+      | Opcode::_16Bit(B16::ROR) | Opcode::_16Bit(B16::RSB) | Opcode::_16Bit(B16::SBC)
+      | Opcode::_16Bit(B16::SUB_Imm3) | Opcode::_16Bit(B16::SUB_Imm8) | Opcode::_16Bit(B16::SUB_REG)
+   )
+}
+
+fn exclusive_width(code: &B32)->Width{
+   match code{
+      B32::LDREXB | B32::STREXB => Width::Byte,
+      B32::LDREXH | B32::STREXH => Width::HalfWord,
+      B32::LDAEX(width) | B32::STLEX(width) => *width,
+      _ => Width::Word
+   }
+}
+
+fn logical_flags(result: u32, carry: bool, overflow: bool)->ConditionFlags{
+   ConditionFlags{
+      negative: result & 0x80000000 > 0,
+      zero: result == 0,
+      carry,
+      overflow
+   }
+}
+
+fn target_address(base: u32, address: &Address, index: u32)->(u32,Option<u32>){
+   match address{
+      Address::Offset(imm) => ((Wrapping(base) + Wrapping(*imm as u32)).0, None),
+      Address::PreIndexed(imm) => {
+         let addr = (Wrapping(base) + Wrapping(*imm as u32)).0;
+         (addr, Some(addr))
+      },
+      Address::PostIndexed(imm) => (base, Some((Wrapping(base) + Wrapping(*imm as u32)).0)),
+      Address::Shifted(_, shift) => ((Wrapping(base) + Wrapping(index << shift)).0, None)
+   }
+}
+
+impl System{
+   pub fn step(&mut self)->Result<i32, ArmException>{
+      if !self.profile.has_main_extension() || !self.in_it_block() || self.is_locked_up() || !self.epsr_t_bit(){
+         return self.observed_step();
+      }
+
+      let state = self.it_state();
+      let hw = load_thumb_instr(self, self.registers.pc as u32)?;
+      let size = instruction_size(hw);
+      if !condition_holds(from_arm_bytes(self.xpsr), state >> 4){
+         if self.trace_enabled{
+            self.trace.push_str("==IT condition failed, instruction skipped==\n");
+         }
+         self.advance_it();
+         return Ok(size.in_bytes() as i32);
+      }
+
+      let keep_flags = matches!(size, InstructionSize::B16) && sets_flags_outside_it(&Opcode::from(hw));
+      let flags = from_arm_bytes(self.xpsr) & APSR_NZCV;
+      let result = self.observed_step();
+      if keep_flags{
+         let xpsr = (from_arm_bytes(self.xpsr) & !APSR_NZCV) | flags;
+         self.xpsr = into_arm_bytes(xpsr);
+      }
+      // exception entry and return replace the IT state themselves
+      if result.is_ok() && self.it_state() == state && !self.sleep.waiting{
+         self.advance_it();
+      }
+      result
+   }
+
+   pub(crate) fn it_state(&self)->u8{
+      let xpsr = from_arm_bytes(self.xpsr);
+      (((xpsr >> 25) & 3) | ((xpsr >> 8) & 0xFC)) as u8
+   }
+
+   pub(crate) fn set_it_state(&mut self, state: u8){
+      let it = state as u32;
+      let xpsr = (from_arm_bytes(self.xpsr) & !EPSR_IT_MASK) | ((it & 3) << 25) | ((it & 0xFC) << 8);
+      self.xpsr = into_arm_bytes(xpsr);
+   }
+
+   pub(crate) fn in_it_block(&self)->bool{
+      self.it_state() & 0xF != 0
+   }
+
+   fn advance_it(&mut self){
+      let state = self.it_state();
+      if state & 0x7 == 0{
+         self.set_it_state(0);
+      }else{
+         self.set_it_state((state & 0xE0) | ((state << 1) & 0x1F));
+      }
+   }
+
+   // sets the usage fault status, the fault itself is raised by the caller as a HardFault
+   pub(crate) fn record_usage_fault(&mut self, status: u32){
+      if !self.profile.has_main_extension(){
+         return;
+      }
+      self.scs.cfsr |= status;
+      self.scs.hfsr |= HFSR_FORCED;
+   }
+
+   pub(crate) fn unsupported_instruction(&mut self, code: &Opcode)->Result<i32, ArmException>{
+      let priority = self.execution_priority(self.primask, &self.scs);
+      if priority == -1 || priority == -2{
+         self.lockup(&format!("{} is not part of the {} instruction set",code,self.profile.name()));
+         return Ok(0);
+      }
+      println!("WARN: {} is not part of the {} instruction set, it will result in a hardfault",code,self.profile.name());
+      self.record_usage_fault(UFSR_UNDEFINSTR);
+      return Err(ArmException::HardFault(format!("{} is not part of the {} instruction set",code,self.profile.name())));
+   }
+
+   // BASEPRI, BASEPRI_MAX and FAULTMASK need the main extension, the reserved SYSm values never exist
+   pub(crate) fn special_register_available(&self, special: &SpecialRegister)->bool{
+      match special{
+         SpecialRegister::Reserved(_) => false,
+         _ => !special.needs_main_extension() || self.profile.has_main_extension()
+      }
+   }
+
+   pub(crate) fn unavailable_special_register(&mut self, special: &SpecialRegister)->Result<i32, ArmException>{
+      let priority = self.execution_priority(self.primask, &self.scs);
+      if priority == -1 || priority == -2{
+         self.lockup(&format!("{:?} is not available on {}",special,self.profile.name()));
+         return Ok(0);
+      }
+      self.record_usage_fault(UFSR_UNDEFINSTR);
+      Err(ArmException::HardFault(format!("{:?} is not available on {}",special,self.profile.name())))
+   }
+
+   // MSR and CPS writes to the priority masks, FAULTMASK cannot be set from the HardFault or NMI handlers
+   pub(crate) fn write_priority_mask(&mut self, special: &SpecialRegister, v: u32){
+      let masked = (v & special.mask()) as u8;
+      match special{
+         SpecialRegister::BASEPRI => self.basepri = masked,
+         SpecialRegister::BASEPRI_MAX => {
+            if masked != 0 && (self.basepri == 0 || masked < self.basepri){
+               self.basepri = masked;
+            }
+         },
+         SpecialRegister::FAULTMASK => {
+            if masked == 0 || self.execution_priority(self.primask, &self.scs) > -1{
+               self.faultmask = masked > 0;
+            }
+         },
+         _ => unreachable!("{:?} is not a priority mask",special)
+      }
+   }
+
+   pub(crate) fn step_thumb2_16b(&mut self, code: &B16, operands: Option<Operands>)->Result<i32, ArmException>{
+      match (code, operands){
+         (B16::CBZ, Some(Operands::CBZ(n, offset))) => {
+            if self.registers.generic[n.0 as usize] == 0 {Ok(offset)} else {Ok(2)}
+         },
+         (B16::CBNZ, Some(Operands::CBZ(n, offset))) => {
+            if self.registers.generic[n.0 as usize] != 0 {Ok(offset)} else {Ok(2)}
+         },
+         (B16::IT, Some(Operands::IT(firstcond, mask))) => {
+            if self.in_it_block(){
+               return Err(ArmException::HardFault("IT inside an IT block is UNPREDICTABLE".into()));
+            }
+            self.set_it_state((firstcond << 4) | mask);
+            Ok(2)
+         },
+         (op, args) => panic!("{:?} is not a thumb-2 16 bit instruction or has the wrong operands {:?}",op,args)
+      }
+   }
+
+   pub(crate) fn step_thumb2(&mut self, code: &B32, operands: Option<Operands>)->Result<i32, ArmException>{
+      match (code, operands){
+         (B32::DATA_Imm(op, setflags), Some(Operands::DataImm(d, n, imm, carry))) => {
+            let carry = carry.unwrap_or(carry_flag(self.xpsr));
+            let first = n.map_or(0, |r| self.read_any_register(r.0));
+            self.data_processing(*op, *setflags, d.map(|r| r.0), first, imm, carry)?;
+         },
+         (B32::DATA_REG(op, setflags), Some(Operands::DataReg(d, n, m, shift))) => {
+            let (operand, carry) = shift_c(self.read_any_register(m.0), &shift, carry_flag(self.xpsr));
+            let first = n.map_or(0, |r| self.read_any_register(r.0));
+            self.data_processing(*op, *setflags, d.map(|r| r.0), first, operand, carry)?;
+         },
+         (B32::ADDW, Some(Operands::RegPairImm12(d, n, imm))) => {
+            let v = (Wrapping(self.read_any_register(n.0)) + Wrapping(imm)).0;
+            self.write_core_register(d.0, v)?;
+         },
+         (B32::SUBW, Some(Operands::RegPairImm12(d, n, imm))) => {
+            let v = (Wrapping(self.read_any_register(n.0)) - Wrapping(imm)).0;
+            self.write_core_register(d.0, v)?;
+         },
+         (B32::MOVW, Some(Operands::DestImm16(d, imm))) => {
+            self.write_core_register(d.0, imm)?;
+         },
+         (B32::MOVT, Some(Operands::DestImm16(d, imm))) => {
+            let v = (self.read_any_register(d.0) & 0xFFFF) | (imm << 16);
+            self.write_core_register(d.0, v)?;
+         },
+         (B32::SSAT, Some(Operands::Saturate(d, bits, n, shift))) => {
+            let (operand, _) = shift_c(self.read_any_register(n.0), &shift, carry_flag(self.xpsr));
+            let max = (1_i64 << (bits - 1)) - 1;
+            let min = -(1_i64 << (bits - 1));
+            let v = operand as i32 as i64;
+            self.saturate(d.0, v.clamp(min, max), v)?;
+         },
+         (B32::USAT, Some(Operands::Saturate(d, bits, n, shift))) => {
+            let (operand, _) = shift_c(self.read_any_register(n.0), &shift, carry_flag(self.xpsr));
+            let max = (1_i64 << bits) - 1;
+            let v = operand as i32 as i64;
+            self.saturate(d.0, v.clamp(0, max), v)?;
+         },
+         (B32::SBFX | B32::UBFX, Some(Operands::BitRange(d, Some(n), lsb, width))) => {
+            if lsb as u32 + width as u32 > 32{
+               return Err(ArmException::HardFault(format!("{} with lsb {} and width {} is UNPREDICTABLE",code,lsb,width)));
+            }
+            let mask = ((1_u64 << width) - 1) as u32;
+            let field = (self.read_any_register(n.0) >> lsb) & mask;
+            let v = if *code == B32::SBFX && field & (1 << (width - 1)) > 0{
+               field | !mask
+            }else{
+               field
+            };
+            self.write_core_register(d.0, v)?;
+         },
+         (B32::BFI | B32::BFC, Some(Operands::BitRange(d, n, lsb, width))) => {
+            if width == 0{
+               return Err(ArmException::HardFault(format!("{} with msb < lsb is UNPREDICTABLE",code)));
+            }
+            let mask = (((1_u64 << width) - 1) as u32) << lsb;
+            let insert = n.map_or(0, |r| self.read_any_register(r.0) << lsb);
+            let v = (self.read_any_register(d.0) & !mask) | (insert & mask);
+            self.write_core_register(d.0, v)?;
+         },
+         (B32::LSL_W(setflags) | B32::LSR_W(setflags) | B32::ASR_W(setflags) | B32::ROR_W(setflags),
+          Some(Operands::RegisterTriplet(d, n, m))) => {
+            let amount = self.read_any_register(m.0) & 0xFF;
+            let shift = match code{
+               B32::LSL_W(_) => Shift::LSL(amount),
+               B32::LSR_W(_) => Shift::LSR(amount),
+               B32::ASR_W(_) => Shift::ASR(amount),
+               _ => Shift::ROR(amount)
+            };
+            let (v, carry) = shift_c(self.read_any_register(n.0), &shift, carry_flag(self.xpsr));
+            self.write_core_register(d.0, v)?;
+            if *setflags{
+               self.update_apsr(&logical_flags(v, carry, overflow_flag(self.xpsr)));
+            }
+         },
+         (B32::SXTH_W | B32::SXTB_W | B32::UXTH_W | B32::UXTB_W, Some(Operands::Extend(d, m, rotation))) => {
+            let rotated = self.read_any_register(m.0).rotate_right(rotation as u32);
+            let v = match code{
+               B32::SXTH_W => sign_extend_u32::<16>(rotated & 0xFFFF),
+               B32::SXTB_W => sign_extend_u32::<8>(rotated & 0xFF),
+               B32::UXTH_W => rotated & 0xFFFF,
+               _ => rotated & 0xFF
+            };
+            self.write_core_register(d.0, v)?;
+         },
+         (B32::REV_W | B32::REV16_W | B32::REVSH_W | B32::RBIT | B32::CLZ, Some(Operands::RegisterPair(d, m))) => {
+            let v = self.read_any_register(m.0);
+            let result = match code{
+               B32::REV_W => v.swap_bytes(),
+               B32::REV16_W => ((v & 0xFF00FF00) >> 8) | ((v & 0x00FF00FF) << 8),
+               B32::REVSH_W => (v as u16).swap_bytes() as i16 as i32 as u32,
+               B32::RBIT => v.reverse_bits(),
+               _ => v.leading_zeros()
+            };
+            self.write_core_register(d.0, result)?;
+         },
+         (B32::MUL_W, Some(Operands::RegisterTriplet(d, n, m))) => {
+            let v = self.read_any_register(n.0).wrapping_mul(self.read_any_register(m.0));
+            self.write_core_register(d.0, v)?;
+         },
+         (B32::MLA | B32::MLS, Some(Operands::MulAcc(d, n, m, a))) => {
+            let product = Wrapping(self.read_any_register(n.0)) * Wrapping(self.read_any_register(m.0));
+            let acc = Wrapping(self.read_any_register(a.0));
+            let v = if *code == B32::MLA {acc + product} else {acc - product};
+            self.write_core_register(d.0, v.0)?;
+         },
+         (B32::SMULL | B32::UMULL | B32::SMLAL | B32::UMLAL, Some(Operands::LongMul(lo, hi, n, m))) => {
+            let a = self.read_any_register(n.0);
+            let b = self.read_any_register(m.0);
+            let acc = ((self.read_any_register(hi.0) as u64) << 32) | self.read_any_register(lo.0) as u64;
+            let v: u64 = match code{
+               B32::SMULL => ((a as i32 as i64) * (b as i32 as i64)) as u64,
+               B32::UMULL => (a as u64) * (b as u64),
+               B32::SMLAL => ((a as i32 as i64) * (b as i32 as i64)).wrapping_add(acc as i64) as u64,
+               _ => ((a as u64) * (b as u64)).wrapping_add(acc)
+            };
+            self.write_core_register(lo.0, v as u32)?;
+            self.write_core_register(hi.0, (v >> 32) as u32)?;
+         },
+         (B32::SDIV | B32::UDIV, Some(Operands::RegisterTriplet(d, n, m))) => {
+            let dividend = self.read_any_register(n.0);
+            let divisor = self.read_any_register(m.0);
+            let v = if divisor == 0{
+               if self.scs.ccr & CCR_DIV_0_TRP > 0{
+                  self.record_usage_fault(UFSR_DIVBYZERO);
+                  return Err(ArmException::HardFault(format!("{} by zero with CCR.DIV_0_TRP set",code)));
+               }
+               0
+            }else if *code == B32::SDIV{
+               (dividend as i32).wrapping_div(divisor as i32) as u32
+            }else{
+               dividend / divisor
+            };
+            self.write_core_register(d.0, v)?;
+         },
+         (B32::B_W, Some(Operands::B_ALWAYS(offset))) => {
+            return Ok(offset);
+         },
+         (B32::B_COND_W(cond), Some(Operands::COND_BRANCH(offset))) => {
+            if condition_holds(from_arm_bytes(self.xpsr), *cond){
+               return Ok(offset);
+            }
+         },
+         (B32::NOP_W | B32::YIELD_W | B32::PRELOAD, _) => {},
+         (B32::WFE_W, None) => return Ok(self.wait_for_event(4)),
+         (B32::WFI_W, None) => return Ok(self.wait_for_interrupt(4)),
+         (B32::SEV_W, None) => return Ok(self.send_event(4)),
+         (B32::CLREX, None) => {
+            self.exclusive_monitor = None;
+         },
+         (B32::LDM_W | B32::LDMDB | B32::STM_W | B32::STMDB, Some(Operands::RegListWb(n, write_back, list))) => {
+            let base = self.read_any_register(n.0);
+            let span = 4 * list.count_ones();
+            let (start, end) = match code{
+               B32::LDM_W | B32::STM_W => (base, base.wrapping_add(span)),
+               _ => (base.wrapping_sub(span), base.wrapping_sub(span))
+            };
+            let wb = if write_back {Some((n.0, end))} else {None};
+            return match code{
+               B32::LDM_W | B32::LDMDB => self.load_multiple(start, list, wb),
+               _ => self.store_multiple(start, list, wb)
+            };
+         },
+         (B32::POP_W, Some(Operands::RegisterList(list))) => {
+            let sp = self.get_sp();
+            return self.load_multiple(sp, list, Some((13, sp.wrapping_add(4 * list.count_ones()))));
+         },
+         (B32::PUSH_W, Some(Operands::RegisterList(list))) => {
+            let start = self.get_sp().wrapping_sub(4 * list.count_ones());
+            return self.store_multiple(start, list, Some((13, start)));
+         },
+         (B32::LDREX | B32::LDREXB | B32::LDREXH | B32::LDAEX(_), Some(Operands::Exclusive(None, t, n, imm))) => {
+            let address = self.read_any_register(n.0).wrapping_add(imm);
+            let width = exclusive_width(code);
+            fault_if_not_aligned(address, width.in_bytes() as usize)?;
+            let v = self.load_width(address, &width)?;
+            self.exclusive_monitor = Some(address);
+            self.write_core_register(t.0, v)?;
+         },
+         // every access is committed instantly, so acquire/release ordering needs nothing extra
+         (B32::LDA(width), Some(Operands::Exclusive(None, t, n, _))) => {
+            let address = self.read_any_register(n.0);
+            fault_if_not_aligned(address, width.in_bytes() as usize)?;
+            let v = self.load_width(address, width)?;
+            self.write_core_register(t.0, v)?;
+         },
+         (B32::STL(width), Some(Operands::Exclusive(None, t, n, _))) => {
+            let address = self.read_any_register(n.0);
+            fault_if_not_aligned(address, width.in_bytes() as usize)?;
+            self.store_width(address, width, self.read_any_register(t.0))?;
+         },
+         (B32::STREX | B32::STREXB | B32::STREXH | B32::STLEX(_), Some(Operands::Exclusive(Some(d), t, n, imm))) => {
+            let address = self.read_any_register(n.0).wrapping_add(imm);
+            let width = exclusive_width(code);
+            fault_if_not_aligned(address, width.in_bytes() as usize)?;
+            let status = if self.exclusive_monitor == Some(address){
+               self.store_width(address, &width, self.read_any_register(t.0))?;
+               0
+            }else{
+               1
+            };
+            self.exclusive_monitor = None;
+            self.write_core_register(d.0, status)?;
+         },
+         (B32::LDRD | B32::STRD, Some(Operands::Dual(t, t2, n, address))) => {
+            let (target, write_back) = target_address(self.read_any_register(n.0), &address, 0);
+            if *code == B32::LDRD{
+               let first = from_arm_bytes(load_memory::<4>(self, target)?);
+               let second = from_arm_bytes(load_memory::<4>(self, target.wrapping_add(4))?);
+               if let Some(v) = write_back{
+                  self.write_core_register(n.0, v)?;
+               }
+               self.write_core_register(t.0, first)?;
+               self.write_core_register(t2.0, second)?;
+            }else{
+               let first = self.read_any_register(t.0);
+               let second = self.read_any_register(t2.0);
+               write_memory(self, target, into_arm_bytes(first))?;
+               write_memory(self, target.wrapping_add(4), into_arm_bytes(second))?;
+               if let Some(v) = write_back{
+                  self.write_core_register(n.0, v)?;
+               }
+            }
+         },
+         (B32::TBB | B32::TBH, Some(Operands::TableBranch(n, m, half))) => {
+            // the table base is the unaligned address of the next instruction when Rn is PC
+            let base = if n.0 == 15 {self.registers.pc as u32 + 4} else {self.read_any_register(n.0)};
+            let index = self.read_any_register(m.0);
+            let entry = if half{
+               self.load_width(base.wrapping_add(index << 1), &Width::HalfWord)?
+            }else{
+               self.load_width(base.wrapping_add(index), &Width::Byte)?
+            };
+            return Ok(4 + 2 * entry as i32);
+         },
+         (B32::LDR_W(width), Some(Operands::Memory(t, n, address))) => {
+            let index = match address {Address::Shifted(m, _) => self.read_any_register(m), _ => 0};
+            let (target, write_back) = target_address(self.read_any_register(n.0), &address, index);
+            let v = self.load_width(target, width)?;
+            if *width == Width::Word{
+               if let Some(MemoryMappedRegister::syst_csr) = MemoryMappedRegister::from_address(target){
+                  self.scs.clear_countflag();
+               }
+            }
+            if let Some(wb) = write_back{
+               self.write_core_register(n.0, wb)?;
+            }
+            if t.0 == 15{
+               return self.bx_interworking_pc_offset(v);
+            }
+            self.write_core_register(t.0, v)?;
+         },
+         (B32::STR_W(width), Some(Operands::Memory(t, n, address))) => {
+            let index = match address {Address::Shifted(m, _) => self.read_any_register(m), _ => 0};
+            let (target, write_back) = target_address(self.read_any_register(n.0), &address, index);
+            self.store_width(target, width, self.read_any_register(t.0))?;
+            if let Some(wb) = write_back{
+               self.write_core_register(n.0, wb)?;
+            }
+         },
+         (op, args) => panic!("{} has unexpected operands {:?}",op,args)
+      }
+      return Ok(4);
+   }
+
+   fn data_processing(&mut self, op: DataOp, setflags: bool, dest: Option<u8>, n: u32, operand: u32, carry: bool)->Result<(), ArmException>{
+      let overflow = overflow_flag(self.xpsr);
+      let (result, flags) = match op{
+         DataOp::AND | DataOp::TST => (n & operand, None),
+         DataOp::BIC => (n & !operand, None),
+         DataOp::ORR => (n | operand, None),
+         DataOp::ORN => (n | !operand, None),
+         DataOp::MOV => (operand, None),
+         DataOp::MVN => (!operand, None),
+         DataOp::EOR | DataOp::TEQ => (n ^ operand, None),
+         DataOp::ADD | DataOp::CMN => {
+            let (v, f) = adc_flags(n, operand, false);
+            (v, Some(f))
+         },
+         DataOp::ADC => {
+            let (v, f) = adc_flags(n, operand, carry_flag(self.xpsr));
+            (v, Some(f))
+         },
+         DataOp::SBC => {
+            let (v, f) = adc_flags(n, !operand, carry_flag(self.xpsr));
+            (v, Some(f))
+         },
+         DataOp::SUB | DataOp::CMP => {
+            let (v, f) = adc_flags(n, !operand, true);
+            (v, Some(f))
+         },
+         DataOp::RSB => {
+            let (v, f) = adc_flags(!n, operand, true);
+            (v, Some(f))
+         }
+      };
+      if let Some(d) = dest{
+         if d == 15{
+            return Err(ArmException::HardFault(format!("{:?}.W with PC as the destination is UNPREDICTABLE",op)));
+         }
+         self.write_core_register(d, result)?;
+      }
+      if setflags{
+         let flags = flags.unwrap_or(logical_flags(result, carry, overflow));
+         self.update_apsr(&flags);
+      }
+      Ok(())
+   }
+
+   fn saturate(&mut self, dest: u8, saturated: i64, original: i64)->Result<(), ArmException>{
+      if saturated != original{
+         let xpsr = from_arm_bytes(self.xpsr) | APSR_Q;
+         self.xpsr = into_arm_bytes(xpsr);
+      }
+      self.write_core_register(dest, saturated as u32)
+   }
+
+   // with writeback the base register is updated before a loaded PC interworks
+   fn load_multiple(&mut self, start: u32, list: u16, write_back: Option<(u8,u32)>)->Result<i32, ArmException>{
+      fault_if_not_aligned(start, 4)?;
+      let mut loaded = Vec::new();
+      for (i, r) in get_set_bits(list).into_iter().enumerate(){
+         let v = from_arm_bytes(load_memory::<4>(self, start.wrapping_add(4 * i as u32))?);
+         loaded.push((r, v));
+      }
+      if let Some((base, v)) = write_back{
+         if list & (1 << base) == 0{
+            self.write_core_register(base, v)?;
+         }
+      }
+      for (r, v) in loaded{
+         if r == 15{
+            return self.bx_interworking_pc_offset(v);
+         }
+         self.write_core_register(r, v)?;
+      }
+      Ok(4)
+   }
+
+   fn store_multiple(&mut self, start: u32, list: u16, write_back: Option<(u8,u32)>)->Result<i32, ArmException>{
+      fault_if_not_aligned(start, 4)?;
+      for (i, r) in get_set_bits(list).into_iter().enumerate(){
+         let v = self.read_any_register(r);
+         write_memory(self, start.wrapping_add(4 * i as u32), into_arm_bytes(v))?;
+      }
+      if let Some((base, v)) = write_back{
+         self.write_core_register(base, v)?;
+      }
+      Ok(4)
+   }
+
+   // unaligned word and halfword accesses are split into bytes unless CCR.UNALIGN_TRP is set
+   fn check_unaligned(&mut self, address: u32, width: &Width)->Result<bool, ArmException>{
+      if is_aligned(address, width.in_bytes()){
+         return Ok(false);
+      }
+      if self.scs.ccr & CCR_UNALIGN_TRP > 0{
+         self.record_usage_fault(UFSR_UNALIGNED);
+         return Err(ArmException::HardFault(format!("unaligned access to {:#x} with CCR.UNALIGN_TRP set",address)));
+      }
+      Ok(true)
+   }
+
+   fn load_width(&mut self, address: u32, width: &Width)->Result<u32, ArmException>{
+      let v = if self.check_unaligned(address, width)?{
+         let mut bytes = [0_u8;4];
+         for i in 0 .. width.in_bytes(){
+            bytes[i as usize] = load_memory::<1>(self, address.wrapping_add(i))?[0];
+         }
+         from_data_bytes(&bytes[.. width.in_bytes() as usize], self.is_big_endian())
+      }else{
+         match width.in_bytes(){
+            1 => load_memory::<1>(self, address)?[0] as u32,
+            2 => from_arm_bytes_16b(load_memory::<2>(self, address)?) as u32,
+            _ => from_arm_bytes(load_memory::<4>(self, address)?)
+         }
+      };
+      Ok(match width{
+         Width::SignedByte => sign_extend_u32::<8>(v),
+         Width::SignedHalfWord => sign_extend_u32::<16>(v),
+         _ => v
+      })
+   }
+
+   fn store_width(&mut self, address: u32, width: &Width, v: u32)->Result<(), ArmException>{
+      let mut bytes = into_arm_bytes(v);
+      if self.check_unaligned(address, width)?{
+         let n = width.in_bytes() as usize;
+         // byte sized writes are never swapped, so the data endianness is applied here
+         if self.is_big_endian(){
+            bytes[.. n].reverse();
+         }
+         for (i, b) in bytes[.. n].iter().enumerate(){
+            write_memory(self, address.wrapping_add(i as u32), [*b])?;
+         }
+         return Ok(());
+      }
+      match width.in_bytes(){
+         1 => write_memory(self, address, [bytes[0]]),
+         2 => write_memory(self, address, [bytes[0], bytes[1]]),
+         _ => write_memory(self, address, bytes)
+      }
+   }
+}
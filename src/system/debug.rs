@@ -0,0 +1,216 @@
+use std::cell::Cell;
+
+use super::System;
+use super::expression::RegisterName;
+
+// the ARMv6-M halting debug registers, the simulator's front ends play the part of the
+// debugger so C_DEBUGEN is set by the host rather than by software
+
+pub const DHCSR_DBGKEY: u32 = 0xA05F;
+pub const C_DEBUGEN: u32 = 1 << 0;
+pub const C_HALT: u32 = 1 << 1;
+pub const C_STEP: u32 = 1 << 2;
+pub const C_MASKINTS: u32 = 1 << 3;
+pub const S_REGRDY: u32 = 1 << 16;
+pub const S_HALT: u32 = 1 << 17;
+pub const S_SLEEP: u32 = 1 << 18;
+pub const S_LOCKUP: u32 = 1 << 19;
+pub const S_RETIRE_ST: u32 = 1 << 24;
+pub const S_RESET_ST: u32 = 1 << 25;
+
+pub const DFSR_HALTED: u32 = 1 << 0;
+pub const DFSR_BKPT: u32 = 1 << 1;
+pub const DFSR_VCATCH: u32 = 1 << 3;
+const DFSR_MASK: u32 = 0x1F;
+
+pub const DEMCR_VC_CORERESET: u32 = 1 << 0;
+pub const DEMCR_VC_HARDERR: u32 = 1 << 10;
+pub const DEMCR_DWTENA: u32 = 1 << 24;
+const DEMCR_MASK: u32 = DEMCR_VC_CORERESET | DEMCR_VC_HARDERR | DEMCR_DWTENA;
+
+const DCRSR_REGWNR: u32 = 1 << 16;
+
+#[derive(Clone,Debug,PartialEq)]
+pub enum DebugHalt{
+   // software set DHCSR.C_HALT
+   Request,
+   // DHCSR.C_STEP halted the core after one instruction
+   Step,
+   // DEMCR vector catch, carries the exception number that was caught (1 for reset)
+   VectorCatch(u32)
+}
+
+pub fn debug_halt_msg(reason: &DebugHalt)->String{
+   match reason{
+      DebugHalt::Request => "DHCSR.C_HALT set".into(),
+      DebugHalt::Step => "DHCSR.C_STEP".into(),
+      DebugHalt::VectorCatch(1) => "vector catch on reset".into(),
+      DebugHalt::VectorCatch(_) => "vector catch on HardFault".into()
+   }
+}
+
+// only a power on reset clears the debug registers, a local reset through AIRCR keeps them
+#[derive(Clone,Debug,Default)]
+pub struct DebugState{
+   // DHCSR C_* control bits
+   pub control: u32,
+   pub halted: bool,
+   // sticky status bits, cleared when software reads DHCSR
+   pub retired: Cell<bool>,
+   pub reset: Cell<bool>,
+   pub dcrdr: u32,
+   pub demcr: u32,
+   pub dfsr: u32,
+   // the address and immediate of the BKPT the core is halted on
+   pub bkpt: Option<(u32,u8)>,
+   pending: Option<DebugHalt>
+}
+
+impl DebugState{
+   pub fn enabled(&self)->bool{
+      self.control & C_DEBUGEN > 0
+   }
+
+   // the host debugger attaching or detaching, software cannot change C_DEBUGEN
+   pub fn set_enabled(&mut self, enabled: bool){
+      if enabled{
+         self.control |= C_DEBUGEN;
+      }else{
+         self.control = 0;
+      }
+   }
+
+   pub fn dhcsr(&self, sys: &System)->u32{
+      let mut v = self.control | S_REGRDY;
+      if self.halted { v |= S_HALT; }
+      if sys.sleep.waiting { v |= S_SLEEP; }
+      if sys.is_locked_up() { v |= S_LOCKUP; }
+      if self.retired.replace(false) { v |= S_RETIRE_ST; }
+      if self.reset.replace(false) { v |= S_RESET_ST; }
+      return v;
+   }
+
+   pub fn write_dhcsr(&mut self, v: u32){
+      if (v >> 16) != DHCSR_DBGKEY{
+         println!("WARN: write to DHCSR without DBGKEY 0xA05F ignored ({:#010x})",v);
+         return;
+      }
+      if !self.enabled(){
+         println!("WARN: DHCSR is ignored while no debugger is attached (C_DEBUGEN == 0)");
+         return;
+      }
+      self.control = C_DEBUGEN | (v & (C_HALT | C_STEP | C_MASKINTS));
+      if v & C_HALT > 0{
+         self.request_halt(DebugHalt::Request);
+      }
+   }
+
+   pub fn write_demcr(&mut self, v: u32){
+      self.demcr = v & DEMCR_MASK;
+   }
+
+   // DFSR bits are cleared by writing 1 to them
+   pub fn write_dfsr(&mut self, v: u32){
+      self.dfsr &= !(v & DFSR_MASK);
+   }
+
+   pub fn request_halt(&mut self, reason: DebugHalt){
+      if self.enabled() && self.pending.is_none(){
+         self.pending = Some(reason);
+      }
+   }
+
+   pub fn catch_vector(&mut self, exc_n: u32){
+      let caught = match exc_n{
+         1 => self.demcr & DEMCR_VC_CORERESET > 0,
+         3 => self.demcr & DEMCR_VC_HARDERR > 0,
+         _ => false
+      };
+      if caught{
+         self.request_halt(DebugHalt::VectorCatch(exc_n));
+      }
+   }
+
+   // enters debug state if a halt is pending
+   pub fn take_halt(&mut self)->Option<DebugHalt>{
+      let reason = self.pending.take()?;
+      self.dfsr |= match reason{
+         DebugHalt::VectorCatch(_) => DFSR_VCATCH,
+         DebugHalt::Request | DebugHalt::Step => DFSR_HALTED
+      };
+      self.halted = true;
+      return Some(reason);
+   }
+
+   // BKPT with a debugger attached, the core halts on the BKPT rather than after it
+   pub fn breakpoint(&mut self, addr: u32, imm: u8){
+      self.dfsr |= DFSR_BKPT;
+      self.halted = true;
+      self.bkpt = Some((addr, imm));
+   }
+
+   // the host stepping or continuing a halted core takes it out of debug state,
+   // returns the address of the BKPT it was halted on so the resuming step can move past it
+   pub fn resume(&mut self)->Option<u32>{
+      if !self.halted{
+         return None;
+      }
+      self.halted = false;
+      self.control &= !(C_HALT | C_STEP);
+      return self.bkpt.take().map(|(addr, _)| addr);
+   }
+
+   pub fn retire(&mut self){
+      self.retired.set(true);
+      if self.control & C_STEP > 0{
+         self.request_halt(DebugHalt::Step);
+      }
+   }
+}
+
+fn transfer_register(regsel: u32)->Option<RegisterName>{
+   match regsel{
+      0 ..= 12 => Some(RegisterName::Generic(regsel as u8)),
+      13 => Some(RegisterName::SP),
+      14 => Some(RegisterName::LR),
+      15 => Some(RegisterName::PC),
+      16 => Some(RegisterName::XPSR),
+      17 => Some(RegisterName::MSP),
+      18 => Some(RegisterName::PSP),
+      _ => None
+   }
+}
+
+// DCRSR moves a core register to or from DCRDR, REGSEL 20 packs CONTROL into [31:24], FAULTMASK into [23:16],
+// BASEPRI into [15:8] and PRIMASK into [7:0]
+pub fn write_dcrsr(sys: &mut System, v: u32){
+   let regsel = v & 0x1F;
+   let write = v & DCRSR_REGWNR > 0;
+   if regsel == 20{
+      if write{
+         let data = sys.debug.dcrdr;
+         let _ = RegisterName::CONTROL.write(sys, data >> 24);
+         let _ = RegisterName::PRIMASK.write(sys, data & 1);
+         if sys.profile.has_main_extension(){
+            let _ = RegisterName::BASEPRI.write(sys, (data >> 8) & 0xFF);
+            let _ = RegisterName::FAULTMASK.write(sys, (data >> 16) & 1);
+         }
+      }else{
+         sys.debug.dcrdr = (RegisterName::CONTROL.read(sys) << 24)
+            | (RegisterName::FAULTMASK.read(sys) << 16)
+            | (RegisterName::BASEPRI.read(sys) << 8)
+            | (RegisterName::PRIMASK.read(sys) & 1);
+      }
+      return;
+   }
+   match transfer_register(regsel){
+      Some(reg) if write => {
+         let data = sys.debug.dcrdr;
+         if let Err(e) = reg.write(sys, data){
+            println!("WARN: DCRSR write to {:?} failed {:?}",reg,e);
+         }
+      },
+      Some(reg) => sys.debug.dcrdr = reg.read(sys),
+      None => println!("WARN: DCRSR.REGSEL {} does not select a register",regsel)
+   }
+}
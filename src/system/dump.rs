@@ -0,0 +1,128 @@
+use std::path::PathBuf;
+
+use crate::binutils::from_arm_bytes;
+use crate::json::Json;
+use super::{System, ArmException, exception_name};
+use super::simulator::{HaltType, Machine};
+use super::debug::debug_halt_msg;
+
+// machine readable snapshots of the architectural state, written when the simulator halts
+
+pub const DEFAULT_DUMP_PATH: &'static str = "armageddon_state.json";
+
+#[derive(Clone,Debug)]
+pub struct StateDump{
+   pub path: PathBuf,
+   // inclusive start and end addresses of the memory to include
+   pub ranges: Vec<(u32,u32)>
+}
+
+impl StateDump{
+   pub fn write(&self, machine: &Machine, halt: Option<&HaltType>)->std::io::Result<()>{
+      std::fs::write(&self.path, machine_state(machine, halt, &self.ranges).to_string())
+   }
+}
+
+pub fn halt_json(halt: Option<&HaltType>)->Json{
+   match halt{
+      None => Json::Null,
+      Some(HaltType::breakpoint(None)) => Json::object(vec![("type", "breakpoint".into())]),
+      Some(HaltType::breakpoint(Some(imm))) => Json::object(vec![("type", "breakpoint".into()), ("bkpt", (*imm as u32).into())]),
+      Some(HaltType::usercmd) => Json::object(vec![("type", "usercmd".into())]),
+      Some(HaltType::lockup) => Json::object(vec![("type", "lockup".into())]),
+      Some(HaltType::debug(reason)) => Json::object(vec![("type", "debug".into()), ("reason", debug_halt_msg(reason).into())]),
+      Some(HaltType::error(e)) => {
+         let mut fields = vec![
+            ("type", Json::from("error")),
+            ("exception", exception_name(e.number()).into())
+         ];
+         if let ArmException::HardFault(msg) = e{
+            fields.push(("message", msg.clone().into()));
+         }
+         Json::object(fields)
+      }
+   }
+}
+
+pub fn core_state(sys: &System)->Json{
+   let mut registers: Vec<(String,Json)> = sys.registers.generic.iter().enumerate()
+      .map(|(i,v)| (format!("r{}",i), Json::from(*v)))
+      .collect();
+   registers.push(("sp".into(), sys.get_sp().into()));
+   registers.push(("lr".into(), sys.registers.lr.into()));
+   registers.push(("pc".into(), (sys.registers.pc as u32).into()));
+   registers.push(("msp".into(), sys.registers.sp_main.into()));
+   registers.push(("psp".into(), sys.registers.sp_process.into()));
+
+   let xpsr = from_arm_bytes(sys.xpsr);
+   let control = from_arm_bytes(sys.control_register);
+   let mut exceptions = Vec::new();
+   for n in 1 .. sys.active_exceptions.len() as u32{
+      if let Some(exc) = ArmException::from_exception_number(n){
+         let mut fields = vec![
+            ("number", Json::from(n)),
+            ("name", exception_name(n).into()),
+            ("status", format!("{:?}",sys.active_exceptions[n as usize]).into()),
+            ("priority", (exc.priority_group(&sys.scs) as i64).into())
+         ];
+         if n >= 16{
+            fields.push(("enabled", sys.scs.is_nvic_interrupt_enabled(n - 16).into()));
+         }
+         exceptions.push(Json::object(fields));
+      }
+   }
+
+   Json::object(vec![
+      ("core", sys.sio.as_ref().map(|link| link.core).unwrap_or(0).into()),
+      ("registers", Json::Object(registers)),
+      ("xpsr", Json::object(vec![
+         ("value", xpsr.into()),
+         ("n", (xpsr & (1 << 31) > 0).into()),
+         ("z", (xpsr & (1 << 30) > 0).into()),
+         ("c", (xpsr & (1 << 29) > 0).into()),
+         ("v", (xpsr & (1 << 28) > 0).into()),
+         ("t", (xpsr & (1 << 24) > 0).into()),
+         ("ipsr", (xpsr & 0x3F).into())
+      ])),
+      ("control", Json::object(vec![
+         ("value", control.into()),
+         ("npriv", (control & 1 > 0).into()),
+         ("spsel", (control & 2 > 0).into())
+      ])),
+      ("primask", sys.primask().into()),
+      ("basepri", (sys.basepri() as u32).into()),
+      ("faultmask", sys.faultmask().into()),
+      ("mode", format!("{:?}",sys.mode).into()),
+      ("privileged", sys.in_privileged_mode().into()),
+      ("locked_up", sys.is_locked_up().into()),
+      ("steps", sys.stimuli.steps().into()),
+      ("sleep", Json::object(vec![
+         ("asleep", sys.sleep.asleep.into()),
+         ("skipped", sys.sleep.skipped.into())
+      ])),
+      ("exceptions", exceptions.into())
+   ])
+}
+
+// memory is read straight from the pages so dumping has no side effects on peripherals
+pub fn memory_json(sys: &System, ranges: &[(u32,u32)])->Json{
+   ranges.iter().map(|(start, end)|{
+      let bytes: String = sys.alloc.view(*start, *end).iter().map(|b| format!("{:02x}",b)).collect();
+      Json::object(vec![
+         ("start", (*start).into()),
+         ("end", (*end).into()),
+         ("bytes", bytes.into())
+      ])
+   }).collect::<Vec<Json>>().into()
+}
+
+pub fn machine_state(machine: &Machine, halt: Option<&HaltType>, ranges: &[(u32,u32)])->Json{
+   let cores: Vec<Json> = machine.cores.iter().map(|c| core_state(&c.lock().unwrap())).collect();
+   let memory = memory_json(&machine.cores[0].lock().unwrap(), ranges);
+   Json::object(vec![
+      ("halt", halt_json(halt)),
+      ("cores", cores.into()),
+      ("memory", memory)
+   ])
+}
+
@@ -0,0 +1,139 @@
+use crate::binutils::{from_arm_bytes, into_arm_bytes};
+use crate::to_arm_bytes;
+use super::registers::SpecialRegister;
+use super::{System, ArmException, write_memory};
+
+// values the debugger can read and write: registers, memory cells and plain numbers
+
+#[derive(Clone,Debug,PartialEq)]
+pub enum RegisterName{
+   Generic(u8),
+   SP,
+   MSP,
+   PSP,
+   LR,
+   PC,
+   XPSR,
+   PRIMASK,
+   BASEPRI,
+   FAULTMASK,
+   CONTROL
+}
+
+impl RegisterName{
+   pub fn from_name(name: &str)->Option<Self>{
+      let name = name.trim().to_lowercase();
+      match name.as_str(){
+         "sp" | "r13" => Some(Self::SP),
+         "msp" => Some(Self::MSP),
+         "psp" => Some(Self::PSP),
+         "lr" | "r14" => Some(Self::LR),
+         "pc" | "r15" => Some(Self::PC),
+         "xpsr" => Some(Self::XPSR),
+         "primask" => Some(Self::PRIMASK),
+         "basepri" => Some(Self::BASEPRI),
+         "faultmask" => Some(Self::FAULTMASK),
+         "control" => Some(Self::CONTROL),
+         _ => match name.strip_prefix('r').map(|n| n.parse::<u8>()){
+            Some(Ok(n)) if n <= 12 => Some(Self::Generic(n)),
+            _ => None
+         }
+      }
+   }
+
+   pub fn read(&self, sys: &System)->u32{
+      match self{
+         Self::Generic(n) => sys.registers.generic[*n as usize],
+         Self::SP => sys.get_sp(),
+         Self::MSP => sys.registers.sp_main,
+         Self::PSP => sys.registers.sp_process,
+         Self::LR => sys.registers.lr,
+         Self::PC => sys.registers.pc as u32,
+         Self::XPSR => from_arm_bytes(sys.xpsr),
+         Self::PRIMASK => sys.primask() as u32,
+         Self::BASEPRI => sys.basepri() as u32,
+         Self::FAULTMASK => sys.faultmask() as u32,
+         Self::CONTROL => from_arm_bytes(sys.control_register)
+      }
+   }
+
+   pub fn write(&self, sys: &mut System, v: u32)->Result<(),ArmException>{
+      match self{
+         Self::Generic(n) => sys.write_core_register(*n, v),
+         Self::SP => sys.write_core_register(13, v),
+         Self::LR => sys.write_core_register(14, v),
+         Self::PC => sys.write_core_register(15, v),
+         Self::MSP => sys.write_special_register(&SpecialRegister::MSP, v),
+         Self::PSP => sys.write_special_register(&SpecialRegister::PSP, v),
+         Self::XPSR => sys.write_special_register(&SpecialRegister::XPSR, v),
+         Self::PRIMASK => sys.write_special_register(&SpecialRegister::PRIMASK, v),
+         Self::BASEPRI => sys.write_special_register(&SpecialRegister::BASEPRI, v),
+         Self::FAULTMASK => sys.write_special_register(&SpecialRegister::FAULTMASK, v),
+         Self::CONTROL => sys.write_special_register(&SpecialRegister::CONTROL, v)
+      }
+   }
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub enum Value{
+   Literal(u32),
+   Register(RegisterName),
+   // address and access size in bytes
+   Memory(u32,usize)
+}
+
+impl Value{
+   // memory is read straight from the pages so that evaluating a value has no side effects,
+   // None when a memory value runs past the top of the address space
+   pub fn read(&self, sys: &System)->Option<u32>{
+      match self{
+         Self::Literal(v) => Some(*v),
+         Self::Register(reg) => Some(reg.read(sys)),
+         Self::Memory(addr, size) => sys.peek(*addr, *size)
+      }
+   }
+
+   pub fn write(&self, sys: &mut System, v: u32)->Result<(),ArmException>{
+      match self{
+         Self::Literal(_) => Err(ArmException::HardFault("cannot assign to a literal".into())),
+         Self::Register(reg) => reg.write(sys, v),
+         Self::Memory(addr, 4) => write_memory(sys, *addr, into_arm_bytes(v)),
+         Self::Memory(addr, 2) => write_memory(sys, *addr, to_arm_bytes!(u16, v as u16)),
+         Self::Memory(addr, _) => write_memory(sys, *addr, [v as u8])
+      }
+   }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Comparison{
+   Eq,
+   Ne,
+   Lt,
+   Le,
+   Gt,
+   Ge
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Condition{
+   pub lhs: Value,
+   pub cmp: Comparison,
+   pub rhs: Value
+}
+
+impl Condition{
+   pub fn holds(&self, sys: &System)->bool{
+      let (a, b) = match (self.lhs.read(sys), self.rhs.read(sys)){
+         (Some(a), Some(b)) => (a, b),
+         _ => return false
+      };
+      match self.cmp{
+         Comparison::Eq => a == b,
+         Comparison::Ne => a != b,
+         Comparison::Lt => a < b,
+         Comparison::Le => a <= b,
+         Comparison::Gt => a > b,
+         Comparison::Ge => a >= b
+      }
+   }
+}
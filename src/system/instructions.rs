@@ -1,4 +1,5 @@
 use crate::asm::decode::{Opcode,B16};
+use crate::asm::decode_operands::Shift;
 use crate::system::registers::{
    Apsr,
    set_carry_bit,
@@ -100,6 +101,72 @@ pub fn cond_passed(apsr: Apsr, b_cond: &Opcode)->bool{
    }
 }
 
+// evaluates a 4 bit condition field against the flags, as used by IT blocks and B<cond>.W
+pub fn condition_holds(xpsr: u32, cond: u8)->bool{
+   let n = negative_flag_u32(xpsr);
+   let z = zero_flag_u32(xpsr);
+   let c = carry_flag_u32(xpsr);
+   let v = overflow_flag_u32(xpsr);
+   match cond & 0xF{
+      0x0 => z,
+      0x1 => !z,
+      0x2 => c,
+      0x3 => !c,
+      0x4 => n,
+      0x5 => !n,
+      0x6 => v,
+      0x7 => !v,
+      0x8 => c && !z,
+      0x9 => !c || z,
+      0xA => n == v,
+      0xB => n != v,
+      0xC => !z && (n == v),
+      0xD => z || (n != v),
+      _ => true
+   }
+}
+
+// Shift_C from the ARMv7-M pseudocode, amounts of 32 and over are valid for register shifts
+pub fn shift_c(value: u32, shift: &Shift, carry_in: bool)->(u32,bool){
+   match *shift{
+      Shift::LSL(0) | Shift::LSR(0) | Shift::ASR(0) | Shift::ROR(0) => (value,carry_in),
+      Shift::LSL(n) => {
+         if n < 32{
+            (value << n, (value >> (32 - n)) & 1 > 0)
+         }else if n == 32{
+            (0, value & 1 > 0)
+         }else{
+            (0,false)
+         }
+      },
+      Shift::LSR(n) => {
+         if n < 32{
+            (value >> n, (value >> (n - 1)) & 1 > 0)
+         }else if n == 32{
+            (0, value & 0x80000000 > 0)
+         }else{
+            (0,false)
+         }
+      },
+      Shift::ASR(n) => {
+         let amount = std::cmp::min(n,32);
+         let result = if amount == 32{
+            ((value as i32) >> 31) as u32
+         }else{
+            ((value as i32) >> amount) as u32
+         };
+         (result, ((value as i32 as i64) >> (amount - 1)) & 1 > 0)
+      },
+      Shift::ROR(n) => {
+         let result = value.rotate_right(n % 32);
+         (result, result & 0x80000000 > 0)
+      },
+      Shift::RRX => {
+         ((value >> 1) | ((carry_in as u32) << 31), value & 1 > 0)
+      }
+   }
+}
+
 pub fn compare(a: u32, b: u32)->ConditionFlags{
    let (_, flags) = subtract(a,b);
    return flags;
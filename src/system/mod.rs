@@ -1,9 +1,8 @@
 use std::fmt::Display;
 use std::num::Wrapping;
 
-use crate::asm::interpreter::serialise_instruction;
 use crate::asm::{self, PROGRAM_COUNTER, DestRegister, SrcRegister, Literal};
-use crate::binutils::{from_arm_bytes, clear_bit, set_bit, into_arm_bytes, get_set_bits, sign_extend_u32, from_arm_bytes_16b, BitField, sign_extend};
+use crate::binutils::{from_arm_bytes, clear_bit, set_bit, into_arm_bytes, get_set_bits, sign_extend_u32, from_arm_bytes_16b, from_data_bytes, BitField, sign_extend};
 use crate::asm::decode::{Opcode, instruction_size, InstructionSize, B16, B32};
 use crate::asm::decode_operands::{Operands,get_operands, get_operands_32b};
 use crate::system::registers::RegAccess;
@@ -14,11 +13,30 @@ use self::instructions::{cond_passed, shift_left, shift_right};
 use self::registers::{Registers, Apsr, SpecialRegister, get_overflow_bit};
 
 use crate::system::trace::Trace;
+use crate::system::observer::{Observers, SystemObserver, MemoryAccess};
+use crate::system::rp2040::{Sio, SioLink};
+use crate::system::expression::Condition;
+use crate::system::stimulus::Schedule;
+use crate::system::sleep::SleepStats;
+use crate::system::debug::{DebugState, write_dcrsr};
+use crate::system::profile::Profile;
+use crate::system::mpu::Mpu;
+use crate::system::armv7m::{UFSR_INVSTATE, UFSR_UNDEFINSTR, CCR_MASK, CCR_STKALIGN, EPSR_IT_MASK};
 
 pub mod registers;
 pub mod instructions;
 pub mod simulator;
 pub mod trace;
+pub mod rp2040;
+pub mod expression;
+pub mod dump;
+pub mod stimulus;
+pub mod sleep;
+pub mod debug;
+pub mod profile;
+pub mod mpu;
+pub mod armv7m;
+pub mod observer;
 
 pub struct System{
    pub registers: Registers,
@@ -29,16 +47,34 @@ pub struct System{
    pub scs: SystemControlSpace,
    pub mode: Mode,
    primask: bool,
+   // ARMv7-M only, both stay clear on ARMv6-M
+   basepri: u8,
+   faultmask: bool,
    //pub memory: Vec<u8>,
    pub breakpoints: Vec<usize>,
+   // breakpoints listed here only halt when their condition holds
+   pub breakpoint_conditions: HashMap<usize,Condition>,
    pub trace_enabled: bool,
    //pub trace: String,
    pub trace: Trace,
+   // instrumentation registered with add_observer
+   pub observers: Observers,
    pub alloc: BlockAllocator,
    pub reset_cfg: Option<ResetCfg>,
    pub vtor_override: Option<u32>,
    locked_up: bool,
-   pub error_msg: String
+   pub error_msg: String,
+   pub sio: Option<SioLink>,
+   // externally injected interrupts and events
+   pub stimuli: Schedule,
+   pub sleep: SleepStats,
+   pub debug: DebugState,
+   // the architecture the core executes, ARMv6-M unless selected otherwise
+   pub profile: Profile,
+   // loaded from a BE8 image, AIRCR.ENDIANNESS is set from reset
+   pub big_endian: bool,
+   // address tagged by the last LDREX, cleared by STREX, CLREX and exception entry/return
+   pub exclusive_monitor: Option<u32>
 }
 
 pub struct ResetCfg{
@@ -55,7 +91,6 @@ const IPSR_MASK: u32 = 0xFFFFFFC0;
 const EXC_RETURN_TO_HANDLER: u32 = 1;
 const EXC_RETURN_TO_THREAD_MSP: u32 = 9;
 const EXC_RETURN_TO_THREAD_PSP: u32 = 0xD;
-const COMPRESS_BAL: &'static str = "\n    ...\n";
 
 macro_rules! unpack_operands {
     ($variant:expr, $_type:path, $($vari:ident),+) => {
@@ -92,8 +127,56 @@ type ProcessStackFrame = [u32;8];
 pub const PAGE_SIZE: usize = 2048; 
 pub type Page = [u8;PAGE_SIZE];
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::ops::{Deref, DerefMut};
+
+// a single core owns its pages outright, they only move behind a lock once share hands them to another core
+enum Pages{
+   Owned(HashMap<u32,Page>),
+   Shared(Arc<Mutex<HashMap<u32,Page>>>)
+}
+
+enum PagesRef<'a>{
+   Owned(&'a HashMap<u32,Page>),
+   Shared(MutexGuard<'a,HashMap<u32,Page>>)
+}
+
+enum PagesMut<'a>{
+   Owned(&'a mut HashMap<u32,Page>),
+   Shared(MutexGuard<'a,HashMap<u32,Page>>)
+}
+
+impl Deref for PagesRef<'_>{
+   type Target = HashMap<u32,Page>;
+   fn deref(&self)->&Self::Target{
+      match self{
+         PagesRef::Owned(m) => m,
+         PagesRef::Shared(m) => m
+      }
+   }
+}
+
+impl Deref for PagesMut<'_>{
+   type Target = HashMap<u32,Page>;
+   fn deref(&self)->&Self::Target{
+      match self{
+         PagesMut::Owned(m) => m,
+         PagesMut::Shared(m) => m
+      }
+   }
+}
+
+impl DerefMut for PagesMut<'_>{
+   fn deref_mut(&mut self)->&mut Self::Target{
+      match self{
+         PagesMut::Owned(m) => m,
+         PagesMut::Shared(m) => m
+      }
+   }
+}
+
 pub struct BlockAllocator{
-   memory: HashMap<u32,Page>
+   memory: Pages
 }
 
 impl BlockAllocator{
@@ -101,11 +184,11 @@ impl BlockAllocator{
    pub fn create()->Self{
       let mut mem = HashMap::new();
       mem.insert(0_u32,[0_u8;PAGE_SIZE]);
-      Self{memory: mem}
+      Self::init(mem)
    }
 
    pub fn init(memory: HashMap<u32,Page>)->Self{
-      Self{memory}
+      Self{memory: Pages::Owned(memory)}
    }
 
    pub fn fill(data: &[u8])->Self{
@@ -115,10 +198,36 @@ impl BlockAllocator{
          page[.. block.len()].copy_from_slice(block);
          memory.insert(page_num as u32, page);
       }
-      Self{memory}
+      Self::init(memory)
+   }
+
+   // another handle onto the same pages, writes through either are seen by both
+   pub fn share(&mut self)->Self{
+      if let Pages::Owned(memory) = &mut self.memory{
+         self.memory = Pages::Shared(Arc::new(Mutex::new(std::mem::take(memory))));
+      }
+      match &self.memory{
+         Pages::Shared(memory) => Self{memory: Pages::Shared(memory.clone())},
+         Pages::Owned(_) => unreachable!()
+      }
+   }
+
+   fn pages_ref(&self)->PagesRef<'_>{
+      match &self.memory{
+         Pages::Owned(m) => PagesRef::Owned(m),
+         Pages::Shared(m) => PagesRef::Shared(m.lock().unwrap())
+      }
+   }
+
+   fn pages_mut(&mut self)->PagesMut<'_>{
+      match &mut self.memory{
+         Pages::Owned(m) => PagesMut::Owned(m),
+         Pages::Shared(m) => PagesMut::Shared(m.lock().unwrap())
+      }
    }
 
    pub fn view(&self, start: u32, inclusive_end: u32)->Vec<u8>{
+      let memory = self.pages_ref();
       let page_num = start / (PAGE_SIZE as u32);
       let offset = start - (page_num * PAGE_SIZE as u32);
 
@@ -128,7 +237,7 @@ impl BlockAllocator{
       let mut page_counter = page_num;
 
       let def_page: Page = [0;PAGE_SIZE];
-      let mut block = match self.memory.get(&page_counter){
+      let mut block = match memory.get(&page_counter){
          Some(p) => p,
          None => &def_page,
       };
@@ -138,7 +247,7 @@ impl BlockAllocator{
             i = 0; 
             page_counter += 1;
 
-            block = match self.memory.get(&page_counter){
+            block = match memory.get(&page_counter){
                Some(p) => p,
                None => &def_page,
             };
@@ -151,14 +260,15 @@ impl BlockAllocator{
    }
 
    pub fn pages(&self)->usize{
-      self.memory.len()
+      self.pages_ref().len()
    }
 
    pub fn get_instr_32b(&self, addr: u32)->[u8;4]{
+      let memory = self.pages_ref();
       let page_num = addr / (PAGE_SIZE as u32);
       let offset = addr - (page_num * PAGE_SIZE as u32);
 
-      match self.memory.get(&page_num){
+      match memory.get(&page_num){
          Some(page) => {
             if (offset as usize + 4) <= PAGE_SIZE{
                page[offset as usize .. (offset as usize + 4)]
@@ -168,7 +278,7 @@ impl BlockAllocator{
                assert_eq!(offset as usize,PAGE_SIZE - 2,"simulator err: tried instruction fetch not 16b aligned");
                let end_page_num = page_num + 1;
                let mut word = [page[PAGE_SIZE-2], page[PAGE_SIZE-1],0,0];
-               match self.memory.get(&end_page_num){
+               match memory.get(&end_page_num){
                   Some(next_page) => {
                      word[2] = next_page[0];
                      word[3] = next_page[1];
@@ -187,7 +297,7 @@ impl BlockAllocator{
       let offset = addr - (page_num * PAGE_SIZE as u32);
       assert!((offset as usize) + T <= PAGE_SIZE,"{}-byte access to {} should be aligned so this should never happen, this is a simulator bug",T,addr);
 
-      match self.memory.get(&page_num){
+      match self.pages_ref().get(&page_num){
          Some(page) => {
             page[offset as usize .. (offset as usize + T)]
                .try_into()
@@ -201,14 +311,15 @@ impl BlockAllocator{
       let page_num = start_addr / (PAGE_SIZE as u32);
       let offset = start_addr - (page_num * PAGE_SIZE as u32);
       assert!((offset as usize) + T <= PAGE_SIZE,"{}-byte access to {} should be aligned so this should never happen, this is a simulator bug",T,start_addr);
-      match self.memory.get_mut(&page_num){
+      let mut memory = self.pages_mut();
+      match memory.get_mut(&page_num){
         Some(page) => {
            page[offset as usize .. (offset as usize + T)].copy_from_slice(&values);
         },
         None => {
            let mut new_page: Page = [0;PAGE_SIZE];
            new_page[offset as usize .. (offset as usize + T)].copy_from_slice(&values);
-           self.memory.insert(page_num, new_page);
+           memory.insert(page_num, new_page);
         },
       }
    }
@@ -228,18 +339,29 @@ impl System{
          event_register: false,
          active_exceptions: [ExceptionStatus::Inactive; 48],
          primask: false,
+         basepri: 0,
+         faultmask: false,
          scs: SystemControlSpace::reset(),
          mode: Mode::Thread, // when not in a exception the processor is in thread mode
          //memory: vec![0;capacity],
          breakpoints: Vec::new(),
+         breakpoint_conditions: HashMap::new(),
          trace_enabled: false,
          //trace: String::new(),
          trace: Trace::create(200),
+         observers: Observers::default(),
          alloc: BlockAllocator::create(),
          reset_cfg: None,
          vtor_override: None,
          locked_up: false,
-         error_msg: String::new()
+         error_msg: String::new(),
+         sio: None,
+         stimuli: Schedule::default(),
+         sleep: SleepStats::default(),
+         debug: DebugState::default(),
+         profile: Profile::default(),
+         big_endian: false,
+         exclusive_monitor: None
       }
    }
 
@@ -253,17 +375,28 @@ impl System{
          active_exceptions: [ExceptionStatus::Inactive; 48],
          scs: SystemControlSpace::reset(),
          primask: false,
+         basepri: 0,
+         faultmask: false,
          mode: Mode::Thread, // when not in a exception the processor is in thread mode
          //memory: Vec::new(),
          breakpoints: Vec::new(),
+         breakpoint_conditions: HashMap::new(),
          trace_enabled: false,
          //trace: String::new(),
          trace: Trace::create(200),
+         observers: Observers::default(),
          alloc: BlockAllocator::fill(text),
          reset_cfg: None,
          vtor_override: None,
          locked_up: false,
-         error_msg: String::new()
+         error_msg: String::new(),
+         sio: None,
+         stimuli: Schedule::default(),
+         sleep: SleepStats::default(),
+         debug: DebugState::default(),
+         profile: Profile::default(),
+         big_endian: false,
+         exclusive_monitor: None
       }
    }
 
@@ -295,17 +428,28 @@ impl System{
          active_exceptions: [ExceptionStatus::Inactive; 48],
          scs: SystemControlSpace::reset(),
          primask: false,
+         basepri: 0,
+         faultmask: false,
          mode: Mode::Thread, // when not in a exception the processor is in thread mode
          //memory: Vec::new(),
          breakpoints: Vec::new(),
+         breakpoint_conditions: HashMap::new(),
          trace_enabled: false,
          //trace: String::new(),
          trace: Trace::create(200),
+         observers: Observers::default(),
          alloc: BlockAllocator::init(memory),
          reset_cfg: None,
          vtor_override: None,
          locked_up: false,
-         error_msg: String::new()
+         error_msg: String::new(),
+         sio: None,
+         stimuli: Schedule::default(),
+         sleep: SleepStats::default(),
+         debug: DebugState::default(),
+         profile: Profile::default(),
+         big_endian: false,
+         exclusive_monitor: None
       }
    }
 
@@ -423,6 +567,72 @@ impl System{
       return Ok(());
    }
 
+   pub fn primask(&self)->bool{
+      self.primask
+   }
+
+   pub fn basepri(&self)->u8{
+      self.basepri
+   }
+
+   pub fn faultmask(&self)->bool{
+      self.faultmask
+   }
+
+   // register writes made from outside the core (debugger, UI), checked the same way the core would
+   pub fn write_core_register(&mut self, register: u8, v: u32)->Result<(),ArmException>{
+      match register{
+         0 ..=12 => {
+            self.registers.generic[register as usize] = v;
+            Ok(())
+         },
+         13 => self.set_sp_with(&self.mode.clone(), self.sp_select_bit(), v),
+         14 => {
+            self.registers.lr = v;
+            Ok(())
+         },
+         15 => self.set_pc(v as usize),
+         _ => Err(ArmException::HardFault(format!("r{} is not a core register",register)))
+      }
+   }
+
+   pub fn write_special_register(&mut self, special: &SpecialRegister, v: u32)->Result<(),ArmException>{
+      if special.needs_privileged_access(RegAccess::WRITE) && !self.in_privileged_mode(){
+         return Err(ArmException::HardFault(format!("Do not have WRITE access to {:?} in {:?} mode",special,self.mode)));
+      }
+      if !self.special_register_available(special){
+         return Err(ArmException::HardFault(format!("{:?} is not available on {}",special,self.profile.name())));
+      }
+      match special{
+         SpecialRegister::MSP => {
+            fault_if_not_aligned(v, 4)?;
+            self.registers.sp_main = v;
+         },
+         SpecialRegister::PSP => {
+            fault_if_not_aligned(v, 4)?;
+            self.registers.sp_process = v;
+         },
+         SpecialRegister::CONTROL => {
+            if matches!(self.mode,Mode::Handler) && (v & 2) != (from_arm_bytes(self.control_register) & 2){
+               return Err(ArmException::HardFault("cannot change CONTROL.SP_SEL in HANDLER mode".into()));
+            }
+            self.control_register = into_arm_bytes(v & special.mask());
+         },
+         SpecialRegister::PRIMASK => {
+            self.primask = v & 1 > 0;
+         },
+         SpecialRegister::BASEPRI | SpecialRegister::BASEPRI_MAX | SpecialRegister::FAULTMASK => {
+            self.write_priority_mask(special, v);
+         },
+         SpecialRegister::Reserved(_) => {},
+         xpsr_registers!() => {
+            let xpsr = (from_arm_bytes(self.xpsr) & !special.mask()) | (v & special.mask());
+            self.xpsr = into_arm_bytes(xpsr);
+         }
+      }
+      return Ok(());
+   }
+
    pub fn read_raw_ir(&self)->u32{
       self.registers.pc as u32
    }
@@ -456,10 +666,7 @@ impl System{
          match self.exception_return(addr){
             Ok(exc_n) => {
                println!("returned from {} exception ",exception_name(exc_n));
-               if self.trace_enabled{
-                  self.trace.push_str(&format!("returned from {} exception ",exception_name(exc_n)));
-                  self.trace.push('\n');
-               }
+               self.notify(|o| o.on_exception_return(exc_n));
                return Ok(0);
             }
             Err(e)=>{
@@ -546,6 +753,23 @@ impl System{
       println!("locked up at priority lvl: {} [{}]",self.execution_priority(self.primask,&self.scs),error_msg);
       self.error_msg.clear();
       self.error_msg.push_str(error_msg);
+      self.notify(|o| o.on_lockup(error_msg));
+   }
+
+   pub fn add_observer(&mut self, observer: Box<dyn SystemObserver>){
+      self.observers.list.get_mut().push(observer);
+   }
+
+   // the trace is fed first when enabled, then every registered observer
+   fn notify(&mut self, event: impl Fn(&mut dyn SystemObserver)){
+      if self.trace_enabled{
+         event(&mut self.trace);
+      }
+      self.observers.notify(&event);
+   }
+
+   fn is_observed(&self)->bool{
+      self.trace_enabled || !self.observers.is_empty()
    }
 
    pub fn set_exc_pending(&mut self, exc: ArmException){
@@ -575,10 +799,33 @@ impl System{
       }
    }
 
+   pub fn clear_exc_pending(&mut self, exc_n: u32){
+      match self.active_exceptions[exc_n as usize]{
+         ExceptionStatus::Pending => {
+            println!("{} downgraded Pending -> Inactive",exception_name(exc_n));
+            self.active_exceptions[exc_n as usize] = ExceptionStatus::Inactive;
+         },
+         ExceptionStatus::ActiveAndPending => {
+            println!("{} downgraded ActiveAndPending -> Active",exception_name(exc_n));
+            self.active_exceptions[exc_n as usize] = ExceptionStatus::Active;
+         },
+         _ => {}
+      }
+   }
+
+   pub fn current_priority(&self)->i32{
+      self.execution_priority(self.primask, &self.scs)
+   }
+
    pub fn check_for_exceptions(&mut self,offset: i32)->Option<u32>{
+      let reloads = self.scs.sys_timer_enabled && self.scs.clock_value == 0;
       if let Err(_) = self.scs.clock_tick(&self.mode){
          self.set_exc_pending(ArmException::SysTick);
       }
+      if reloads{
+         let reload = self.scs.clock_reset;
+         self.notify(|o| o.on_systick_reload(reload));
+      }
       let mut maybe_taken: Option<ArmException> =  None; 
       for i in 0 .. self.active_exceptions.len(){
          if let ExceptionStatus::Pending = &self.active_exceptions[i]{
@@ -647,16 +894,14 @@ impl System{
          return Ok(None);
       }else{
          println!("initialising {:?}(priority: {}) exception",exc_type,exc_type.priority_group(&self.scs));
-         if self.trace_enabled{
-            self.trace.push_str(&format!("initialising {:?}(priority: {}) exception",exc_type,exc_type.priority_group(&self.scs)));
-            self.trace.push('\n');
-         }
          //TODO once true async interrupts are supported check for late arriving async exceptions here
          self.save_context_frame(&exc_type,offset)?;
          let offset = self.jump_to_exception(&exc_type)?;
          println!("exception offset: {:#x}",offset);
          self.offset_pc(offset)?;
          println!("{:?} exception entry successful branched pc -> {:#x}",exc_type,offset);
+         let priority = exc_type.priority_group(&self.scs);
+         self.notify(|o| o.on_exception_entry(&exc_type, priority));
          return Ok(Some(self.get_ipsr()));
       }
    }
@@ -752,6 +997,9 @@ impl System{
       self.active_exceptions[exc_type.number() as usize] = ExceptionStatus::Active;
       self.scs.set_vec_active(exc_type.number());
       let vector_table = self.scs.vtor;
+      self.debug.catch_vector(exc_type.number());
+      self.set_it_state(0);
+      self.exclusive_monitor = None;
       let handler_addr = vector_table + (4 * exc_type.number());
       let handler_ptr = from_arm_bytes(load_memory::<4>(self, handler_addr)?);
       self.event_register = true;
@@ -804,11 +1052,13 @@ impl System{
       let new_sp = (self.get_sp() + 0x20) | (frame_alignment as u32 >> 3);
       println!("new SP computed for exception exit = {:#x}",new_sp);
       self.set_sp(new_sp)?;
+      // the IT bits only exist with the main extension
+      let it_mask = if self.profile.has_main_extension() {EPSR_IT_MASK} else {0};
       let new_xpsr = if matches!(self.mode,Mode::Thread) && self.get_npriv(){
          println!("forced into thread mode");
-         0xF1000000 & frame_xpsr
+         (0xF1000000 | it_mask) & frame_xpsr
       }else{
-         0xF100003F & frame_xpsr
+         (0xF100003F | it_mask) & frame_xpsr
       };
       println!("SP={:#x}({}) after loaded context frame ",self.get_sp(),self.get_sp());
       self.xpsr = into_arm_bytes(new_xpsr);
@@ -831,6 +1081,10 @@ impl System{
       }
 
       assert!(nested_exceptions > 0, "emulator err: return from an already inactive handler");
+      // returning from anything but the NMI clears FAULTMASK
+      if handled_exception != 2{
+         self.faultmask = false;
+      }
 
       let exc_ret_type = return_address & 0xF;
       println!("{} active exceptions", nested_exceptions);
@@ -888,6 +1142,7 @@ impl System{
 
       self.scs.clear_vec_active();
       self.scs.clear_vec_pending();
+      self.exclusive_monitor = None;
       self.load_context_frame(return_address)?;
       match self.mode{
          Mode::Thread => assert!(self.get_ipsr() == 0,"Thread mode must mean IPSR is 0"),
@@ -902,7 +1157,13 @@ impl System{
 
    #[inline]
    pub fn on_breakpoint(&self)->bool{
-      self.breakpoints.contains(&self.registers.pc)
+      if !self.breakpoints.contains(&self.registers.pc){
+         return false;
+      }
+      match self.breakpoint_conditions.get(&self.registers.pc){
+         Some(cond) => cond.holds(self),
+         None => true
+      }
    }
 
    #[inline]
@@ -917,22 +1178,66 @@ impl System{
       self.breakpoints.contains(&(addr as usize))
    }
 
+   pub fn add_conditional_breakpoint(&mut self, addr: u32, cond: Condition){
+      self.add_breakpoint(addr);
+      self.breakpoint_conditions.insert(addr as usize, cond);
+   }
+
    #[inline]
    pub fn remove_breakpoint(&mut self,addr: u32){
       self.breakpoints.retain(|brkpt| *brkpt != (addr as usize));
+      self.breakpoint_conditions.remove(&(addr as usize));
    }
 
    #[inline]
    pub fn clear_breakpoints(&mut self){
       self.breakpoints.clear();
+      self.breakpoint_conditions.clear();
    }
 
    pub fn is_locked_up(&self)->bool{
       self.locked_up
    }
 
-   pub fn step(&mut self)->Result<i32, ArmException>{
+   // decodes the instruction up front only when somebody is told about it executing
+   fn observed_step(&mut self)->Result<i32, ArmException>{
+      if self.locked_up || !self.epsr_t_bit() || !self.is_observed(){
+         return self.step_instruction();
+      }
+      let pc = self.registers.pc as u32;
+      let decoded = self.decode_at(pc);
+      if let Some((code, operands)) = &decoded{
+         self.notify(|o| o.on_execute(pc, code, operands));
+      }
+      let result = self.step_instruction();
+      if let (Ok(_), Some((code, operands))) = (&result, decoded){
+         if !self.locked_up{
+            self.notify(|o| o.on_retire(pc, &code, &operands));
+         }
+      }
+      result
+   }
+
+   pub(crate) fn decode_at(&self, pc: u32)->Option<(Opcode, Option<Operands>)>{
+      let hw = load_thumb_instr(self, pc).ok()?;
+      match instruction_size(hw){
+         InstructionSize::B16 => {
+            let code = Opcode::from(hw);
+            let operands = get_operands(&code, hw);
+            Some((code, operands))
+         },
+         InstructionSize::B32 => {
+            let word = load_instr_32b(self, pc).ok()?;
+            let code = Opcode::from(word);
+            let operands = get_operands_32b(&code, word);
+            Some((code, operands))
+         }
+      }
+   }
+
+   fn step_instruction(&mut self)->Result<i32, ArmException>{
       if !self.epsr_t_bit(){
+         self.record_usage_fault(UFSR_INVSTATE);
          return Err(ArmException::HardFault("EPSR.T must always be set".into()));
       }
 
@@ -956,41 +1261,8 @@ impl System{
                code,
                operands
             );
-            if self.trace_enabled{
-               if matches!(code,Opcode::_16Bit(B16::B_ALWAYS)){
-                  let offset = unpack_operands!(operands,Operands::B_ALWAYS,off);
-                  if offset != 0{
-                     serialise_instruction(
-                        self.trace.get(),
-                        self.registers.pc as u32,
-                        &code,
-                        &operands
-                     );
-                     //self.trace.push_str(&print_instruction(self.registers.pc as u32 , &code, &operands));
-                     self.trace.push('\n');
-                     self.trace.trim();
-                  }else{
-                     if !self.trace.ends_with(COMPRESS_BAL){
-                        serialise_instruction(
-                           self.trace.get(),
-                           self.registers.pc as u32,
-                           &code,
-                           &operands
-                        );
-                        self.trace.push_str(COMPRESS_BAL);
-                     }
-                  }
-               }else{
-                  serialise_instruction(
-                     &mut self.trace.get(),
-                     self.registers.pc as u32,
-                     &code,
-                     &operands
-                  );
-                  //self.trace.push_str(&print_instruction(self.registers.pc as u32 , &code, &operands));
-                  self.trace.push('\n');
-                  self.trace.trim();
-               }
+            if !self.profile.supports(&code){
+               return self.unsupported_instruction(&code);
             }
             match code {
                Opcode::_16Bit(B16::ADCS)=>{
@@ -1301,9 +1573,13 @@ impl System{
                },
 
                Opcode::_16Bit(B16::BREAKPOINT)=>{
-                  self.add_breakpoint(self.read_raw_ir());
-                  dbg_ln!("HIT breakpoint must explicitally step_over()");
-
+                  let imm8 = unpack_operands!(operands, Operands::BREAKPOINT, imm);
+                  if !self.debug.enabled(){
+                     // without a debugger BKPT escalates to HardFault
+                     return Err(ArmException::HardFault(format!("BKPT #{} executed with no debugger attached",imm8.0)));
+                  }
+                  dbg_ln!("HIT BKPT #{}",imm8.0);
+                  self.debug.breakpoint(self.read_raw_ir(), imm8.0 as u8);
                   return Ok(0);
                }
 
@@ -1366,7 +1642,7 @@ impl System{
                   return Ok(instr_size.in_bytes() as i32);
                },
 
-               Opcode::_16Bit(B16::CPS) => {
+               Opcode::_16Bit(B16::CPS) if matches!(operands,Some(Operands::Primask(_))) => {
                   let interrupt_flag = unpack_operands!(
                      operands,
                      Operands::Primask,
@@ -1384,6 +1660,25 @@ impl System{
                   return Ok(instr_size.in_bytes() as i32);
                },
 
+               Opcode::_16Bit(B16::CPS) => {
+                  let (disable, primask) = unpack_operands!(
+                     operands,
+                     Operands::Faultmask,
+                     a,b
+                  );
+                  if !self.special_register_available(&SpecialRegister::FAULTMASK){
+                     return self.unavailable_special_register(&SpecialRegister::FAULTMASK);
+                  }
+                  if self.in_privileged_mode(){
+                     if primask{
+                        self.primask = disable;
+                     }
+                     self.write_priority_mask(&SpecialRegister::FAULTMASK, disable as u32);
+                     dbg_ln!("FAULTMASK := {}",self.faultmask as u8);
+                  }
+                  Ok(instr_size.in_bytes() as i32)
+               },
+
                Opcode::_16Bit(B16::XOR_REG)=>{
                   let (dest,arg) = unpack_operands!(
                      operands,
@@ -1879,13 +2174,7 @@ impl System{
                },
 
                Opcode::_16Bit(B16::SEV)=>{
-                  self.event_register = true;
-
-                  if self.trace_enabled{
-                     self.trace.push_str("==SEV set event register==\n");
-                  }
-
-                  return Ok(instr_size.in_bytes() as i32);
+                  return Ok(self.send_event(instr_size.in_bytes() as i32));
                },
                
                Opcode::_16Bit(B16::STR_Imm5) => {
@@ -2238,21 +2527,11 @@ impl System{
                },
 
                Opcode::_16Bit(B16::WFE) => {
-                  if self.event_register{
-                     self.event_register = false;
-                     return Ok(instr_size.in_bytes() as i32);
-                  }else{
-                     return Ok(0_i32);
-                  }
+                  return Ok(self.wait_for_event(instr_size.in_bytes() as i32));
                },
 
                Opcode::_16Bit(B16::WFI)=>{
-                  if self.scs.wfi_wake_up{
-                     self.scs.wfi_wake_up = false;
-                     return Ok(instr_size.in_bytes() as i32);
-                  }else{
-                     return Ok(0);
-                  }
+                  return Ok(self.wait_for_interrupt(instr_size.in_bytes() as i32));
                },
 
                Opcode::_16Bit(B16::YIELD)=>{
@@ -2266,8 +2545,12 @@ impl System{
                      return Ok(0);
                   }
                   println!("WARN: execution of the UDF instructions will result in a hardfault");
+                  self.record_usage_fault(UFSR_UNDEFINSTR);
                   return Err(ArmException::HardFault(String::from("execution of a UDF instruction is undefined")));
                },
+               Opcode::_16Bit(ref op @ (B16::CBZ | B16::CBNZ | B16::IT)) => {
+                  return self.step_thumb2_16b(op, operands);
+               },
                Opcode::_32Bit(e) => {
                   panic!("decoded 32 bit instruction ({}), but expected instruction size of 16bits",e);
                }
@@ -2277,16 +2560,8 @@ impl System{
             let word: [u8;4] = load_instr_32b(&self, self.registers.pc as u32)?;
             let instr_32b = Opcode::from(word);
             let operands = get_operands_32b(&instr_32b, word);
-            if self.trace_enabled{
-                  serialise_instruction(
-                     &mut self.trace.get(),
-                     self.registers.pc as u32,
-                     &instr_32b,
-                     &operands
-                  );
-               //self.trace.push_str(&print_instruction(self.registers.pc as u32 , &instr_32b, &operands));
-               self.trace.push('\n');
-               self.trace.trim();
+            if !self.profile.supports(&instr_32b){
+               return self.unsupported_instruction(&instr_32b);
             }
             match instr_32b{
                Opcode::_32Bit(B32::MSR) => {
@@ -2304,6 +2579,9 @@ impl System{
                      return Ok(instr_size.in_bytes() as i32);
                   }
 
+                  if !self.special_register_available(&special){
+                     return self.unavailable_special_register(&special);
+                  }
                   assert!(src.0 != 13 && src.0 != 15,"MSR for R13 and R15 is UNDEFINED");
                   let src_value = self.read_any_register(src.0);
                   match special{
@@ -2333,6 +2611,10 @@ impl System{
                      SpecialRegister::PRIMASK =>{
                         self.primask = src_value & 1 > 0;
                      },
+                     SpecialRegister::BASEPRI | SpecialRegister::BASEPRI_MAX | SpecialRegister::FAULTMASK => {
+                        self.write_priority_mask(&special, src_value);
+                     },
+                     SpecialRegister::Reserved(_) => {},
                      xpsr_registers!() =>{
                         let xpsr = src_value & special.mask();
                         self.xpsr = into_arm_bytes(xpsr);
@@ -2349,6 +2631,9 @@ impl System{
                      a,b
                   );
 
+                  if !self.special_register_available(&special){
+                     return self.unavailable_special_register(&special);
+                  }
                   assert!(dest.0 != 13 && dest.0 != 15,"MRS for R13 and R15 is UNDEFINED");
                   let v = if special.needs_privileged_access(RegAccess::READ) && !self.in_privileged_mode(){
                      println!("Do not have READ access to {:?} in {:?} mode",special,self.mode);
@@ -2375,6 +2660,9 @@ impl System{
                         SpecialRegister::CONTROL => {
                            from_arm_bytes(self.control_register)
                         },
+                        SpecialRegister::BASEPRI | SpecialRegister::BASEPRI_MAX => self.basepri as u32,
+                        SpecialRegister::FAULTMASK => self.faultmask as u32,
+                        SpecialRegister::Reserved(_) => 0,
                      }
                   };
 
@@ -2413,28 +2701,96 @@ impl System{
                      return Ok(0);
                   }
                   println!("WARN: execution of the UDF instructions will result in a hardfault");
+                  self.record_usage_fault(UFSR_UNDEFINSTR);
                   return Err(ArmException::HardFault(String::from("execution of a UDF.W instruction is undefined")));
                },
+               Opcode::_32Bit(ref op) => self.step_thumb2(op, operands),
                Opcode::_16Bit(op) => panic!("ERR: simulator decoded 16bit {:?} but anticipated instruction size was 32 bits",op)
             }
          }
       }
    }
 
+   // WFE and WFE.W, returns the pc offset, 0 while the core waits
+   fn wait_for_event(&mut self, size: i32)->i32{
+      if self.event_register{
+         self.event_register = false;
+         return size;
+      }
+      self.sleep.waiting = true;
+      return 0;
+   }
+
+   fn wait_for_interrupt(&mut self, size: i32)->i32{
+      if self.scs.wfi_wake_up{
+         self.scs.wfi_wake_up = false;
+         return size;
+      }
+      self.sleep.waiting = true;
+      return 0;
+   }
+
+   fn send_event(&mut self, size: i32)->i32{
+      self.event_register = true;
+      if let Some(link) = &self.sio{
+         link.sio.lock().unwrap().send_event(link.core);
+      }
+
+      if self.trace_enabled{
+         self.trace.push_str("==SEV set event register==\n");
+      }
+      return size;
+   }
+
    pub fn set_vtor(&mut self, v: u32){
       self.vtor_override = Some(v);
       self.scs.vtor = v;
    }
 
+   pub fn set_endianness(&mut self, big_endian: bool){
+      self.big_endian = big_endian;
+      if big_endian{
+         self.scs.aircr |= AIRCR_ENDIANNESS;
+      }else{
+         self.scs.aircr &= !AIRCR_ENDIANNESS;
+      }
+   }
+
+   pub fn is_big_endian(&self)->bool{
+      self.scs.aircr & AIRCR_ENDIANNESS > 0
+   }
+
+   // reads a value straight from the pages, no permission checks or side effects. SIO registers read
+   // as they would on the bus but without popping the FIFO or claiming a spinlock.
+   // None when the access would run past the top of the address space
+   pub fn peek(&self, addr: u32, size: usize)->Option<u32>{
+      let end = addr.checked_add(size as u32 - 1)?;
+      if let Some(link) = self.sio.as_ref().filter(|_| Sio::is_region_sio(addr)){
+         let word = into_arm_bytes(link.peek(addr & !3));
+         return Some(from_data_bytes(&word[.. size], false));
+      }
+      Some(from_data_bytes(&self.alloc.view(addr, end), self.is_big_endian()))
+   }
+
    pub fn reset(&mut self){
       self.locked_up = false;
       self.mode = Mode::Thread;
       self.reset_ipsr();
       self.primask = false;
+      self.basepri = 0;
+      self.faultmask = false;
       self.control_register = [0;4];
       self.scs = SystemControlSpace::reset();
+      self.set_endianness(self.big_endian);
+      if self.profile.has_main_extension(){
+         self.scs.ccr = CCR_STKALIGN;
+      }
+      self.set_it_state(0);
+      self.exclusive_monitor = None;
       self.active_exceptions = [ExceptionStatus::Inactive;48];
       self.event_register = false;
+      self.stimuli.restart();
+      self.sleep = SleepStats::default();
       if self.vtor_override.is_some(){
          self.scs.vtor = self.vtor_override.unwrap();
       }
@@ -2444,9 +2800,9 @@ impl System{
       };
       self.registers.sp_main = main_sp_reset_val;
       self.registers.pc = reset_handler_ptr as usize;
-      if self.trace_enabled{
-         self.trace.push_str("====SYSTEM RESET OCCURED====\n");
-      }
+      self.debug.reset.set(true);
+      self.debug.catch_vector(1);
+      self.notify(|o| o.on_reset());
    }
 
    fn do_move(&mut self, dest: usize, value: u32)->Result<(),ArmException>{
@@ -2548,7 +2904,9 @@ impl System{
    }
 
    pub fn get_permissions(&self, addr: u32)->(MemPermission,bool){
-      //TODO allow configurable address attributes map with MPU
+      if let Some(permissions) = self.mpu_permissions(addr){
+         return permissions;
+      }
       self.default_permissions(addr)
    }
 
@@ -2577,7 +2935,13 @@ impl System{
 
    fn execution_priority(&self,primask: bool, scs: &SystemControlSpace)->i32{
       let mut cur_priority: i32 = 4;
-      let boosted_priority = if primask {0}else{4};
+      let mut boosted_priority = if primask {0}else{4};
+      if self.basepri != 0{
+         boosted_priority = std::cmp::min(boosted_priority,(self.basepri >> 6) as i32);
+      }
+      if self.faultmask{
+         boosted_priority = -1;
+      }
 
       dbg_ln!("boosted priority = {}",boosted_priority);
       for (i,status) in self.active_exceptions.iter().enumerate(){
@@ -2650,6 +3014,29 @@ fn load_memory_mapped_register(sys: &System, address: u32)->Result<u32,ArmExcept
 }
 
 pub fn load_memory<const T: usize>(sys: &System, v_addr: u32)->Result<[u8;T],ArmException>{
+   let bytes = read_bus::<T>(sys, v_addr)?;
+   observe_read(sys, v_addr, &bytes, sys.in_privileged_mode());
+   Ok(bytes)
+}
+
+pub fn load_memory_unprivileged<const T: usize>(sys: &System, v_addr: u32)->Result<[u8;T],ArmException>{
+   let bytes = read_bus_unprivileged::<T>(sys, v_addr)?;
+   observe_read(sys, v_addr, &bytes, false);
+   Ok(bytes)
+}
+
+fn observe_read(sys: &System, v_addr: u32, bytes: &[u8], privileged: bool){
+   if !sys.observers.is_empty(){
+      let access = MemoryAccess::from_bytes(v_addr, bytes, privileged);
+      sys.observers.notify(|o| o.on_read(&access));
+   }
+}
+
+fn read_bus<const T: usize>(sys: &System, v_addr: u32)->Result<[u8;T],ArmException>{
+   if let Some(v) = load_sio_register(sys, v_addr){
+      let mem_arr = into_arm_bytes(v);
+      return Ok(mem_arr[0 .. T].try_into().expect("these are definately the same size"));
+   }
    if is_region_ppb(v_addr){
       if T == 4{
          let v = load_memory_mapped_register(sys,v_addr)?;
@@ -2669,11 +3056,15 @@ pub fn load_memory<const T: usize>(sys: &System, v_addr: u32)->Result<[u8;T],Arm
       //   .try_into()
       //   .expect("should not access out of bounds memory");
       let mem = sys.alloc.get(v_addr);
-      return Ok(mem);
+      return Ok(data_order(sys, mem));
    }
 }
 
-pub fn load_memory_unprivileged<const T: usize>(sys: &System, v_addr: u32)->Result<[u8;T],ArmException>{
+fn read_bus_unprivileged<const T: usize>(sys: &System, v_addr: u32)->Result<[u8;T],ArmException>{
+   if let Some(v) = load_sio_register(sys, v_addr){
+      let mem_arr = into_arm_bytes(v);
+      return Ok(mem_arr[0 .. T].try_into().expect("these are definately the same size"));
+   }
    if is_region_ppb(v_addr){
       if T == 4{
          let v = load_memory_mapped_register(sys,v_addr)?;
@@ -2693,10 +3084,26 @@ pub fn load_memory_unprivileged<const T: usize>(sys: &System, v_addr: u32)->Resu
       //   .try_into()
       //   .expect("should not access out of bounds memory");
       let mem = sys.alloc.get(v_addr);
-      return Ok(mem);
+      return Ok(data_order(sys, mem));
+   }
+}
+
+
+// data accesses follow AIRCR.ENDIANNESS, instruction fetches and the PPB are always little endian
+fn data_order<const T: usize>(sys: &System, mut bytes: [u8;T])->[u8;T]{
+   if sys.is_big_endian(){
+      bytes.reverse();
    }
+   bytes
 }
 
+// SIO registers only exist on cores that are part of a multicore machine
+fn load_sio_register(sys: &System, v_addr: u32)->Option<u32>{
+   match &sys.sio{
+      Some(link) if Sio::is_region_sio(v_addr) => Some(link.read(v_addr & !3)),
+      _ => None
+   }
+}
 
 pub fn load_thumb_instr(sys: &System, v_addr: u32)->Result<[u8;2],ArmException>{
    fault_if_not_aligned(v_addr, 2)?;
@@ -2733,6 +3140,23 @@ pub fn load_memory<'a, const T: usize>(sys: &'a System, v_addr: u32)->Result<&'a
 */
 
 pub fn write_memory<const T: usize>(sys: &mut System, v_addr: u32, value: [u8;T])->Result<(), ArmException>{
+   write_bus(sys, v_addr, value)?;
+   if !sys.observers.is_empty(){
+      let access = MemoryAccess::from_bytes(v_addr, &value, sys.in_privileged_mode());
+      sys.observers.notify(|o| o.on_write(&access));
+   }
+   Ok(())
+}
+
+fn write_bus<const T: usize>(sys: &mut System, v_addr: u32, value: [u8;T])->Result<(), ArmException>{
+   if let Some(link) = sys.sio.as_ref().filter(|_| Sio::is_region_sio(v_addr)){
+      fault_if_not_aligned(v_addr, 4)?;
+      if T != 4{
+         return Err(ArmException::HardFault("Writes to SIO must be word sized".into()));
+      }
+      link.write(v_addr, from_arm_bytes(value[0..4].try_into().expect("size of write already valid")));
+      return Ok(());
+   }
    if is_region_ppb(v_addr){
       if T == 4{
          let mem: [u8;4] = value[0..4].try_into().expect("size of write already valid");
@@ -2745,7 +3169,7 @@ pub fn write_memory<const T: usize>(sys: &mut System, v_addr: u32, value: [u8;T]
       fault_if_not_aligned(v_addr, T)?;
       sys.check_permission(v_addr, Access::WRITE)?;
       //sys.memory[v_addr as usize ..(v_addr as usize + T )].copy_from_slice(&value);
-      sys.alloc.put(v_addr, value);
+      sys.alloc.put(v_addr, data_order(sys, value));
       return Ok(());
    }
 }
@@ -2774,7 +3198,7 @@ pub enum ArmException{
    ExternInterrupt(u32),
 }
 
-fn exception_name(num: u32)->String{
+pub fn exception_name(num: u32)->String{
    let mut ext: String = "NVIC Interrupt #".into();
    match num{
       0 => panic!("reserved exception number {}",num),
@@ -2895,7 +3319,24 @@ enum MemoryMappedRegister{
    scr,
    ccr,
    shpr2,
-   shpr3
+   shpr3,
+   shcsr,
+   cfsr,
+   hfsr,
+   mmfar,
+   bfar,
+   mpu_type,
+   mpu_ctrl,
+   mpu_rnr,
+   mpu_rbar,
+   mpu_rlar,
+   mpu_mair0,
+   mpu_mair1,
+   dfsr,
+   dhcsr,
+   dcrsr,
+   dcrdr,
+   demcr
 }
 
 impl MemoryMappedRegister{
@@ -2922,6 +3363,23 @@ impl MemoryMappedRegister{
          0xE000ED14 =>{ Some(Self::ccr) },
          0xE000ED1C =>{ Some(Self::shpr2) },
          0xE000ED20 =>{ Some(Self::shpr3) },
+         0xE000ED24 =>{ Some(Self::shcsr) },
+         0xE000ED28 =>{ Some(Self::cfsr) },
+         0xE000ED2C =>{ Some(Self::hfsr) },
+         0xE000ED30 =>{ Some(Self::dfsr) },
+         0xE000ED34 =>{ Some(Self::mmfar) },
+         0xE000ED38 =>{ Some(Self::bfar) },
+         0xE000ED90 =>{ Some(Self::mpu_type) },
+         0xE000ED94 =>{ Some(Self::mpu_ctrl) },
+         0xE000ED98 =>{ Some(Self::mpu_rnr) },
+         0xE000ED9C =>{ Some(Self::mpu_rbar) },
+         0xE000EDA0 =>{ Some(Self::mpu_rlar) },
+         0xE000EDC0 =>{ Some(Self::mpu_mair0) },
+         0xE000EDC4 =>{ Some(Self::mpu_mair1) },
+         0xE000EDF0 =>{ Some(Self::dhcsr) },
+         0xE000EDF4 =>{ Some(Self::dcrsr) },
+         0xE000EDF8 =>{ Some(Self::dcrdr) },
+         0xE000EDFC =>{ Some(Self::demcr) },
          _ => {
             dbg_ln!("WARN: {:#x} has no associated system register in the PPB space",address);
             None
@@ -2929,6 +3387,12 @@ impl MemoryMappedRegister{
       }
    }
 
+   fn is_mpu(&self)->bool{
+      matches!(self,
+         Self::mpu_type | Self::mpu_ctrl | Self::mpu_rnr | Self::mpu_rbar | Self::mpu_rlar | Self::mpu_mair0 | Self::mpu_mair1
+      )
+   }
+
    pub fn pending_external_interrupts(sys: &System)->u32{
       let mut pending: u32 = 0;
       for i in 0 .. 32{
@@ -2945,12 +3409,28 @@ impl MemoryMappedRegister{
    pub fn read(&self, sys: &System)->u32{
       match self{
          MemoryMappedRegister::icsr => sys.scs.icsr,
-         MemoryMappedRegister::vtor => 0,
-         MemoryMappedRegister::aircr => 0,
+         MemoryMappedRegister::vtor => sys.scs.vtor,
+         // VECTKEYSTAT reads back as 0xFA05, SYSRESETREQ and VECTCLRACTIVE are write only
+         MemoryMappedRegister::aircr => (0xFA05 << 16) | (sys.scs.aircr & AIRCR_ENDIANNESS),
          MemoryMappedRegister::scr => 0,
-         MemoryMappedRegister::ccr => 0x208,
+         // without the main extension STKALIGN and UNALIGN_TRP are fixed
+         MemoryMappedRegister::ccr => if sys.profile.has_main_extension() {sys.scs.ccr} else {0x208},
          MemoryMappedRegister::shpr2 => sys.scs.shpr2,
          MemoryMappedRegister::shpr3 => sys.scs.shpr3,
+         MemoryMappedRegister::shcsr => sys.scs.shcsr,
+         MemoryMappedRegister::cfsr => sys.scs.cfsr,
+         MemoryMappedRegister::hfsr => sys.scs.hfsr,
+         // no MPU or bus faults are simulated, so there is never a valid fault address
+         MemoryMappedRegister::mmfar | MemoryMappedRegister::bfar => 0,
+         // MPU_TYPE reads 0 when there is no MPU
+         _ if self.is_mpu() && !sys.profile.has_mpu() => 0,
+         MemoryMappedRegister::mpu_type => sys.scs.mpu.mpu_type(),
+         MemoryMappedRegister::mpu_ctrl => sys.scs.mpu.ctrl,
+         MemoryMappedRegister::mpu_rnr => sys.scs.mpu.rnr,
+         MemoryMappedRegister::mpu_rbar => sys.scs.mpu.rbar(),
+         MemoryMappedRegister::mpu_rlar => sys.scs.mpu.rlar(),
+         MemoryMappedRegister::mpu_mair0 => sys.scs.mpu.mair[0],
+         MemoryMappedRegister::mpu_mair1 => sys.scs.mpu.mair[1],
          MemoryMappedRegister::nvic_iser => sys.scs.enabled_interrupts,
          MemoryMappedRegister::nvic_icer => sys.scs.enabled_interrupts,
          MemoryMappedRegister::nvic_ispr => {
@@ -2971,7 +3451,12 @@ impl MemoryMappedRegister{
          },
          MemoryMappedRegister::syst_rvr=>{sys.scs.clock_reset},
          MemoryMappedRegister::syst_cvr=>{sys.scs.clock_value},
-         MemoryMappedRegister::syst_calib=>{0x80000000}
+         MemoryMappedRegister::syst_calib=>{0x80000000},
+         MemoryMappedRegister::dfsr => sys.debug.dfsr,
+         MemoryMappedRegister::dhcsr => sys.debug.dhcsr(sys),
+         MemoryMappedRegister::dcrsr => 0,
+         MemoryMappedRegister::dcrdr => sys.debug.dcrdr,
+         MemoryMappedRegister::demcr => sys.debug.demcr
       }
    }
 
@@ -3064,11 +3549,36 @@ impl MemoryMappedRegister{
             }
             sys.scs.icsr = v; 
          },
-         MemoryMappedRegister::vtor => {dbg_ln!("WARN: writes to VTOR are ignored");},
+         MemoryMappedRegister::vtor => {
+            // TBLOFF is bits [31:7], the new table is used from the next exception entry
+            dbg_ln!("VTOR set to {:#x}",v & VTOR_TBLOFF_MASK);
+            sys.scs.vtor = v & VTOR_TBLOFF_MASK;
+         },
          MemoryMappedRegister::aircr => {
-            if v & 0x4 > 0 {
-               dbg_ln!("Write to AIRCR.SYS_RESET_REQ system reset initialised");
-               sys.reset();
+            if (v >> 16) != AIRCR_VECTKEY{
+               println!("WARN: write to AIRCR without VECTKEY 0x05FA ignored ({:#010x})",v);
+               return;
+            }
+            if v & AIRCR_VECTCLRACTIVE > 0{
+               dbg_ln!("AIRCR.VECTCLRACTIVE clearing the active state of every exception");
+               for status in sys.active_exceptions.iter_mut(){
+                  *status = match *status{
+                     ExceptionStatus::Active => ExceptionStatus::Inactive,
+                     ExceptionStatus::ActiveAndPending => ExceptionStatus::Pending,
+                     other => other
+                  };
+               }
+               sys.reset_ipsr();
+               sys.scs.clear_vec_active();
+               sys.mode = Mode::Thread;
+            }
+            if v & AIRCR_SYSRESETREQ > 0{
+               dbg_ln!("Write to AIRCR.SYS_RESET_REQ system reset requested");
+               // the reset happens once the store completes, see Simulator::step_or_signal_halt_type
+               sys.scs.reset_requested = true;
+               if let Some(link) = &sys.sio{
+                  link.sio.lock().unwrap().request_reset();
+               }
             }
          },
          MemoryMappedRegister::scr => {
@@ -3076,8 +3586,37 @@ impl MemoryMappedRegister{
             sys.scs.scr = v;
          },
          MemoryMappedRegister::ccr => {
-            dbg_ln!("WARN: CCR is readonly");
+            if !sys.profile.has_main_extension(){
+               dbg_ln!("WARN: CCR is readonly");
+            }else{
+               sys.scs.ccr = v & CCR_MASK;
+            }
+         },
+         MemoryMappedRegister::shcsr => {
+            sys.scs.shcsr = v;
+         },
+         // the fault status registers are write one to clear
+         MemoryMappedRegister::cfsr => {
+            sys.scs.cfsr &= !v;
+         },
+         MemoryMappedRegister::hfsr => {
+            sys.scs.hfsr &= !v;
+         },
+         MemoryMappedRegister::mmfar | MemoryMappedRegister::bfar => {
+            dbg_ln!("WARN: MMFAR and BFAR are not implemented, writes are ignored");
+         },
+         _ if self.is_mpu() && !sys.profile.has_mpu() => {
+            dbg_ln!("WARN: {} has no MPU, write to {:?} ignored",sys.profile.name(),self);
          },
+         MemoryMappedRegister::mpu_type => {
+            dbg_ln!("WARN: MPU_TYPE is readonly");
+         },
+         MemoryMappedRegister::mpu_ctrl => sys.scs.mpu.write_ctrl(v),
+         MemoryMappedRegister::mpu_rnr => sys.scs.mpu.write_rnr(v),
+         MemoryMappedRegister::mpu_rbar => sys.scs.mpu.write_rbar(v),
+         MemoryMappedRegister::mpu_rlar => sys.scs.mpu.write_rlar(v),
+         MemoryMappedRegister::mpu_mair0 => sys.scs.mpu.mair[0] = v,
+         MemoryMappedRegister::mpu_mair1 => sys.scs.mpu.mair[1] = v,
          MemoryMappedRegister::shpr2 => {
             sys.scs.shpr2 = (v & 0xC0000000);
          },
@@ -3190,11 +3729,22 @@ impl MemoryMappedRegister{
             if sys.trace_enabled{
                sys.trace.push_str("WARN: SYST.CALIB is a read only register, write will be ignored\n");
             }
-         }
+         },
+         MemoryMappedRegister::dfsr => sys.debug.write_dfsr(v),
+         MemoryMappedRegister::dhcsr => sys.debug.write_dhcsr(v),
+         MemoryMappedRegister::dcrsr => write_dcrsr(sys, v),
+         MemoryMappedRegister::dcrdr => sys.debug.dcrdr = v,
+         MemoryMappedRegister::demcr => sys.debug.write_demcr(v)
       }
    }
 }
 
+pub const VTOR_TBLOFF_MASK: u32 = 0xFFFFFF80;
+pub const AIRCR_VECTKEY: u32 = 0x05FA;
+pub const AIRCR_ENDIANNESS: u32 = 1 << 15;
+pub const AIRCR_SYSRESETREQ: u32 = 1 << 2;
+pub const AIRCR_VECTCLRACTIVE: u32 = 1 << 1;
+
 pub struct SystemControlSpace{
    pub enabled_interrupts: u32,
    pub wfi_wake_up: bool,
@@ -3205,11 +3755,20 @@ pub struct SystemControlSpace{
    pub clock_reset: u32,
    pub icsr: u32,
    pub vtor: u32,
+   // only the read only ENDIANNESS bit is stored
    pub aircr: u32,
+   // set by AIRCR.SYSRESETREQ
+   pub reset_requested: bool,
    pub scr: u32,
    pub ccr: u32,
    pub shpr2: u32,
    pub shpr3: u32,
+   // the ARMv7-M fault status registers, always 0 on ARMv6-M
+   pub shcsr: u32,
+   pub cfsr: u32,
+   pub hfsr: u32,
+   // only implemented on ARMv8-M Baseline
+   pub mpu: Mpu,
    pub ipr: [u32;8]
 }
 
@@ -3226,10 +3785,15 @@ impl SystemControlSpace{
          icsr: 0,
          vtor: 0,
          aircr: 0,
+         reset_requested: false,
          scr: 0,
          ccr: 0x108,
          shpr2: 0,
          shpr3: 0,
+         shcsr: 0,
+         cfsr: 0,
+         hfsr: 0,
+         mpu: Mpu::default(),
          ipr: [0; 8] 
       }
    }
@@ -3278,6 +3842,14 @@ impl SystemControlSpace{
       self.icsr &= !(IPSR_MASK << 12);
    }
 
+   pub fn vec_active(&self)->u32{
+      self.icsr & 0x3F
+   }
+
+   pub fn vec_pending(&self)->u32{
+      (self.icsr >> 12) & 0x3F
+   }
+
    pub fn nvic_priority_of(&self, exec: u32)->i32{
       let word_offset = (exec - 16) & 0xFFFFFFFC;
       let intra_word_offset = (exec - 16) - word_offset;
@@ -3340,6 +3912,14 @@ impl MemPermission{
       }
    }
 
+   #[inline]
+   pub fn no_access()->Self{
+      Self{
+         privileged: AccessPermission::NoAccess,
+         unprivileged: AccessPermission::NoAccess
+      }
+   }
+
    pub fn from_mpu_rasr(raw: u32)->Result<Self, ArmException>{
       let perms = (raw & 0x03000000) >> 24;
       match perms {
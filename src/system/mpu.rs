@@ -0,0 +1,103 @@
+use super::{System, MemPermission, AccessPermission, is_region_ppb};
+
+// the ARMv8-M (PMSAv8) memory protection unit, each region covers [BASE, LIMIT] in 32 byte granules.
+// ARMv8-M Baseline has no MemManage exception, so every MPU fault is a HardFault
+
+pub const MPU_REGIONS: usize = 8;
+pub const MPU_CTRL_ENABLE: u32 = 1 << 0;
+pub const MPU_CTRL_HFNMIENA: u32 = 1 << 1;
+pub const MPU_CTRL_PRIVDEFENA: u32 = 1 << 2;
+const MPU_CTRL_MASK: u32 = 0x7;
+pub const MPU_RBAR_XN: u32 = 1 << 0;
+pub const MPU_RLAR_EN: u32 = 1 << 0;
+// LIMIT, AttrIndx and EN, bit 4 is reserved
+const MPU_RLAR_MASK: u32 = 0xFFFFFFEF;
+const MPU_GRANULE_MASK: u32 = 0xFFFFFFE0;
+
+#[derive(Clone,Debug,Default)]
+pub struct Mpu{
+   pub ctrl: u32,
+   pub rnr: u32,
+   pub rbar: [u32; MPU_REGIONS],
+   pub rlar: [u32; MPU_REGIONS],
+   // the attributes are stored but have no effect, there are no caches or buffers to configure
+   pub mair: [u32; 2]
+}
+
+impl Mpu{
+   // DREGION, a unified MPU
+   pub fn mpu_type(&self)->u32{
+      (MPU_REGIONS as u32) << 8
+   }
+
+   pub fn enabled(&self)->bool{
+      self.ctrl & MPU_CTRL_ENABLE > 0
+   }
+
+   pub fn write_ctrl(&mut self, v: u32){
+      self.ctrl = v & MPU_CTRL_MASK;
+   }
+
+   pub fn write_rnr(&mut self, v: u32){
+      if v as usize >= MPU_REGIONS{
+         println!("WARN: MPU_RNR {} is out of range, only {} regions are implemented",v,MPU_REGIONS);
+      }
+      self.rnr = v % MPU_REGIONS as u32;
+   }
+
+   pub fn rbar(&self)->u32{
+      self.rbar[self.rnr as usize]
+   }
+
+   pub fn rlar(&self)->u32{
+      self.rlar[self.rnr as usize]
+   }
+
+   pub fn write_rbar(&mut self, v: u32){
+      self.rbar[self.rnr as usize] = v;
+   }
+
+   pub fn write_rlar(&mut self, v: u32){
+      self.rlar[self.rnr as usize] = v & MPU_RLAR_MASK;
+   }
+
+   fn regions_containing(&self, addr: u32)->Vec<usize>{
+      (0 .. MPU_REGIONS).filter(|&i|{
+         let base = self.rbar[i] & MPU_GRANULE_MASK;
+         let limit = self.rlar[i] | !MPU_GRANULE_MASK;
+         self.rlar[i] & MPU_RLAR_EN > 0 && base <= addr && addr <= limit
+      }).collect()
+   }
+}
+
+fn region_permissions(rbar: u32)->(MemPermission,bool){
+   let (privileged, unprivileged) = match (rbar >> 1) & 3{
+      0 => (AccessPermission::ReadAndWrite, AccessPermission::NoAccess),
+      1 => (AccessPermission::ReadAndWrite, AccessPermission::ReadAndWrite),
+      2 => (AccessPermission::ReadOnly, AccessPermission::NoAccess),
+      _ => (AccessPermission::ReadOnly, AccessPermission::ReadOnly)
+   };
+   (MemPermission{privileged, unprivileged}, rbar & MPU_RBAR_XN > 0)
+}
+
+impl System{
+   // None when the MPU does not apply to the access and the default memory map is used
+   pub(crate) fn mpu_permissions(&self, addr: u32)->Option<(MemPermission,bool)>{
+      let mpu = &self.scs.mpu;
+      if !self.profile.has_mpu() || !mpu.enabled() || is_region_ppb(addr){
+         return None;
+      }
+      if self.current_priority() < 0 && mpu.ctrl & MPU_CTRL_HFNMIENA == 0{
+         return None;
+      }
+      match mpu.regions_containing(addr)[..]{
+         [region] => Some(region_permissions(mpu.rbar[region])),
+         [] if mpu.ctrl & MPU_CTRL_PRIVDEFENA > 0 => {
+            let (default, exec_never) = self.default_permissions(addr);
+            Some((MemPermission{privileged: default.privileged, unprivileged: AccessPermission::NoAccess}, exec_never))
+         },
+         // a miss without the background region and hitting overlapping regions both fault
+         _ => Some((MemPermission::no_access(), true))
+      }
+   }
+}
@@ -0,0 +1,61 @@
+use std::cell::RefCell;
+
+use crate::asm::decode::Opcode;
+use crate::asm::decode_operands::Operands;
+use crate::binutils::from_arm_bytes;
+use super::ArmException;
+
+// execution events for tracing, coverage, profiling and checkers, register one on a System with
+// add_observer. every callback does nothing unless implemented, observers see the core but cannot change it
+
+pub trait SystemObserver: Send{
+   // an instruction at pc is about to run and may still fault, instructions skipped by IT are not executed
+   fn on_execute(&mut self, _pc: u32, _code: &Opcode, _operands: &Option<Operands>){}
+   // an instruction at pc completed without faulting, instructions skipped by IT are not retired
+   fn on_retire(&mut self, _pc: u32, _code: &Opcode, _operands: &Option<Operands>){}
+   // data accesses through the bus (including exception stacking), values are as the core sees them
+   fn on_read(&mut self, _access: &MemoryAccess){}
+   fn on_write(&mut self, _access: &MemoryAccess){}
+   // the exception has been stacked and its handler is about to run, priority is its group on entry
+   fn on_exception_entry(&mut self, _exception: &ArmException, _priority: i32){}
+   fn on_exception_return(&mut self, _exc_n: u32){}
+   fn on_reset(&mut self){}
+   fn on_lockup(&mut self, _msg: &str){}
+   // SYST_CVR wrapped from 0 to the SYST_RVR value
+   fn on_systick_reload(&mut self, _reload: u32){}
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct MemoryAccess{
+   pub address: u32,
+   pub size: usize,
+   pub value: u32,
+   pub privileged: bool
+}
+
+impl MemoryAccess{
+   pub fn from_bytes(address: u32, bytes: &[u8], privileged: bool)->Self{
+      let mut word = [0_u8;4];
+      word[.. bytes.len()].copy_from_slice(bytes);
+      Self{address, size: bytes.len(), value: from_arm_bytes(word), privileged}
+   }
+}
+
+// memory is read through &System, so the observers sit behind a RefCell like the debug state's Cells
+#[derive(Default)]
+pub struct Observers{
+   pub(crate) list: RefCell<Vec<Box<dyn SystemObserver>>>
+}
+
+impl Observers{
+   pub fn is_empty(&self)->bool{
+      self.list.borrow().is_empty()
+   }
+
+   pub fn notify(&self, mut event: impl FnMut(&mut dyn SystemObserver)){
+      // an observer is never handed the System, so this borrow cannot be re-entered
+      for observer in self.list.borrow_mut().iter_mut(){
+         event(observer.as_mut());
+      }
+   }
+}
@@ -0,0 +1,80 @@
+use crate::asm::decode::{Opcode, B16, B32};
+
+// the architecture a core implements, the decoder understands every Thumb encoding
+// but only the instructions of the selected profile will execute
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub enum Profile{
+   // Cortex-M0/M0+, the default
+   #[default]
+   ArmV6M,
+   // Cortex-M3, adds the 32 bit Thumb-2 encodings, IT blocks and the configurable fault registers
+   ArmV7M,
+   // Cortex-M23, ARMv6-M plus a handful of 32 bit instructions and the PMSAv8 MPU
+   ArmV8MBaseline
+}
+
+impl Profile{
+   pub fn from_name(name: &str)->Option<Self>{
+      match name.to_lowercase().as_str(){
+         "armv6-m" | "cortex-m0" | "cortex-m0+" => Some(Profile::ArmV6M),
+         "armv7-m" | "cortex-m3" => Some(Profile::ArmV7M),
+         "armv8-m.base" | "armv8-m.baseline" | "cortex-m23" => Some(Profile::ArmV8MBaseline),
+         _ => None
+      }
+   }
+
+   pub fn name(&self)->&'static str{
+      match self{
+         Profile::ArmV6M => "armv6-m",
+         Profile::ArmV7M => "armv7-m",
+         Profile::ArmV8MBaseline => "armv8-m.base"
+      }
+   }
+
+   pub fn supports(&self, code: &Opcode)->bool{
+      match self{
+         Profile::ArmV6M => is_armv6m(code),
+         Profile::ArmV7M => !is_acquire_release(code),
+         Profile::ArmV8MBaseline => is_armv6m(code) || is_armv8m_baseline(code)
+      }
+   }
+
+   // IT blocks, CCR.UNALIGN_TRP/DIV_0_TRP and the configurable fault status registers
+   pub fn has_main_extension(&self)->bool{
+      *self == Profile::ArmV7M
+   }
+
+   pub fn has_mpu(&self)->bool{
+      *self == Profile::ArmV8MBaseline
+   }
+}
+
+fn is_armv6m(code: &Opcode)->bool{
+   match code{
+      Opcode::_16Bit(B16::CBZ) | Opcode::_16Bit(B16::CBNZ) | Opcode::_16Bit(B16::IT) => false,
+      Opcode::_16Bit(_) => true,
+      Opcode::_32Bit(op) => matches!(
+         op,
+         B32::UNDEFINED | B32::BR_AND_LNK | B32::DMB | B32::DSB | B32::ISB | B32::MRS | B32::MSR
+      )
+   }
+}
+
+// the instructions ARMv8-M Baseline adds to ARMv6-M
+fn is_armv8m_baseline(code: &Opcode)->bool{
+   match code{
+      Opcode::_16Bit(op) => matches!(op, B16::CBZ | B16::CBNZ),
+      Opcode::_32Bit(op) => is_acquire_release(code) || matches!(
+         op,
+         B32::MOVW | B32::MOVT | B32::SDIV | B32::UDIV | B32::B_W | B32::CLREX
+         | B32::LDREX | B32::LDREXB | B32::LDREXH | B32::STREX | B32::STREXB | B32::STREXH
+      )
+   }
+}
+
+fn is_acquire_release(code: &Opcode)->bool{
+   matches!(
+      code,
+      Opcode::_32Bit(B32::LDA(_)) | Opcode::_32Bit(B32::STL(_)) | Opcode::_32Bit(B32::LDAEX(_)) | Opcode::_32Bit(B32::STLEX(_))
+   )
+}
@@ -4,6 +4,7 @@ use crate::asm::Word;
 use super::Access;
 pub type Apsr = Word;//Application Program Status Register
 
+#[allow(non_camel_case_types)]
 #[derive(Debug,PartialEq)]
 pub enum SpecialRegister{
    APSR,
@@ -16,7 +17,12 @@ pub enum SpecialRegister{
    MSP,
    PSP,
    PRIMASK,
-   CONTROL
+   BASEPRI,
+   BASEPRI_MAX,
+   FAULTMASK,
+   CONTROL,
+   // SYSm values with no register behind them, MSR/MRS on these is UNPREDICTABLE
+   Reserved(u8)
 }
 pub enum RegAccess{
    READ,
@@ -36,13 +42,22 @@ impl SpecialRegister{
          SpecialRegister::MSP => true,
          SpecialRegister::PSP => true,
          SpecialRegister::PRIMASK => true,
+         SpecialRegister::BASEPRI => true,
+         SpecialRegister::BASEPRI_MAX => true,
+         SpecialRegister::FAULTMASK => true,
          SpecialRegister::CONTROL => match access{
             RegAccess::READ => false,
             RegAccess::WRITE => true,
         },
+         SpecialRegister::Reserved(_) => true,
       }
    }
 
+   // BASEPRI, BASEPRI_MAX and FAULTMASK only exist with the main extension (ARMv7-M)
+   pub fn needs_main_extension(&self)->bool{
+      matches!(self,SpecialRegister::BASEPRI | SpecialRegister::BASEPRI_MAX | SpecialRegister::FAULTMASK)
+   }
+
    pub fn mask(&self)->u32{
       let apsr = 0xF0000000;
       let epsr = 0x01000200;
@@ -58,7 +73,12 @@ impl SpecialRegister{
         SpecialRegister::MSP => u32::MAX,
         SpecialRegister::PSP => u32::MAX,
         SpecialRegister::PRIMASK => 1,
+        // only the two priority bits the NVIC implements are kept
+        SpecialRegister::BASEPRI => 0xC0,
+        SpecialRegister::BASEPRI_MAX => 0xC0,
+        SpecialRegister::FAULTMASK => 1,
         SpecialRegister::CONTROL => 3,
+        SpecialRegister::Reserved(_) => 0,
       }
    }
 }
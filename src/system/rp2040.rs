@@ -0,0 +1,245 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use crate::dbg_ln;
+use super::{System, ExceptionStatus, ArmException};
+
+// the single-cycle IO block of the RP2040, only the parts the cores use to talk to each other
+// are modelled: CPUID, the inter-core FIFOs and the hardware spinlocks
+
+pub const SIO_BASE: u32 = 0xD0000000;
+const SIO_END: u32 = 0xD0000FFF;
+
+const CPUID: u32 = 0x000;
+const FIFO_ST: u32 = 0x050;
+const FIFO_WR: u32 = 0x054;
+const FIFO_RD: u32 = 0x058;
+const SPINLOCK_ST: u32 = 0x05C;
+const SPINLOCK_0: u32 = 0x100;
+const SPINLOCK_31: u32 = 0x17C;
+
+const FIFO_DEPTH: usize = 8;
+const FIFO_ST_VLD: u32 = 1;
+const FIFO_ST_RDY: u32 = 2;
+const FIFO_ST_WOF: u32 = 4;
+const FIFO_ST_ROE: u32 = 8;
+
+// SIO_IRQ_PROC0 and SIO_IRQ_PROC1 are wired to NVIC lines 15 and 16 of their own core
+pub const SIO_IRQ_PROC0: u32 = 15;
+
+// the words multicore_launch_core1 sends before the vector table, stack pointer and entry point
+const LAUNCH_PREAMBLE: [u32;3] = [0, 0, 1];
+
+pub struct Sio{
+   // fifos[n] holds the words written by core n, waiting to be read by the other core
+   fifos: [VecDeque<u32>;2],
+   fifo_flags: [u32;2],
+   spinlocks: u32,
+   events: [bool;2],
+   core1_parked: bool,
+   launch_seq: usize,
+   launch_args: [u32;3],
+   reset_requested: bool
+}
+
+impl Sio{
+   pub fn create()->Self{
+      Self{
+         fifos: [VecDeque::new(), VecDeque::new()],
+         fifo_flags: [0;2],
+         spinlocks: 0,
+         events: [false;2],
+         core1_parked: true,
+         launch_seq: 0,
+         launch_args: [0;3],
+         reset_requested: false
+      }
+   }
+
+   pub fn is_region_sio(addr: u32)->bool{
+      (SIO_BASE ..= SIO_END).contains(&addr)
+   }
+
+   pub fn read(&mut self, core: u32, addr: u32)->u32{
+      let other = (1 - core) as usize;
+      match addr - SIO_BASE{
+         CPUID => core,
+         FIFO_ST => self.fifo_status(core),
+         FIFO_RD => match self.fifos[other].pop_front(){
+            Some(v) => v,
+            None => {
+               dbg_ln!("WARN: core{} read from an empty FIFO",core);
+               self.fifo_flags[core as usize] |= FIFO_ST_ROE;
+               0
+            }
+         },
+         SPINLOCK_ST => self.spinlocks,
+         offset @ SPINLOCK_0 ..= SPINLOCK_31 => {
+            let lock = 1 << ((offset - SPINLOCK_0) / 4);
+            if self.spinlocks & lock > 0{
+               0
+            }else{
+               self.spinlocks |= lock;
+               lock
+            }
+         },
+         offset => {
+            dbg_ln!("WARN: SIO register at offset {:#x} is not implemented",offset);
+            0
+         }
+      }
+   }
+
+   // what read would return, without popping the FIFO or claiming a spinlock. for the debugger and UI
+   pub fn peek(&self, core: u32, addr: u32)->u32{
+      let other = (1 - core) as usize;
+      match addr - SIO_BASE{
+         CPUID => core,
+         FIFO_ST => self.fifo_status(core),
+         FIFO_RD => self.fifos[other].front().copied().unwrap_or(0),
+         SPINLOCK_ST => self.spinlocks,
+         offset @ SPINLOCK_0 ..= SPINLOCK_31 => {
+            let lock = 1 << ((offset - SPINLOCK_0) / 4);
+            if self.spinlocks & lock > 0 { 0 } else { lock }
+         },
+         _ => 0
+      }
+   }
+
+   pub fn write(&mut self, core: u32, addr: u32, v: u32){
+      match addr - SIO_BASE{
+         FIFO_ST => {
+            // WOF and ROE are cleared by writing any value
+            self.fifo_flags[core as usize] = 0;
+         },
+         FIFO_WR => self.push(core, v),
+         offset @ SPINLOCK_0 ..= SPINLOCK_31 => {
+            self.spinlocks &= !(1 << ((offset - SPINLOCK_0) / 4));
+         },
+         offset => {
+            dbg_ln!("WARN: write to SIO register at offset {:#x} ignored",offset);
+         }
+      }
+   }
+
+   fn push(&mut self, core: u32, v: u32){
+      if self.fifos[core as usize].len() < FIFO_DEPTH{
+         self.fifos[core as usize].push_back(v);
+      }else{
+         dbg_ln!("WARN: core{} wrote to a full FIFO",core);
+         self.fifo_flags[core as usize] |= FIFO_ST_WOF;
+      }
+   }
+
+   pub fn fifo_status(&self, core: u32)->u32{
+      let other = (1 - core) as usize;
+      let mut status = self.fifo_flags[core as usize];
+      if !self.fifos[other].is_empty(){
+         status |= FIFO_ST_VLD;
+      }
+      if self.fifos[core as usize].len() < FIFO_DEPTH{
+         status |= FIFO_ST_RDY;
+      }
+      status
+   }
+
+   // the FIFO interrupt is asserted while there is data to read or an error flag is set
+   pub fn fifo_irq_asserted(&self, core: u32)->bool{
+      self.fifo_status(core) & (FIFO_ST_VLD | FIFO_ST_WOF | FIFO_ST_ROE) > 0
+   }
+
+   pub fn send_event(&mut self, from_core: u32){
+      self.events[(1 - from_core) as usize] = true;
+   }
+
+   pub fn take_event(&mut self, core: u32)->bool{
+      let ev = self.events[core as usize];
+      self.events[core as usize] = false;
+      ev
+   }
+
+   // nothing is waiting to be delivered to either core
+   pub fn quiescent(&self)->bool{
+      !self.events.iter().any(|e| *e) && !self.fifo_irq_asserted(0) && !self.fifo_irq_asserted(1)
+   }
+
+   pub fn request_reset(&mut self){
+      self.reset_requested = true;
+   }
+
+   pub fn take_reset_request(&mut self)->bool{
+      let requested = self.reset_requested;
+      self.reset_requested = false;
+      requested
+   }
+
+   pub fn core1_parked(&self)->bool{
+      self.core1_parked
+   }
+
+   pub fn reset(&mut self){
+      *self = Self::create();
+   }
+
+   // stands in for the bootrom loop core1 runs after reset, every word from core0 is echoed back
+   // and once the full launch sequence has arrived the vector table, stack pointer and entry point are returned
+   pub fn bootrom_step(&mut self)->Option<[u32;3]>{
+      let cmd = self.fifos[0].pop_front()?;
+      self.push(1, cmd);
+      self.events[0] = true;
+      if self.launch_seq < LAUNCH_PREAMBLE.len(){
+         self.launch_seq = if cmd == LAUNCH_PREAMBLE[self.launch_seq]{ self.launch_seq + 1 }else{ 0 };
+         return None;
+      }
+      self.launch_args[self.launch_seq - LAUNCH_PREAMBLE.len()] = cmd;
+      self.launch_seq += 1;
+      if self.launch_seq == LAUNCH_PREAMBLE.len() + self.launch_args.len(){
+         self.launch_seq = 0;
+         self.core1_parked = false;
+         return Some(self.launch_args);
+      }
+      return None;
+   }
+}
+
+// how a core reaches the SIO block it shares with the other core
+pub struct SioLink{
+   pub core: u32,
+   pub sio: Arc<Mutex<Sio>>
+}
+
+impl SioLink{
+   pub fn read(&self, addr: u32)->u32{
+      self.sio.lock().unwrap().read(self.core, addr)
+   }
+
+   pub fn write(&self, addr: u32, v: u32){
+      self.sio.lock().unwrap().write(self.core, addr, v)
+   }
+
+   pub fn peek(&self, addr: u32)->u32{
+      self.sio.lock().unwrap().peek(self.core, addr)
+   }
+}
+
+// called before each step of a core, carries events and the FIFO interrupt over from the SIO block
+pub fn sync_core(sys: &mut System){
+   let (event, irq) = match &sys.sio{
+      Some(link) => {
+         let mut sio = link.sio.lock().unwrap();
+         (sio.take_event(link.core), sio.fifo_irq_asserted(link.core))
+      },
+      None => return
+   };
+   if event{
+      sys.event_register = true;
+   }
+   if irq{
+      let core = sys.sio.as_ref().unwrap().core;
+      let n = 16 + SIO_IRQ_PROC0 + core;
+      if matches!(sys.active_exceptions[n as usize], ExceptionStatus::Inactive){
+         sys.set_exc_pending(ArmException::ExternInterrupt(n));
+         sys.scs.wfi_wake_up = true;
+      }
+   }
+}
@@ -1,49 +1,74 @@
 use std::{thread::{JoinHandle, self}, sync::mpsc::{Sender, channel, Receiver,TryRecvError}};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use std::time::{Duration, Instant};
 
 use super::{System, ArmException};
-use crate::ui::Debug; 
+use super::rp2040::{Sio, SioLink, sync_core};
+use super::expression::Condition;
+use super::stimulus::apply_stimuli;
+use super::sleep::{idle_steps, skip};
+use super::debug::DebugHalt;
+use crate::ui::Debug;
 
 #[derive(Clone,Debug)]
 pub enum HaltType{
    error(ArmException),
    lockup,
-   breakpoint,
-   usercmd
+   // Some(imm) when the core executed BKPT #imm, None for breakpoints set from the front end
+   breakpoint(Option<u8>),
+   usercmd,
+   // halting debug through DHCSR or a DEMCR vector catch
+   debug(DebugHalt)
 }
 
 pub struct Simulator;
 
 //TODO consider having the step signal return the current ip address
 impl Simulator{
+   // same step as step_or_signal_halt_type, for front ends that handle halts as debug events
    pub fn step_or_signal_halt(sys: &mut System)->Result<(),Debug>{
-      if sys.is_locked_up(){
-         return Err(Debug::Halt(HaltType::lockup));
-      }
-      match sys.step(){
-         Ok(offset) => {
-            if sys.check_for_exceptions(offset).is_none(){
-               Self::halt_if_err(sys.offset_pc(offset))
-            }else{
-               return Ok(());
-            }
-         },
-         Err(e) => {
-            let offset = match e{
-                ArmException::HardFault(_) => 0,
-                _ => panic!("simulator error: {:?} caused simualator to abandon instruction execution, but that should only occur when a hardfault occurs",e)
-            };
-            sys.set_exc_pending(e);
-            sys.check_for_exceptions(offset);
-            return Ok(());
-         },
-      }
+      Self::halt_if_err(Self::step_or_signal_halt_type(sys))
    }
 
    pub fn step_or_signal_halt_type(sys: &mut System)->Result<(),HaltType>{
       if sys.is_locked_up(){
          return Err(HaltType::lockup);
       }
-      match sys.step(){
+      // stepping a core in debug state resumes it, a core halted on a BKPT steps past it unless
+      // the debugger moved PC. halts raised outside a step (e.g. reset vector catch) stop it before it executes anything
+      if let Some(addr) = sys.debug.resume(){
+         if sys.read_raw_ir() == addr{
+            return sys.offset_pc(2).map_err(HaltType::error);
+         }
+      }
+      if let Some(reason) = sys.debug.take_halt(){
+         return Err(HaltType::debug(reason));
+      }
+      Self::execute(sys)?;
+      match sys.debug.take_halt(){
+         Some(reason) => Err(HaltType::debug(reason)),
+         None => Ok(())
+      }
+   }
+
+   fn execute(sys: &mut System)->Result<(),HaltType>{
+      apply_stimuli(sys);
+      sys.sleep.waiting = false;
+      let stepped = sys.step();
+      if sys.sleep.waiting{
+         sys.sleep.asleep += 1;
+      }else if stepped.is_ok() && !sys.debug.halted{
+         sys.debug.retire();
+      }
+      match stepped{
+         Ok(_) if sys.debug.halted => {
+            let imm = sys.debug.bkpt.map(|(_, imm)| imm);
+            return Err(HaltType::breakpoint(imm));
+         },
+         Ok(_) if sys.scs.reset_requested => {
+            sys.reset();
+            return Ok(());
+         },
          Ok(offset) => {
             if sys.check_for_exceptions(offset).is_none(){
                match sys.offset_pc(offset){
@@ -66,10 +91,341 @@ impl Simulator{
       }
    }
 
-   fn halt_if_err(cond: Result<(),ArmException>)->Result<(),Debug>{
+   fn halt_if_err(cond: Result<(),HaltType>)->Result<(),Debug>{
       match cond{
          Ok(_) => Ok(()),
-         Err(e) => Err(Debug::Halt(HaltType::error(e))),
+         Err(e) => Err(Debug::Halt(e)),
+      }
+   }
+}
+
+// skips at most limit steps, returns how many were skipped
+fn skip_idle(cores: &mut [MutexGuard<System>], limit: u64)->u64{
+   let mut n = limit;
+   for sys in cores.iter(){
+      if !sys.sleep.waiting{
+         return 0;
+      }
+      match idle_steps(sys){
+         Some(steps) => n = n.min(steps),
+         None => return 0
+      }
+   }
+   if n == 0{
+      return 0;
+   }
+   for sys in cores.iter_mut(){
+      skip(sys, n);
+   }
+   n
+}
+
+pub const MIN_BATCH: u64 = 64;
+pub const MAX_BATCH: u64 = 1 << 20;
+// how long a batch should hold the core, short enough that halt and breakpoint commands feel immediate
+const BATCH_TIME: Duration = Duration::from_millis(10);
+const REPORT_INTERVAL: Duration = Duration::from_millis(500);
+
+// sizes the batches given to Machine::run and measures instructions per second while running
+pub struct Throughput{
+   pub batch: u64,
+   steps: u64,
+   started: Instant
+}
+
+impl Throughput{
+   pub fn start()->Self{
+      Self{batch: MIN_BATCH, steps: 0, started: Instant::now()}
+   }
+
+   // doubles or halves the batch towards BATCH_TIME, returns the instructions per second once per REPORT_INTERVAL
+   pub fn record(&mut self, steps: u64, elapsed: Duration)->Option<u64>{
+      if steps == self.batch{
+         if elapsed < BATCH_TIME / 2{
+            self.batch = (self.batch * 2).min(MAX_BATCH);
+         }else if elapsed > BATCH_TIME * 2{
+            self.batch = (self.batch / 2).max(MIN_BATCH);
+         }
+      }
+      self.steps += steps;
+      if self.started.elapsed() >= REPORT_INTERVAL{
+         return Some(self.report());
+      }
+      None
+   }
+
+   // the rate since the last report, restarts the measurement
+   pub fn report(&mut self)->u64{
+      let secs = self.started.elapsed().as_secs_f64();
+      let ips = if secs > 0.0 { (self.steps as f64 / secs) as u64 } else { 0 };
+      self.steps = 0;
+      self.started = Instant::now();
+      ips
+   }
+}
+
+// the simulator has no cycle model, every step counts as one cycle of the core clock
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+// holds a running machine to clock_hz steps per second of wall clock time, None runs at full speed
+pub struct Pacer{
+   pub clock_hz: Option<u64>,
+   steps: u64,
+   started: Instant,
+   // the time source, Instant::now outside of tests
+   now: Box<dyn Fn()->Instant>
+}
+
+impl Pacer{
+   pub fn new(clock_hz: Option<u64>)->Self{
+      Self::with_clock(clock_hz, Instant::now)
+   }
+
+   pub fn with_clock(clock_hz: Option<u64>, now: impl Fn()->Instant + 'static)->Self{
+      Self{clock_hz, steps: 0, started: now(), now: Box::new(now)}
+   }
+
+   pub fn set_clock(&mut self, clock_hz: Option<u64>){
+      self.clock_hz = clock_hz;
+      self.steps = 0;
+      self.started = (self.now)();
+   }
+
+   // a throttled batch runs at most BATCH_TIME worth of cycles so the machine never races ahead of the clock,
+   // and nothing at all while it is still ahead
+   pub fn budget(&self, batch: u64)->u64{
+      match self.clock_hz{
+         Some(hz) if self.due(hz) > self.elapsed() => 0,
+         Some(hz) => batch.min((hz * BATCH_TIME.as_millis() as u64 / 1000).max(1)),
+         None => batch
+      }
+   }
+
+   // how long to wait after another `steps` so that the cycles match the time since the run started. waits are
+   // at most BATCH_TIME so commands are still polled at slow clocks, a machine that cannot keep up runs flat out
+   // instead of bursting to catch up later
+   pub fn delay(&mut self, steps: u64)->Duration{
+      let hz = match self.clock_hz{
+         Some(hz) => hz,
+         None => return Duration::ZERO
+      };
+      self.steps += steps;
+      let elapsed = self.elapsed();
+      if elapsed > self.due(hz) + MAX_LAG{
+         self.set_clock(Some(hz));
+         return Duration::ZERO;
+      }
+      self.due(hz).saturating_sub(elapsed).min(BATCH_TIME)
+   }
+
+   fn elapsed(&self)->Duration{
+      (self.now)().saturating_duration_since(self.started)
+   }
+
+   // when the steps run so far should have finished
+   fn due(&self, hz: u64)->Duration{
+      Duration::from_secs_f64(self.steps as f64 / hz as f64)
+   }
+}
+
+// one or more cores sharing a bus, multicore machines are stepped round robin one instruction per core
+pub struct Machine{
+   pub cores: Vec<Arc<Mutex<System>>>,
+   pub sio: Option<Arc<Mutex<Sio>>>
+}
+
+impl Machine{
+   pub fn single(sys: System)->Self{
+      Self{cores: vec![Arc::new(Mutex::new(sys))], sio: None}
+   }
+
+   // core1 shares core0's memory and sits in the bootrom until core0 launches it through the FIFO
+   pub fn rp2040(mut core0: System)->Self{
+      let sio = Arc::new(Mutex::new(Sio::create()));
+      let mut core1 = System::create(0);
+      core1.alloc = core0.alloc.share();
+      core1.trace_enabled = core0.trace_enabled;
+      core1.vtor_override = core0.vtor_override;
+      core0.sio = Some(SioLink{core: 0, sio: sio.clone()});
+      core1.sio = Some(SioLink{core: 1, sio: sio.clone()});
+      Self{
+         cores: vec![Arc::new(Mutex::new(core0)), Arc::new(Mutex::new(core1))],
+         sio: Some(sio)
+      }
+   }
+
+   pub fn share(&self)->Self{
+      Self{cores: self.cores.clone(), sio: self.sio.clone()}
+   }
+
+   pub fn n_cores(&self)->usize{
+      self.cores.len()
+   }
+
+   fn core1_parked(&self)->bool{
+      match &self.sio{
+         Some(sio) => sio.lock().unwrap().core1_parked(),
+         None => false
+      }
+   }
+
+   fn lock_cores(&self)->Vec<MutexGuard<'_,System>>{
+      self.cores.iter().map(|core| core.lock().unwrap()).collect()
+   }
+
+   // None when another thread holds one of the cores
+   fn try_lock_cores(&self)->Option<Vec<MutexGuard<'_,System>>>{
+      let mut cores = vec![];
+      for core in self.cores.iter(){
+         match core.try_lock(){
+            Ok(sys) => cores.push(sys),
+            Err(TryLockError::WouldBlock) => return None,
+            Err(TryLockError::Poisoned(_)) => panic!("unexpected error occured in DEBUG thread, this is definately a problem! ")
+         }
+      }
+      Some(cores)
+   }
+
+   // when every running core is waiting in WFI/WFE jump them all forward to the first wake up,
+   // but no more than limit steps. returns the steps skipped
+   fn fast_forward(&self, cores: &mut [MutexGuard<System>], limit: u64)->u64{
+      if let Some(sio) = &self.sio{
+         if !sio.lock().unwrap().quiescent(){
+            return 0;
+         }
+      }
+      let running = if self.core1_parked() { 1 } else { cores.len() };
+      skip_idle(&mut cores[..running], limit)
+   }
+
+   pub fn step(&self)->Result<(),HaltType>{
+      let mut cores = self.lock_cores();
+      self.fast_forward(&mut cores, u64::MAX);
+      self.step_cores(&mut cores)
+   }
+
+   // steps unless another thread holds a core, e.g. the run thread mid batch.
+   // deciding and stepping under the same locks keeps that thread from starting in between
+   pub fn try_step(&self)->Option<Result<(),HaltType>>{
+      let mut cores = self.try_lock_cores()?;
+      self.fast_forward(&mut cores, u64::MAX);
+      Some(self.step_cores(&mut cores))
+   }
+
+   fn step_cores(&self, cores: &mut [MutexGuard<System>])->Result<(),HaltType>{
+      for (i,sys) in cores.iter_mut().enumerate(){
+         if i == 1 && self.core1_parked(){
+            let launch = self.sio.as_ref().unwrap().lock().unwrap().bootrom_step();
+            if let Some([vtor, sp, entry]) = launch{
+               println!("core1 launched: VTOR = {:#x} SP = {:#x} entry = {:#x}",vtor,sp,entry);
+               sys.scs.vtor = vtor;
+               sys.registers.sp_main = sp;
+               if let Err(e) = sys.set_pc((entry & !1) as usize){
+                  return Err(HaltType::error(e));
+               }
+            }
+            continue;
+         }
+         sync_core(sys);
+         Simulator::step_or_signal_halt_type(sys)?;
+      }
+      // a system reset requested by either core resets both, core1 goes back to the bootrom
+      if self.sio.as_ref().is_some_and(|sio| sio.lock().unwrap().take_reset_request()){
+         self.reset_cores(cores);
+      }
+      return Ok(());
+   }
+
+   // runs up to budget steps, stopping on exactly the step that halts or lands on a breakpoint.
+   // steps skipped while every core sleeps count towards the budget and the returned total, so a throttled
+   // machine charges them to the Pacer like executed ones.
+   // a single core stays locked for the whole batch, multicore machines still step round robin
+   pub fn run(&self, budget: u64)->(u64, Result<(),HaltType>){
+      let mut n = 0;
+      if self.cores.len() > 1{
+         while n < budget{
+            let mut cores = self.lock_cores();
+            n += self.fast_forward(&mut cores, budget - n);
+            if n == budget{
+               break;
+            }
+            n += 1;
+            let stepped = self.step_cores(&mut cores);
+            drop(cores);
+            if let Err(e) = stepped{
+               return (n, Err(e));
+            }
+            if self.on_breakpoint(){
+               return (n, Err(HaltType::breakpoint(None)));
+            }
+         }
+         return (n, Ok(()));
+      }
+      let mut sys = self.cores[0].lock().unwrap();
+      while n < budget{
+         n += skip_idle(std::slice::from_mut(&mut sys), budget - n);
+         if n == budget{
+            break;
+         }
+         n += 1;
+         if let Err(e) = Simulator::step_or_signal_halt_type(&mut sys){
+            return (n, Err(e));
+         }
+         if sys.on_breakpoint(){
+            return (n, Err(HaltType::breakpoint(None)));
+         }
+      }
+      (n, Ok(()))
+   }
+
+   pub fn on_breakpoint(&self)->bool{
+      let parked = self.core1_parked();
+      self.cores.iter().enumerate()
+         .filter(|(i,_)| !(*i == 1 && parked))
+         .any(|(_,core)| core.lock().unwrap().on_breakpoint())
+   }
+
+   pub fn reset(&self){
+      self.reset_cores(&mut self.lock_cores());
+   }
+
+   fn reset_cores(&self, cores: &mut [MutexGuard<System>]){
+      if let Some(sio) = &self.sio{
+         sio.lock().unwrap().reset();
+      }
+      for sys in cores.iter_mut(){
+         sys.reset();
+      }
+   }
+
+   // sets DHCSR.C_DEBUGEN, software can see whether it runs under a debugger and halting debug is enabled
+   pub fn attach_debugger(&self, attached: bool){
+      for core in self.cores.iter(){
+         core.lock().unwrap().debug.set_enabled(attached);
+      }
+   }
+
+   pub fn add_breakpoint(&self, addr: u32){
+      for core in self.cores.iter(){
+         core.lock().unwrap().add_breakpoint(addr);
+      }
+   }
+
+   pub fn add_conditional_breakpoint(&self, addr: u32, cond: &Condition){
+      for core in self.cores.iter(){
+         core.lock().unwrap().add_conditional_breakpoint(addr, cond.clone());
+      }
+   }
+
+   pub fn remove_breakpoint(&self, addr: u32){
+      for core in self.cores.iter(){
+         core.lock().unwrap().remove_breakpoint(addr);
+      }
+   }
+
+   pub fn clear_breakpoints(&self){
+      for core in self.cores.iter(){
+         core.lock().unwrap().clear_breakpoints();
       }
    }
 }
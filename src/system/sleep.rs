@@ -0,0 +1,78 @@
+use crate::asm::decode::{Opcode, B16, B32, instruction_size, InstructionSize};
+use super::{System, ExceptionStatus, Mode, load_thumb_instr, load_instr_32b};
+
+// a core waiting in WFI/WFE changes nothing but the SysTick counter and the stimulus step count,
+// so instead of busy stepping it the simulator skips straight to the step where it can wake up
+
+// with no wake up source a sleeping core is skipped forward this far per step
+pub const MAX_SLEEP_SKIP: u64 = 1 << 24;
+
+#[derive(Clone,Debug,Default)]
+pub struct SleepStats{
+   // steps spent waiting in WFI/WFE, skipped or not
+   pub asleep: u64,
+   // of those, the steps that were skipped rather than executed
+   pub skipped: u64,
+   // set by WFI/WFE when the last step waited, only these cores are checked for fast-forwarding
+   pub waiting: bool
+}
+
+fn is_asleep(sys: &System)->bool{
+   if sys.is_locked_up(){
+      return false;
+   }
+   let code = match load_thumb_instr(sys, sys.registers.pc as u32){
+      Ok(hw) if matches!(instruction_size(hw), InstructionSize::B32) => {
+         load_instr_32b(sys, sys.registers.pc as u32).map(Opcode::from)
+      },
+      hw => hw.map(Opcode::from)
+   };
+   match code{
+      Ok(Opcode::_16Bit(B16::WFI)) | Ok(Opcode::_32Bit(B32::WFI_W)) => !sys.scs.wfi_wake_up,
+      Ok(Opcode::_16Bit(B16::WFE)) | Ok(Opcode::_32Bit(B32::WFE_W)) => !sys.event_register,
+      _ => false
+   }
+}
+
+// a pending exception the core will take on its next step ends the sleep
+fn can_take_pending(sys: &System)->bool{
+   sys.active_exceptions.iter().enumerate().any(|(n, status)|{
+      matches!(status, ExceptionStatus::Pending) && can_preempt(sys, n as u32)
+   })
+}
+
+// exception n is enabled and beats the current execution priority, so pending it would wake a WFI
+pub fn can_preempt(sys: &System, n: u32)->bool{
+   (n < 16 || sys.scs.is_nvic_interrupt_enabled(n - 16))
+      && super::ArmException::from_exception_number(n).is_some_and(|exc| exc.priority_group(&sys.scs) < sys.current_priority())
+}
+
+// how many steps can be skipped without missing a wake up, None when the core is awake
+pub fn idle_steps(sys: &System)->Option<u64>{
+   if !is_asleep(sys) || can_take_pending(sys){
+      return None;
+   }
+   let mut steps = MAX_SLEEP_SKIP;
+   // SysTick only counts down in thread mode, stop before it reloads
+   if sys.scs.sys_timer_enabled && (matches!(sys.mode, Mode::Thread) || sys.scs.clock_value == 0){
+      steps = steps.min(sys.scs.clock_value as u64);
+   }
+   if let Some(n) = sys.stimuli.steps_until_next(sys.registers.pc as u32){
+      steps = steps.min(n);
+   }
+   return Some(steps);
+}
+
+// leaves the core exactly as n busy steps of WFI/WFE would, n must not exceed idle_steps
+pub fn skip(sys: &mut System, n: u64){
+   if sys.scs.sys_timer_enabled && matches!(sys.mode, Mode::Thread){
+      sys.scs.clock_value -= n as u32;
+   }
+   let pc = sys.registers.pc as u32;
+   sys.stimuli.advance(pc, n);
+   sys.sleep.skipped += n;
+   sys.sleep.asleep += n;
+   if sys.trace_enabled{
+      sys.trace.push_str(&format!("slept for {} steps at {:#010x}\n",n,pc));
+   }
+}
@@ -0,0 +1,229 @@
+use std::fmt::Display;
+
+use crate::dbg_ln;
+use crate::elf::decoder::SymbolDefinition;
+use super::{System, ArmException, MemoryMappedRegister};
+
+// external stimuli injected into a core on a fixed schedule, e.g.
+//    pend irq 5 at instruction 10000
+//    pend irq 3 every 2500 cycles
+//    trigger nmi when pc == handler_x
+//    set event register at step 40
+// the simulator has no cycle model so cycles, steps and instructions all count the
+// instructions stepped on the core since reset, which keeps every run reproducible
+
+#[derive(Clone,Debug,PartialEq)]
+pub enum Trigger{
+   At(u64),
+   Every(u64),
+   // fires when PC arrives at the address, staying on it (e.g. BAL .) does not refire
+   PcEquals(u32)
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub enum Action{
+   // the external interrupt line, 0 .. 31, not the exception number
+   PendIrq(u32),
+   Nmi,
+   PendSV,
+   SysTick,
+   SetEvent
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Stimulus{
+   pub action: Action,
+   pub trigger: Trigger
+}
+
+#[derive(Clone,Debug,Default)]
+pub struct Schedule{
+   pub stimuli: Vec<Stimulus>,
+   steps: u64,
+   last_pc: Option<u32>
+}
+
+impl Display for Action{
+   fn fmt(&self, f: &mut std::fmt::Formatter<'_>)->std::fmt::Result{
+      match self{
+         Action::PendIrq(n) => write!(f, "pend irq {}", n),
+         Action::Nmi => write!(f, "trigger nmi"),
+         Action::PendSV => write!(f, "pend pendsv"),
+         Action::SysTick => write!(f, "pend systick"),
+         Action::SetEvent => write!(f, "set event register")
+      }
+   }
+}
+
+impl Display for Stimulus{
+   fn fmt(&self, f: &mut std::fmt::Formatter<'_>)->std::fmt::Result{
+      match self.trigger{
+         Trigger::At(n) => write!(f, "{} at step {}", self.action, n),
+         Trigger::Every(n) => write!(f, "{} every {} steps", self.action, n),
+         Trigger::PcEquals(addr) => write!(f, "{} when pc == {:#010x}", self.action, addr)
+      }
+   }
+}
+
+fn parse_number(input: &str)->Option<u64>{
+   match input.strip_prefix("0x").or(input.strip_prefix("0X")){
+      Some(hex) => u64::from_str_radix(hex, 16).ok(),
+      None => input.parse::<u64>().ok()
+   }
+}
+
+fn resolve_addr(input: &str, symbols: &[SymbolDefinition])->Result<u32,String>{
+   if let Some(v) = parse_number(input){
+      return u32::try_from(v).map_err(|_| format!("{} is not a 32 bit address",input));
+   }
+   symbols.iter()
+      .find(|s| s.name.eq(input))
+      .map(|s| s.position as u32 & !1)
+      .ok_or(format!("no symbol or address matches '{}'",input))
+}
+
+impl Schedule{
+   // one stimulus per line, blank lines and anything after a # are ignored
+   pub fn parse(text: &str, symbols: &[SymbolDefinition])->Result<Self,String>{
+      let mut schedule = Schedule::default();
+      for (i, line) in text.lines().enumerate(){
+         let line = line.split('#').next().unwrap().trim();
+         if line.is_empty(){
+            continue;
+         }
+         let stimulus = Self::parse_line(line, symbols).map_err(|e| format!("line {}: {}",i + 1,e))?;
+         schedule.stimuli.push(stimulus);
+      }
+      return Ok(schedule);
+   }
+
+   pub fn parse_line(line: &str, symbols: &[SymbolDefinition])->Result<Stimulus,String>{
+      let spaced = line.replace("==", " == ");
+      let mut tokens: Vec<&str> = spaced.split_whitespace().collect();
+      let is = |token: Option<&&str>, word: &str| token.is_some_and(|t| t.eq_ignore_ascii_case(word));
+
+      if is(tokens.first(), "pend") || is(tokens.first(), "trigger") || is(tokens.first(), "set") || is(tokens.first(), "raise"){
+         tokens.remove(0);
+      }
+      let first = tokens.first().map(|t| t.to_lowercase()).unwrap_or_default();
+      let (action, used) = match first.as_str(){
+         "irq" => {
+            let n = tokens.get(1).and_then(|t| parse_number(t)).ok_or("expected an IRQ number after irq".to_owned())?;
+            if n >= 32{
+               return Err(format!("IRQ {} does not exist, the NVIC has 32 interrupt lines",n));
+            }
+            (Action::PendIrq(n as u32), 2)
+         },
+         "nmi" => (Action::Nmi, 1),
+         "pendsv" => (Action::PendSV, 1),
+         "systick" => (Action::SysTick, 1),
+         "event" if is(tokens.get(1), "register") => (Action::SetEvent, 2),
+         "event" => (Action::SetEvent, 1),
+         "" => return Err("expected a stimulus (irq <n>, nmi, pendsv, systick or event)".into()),
+         other => return Err(format!("unknown stimulus '{}', expected irq <n>, nmi, pendsv, systick or event",other))
+      };
+
+      let rest = &tokens[used ..];
+      let trigger = match rest.first().map(|t| t.to_lowercase()).as_deref(){
+         Some("at") => {
+            let mut args = &rest[1 ..];
+            if args.first().is_some_and(|t| ["instruction","step","cycle"].contains(&t.to_lowercase().as_str())){
+               args = &args[1 ..];
+            }
+            match args{
+               [n] => Trigger::At(parse_number(n).ok_or(format!("'{}' is not a step count",n))?),
+               _ => return Err("expected at [instruction|step|cycle] <n>".into())
+            }
+         },
+         Some("every") => {
+            let period = match &rest[1 ..]{
+               [n] => n,
+               [n, unit] if ["instructions","instruction","steps","step","cycles","cycle"].contains(&unit.to_lowercase().as_str()) => n,
+               _ => return Err("expected every <n> [instructions|steps|cycles]".into())
+            };
+            match parse_number(period){
+               Some(0) => return Err("the period must be at least 1".into()),
+               Some(n) => Trigger::Every(n),
+               None => return Err(format!("'{}' is not a step count",period))
+            }
+         },
+         Some("when") => match &rest[1 ..]{
+            [reg, "==", target] if reg.eq_ignore_ascii_case("pc") || reg.eq_ignore_ascii_case("$pc") => {
+               Trigger::PcEquals(resolve_addr(target, symbols)?)
+            },
+            _ => return Err("expected when pc == <sym|addr>".into())
+         },
+         _ => return Err("expected a trigger (at <n>, every <n> or when pc == <sym|addr>)".into())
+      };
+      return Ok(Stimulus{action, trigger});
+   }
+
+   // instructions stepped since reset
+   pub fn steps(&self)->u64{
+      self.steps
+   }
+
+   pub fn restart(&mut self){
+      self.steps = 0;
+      self.last_pc = None;
+   }
+
+   // steps until the next stimulus fires on a core whose PC stays put, e.g. one asleep in WFI
+   pub fn steps_until_next(&self, pc: u32)->Option<u64>{
+      self.stimuli.iter().filter_map(|s| match s.trigger{
+         Trigger::At(n) if n >= self.steps => Some(n - self.steps),
+         Trigger::At(_) => None,
+         Trigger::Every(period) if self.steps == 0 => Some(period),
+         Trigger::Every(period) => Some((period - self.steps % period) % period),
+         Trigger::PcEquals(addr) if addr == pc && self.last_pc != Some(addr) => Some(0),
+         Trigger::PcEquals(_) => None
+      }).min()
+   }
+
+   // accounts for steps skipped while PC stayed at pc, none of them may be due
+   pub fn advance(&mut self, pc: u32, n: u64){
+      self.steps += n;
+      self.last_pc = Some(pc);
+   }
+
+   fn due(&self, pc: u32)->Vec<Action>{
+      self.stimuli.iter().filter(|s| match s.trigger{
+         Trigger::At(n) => n == self.steps,
+         Trigger::Every(period) => self.steps > 0 && self.steps % period == 0,
+         Trigger::PcEquals(addr) => addr == pc && self.last_pc != Some(addr)
+      }).map(|s| s.action.clone()).collect()
+   }
+}
+
+fn inject(sys: &mut System, action: &Action){
+   match action{
+      // same path as a write to NVIC_ISPR so SEVONPEND and WFI wake up behave as on hardware
+      Action::PendIrq(n) => MemoryMappedRegister::nvic_ispr.update(sys, 1 << n),
+      Action::Nmi => {
+         sys.set_exc_pending(ArmException::Nmi);
+         sys.scs.wfi_wake_up = true;
+      },
+      Action::PendSV => {
+         sys.set_exc_pending(ArmException::PendSV);
+         sys.scs.wfi_wake_up = true;
+      },
+      Action::SysTick => {
+         sys.set_exc_pending(ArmException::SysTick);
+         sys.scs.wfi_wake_up = true;
+      },
+      Action::SetEvent => sys.event_register = true
+   }
+}
+
+// called before every instruction so a stimulus "at step n" is seen by instruction n
+pub fn apply_stimuli(sys: &mut System){
+   let pc = sys.registers.pc as u32;
+   if !sys.stimuli.stimuli.is_empty(){
+      for action in sys.stimuli.due(pc){
+         dbg_ln!("step {}: {}",sys.stimuli.steps,action);
+         inject(sys, &action);
+      }
+   }
+   sys.stimuli.last_pc = Some(pc);
+   sys.stimuli.steps += 1;
+}
@@ -1,3 +1,11 @@
+use crate::asm::decode::{Opcode, B16};
+use crate::asm::decode_operands::Operands;
+use crate::asm::interpreter::serialise_instruction;
+use crate::system::{exception_name, ArmException};
+use crate::system::observer::SystemObserver;
+
+const COMPRESS_BAL: &str = "\n    ...\n";
+
 pub struct Trace{
    log: String,
    writes: u16,
@@ -54,3 +62,37 @@ impl Trace{
 }
 
 
+
+// the built in trace is an observer like any other, the System feeds it while trace_enabled is set
+impl SystemObserver for Trace{
+   // logged before the instruction runs so that one which faults is still in the trace
+   fn on_execute(&mut self, pc: u32, code: &Opcode, operands: &Option<Operands>){
+      // a branch to itself is logged once and then elided until something else runs
+      if matches!((code, operands), (Opcode::_16Bit(B16::B_ALWAYS), Some(Operands::B_ALWAYS(0)))){
+         if !self.ends_with(COMPRESS_BAL){
+            serialise_instruction(self.get(), pc, code, operands);
+            self.push_str(COMPRESS_BAL);
+         }
+         return;
+      }
+      serialise_instruction(self.get(), pc, code, operands);
+      self.push('\n');
+      self.trim();
+   }
+
+   fn on_exception_entry(&mut self, exception: &ArmException, priority: i32){
+      self.push_str(&format!("initialising {:?}(priority: {}) exception\n",exception,priority));
+   }
+
+   fn on_exception_return(&mut self, exc_n: u32){
+      self.push_str(&format!("returned from {} exception \n",exception_name(exc_n)));
+   }
+
+   fn on_reset(&mut self){
+      self.push_str("====SYSTEM RESET OCCURED====\n");
+   }
+
+   fn on_lockup(&mut self, msg: &str){
+      self.push_str(&format!("====LOCKUP: {}====\n",msg));
+   }
+}
@@ -5,6 +5,8 @@ use crate::asm::decode_operands::{
 };
 
 use crate::elf::decoder::ElfError;
+use crate::elf::encoder::encode_relocatable;
+use crate::asm::assembler;
 use crate::asm::{HalfWord, STACK_POINTER, PROGRAM_COUNTER};
 use crate::system::registers::SpecialRegister;
 use std::path::{Path, PathBuf};
@@ -40,89 +42,34 @@ fn load_instruction_opcodes(file: &Path)->Result<Vec<u8>,ElfError>{
 
 pub fn write_asm(path: &Path, data: &[u8])->Result<File,std::io::Error>{
    println!("writing  asm to {:?}",path);
+   if let Some(dir) = path.parent(){
+      std::fs::create_dir_all(dir)?;
+   }
    let mut file = File::create(path)?;
    file.write_all(data)?;
    println!("written asm to {:?}",file);
    Ok(file)
 }
 
+// assembles with the built-in assembler into a relocatable object next to the source
 pub fn asm_file_to_elf(path: &Path)->Result<PathBuf,std::io::Error>{
-   use std::process::Command;
-   let mut fname = String::new();
-   fname.push_str(path.to_str().unwrap());
-   fname = fname.replace(".s", "");
-   fname.push_str(".elf");
-   println!("writing to {:?}",fname);
-   let ret = PathBuf::from(fname.clone());
-   let cmd = Command::new("arm-none-eabi-as")
-      .arg(path.to_str().unwrap())
-      .arg("-o")
-      .arg(fname)
-      .output()
-      .expect("could not link");
-
-   println!("=======\n{:?}\n=======",std::str::from_utf8(&cmd.stderr[..]).unwrap());
-   Ok(ret)
-}
-
-fn asm_file_to_elf_armv6(path: &Path)->Result<PathBuf,std::io::Error>{
-   println!("forcing T2 encoding");
-   use std::process::Command;
-   let mut fname = String::new();
-   fname.push_str(path.to_str().unwrap());
-   fname = fname.replace(".s", "");
-   fname.push_str(".elf");
-   println!("writing to {:?}",fname);
-   let ret = PathBuf::from(fname.clone());
-   Command::new("arm-none-eabi-as")
-      .arg(path.to_str().unwrap())
-      .arg("-march=armv6-m")
-      .arg("-o")
-      .arg(fname)
-      .status()
-      .expect("could not assemble");
-
-   Ok(ret)
-}
-
-pub fn asm_file_to_elf_armv6m(path: &Path)->Result<PathBuf,std::io::Error>{
-   println!("forcing T2 encoding");
-   use std::process::Command;
    let mut fname = String::new();
    fname.push_str(path.to_str().unwrap());
    fname = fname.replace(".s", "");
    fname.push_str(".elf");
    println!("writing to {:?}",fname);
-   let ret = PathBuf::from(fname.clone());
-   Command::new("arm-none-eabi-as")
-      .arg(path.to_str().unwrap())
-      .arg("-march=armv6-m")
-      .arg("-o")
-      .arg(fname)
-      .status()
-      .expect("could not assemble");
-
+   let ret = PathBuf::from(fname);
+   assemble_file(path, &ret)?;
    Ok(ret)
 }
 
-pub fn asm_file_to_elf_armv6t2(path: &Path)->Result<PathBuf,std::io::Error>{
-   println!("forcing T2 encoding");
-   use std::process::Command;
-   let mut fname = String::new();
-   fname.push_str(path.to_str().unwrap());
-   fname = fname.replace(".s", "");
-   fname.push_str(".elf");
-   println!("writing to {:?}",fname);
-   let ret = PathBuf::from(fname.clone());
-   Command::new("arm-none-eabi-as")
-      .arg(path.to_str().unwrap())
-      .arg("-march=armv6t2")
-      .arg("-o")
-      .arg(fname)
-      .status()
-      .expect("could not assemble");
-
-   Ok(ret)
+pub fn assemble_file(src: &Path, out: &Path)->Result<(),std::io::Error>{
+   use std::io::{Error, ErrorKind};
+   let code = std::fs::read_to_string(src)?;
+   let assembly = assembler::assemble(&code)
+      .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}: {}",src.display(),e)))?;
+   std::fs::write(out, encode_relocatable(&assembly))?;
+   Ok(())
 }
 
 fn decode_single_16b_instruction(path: &Path, asm: &[u8])->Result<Opcode,std::io::Error>{
@@ -281,7 +228,7 @@ pub fn should_recognise_add_with_immediates()-> Result<(),std::io::Error>{
 pub fn should_recognise_add_with_registers()-> Result<(),std::io::Error>{
    let path = Path::new("assembly_tests/addr.s");
    write_asm(path,b".text\n.thumb\nADD r3,r2,r7\nADD r8,r12\n")?;
-   let elf = asm_file_to_elf_armv6t2(path)?;
+   let elf = asm_file_to_elf(path)?;
    let opcodes = load_instruction_opcodes(&elf).unwrap();
    let first_instr: [u8;2] = [opcodes[0], opcodes[1]];
    let sec_instr: [u8;2] = [opcodes[2],opcodes[3]];
@@ -578,7 +525,7 @@ fn should_calculate_labels_correctly()->Result<(),std::io::Error>{
       "BEQ.N .\n",
    );
 
-   let bytes = assemble_by(path,src_code.as_bytes(),asm_file_to_elf_armv6t2).unwrap();
+   let bytes = assemble_by(path,src_code.as_bytes(),asm_file_to_elf).unwrap();
 
    let opcode: Opcode = ([bytes[0],bytes[1]]).into();
    if let Some(Operands::COND_BRANCH(literal)) = get_operands(&Opcode::_16Bit(B16::BEQ), [bytes[0],bytes[1]]){
@@ -594,7 +541,7 @@ fn should_calculate_labels_correctly()->Result<(),std::io::Error>{
       "BEQ.N .+2\n",
    );
 
-   let bytes_1 = assemble_by(path,src_code.as_bytes(),asm_file_to_elf_armv6t2).unwrap();
+   let bytes_1 = assemble_by(path,src_code.as_bytes(),asm_file_to_elf).unwrap();
 
    if let Some(Operands::COND_BRANCH(literal)) = get_operands(&Opcode::_16Bit(B16::BEQ), [bytes_1[0],bytes_1[1]]){
       assert_eq!(literal,2);
@@ -1045,6 +992,22 @@ fn should_recognise_lsl()->Result<(),std::io::Error>{
    Ok(())
 }
 
+#[test]
+fn should_decode_lsl_by_eight_or_more_as_lsl(){
+   // LSLS Rd,Rm,#imm5 only becomes MOVS Rd,Rm when all five bits of imm5 are clear
+   for (hw, shift) in [(0x0040_u16, 1), (0x0200, 8), (0x0400, 16), (0x0600, 24), (0x07C0, 31)]{
+      let code: Opcode = hw.to_le_bytes().into();
+      assert_eq!(code, Opcode::_16Bit(B16::LSL_Imm5), "{:04x}",hw);
+      if let Some(Operands::LS_Imm5(_, _, imm)) = get_operands(&code, hw.to_le_bytes()){
+         assert_eq!(imm.0, shift);
+      }else{
+         panic!("could not parse lsl {:04x}",hw);
+      }
+   }
+   let mov: Opcode = 0x0008_u16.to_le_bytes().into();
+   assert_eq!(mov, Opcode::_16Bit(B16::MOV_REGS_T2));
+}
+
 #[test]
 fn should_recognise_lsr()->Result<(),std::io::Error>{
    let path = Path::new("assembly_tests/lsr.s");
@@ -1084,7 +1047,7 @@ fn should_recognise_mov()->Result<(),std::io::Error>{
    let bytes = assemble_by(
       path,
       b".text\n.thumb\nMOV r0,#7\n",
-      asm_file_to_elf_armv6
+      asm_file_to_elf
    ).unwrap();
 
    let sanity: Opcode = ([bytes[0],bytes[1]]).into();
@@ -1115,7 +1078,7 @@ fn should_recognise_mov()->Result<(),std::io::Error>{
    let bytes = assemble_by(
       path,
       b".text\n.syntax unified\n.thumb\nMOVS.N r4,r7\n",
-      asm_file_to_elf_armv6t2
+      asm_file_to_elf
    ).unwrap();
    let regs_t2: Opcode = ([bytes[0],bytes[1]]).into();
    if let Some(Operands::MOV_REG(dest,src)) = get_operands(&Opcode::_16Bit(B16::MOV_REGS_T2), [bytes[0],bytes[1]]){
@@ -1170,7 +1133,7 @@ fn should_recognise_nop()->Result<(),std::io::Error>{
    let bytes = assemble_by(
       path,
       b".text\n.syntax unified\n.thumb\nNOP\n",
-      asm_file_to_elf_armv6t2
+      asm_file_to_elf
    ).unwrap();
    let encoding_t2: Opcode = ([bytes[0],bytes[1]]).into();
 
@@ -1180,7 +1143,7 @@ fn should_recognise_nop()->Result<(),std::io::Error>{
    let bytes = assemble_by(
       path,
       b".text\n.thumb\nNOP\n",
-      asm_file_to_elf_armv6m
+      asm_file_to_elf
    ).unwrap();
    let encoding_t2: Opcode = ([bytes[0],bytes[1]]).into();
 
@@ -1303,7 +1266,7 @@ fn should_recognise_rsb()->Result<(),std::io::Error>{
       path,
       b".text\n.thumb\nNEG r3,r1\n",// idk why gnu as doesn't assemble RSB D: NEG is the pre-UAL version of RSB tho:D
       // gnu as .. more like gnu ass
-      asm_file_to_elf_armv6t2
+      asm_file_to_elf
    ).unwrap();
    let t1: Opcode = ([bytes[0],bytes[1]]).into();
    if let Some(Operands::RegisterPair(dest, other)) = get_operands(&Opcode::_16Bit(B16::RSB), [bytes[0],bytes[1]]){
@@ -1342,7 +1305,7 @@ fn should_recognise_sev()->Result<(),std::io::Error>{
    let bytes = assemble_by(
       path,
       b".text\n.thumb\nSEV\n",
-      asm_file_to_elf_armv6t2
+      asm_file_to_elf
    ).unwrap();
    let t1: Opcode = ([bytes[0],bytes[1]]).into();
 
@@ -1572,7 +1535,7 @@ fn should_recognise_udfw()->Result<(),std::io::Error>{
    let t1 = assemble_and_decode_32b(
       path,
       b".text\n.thumb\n.syntax unified\n UDF.W #101\n",
-      asm_file_to_elf_armv6t2
+      asm_file_to_elf
    ).unwrap();
 
    println!("raw {}",t1);
@@ -1587,7 +1550,7 @@ fn should_recognise_uxt()->Result<(),std::io::Error>{
    let bytes = assemble_by(
       path,
       b".text\n.thumb\nUXTB r4,r1\n", 
-      asm_file_to_elf_armv6t2
+      asm_file_to_elf
    ).unwrap();
    let uxtb: Opcode = ([bytes[0],bytes[1]]).into();
    if let Some(Operands::RegisterPair(a,b)) = get_operands(&Opcode::_16Bit(B16::UXTB), [bytes[0],bytes[1]]){
@@ -1599,7 +1562,7 @@ fn should_recognise_uxt()->Result<(),std::io::Error>{
    let bytes = assemble_by(
       path,
       b".text\n.thumb\nUXTH r5,r7\n", 
-      asm_file_to_elf_armv6t2
+      asm_file_to_elf
    ).unwrap();
    let uxth: Opcode = ([bytes[0],bytes[1]]).into();
    if let Some(Operands::RegisterPair(a,b)) = get_operands(&Opcode::_16Bit(B16::UXTH), [bytes[0],bytes[1]]){
@@ -1620,7 +1583,7 @@ fn should_recognise_wf()->Result<(),std::io::Error>{
    let bytes = assemble_by(
       path,
       b".thumb\n.text\nWFE\nWFI\n",
-      asm_file_to_elf_armv6t2
+      asm_file_to_elf
    ).unwrap();
    let wfe: Opcode = ([bytes[0],bytes[1]]).into();
    let wfi: Opcode = ([bytes[2],bytes[3]]).into();
@@ -1640,7 +1603,7 @@ fn should_recognise_yield()->Result<(),std::io::Error>{
    let yield_opc = assemble_and_decode(
       path,
       b".thumb\n.text\nYIELD\n",
-      asm_file_to_elf_armv6t2
+      asm_file_to_elf
    ).unwrap();
 
    assert_eq!(Opcode::_16Bit(B16::YIELD),yield_opc);
@@ -1654,7 +1617,7 @@ fn should_recognise_dmb()->Result<(),std::io::Error>{
    let bytes = assemble_by_32b(
       path,
       b".text\nDMB #12\n",
-      asm_file_to_elf_armv6
+      asm_file_to_elf
    ).unwrap();
 
    let instr = [bytes[0],bytes[1],bytes[2],bytes[3]];
@@ -1676,7 +1639,7 @@ fn should_recognise_dsb()->Result<(),std::io::Error>{
    let bytes = assemble_by_32b(
       path,
       b".text\nDSB #12\n",
-      asm_file_to_elf_armv6
+      asm_file_to_elf
    ).unwrap();
 
    let instr = [bytes[0],bytes[1],bytes[2],bytes[3]];
@@ -1697,7 +1660,7 @@ fn should_recognise_isb()->Result<(),std::io::Error>{
    let bytes = assemble_by_32b(
       path,
       b".text\nISB #13\n",
-      asm_file_to_elf_armv6
+      asm_file_to_elf
    ).unwrap();
 
    let instr = [bytes[0],bytes[1],bytes[2],bytes[3]];
@@ -1718,7 +1681,7 @@ fn should_recognise_mrs()->Result<(),std::io::Error>{
    let bytes = assemble_by_32b(
       path,
       b".text\n.thumb\nMRS R1, APSR\n",
-      asm_file_to_elf_armv6t2
+      asm_file_to_elf
    ).unwrap();
 
    let instr = [bytes[0],bytes[1],bytes[2],bytes[3]];
@@ -1739,7 +1702,7 @@ fn should_recognise_msr()->Result<(),std::io::Error>{
    let bytes = assemble_by_32b(
       path,
       b".text\n.thumb\nMSR APSR, R7\n",
-      asm_file_to_elf_armv6t2
+      asm_file_to_elf
    ).unwrap();
 
    let instr = [bytes[0],bytes[1],bytes[2],bytes[3]];
@@ -1765,7 +1728,7 @@ fn should_recognise_instruction_size()->Result<(),std::io::Error>{
          "MSR APSR, r7\n",
          "ADD r0, #7\n"
          ).as_bytes(),
-      asm_file_to_elf_armv6t2
+      asm_file_to_elf
    ).unwrap();
 
    let instr_32 = [bytes[0], bytes[1]];
@@ -1795,3 +1758,123 @@ fn u32_string_functions(){
    u32_to_b10(&mut buffer, u32::MAX);
    assert_eq!(buffer,u32::MAX.to_string());
 }
+
+// lays .data straight after .text the way both example link.ld scripts do
+fn assemble_linked_at_zero(path: &str)->Vec<u8>{
+   let source = std::fs::read_to_string(path).unwrap();
+   let assembly = assembler::assemble(&source).unwrap();
+   let text = assembly.section(".text").unwrap();
+   let data_base = (text.size + 7) & !7;
+   let mut bytes = text.bytes.clone();
+   for reloc in text.relocations.iter().filter(|r| r.symbol.eq(".data")){
+      assert_eq!(reloc.kind, assembler::RelocationType::ABS32);
+      let i = reloc.offset as usize;
+      let value = u32::from_le_bytes([bytes[i],bytes[i + 1],bytes[i + 2],bytes[i + 3]]) + data_base;
+      bytes[i .. i + 4].copy_from_slice(&value.to_le_bytes());
+   }
+   return bytes;
+}
+
+#[test]
+fn assembler_should_match_gnu_as_output(){
+   let expected = load_instruction_opcodes(Path::new("./examples/robin/kernel.o")).unwrap();
+   assert_eq!(assemble_linked_at_zero("./examples/robin/kernel.s"), expected);
+
+   let expected = load_instruction_opcodes(Path::new("./examples/fibonacci/fib_standalone.elf")).unwrap();
+   assert_eq!(assemble_linked_at_zero("./examples/fibonacci/fib.s"), expected);
+}
+
+#[test]
+fn assembler_round_trips_through_the_decoder(){
+   use crate::asm::interpreter::print_instruction;
+   let source = concat!(
+      ".text\n.thumb\n.syntax unified\n",
+      "ADDS r3,r2,r7\nADD r8,r12\nADDS r1,#200\nADD r2,SP,#16\nSUB SP,#8\nADD SP,#12\n",
+      "MOVS r0,#7\nMOV r2,r8\nLSLS r1,r2,#3\nLSLS r0,r0,#24\nLSRS r1,r2,#32\nASRS r3,r4\nANDS r0,r1\n",
+      "EORS r2,r3\nORRS r4,r5\nBICS r6,r7\nMVNS r0,r1\nMULS r1,r2,r1\nRSBS r1,r2,#0\n",
+      "CMP r1,#4\nCMP r1,r2\nCMP r1,LR\nCMN r3,r4\nTST r2,r3\n",
+      "LDR r0,[r1,#4]\nLDR r2,[SP,#8]\nSTR r3,[r4,r5]\nLDRB r1,[r2,#3]\nSTRH r1,[r2,#6]\n",
+      "LDRSB r1,[r2,r3]\nLDRSH r1,[r2,r3]\nLDM r1!,{r2,r3}\nSTM r0!,{r1-r4}\n",
+      "PUSH {r4-r7,LR}\nPOP {r0,PC}\nSXTH r0,r1\nUXTB r2,r3\nREV r1,r2\nREVSH r1,r2\n",
+      "BX LR\nBLX r3\nSVC #3\nBKPT #1\nCPSID i\nCPSIE i\nWFI\nWFE\nSEV\nYIELD\nNOP\n",
+      "MRS r1,PRIMASK\nMSR CONTROL,r2\nDSB SY\nDMB SY\nISB SY\n",
+      "MRS r3,BASEPRI\nMSR BASEPRI_MAX,r4\nMSR FAULTMASK,r5\nCPSID f\nCPSIE if\n",
+   );
+   let bytes = assembler::assemble(source).unwrap().text().to_vec();
+   let mut printed = Vec::new();
+   let mut i = 0;
+   while i < bytes.len(){
+      let hw: HalfWord = [bytes[i],bytes[i + 1]];
+      if instruction_size(hw) == InstructionSize::B32{
+         let word = [bytes[i],bytes[i + 1],bytes[i + 2],bytes[i + 3]];
+         let code: Opcode = word.into();
+         assert!(!matches!(code,Opcode::_32Bit(B32::UNDEFINED)), "{:x?} did not decode",word);
+         printed.push(print_instruction(i as u32, &code, &get_operands_32b(&code, word)));
+         i += 4;
+      }else{
+         let code: Opcode = hw.into();
+         assert!(!matches!(code,Opcode::_16Bit(B16::UNDEFINED)), "{:x?} did not decode",hw);
+         printed.push(print_instruction(i as u32, &code, &get_operands(&code, hw)));
+         i += 2;
+      }
+   }
+   println!("{:#?}",printed);
+
+   let mnemonics: Vec<&str> = source.lines().skip(3)
+      .flat_map(|l| l.split('\n'))
+      .map(|l| l.split_whitespace().next().unwrap())
+      .collect();
+   assert_eq!(printed.len(), mnemonics.len());
+   assert!(printed.iter().any(|l| l.contains("BASEPRI_MAX")) && printed.iter().any(|l| l.contains("CPSIE if")));
+   for (line,mnemonic) in printed.iter().zip(mnemonics){
+      let decoded = line.split_whitespace().nth(1).unwrap();
+      assert!(decoded.eq(mnemonic) || format!("{}S",decoded).eq(mnemonic), "{} decoded as {}",mnemonic,line);
+   }
+}
+
+#[test]
+fn assembler_should_patch_at_an_address(){
+   use crate::elf::decoder::{SymbolDefinition, SymbolType};
+   let symbols = vec![SymbolDefinition{position: 0x41, name: "_reset".into(), section_index: 1, _type: SymbolType::Func}];
+   let bytes = assembler::assemble_at("B _reset\nBL _reset\nMOVS r0,#1", 0x100, &symbols).unwrap();
+   assert_eq!(bytes.len(), 8);
+   // 0x40 - (0x100 + 4)
+   assert_eq!(u16::from_le_bytes([bytes[0],bytes[1]]), 0xE000 | ((-196_i32 >> 1) as u16 & 0x7FF));
+   let first: Opcode = [bytes[2],bytes[3],bytes[4],bytes[5]].into();
+   assert_eq!(first, Opcode::_32Bit(B32::BR_AND_LNK));
+   assert_eq!(bytes[6 ..], [0x01,0x20]);
+
+   assert!(assembler::assemble_at("LDR r0,=0x20000000", 0x100, &symbols).is_err());
+   assert!(assembler::assemble_at("B _missing", 0x100, &symbols).is_err());
+   assert_eq!(assembler::assemble("ADDS r0,r9").unwrap_err().line, 1);
+}
+
+#[test]
+fn thumb2_instructions_should_disassemble(){
+   use crate::asm::interpreter::print_instruction;
+   let words: [(u16,u16,&str); 8] = [
+      (0xF245, 0x6078, "MOVW"),
+      (0xFBB1, 0xF4F3, "UDIV"),
+      (0xF3C0, 0x2607, "UBFX"),
+      (0xE8DF, 0xF003, "TBB"),
+      (0xE859, 0xAF00, "LDREX"),
+      (0xFB84, 0x0105, "SMULL"),
+      (0xF501, 0x427F, "ADD"),
+      (0xF859, 0xCB04, "LDR"),
+   ];
+   for (hw1,hw2,mnemonic) in words{
+      let [a,b] = hw1.to_le_bytes();
+      let [c,d] = hw2.to_le_bytes();
+      let code: Opcode = [a,b,c,d].into();
+      assert!(!matches!(code,Opcode::_32Bit(B32::UNDEFINED)), "{:04x} {:04x} did not decode",hw1,hw2);
+      let printed = print_instruction(0, &code, &get_operands_32b(&code, [a,b,c,d]));
+      assert!(printed.contains(mnemonic), "{:04x} {:04x} printed as {}",hw1,hw2,printed);
+   }
+   // LDR post indexed with P=0 W=0 is undefined
+   let [a,b] = 0xF859_u16.to_le_bytes();
+   let [c,d] = 0xC804_u16.to_le_bytes();
+   assert_eq!(Opcode::from([a,b,c,d]), Opcode::_32Bit(B32::UNDEFINED));
+   let it: Opcode = [0x0C,0xBF].into();
+   assert_eq!(it, Opcode::_16Bit(B16::IT));
+   assert_eq!(print_instruction(0, &it, &get_operands(&it, [0x0C,0xBF])).split_whitespace().nth(1), Some("ITE"));
+}
@@ -0,0 +1,291 @@
+use std::io::{BufReader, Cursor};
+use std::net::{TcpListener, TcpStream};
+
+use crate::asm::assembler::assemble_at;
+use crate::asm::interpreter::disasm_text;
+use crate::dap::{DapServer, read_message, write_message, base64_encode};
+use crate::elf::decoder::{SymbolDefinition, SymbolType};
+use crate::json::Json;
+use crate::system::System;
+use crate::system::simulator::Machine;
+
+struct Client{
+   writer: TcpStream,
+   reader: BufReader<TcpStream>,
+   seq: i64
+}
+
+impl Client{
+   fn request(&mut self, command: &str, arguments: Json)->Json{
+      self.seq += 1;
+      write_message(&mut self.writer, &Json::object(vec![
+         ("seq", self.seq.into()),
+         ("type", "request".into()),
+         ("command", command.into()),
+         ("arguments", arguments)
+      ])).unwrap();
+      let response = self.expect(|m| m.get("type") == Some(&"response".into()) && m.get("command") == Some(&command.into()));
+      assert_eq!(response.get("request_seq"), Some(&self.seq.into()));
+      return response;
+   }
+
+   // reads messages until one matches, events arriving before it are skipped
+   fn expect<F: Fn(&Json)->bool>(&mut self, matches: F)->Json{
+      loop{
+         let msg = read_message(&mut self.reader).expect("server closed the connection").unwrap();
+         if matches(&msg){
+            return msg;
+         }
+      }
+   }
+
+   fn expect_event(&mut self, name: &str)->Json{
+      self.expect(|m| m.get("event") == Some(&name.into()))
+   }
+}
+
+fn start_server()->Client{
+   let code = "
+   .thumb
+   .text
+   main:
+      MOVS r0, #0
+   count:
+      ADDS r0, r0, #1
+      BL bump
+      CMP r0, #10
+      BNE count
+   spin:
+      B spin
+   bump:
+      ADDS r1, r1, #2
+      BX lr
+   ";
+   serve(code, &[("main", 0), ("count", 2), ("spin", 12), ("bump", 14)])
+}
+
+fn serve(code: &str, labels: &[(&str, usize)])->Client{
+   let bytes = assemble_at(code, 0, &Vec::new()).unwrap();
+   let mut symbols = vec![SymbolDefinition{position: 0, name: "$t".into(), section_index: 1, _type: SymbolType::Notype}];
+   for (name, position) in labels{
+      symbols.push(SymbolDefinition{position: *position, name: (*name).into(), section_index: 1, _type: SymbolType::Notype});
+   }
+   let disasm = disasm_text(&bytes, 0, &symbols).join("\n");
+   let server = DapServer::create(Machine::single(System::fill_with(&bytes)), symbols, disasm, None);
+   let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+   let addr = listener.local_addr().unwrap();
+   std::thread::spawn(move || server.accept(listener).unwrap());
+   let stream = TcpStream::connect(addr).unwrap();
+   Client{reader: BufReader::new(stream.try_clone().unwrap()), writer: stream, seq: 0}
+}
+
+fn register(client: &mut Client, scope: i64, name: &str)->String{
+   let vars = client.request("variables", Json::object(vec![("variablesReference", scope.into())]));
+   let vars = vars.get("body").unwrap().get("variables").unwrap().as_array().unwrap().clone();
+   let var = vars.iter().find(|v| v.get("name") == Some(&name.into())).unwrap();
+   var.get("value").unwrap().as_str().unwrap().to_owned()
+}
+
+#[test]
+fn should_round_trip_json(){
+   let text = r#"{"a":[1,-2.5,true,null],"b":"q\"\nA","c":{}}"#;
+   let v = Json::parse(text).unwrap();
+   assert_eq!(v.get("b").unwrap().as_str(), Some("q\"\nA"));
+   assert_eq!(v.get("a").unwrap().as_array().unwrap()[0].as_i64(), Some(1));
+   assert_eq!(Json::parse(&v.to_string()).unwrap(), v);
+   assert!(Json::parse("{\"a\":}").is_err());
+   assert_eq!(base64_encode(b"Man"), "TWFu");
+   assert_eq!(base64_encode(&[0xDE, 0xAD]), "3q0=");
+}
+
+#[test]
+fn messages_without_a_content_length_should_be_rejected(){
+   let mut reader = Cursor::new(b"Content-Length: 2\r\n\r\n{}".to_vec());
+   assert_eq!(read_message(&mut reader), Some(Ok(Json::object(vec![]))));
+   assert_eq!(read_message(&mut reader), None);
+   let mut reader = Cursor::new(b"Content-Type: application/json\r\n\r\n{}".to_vec());
+   assert!(matches!(read_message(&mut reader), Some(Err(e)) if e.contains("no Content-Length")));
+   let mut reader = Cursor::new(b"Content-Length: two\r\n\r\n{}".to_vec());
+   assert!(matches!(read_message(&mut reader), Some(Err(e)) if e.contains("invalid Content-Length")));
+}
+
+#[test]
+fn next_should_step_over_calls(){
+   let mut client = start_server();
+   client.request("initialize", Json::object(vec![]));
+   client.request("launch", Json::object(vec![("stopOnEntry", true.into())]));
+   client.expect_event("stopped");
+   let thread = || Json::object(vec![("threadId", 1_i64.into())]);
+   for pc in ["0x00000002", "0x00000004"]{
+      client.request("next", thread());
+      client.expect_event("stopped");
+      assert_eq!(register(&mut client, 1, "pc"), pc);
+   }
+   // BL bump runs to completion
+   client.request("next", thread());
+   assert_eq!(client.expect_event("stopped").get("body").unwrap().get("reason"), Some(&"step".into()));
+   assert_eq!(register(&mut client, 1, "pc"), "0x00000008");
+   assert_eq!(register(&mut client, 1, "r1"), "0x00000002");
+
+   for _ in 0 .. 3{
+      client.request("next", thread());
+      client.expect_event("stopped");
+   }
+   assert_eq!(register(&mut client, 1, "pc"), "0x00000004");
+   client.request("stepIn", thread());
+   client.expect_event("stopped");
+   assert_eq!(register(&mut client, 1, "pc"), "0x0000000e");
+   client.request("disconnect", Json::object(vec![]));
+}
+
+#[test]
+fn should_debug_through_the_adapter_protocol(){
+   let mut client = start_server();
+   let init = client.request("initialize", Json::object(vec![("adapterID", "armageddon".into())]));
+   assert_eq!(init.get("body").unwrap().get("supportsDisassembleRequest"), Some(&true.into()));
+   client.expect_event("initialized");
+
+   let bps = client.request("setFunctionBreakpoints", Json::object(vec![("breakpoints", vec![
+      Json::object(vec![("name", "bump".into()), ("condition", "$r0 == 3".into())]),
+      Json::object(vec![("name", "missing".into())])
+   ].into())]));
+   let bps = bps.get("body").unwrap().get("breakpoints").unwrap().as_array().unwrap().clone();
+   assert_eq!(bps[0].get("verified"), Some(&true.into()));
+   assert_eq!(bps[1].get("verified"), Some(&false.into()));
+
+   client.request("launch", Json::object(vec![("stopOnEntry", true.into())]));
+   assert_eq!(client.expect_event("stopped").get("body").unwrap().get("reason"), Some(&"entry".into()));
+   client.request("configurationDone", Json::object(vec![]));
+
+   client.request("continue", Json::object(vec![("threadId", 1_i64.into())]));
+   let stopped = client.expect_event("stopped");
+   assert_eq!(stopped.get("body").unwrap().get("reason"), Some(&"breakpoint".into()));
+   assert_eq!(register(&mut client, 1, "r0"), "0x00000003");
+   assert_eq!(register(&mut client, 1, "pc"), "0x0000000e");
+
+   let trace = client.request("stackTrace", Json::object(vec![("threadId", 1_i64.into())]));
+   let frames = trace.get("body").unwrap().get("stackFrames").unwrap().as_array().unwrap().clone();
+   assert_eq!(frames[0].get("name"), Some(&"bump".into()));
+   assert_eq!(frames[1].get("name"), Some(&"count+0x6".into()));
+
+   client.request("stepOut", Json::object(vec![("threadId", 1_i64.into())]));
+   client.expect_event("stopped");
+   assert_eq!(register(&mut client, 1, "pc"), "0x00000008");
+   client.request("next", Json::object(vec![("threadId", 1_i64.into())]));
+   client.expect_event("stopped");
+   assert_eq!(register(&mut client, 1, "pc"), "0x0000000a");
+
+   let mem = client.request("readMemory", Json::object(vec![("memoryReference", "0x0".into()), ("count", 2_i64.into())]));
+   // MOVS r0, #0
+   assert_eq!(mem.get("body").unwrap().get("data"), Some(&"ACA=".into()));
+
+   let dis = client.request("disassemble", Json::object(vec![
+      ("memoryReference", "0x0000000c".into()),
+      ("instructionOffset", (-1_i64).into()),
+      ("instructionCount", 3_i64.into())
+   ]));
+   let instructions = dis.get("body").unwrap().get("instructions").unwrap().as_array().unwrap().clone();
+   assert_eq!(instructions.len(), 3);
+   assert_eq!(instructions[1].get("address"), Some(&"0x0000000c".into()));
+   assert_eq!(instructions[1].get("symbol"), Some(&"spin".into()));
+
+   client.request("setFunctionBreakpoints", Json::object(vec![("breakpoints", Vec::<Json>::new().into())]));
+   client.request("continue", Json::object(vec![]));
+   client.request("pause", Json::object(vec![("threadId", 1_i64.into())]));
+   assert_eq!(client.expect_event("stopped").get("body").unwrap().get("reason"), Some(&"pause".into()));
+   assert_eq!(register(&mut client, 1, "pc"), "0x0000000c");
+   assert_eq!(register(&mut client, 1, "r1"), "0x00000014");
+
+   let unknown = client.request("goto", Json::object(vec![]));
+   assert_eq!(unknown.get("success"), Some(&false.into()));
+   client.request("disconnect", Json::object(vec![]));
+}
+
+#[test]
+fn should_set_breakpoints_on_disassembly_lines(){
+   let bytes = assemble_at(".thumb\n.text\nmain:\nMOVS r0, #1\nspin:\nB spin\n", 0, &Vec::new()).unwrap();
+   let symbols = vec![
+      SymbolDefinition{position: 0, name: "$t".into(), section_index: 1, _type: SymbolType::Notype},
+      SymbolDefinition{position: 2, name: "spin".into(), section_index: 1, _type: SymbolType::Notype}
+   ];
+   let disasm = disasm_text(&bytes, 0, &symbols).join("\n");
+   // a breakpoint on a label line lands on the instruction after it
+   let label_line = disasm.lines().position(|l| l.contains("<spin>")).unwrap() as i64 + 1;
+   let mut server = DapServer::create(Machine::single(System::fill_with(&bytes)), symbols, disasm, None);
+   let request = |source: Json| Json::object(vec![
+      ("seq", 1_i64.into()),
+      ("type", "request".into()),
+      ("command", "setBreakpoints".into()),
+      ("arguments", Json::object(vec![
+         ("source", source),
+         ("breakpoints", vec![Json::object(vec![("line", label_line.into())])].into())
+      ]))
+   ]);
+
+   let out = server.handle(&request(Json::object(vec![("name", "disassembly".into()), ("sourceReference", 1_i64.into())])));
+   let bp = out[0].get("body").unwrap().get("breakpoints").unwrap().as_array().unwrap()[0].clone();
+   assert_eq!(bp.get("verified"), Some(&true.into()));
+   assert_eq!(bp.get("instructionReference"), Some(&"0x00000002".into()));
+   assert_eq!(bp.get("line"), Some(&(label_line + 1).into()));
+
+   let out = server.handle(&request(Json::object(vec![("path", "/src/main.c".into())])));
+   let bp = out[0].get("body").unwrap().get("breakpoints").unwrap().as_array().unwrap()[0].clone();
+   assert_eq!(bp.get("verified"), Some(&false.into()));
+}
+
+#[test]
+fn step_out_should_skip_returns_from_deeper_recursive_calls(){
+   let mut client = serve("
+   .thumb
+   .text
+   main:
+      MOVS r1, #0x20
+      LSLS r1, r1, #24
+      MOV sp, r1
+      MOVS r0, #3
+      BL down
+   spin:
+      B spin
+   down:
+      PUSH {lr}
+      SUBS r0, r0, #1
+      BEQ done
+      BL down
+   done:
+      POP {pc}
+   ", &[("main", 0), ("spin", 12), ("down", 14), ("done", 24)]);
+   client.request("initialize", Json::object(vec![]));
+   client.request("setFunctionBreakpoints", Json::object(vec![("breakpoints", vec![
+      Json::object(vec![("name", "down".into()), ("condition", "$r0 == 2".into())])
+   ].into())]));
+   client.request("launch", Json::object(vec![("stopOnEntry", true.into())]));
+   client.expect_event("stopped");
+   client.request("continue", Json::object(vec![("threadId", 1_i64.into())]));
+   client.expect_event("stopped");
+   let sp = register(&mut client, 1, "sp");
+   client.request("setFunctionBreakpoints", Json::object(vec![("breakpoints", Vec::<Json>::new().into())]));
+
+   // the innermost call returns to the same address first, but lower down the stack
+   client.request("stepOut", Json::object(vec![("threadId", 1_i64.into())]));
+   client.expect_event("stopped");
+   assert_eq!(register(&mut client, 1, "pc"), "0x00000018");
+   assert_eq!(register(&mut client, 1, "sp"), sp);
+   assert_eq!(register(&mut client, 1, "r0"), "0x00000000");
+   client.request("disconnect", Json::object(vec![]));
+}
+
+#[test]
+fn memory_and_disassembly_requests_should_be_bounded(){
+   let mut client = start_server();
+   client.request("initialize", Json::object(vec![]));
+   let mem = client.request("readMemory", Json::object(vec![("memoryReference", "0x0".into()), ("count", i64::MAX.into())]));
+   let data = mem.get("body").unwrap().get("data").unwrap().as_str().unwrap().to_owned();
+   assert_eq!(data.len(), 4 * ((1 << 16) + 2) / 3);
+   let dis = client.request("disassemble", Json::object(vec![
+      ("memoryReference", "0x0".into()),
+      ("instructionOffset", i64::MIN.into()),
+      ("instructionCount", i64::MAX.into())
+   ]));
+   assert_eq!(dis.get("body").unwrap().get("instructions").unwrap().as_array().unwrap().len(), 1 << 12);
+   client.request("disconnect", Json::object(vec![]));
+}
@@ -1,19 +1,24 @@
 use std::path::{Path,PathBuf};
 use std::fs::File;
 use std::io::Write;
-use std::process::Command;
 use crate::asm::interpreter::SymbolTable;
+use crate::tests::asm::assemble_file;
 use crate::binutils::u32_to_arm_bytes;
+use crate::elf::info::describe;
+use crate::elf::linker::{MemoryMap, LinkedObject, link_relocatable};
 use crate::elf::decoder::{
    get_header,
    get_all_section_headers,
    is_text_section_hdr,
    SectionHeader,
-   read_text_section, is_symbol_table_section_hdr, get_section_symbols, get_string_table_section_hdr, get_matching_symbol_names, build_symbol_byte_offset_map,  get_text_section_symbols, get_entry_point_offset, LiteralPools, get_all_symbol_names, SymbolType, SymbolDefinition, get_section_names, get_loadable_sections, Section, LoadType, load_sections
+   read_text_section, is_symbol_table_section_hdr, get_section_symbols, get_string_table_section_hdr, get_matching_symbol_names, build_symbol_byte_offset_map,  get_text_section_symbols, get_entry_point_offset, LiteralPools, SymbolType, SymbolDefinition, get_section_names, Section
 };
 
 pub fn write_asm_make_elf(path: &str, data: &[u8])->Result<PathBuf, std::io::Error>{
    println!("writing to [{:?}]", path);
+   if let Some(dir) = Path::new(path).parent(){
+      std::fs::create_dir_all(dir)?;
+   }
    let mut file = File::create(path)?;
    file.write_all(data)?;
    println!("wrote to {:?}", file);
@@ -22,14 +27,8 @@ pub fn write_asm_make_elf(path: &str, data: &[u8])->Result<PathBuf, std::io::Err
    fname.push_str(path);
    fname = fname.replace(".s", ".elf");
    println!("writing to {:?}",fname);
-   let ret = PathBuf::from(fname.clone());
-   Command::new("arm-none-eabi-as")
-      .arg(path)
-      .arg("-march=armv6-m")
-      .arg("-o")
-      .arg(fname)
-      .status()
-      .expect("could not assemble");
+   let ret = PathBuf::from(fname);
+   assemble_file(Path::new(path), &ret)?;
 
    assert!(ret.exists());
    Ok(ret)
@@ -90,19 +89,11 @@ fn should_read_text_section(){
    assert!(!text_section.is_empty());
 }
 
-pub fn link_elf(new_elf: &Path, elf: &Path, linker_script: &Path){
-   let sh = Command::new("arm-none-eabi-ld")
-      .arg("-T")
-      .arg(linker_script)
-      .arg(elf)
-      .arg("-o")
-      .arg(new_elf)
-      .output()
-      .expect("failed to link");
-
-   println!("{:?}",std::str::from_utf8(&sh.stdout[..]).unwrap());
-   println!("{:?}",std::str::from_utf8(&sh.stderr[..]).unwrap());
-   assert!(sh.status.success());
+// links an object from the built-in assembler the way the loader does
+pub fn link_object(elf: &Path, map: &MemoryMap)->LinkedObject{
+   let (elf_header,mut reader) = get_header(elf).unwrap();
+   let section_headers = get_all_section_headers(&mut reader, &elf_header).unwrap();
+   link_relocatable(&mut reader, &elf_header, &section_headers, map).unwrap()
 }
 
 #[test]
@@ -119,77 +110,37 @@ fn should_map_sections()->Result<(),std::io::Error>{
          ".data\n",
          "      .4byte 70,32,12,700\n",
          ".bss\n",
-         "      .lcomm zero_counter_v, 8\n"
+         "   zero_counter_v:\n",
+         "      .space 8\n"
       ).as_bytes()
    )?;
 
-   let mut ld_script = File::create("./assembly_tests/section.ld")?;
-
-   let text_offset: usize = 0;
-   let data_offset: usize = 0x1000;
-   let bss_offset: usize = 0x2000;
-   ld_script.write_all(
-      format!(
-         "ENTRY(_entry_point);\n\
-         SECTIONS{{\n\
-             \t. = {:#x};\n\
-             \t.text : {{*(.text)}}\n\
-             \t. = {:#x};\n\
-             \t.data : {{*(.data)}}\n\
-             \t. = {:#x};\n\
-             \t.bss : {{*(.bss)}}\n\
-         }}\n",text_offset,data_offset,bss_offset
-      ).as_bytes()
-   )?;
-
-   let linked = Path::new("./assembly_tests/ld_section.out");
-   link_elf(&linked, &rel_elf, Path::new("./assembly_tests/section.ld"));
-
-   let (elf_header,mut reader) = get_header(&linked).unwrap();
-   assert_eq!(text_offset,get_entry_point_offset(&elf_header), "entry point offset should be at the beginning of text segment");
-
-   let section_headers = get_all_section_headers(&mut reader, &elf_header).unwrap();
-   assert!(!section_headers.is_empty());
-
-   let sects  = get_loadable_sections(&mut reader, &elf_header, &section_headers).unwrap();
-
-   let t = sects.iter().position(|ld| ld.name.eq(".text")).unwrap();
-   let d = sects.iter().position(|ld| ld.name.eq(".data")).unwrap();
-   let b = sects.iter().position(|ld| ld.name.eq(".bss")).unwrap();
-
-   assert_eq!(sects[t].name,String::from(".text"));
-   assert_eq!(sects[t].start,text_offset as u32);
-   assert_eq!(sects[t].len,4); // 2 * 16bit instructions = 4 bytes
-   assert_eq!(sects[t].load,LoadType::PROGBITS); // 2 * 16bit instructions = 4 bytes
+   let text_offset: u32 = 0;
+   let data_offset: u32 = 0x1000;
+   let linked = link_object(&rel_elf, &MemoryMap{code: text_offset, data: data_offset});
 
-   assert_eq!(sects[d].name,String::from(".data"));
-   assert_eq!(sects[d].start,data_offset as u32);
-   assert_eq!(sects[d].len,4 * 4); // 4 .4byte numbers
-   assert_eq!(sects[d].load,LoadType::PROGBITS); // 2 * 16bit instructions = 4 bytes
+   let symbol = |name: &str| linked.symbols.iter().find(|s| s.name.eq(name)).unwrap().position as u32;
+   assert_eq!(symbol("_entry_point"), text_offset, "entry point should be at the beginning of text segment");
 
-   assert_eq!(sects[b].name,String::from(".bss"));
-   assert_eq!(sects[b].start,bss_offset as u32);
-   assert_eq!(sects[b].len,8); // 4 .4byte numbers
-   assert_eq!(sects[b].load,LoadType::NOBITS); // 2 * 16bit instructions = 4 bytes
+   let t = linked.sections.iter().position(|(sn,_,_)| sn.eq(".text")).unwrap();
+   let d = linked.sections.iter().position(|(sn,_,_)| sn.eq(".data")).unwrap();
 
-   let section_data = load_sections(&mut reader, &elf_header, &section_headers,sects).unwrap();
-
-   let t = section_data.iter().position(|(sn,_,_)| sn.eq(".text")).unwrap();
-   let d = section_data.iter().position(|(sn,_,_)| sn.eq(".data")).unwrap();
-
-   let (_,t_offset,t_data) = &section_data[t]; 
-   assert_eq!(*t_offset as usize,text_offset);
-   assert_eq!(t_data.len(),4);
-
-   let (_,d_offset,d_data) = &section_data[d];
-   assert_eq!(*d_offset as usize,data_offset);
+   let (_,t_offset,t_data) = &linked.sections[t]; 
+   assert_eq!(*t_offset,text_offset);
+   assert_eq!(t_data.len(),4); // 2 * 16bit instructions = 4 bytes
 
+   let (_,d_offset,d_data) = &linked.sections[d];
+   assert_eq!(*d_offset,data_offset);
    let expected_data_section = vec![u32_to_arm_bytes(70),u32_to_arm_bytes(32),u32_to_arm_bytes(12),u32_to_arm_bytes(700)]
       .into_iter()
       .flatten()
       .collect::<Vec<u8>>();
    assert_eq!(d_data,&expected_data_section);
 
+   // .bss has no bytes to load, it is placed after .data and left to the zeroed memory
+   assert!(!linked.sections.iter().any(|(sn,_,_)| sn.eq(".bss")));
+   assert_eq!(symbol("zero_counter_v"), data_offset + 4 * 4);
+
    Ok(())
 }
 
@@ -213,41 +164,13 @@ fn should_get_all_symbols()->Result<(),std::io::Error>{
       ).as_bytes()
    )?;
 
-   let mut ld_script = File::create("./assembly_tests/load.ld")?;
    let text_offset: usize = 0;
    let data_offset: usize = 0x1000;
-   ld_script.write_all(
-      format!(
-         "ENTRY(_entry_point);\n\
-         SECTIONS{{\n\
-             \t. = {:#x};\n\
-             \t.text : {{*(.text)}}\n\
-             \t. = {:#x};\n\
-             \t.data : {{*(.data)}}\n\
-         }}\n",text_offset,data_offset
-      ).as_bytes()
-   )?;
-   
-   let linked = Path::new("./assembly_tests/allsym.out");
-   link_elf(&linked, &rel_elf, Path::new("./assembly_tests/load.ld"));
-
-   let (elf_header,mut reader) = get_header(&linked).unwrap();
-   assert_eq!(text_offset,get_entry_point_offset(&elf_header), "entry point offset should be at the beginning of text segment");
-
-   let section_headers = get_all_section_headers(&mut reader, &elf_header).unwrap();
-   assert!(!section_headers.is_empty());
-
-   let strtab_idx = get_string_table_section_hdr(&elf_header, &section_headers).unwrap();
-   let str_table_hdr = &section_headers[strtab_idx];
-
-   let maybe_symtab: Vec<&SectionHeader> = section_headers.iter()
-      .filter(|hdr| is_symbol_table_section_hdr(&elf_header, hdr))
-      .collect();
-
-   let sym_entries = get_section_symbols(&mut reader, &elf_header, &maybe_symtab[0]).unwrap();
-   //let text_section_symbols = get_text_section_symbols(&elf_header, &section_headers, &sym_entries).unwrap();
-   //let names = get_matching_symbol_names(&mut reader, &elf_header, &text_section_symbols, &str_table_hdr).unwrap();
-   let symbols  = get_all_symbol_names(&mut reader, &elf_header, &sym_entries, &str_table_hdr).unwrap();
+   let linked = link_object(&rel_elf, &MemoryMap{code: text_offset as u32, data: data_offset as u32});
+   let symbols = linked.symbols;
+   // the literal for _boot_magic is relocated to where .data was placed
+   let (_,_,text) = linked.sections.iter().find(|(sn,_,_)| sn.eq(".text")).unwrap();
+   assert_eq!(u32::from_le_bytes(text[4 .. 8].try_into().unwrap()), data_offset as u32);
    println!("symbols : {:?}",symbols);
    let lit_pools = LiteralPools::create_from_list(&symbols);
    println!("pool records: {:?}",lit_pools);
@@ -261,7 +184,7 @@ fn should_get_all_symbols()->Result<(),std::io::Error>{
    assert_eq!(sym_table.lookup(data_offset),Some(&String::from("_boot_magic")));
    assert_eq!(sym_table.lookup(data_offset + 2),None);
    assert!(symbols.contains(&SymbolDefinition{position: text_offset,name: String::from("_entry_point"), _type: SymbolType::Notype, section_index: 1}));
-   assert!(symbols.contains(&SymbolDefinition{position: data_offset,name: String::from("_boot_magic"), _type: SymbolType::Notype, section_index: 2}));
+   assert!(symbols.contains(&SymbolDefinition{position: data_offset,name: String::from("_boot_magic"), _type: SymbolType::Notype, section_index: 3}));
    assert!(symbols.contains(&SymbolDefinition{position: 8,name: String::from("pl_end"), _type: SymbolType::Notype, section_index: 1}));
    Ok(())
 }
@@ -325,3 +248,249 @@ fn should_get_local_symbols(){
    //TODO test to ensure we can correctly retrive data segment symbols
    //TODO test to ensure we can source see data segment symbols in text segment i.e LDR _SOME_ADDR_LABEL dissassembles propperly
 }
+
+#[test]
+fn info_should_describe_an_elf(){
+   let report = describe(Path::new("./examples/fibonacci/fib_standalone.elf"), None).unwrap();
+   println!("{}",report);
+   assert!(report.contains("Type:                      EXEC (executable file)"));
+   assert!(report.contains("Flags:                     0x5000200, Version5 EABI, soft-float ABI"));
+   assert!(report.contains("[ 1] .text                PROGBITS        00000000 001000 000048 AX"));
+   assert!(report.contains("[ 6] .shstrtab            STRTAB"));
+   assert!(report.contains("LOAD       0x001000 0x00000000 0x00000000 0x00078  0x00078  RWE 0x1000"));
+   assert!(report.contains("Symbol table '.symtab' contains 22 entries:"));
+   assert!(report.contains("8: 00000009     0 FUNC    LOCAL    1 _reset_handler"));
+   assert!(report.contains("21: 0000000a     0 NOTYPE  GLOBAL   1 _main"));
+   assert!(report.contains("Vector table at 0x00000000 in .text (2 entries):"));
+   assert!(report.contains("0 Initial SP      0x00001078 _SP_RESET_VAL"));
+   assert!(report.contains("1 Reset           0x00000009 _reset_handler"));
+}
+
+#[test]
+fn info_should_reject_non_arm_elfs(){
+   let mut bytes = std::fs::read("./examples/fibonacci/fib_standalone.elf").unwrap();
+   let path = std::env::temp_dir().join(format!("armageddon_info_{}.elf",std::process::id()));
+
+   // e_machine = x86-64
+   bytes[18] = 62;
+   std::fs::write(&path, &bytes).unwrap();
+   let err = describe(&path, None).unwrap_err();
+   assert_eq!(err.to_string(), "unsupported machine 62, expected ARM (40)");
+
+   // EABI version 9 in e_flags
+   bytes[18] = 40;
+   bytes[39] = 0x09;
+   std::fs::write(&path, &bytes).unwrap();
+   let err = describe(&path, None).unwrap_err();
+   assert_eq!(err.to_string(), "unknown ARM EABI version 9 in flags 0x9000200");
+   std::fs::remove_file(&path).unwrap();
+}
+
+// fib_standalone.elf has 7 section headers at 0x12b8, .symtab is section 4
+const FIB_SECTION_HEADERS: usize = 0x12b8;
+const SECTION_HEADER_SIZE: usize = 40;
+
+fn write_patched_fib(name: &str, patch: impl Fn(&mut Vec<u8>))->PathBuf{
+   let mut bytes = std::fs::read("./examples/fibonacci/fib_standalone.elf").unwrap();
+   patch(&mut bytes);
+   let path = std::env::temp_dir().join(format!("armageddon_{}_{}.elf",name,std::process::id()));
+   std::fs::write(&path, &bytes).unwrap();
+   return path;
+}
+
+fn load_error(path: &Path)->String{
+   let err = match crate::load_instruction_opcodes(&[path.to_path_buf()], 0, &MemoryMap::default(), None){
+      Ok(_) => panic!("{:?} should not load",path),
+      Err(e) => e.to_string()
+   };
+   std::fs::remove_file(path).unwrap();
+   return err;
+}
+
+#[test]
+fn stripped_elfs_should_load_without_symbols(){
+   // a stripped ELF has no .symtab, turn it into a NULL section
+   let path = write_patched_fib("stripped", |bytes|{
+      bytes[FIB_SECTION_HEADERS + 4 * SECTION_HEADER_SIZE + 4] = 0;
+   });
+   let (disasm, entry_point, symbols, _sys) = crate::load_instruction_opcodes(std::slice::from_ref(&path), 0, &MemoryMap::default(), None).unwrap();
+   std::fs::remove_file(&path).unwrap();
+   assert!(symbols.is_empty());
+   assert!(!disasm.is_empty());
+   assert_eq!(entry_point, 0xa);
+}
+
+#[test]
+fn should_reject_unsupported_elfs(){
+   let elf64 = write_patched_fib("elf64", |bytes| bytes[4] = 2);
+   assert_eq!(load_error(&elf64), "unsupported ELF class 2 (ELF64), only 32 bit ELFs are supported");
+
+   let x86 = write_patched_fib("x86", |bytes| bytes[18] = 3);
+   assert_eq!(load_error(&x86), "unsupported machine 3, expected ARM (40)");
+
+   let version = write_patched_fib("version", |bytes| bytes[20] = 2);
+   assert_eq!(load_error(&version), "unsupported ELF version 2, expected 1 (current)");
+}
+
+#[test]
+fn should_reject_broken_elfs(){
+   let no_strtab = write_patched_fib("no_strtab", |bytes|{
+      bytes[FIB_SECTION_HEADERS + 4 * SECTION_HEADER_SIZE + 24] = 0;
+   });
+   assert_eq!(load_error(&no_strtab), "the ELF has no symbol string table (.strtab)");
+
+   let bad_link = write_patched_fib("bad_link", |bytes|{
+      bytes[FIB_SECTION_HEADERS + 4 * SECTION_HEADER_SIZE + 24] = 42;
+   });
+   assert_eq!(load_error(&bad_link), "symbol string table index 42 is out of range, there are 7 section headers");
+
+   let truncated = write_patched_fib("truncated", |bytes| bytes.truncate(FIB_SECTION_HEADERS + 100));
+   assert_eq!(
+      load_error(&truncated),
+      "section header table at offset 0x12b8 (280 bytes) runs past the end of the file (4892 bytes), the ELF is truncated"
+   );
+
+   // .text claims to be 1MiB
+   let huge_text = write_patched_fib("huge_text", |bytes|{
+      bytes[FIB_SECTION_HEADERS + SECTION_HEADER_SIZE + 22] = 0x10;
+   });
+   assert_eq!(
+      load_error(&huge_text),
+      ".text at offset 0x1000 (1048648 bytes) runs past the end of the file (5072 bytes), the ELF is truncated"
+   );
+
+   let past_end = write_patched_fib("past_end", |bytes|{
+      bytes[FIB_SECTION_HEADERS + SECTION_HEADER_SIZE + 18] = 0x01;
+   });
+   assert_eq!(load_error(&past_end), ".text offset 0x11000 is past the end of the file (5072 bytes)");
+}
+
+// moves the fib_standalone.elf image from 0 to 0x10000000
+fn write_relocated_fib(name: &str)->PathBuf{
+   return write_patched_fib(name, |bytes|{
+      bytes[27] = 0x10;
+      bytes[FIB_SECTION_HEADERS + SECTION_HEADER_SIZE + 15] = 0x10;
+      bytes[FIB_SECTION_HEADERS + 2 * SECTION_HEADER_SIZE + 15] = 0x10;
+   });
+}
+
+#[test]
+fn should_load_multiple_elfs_into_one_system(){
+   let bootloader = PathBuf::from("./examples/fibonacci/fib_standalone.elf");
+   let app = write_relocated_fib("app");
+   let elfs = vec![bootloader.clone(), app.clone()];
+
+   let (disasm, entry_point, symbols, sys) = crate::load_instruction_opcodes(&elfs, 0, &MemoryMap::default(), None).unwrap();
+   assert_eq!(entry_point, 0xa);
+   assert_eq!(sys.scs.vtor, 0);
+   // both images are mapped, .data of the app starts at 0x10000048
+   assert_eq!(sys.peek(0x48, 4), sys.peek(0x10000048, 4));
+   assert_eq!(symbols.iter().filter(|s| s.name == "_reset_handler").count(), 2);
+   assert!(disasm.iter().any(|l| l.contains("0x00000000: <image ./examples/fibonacci/fib_standalone.elf, entry point 0xa>")));
+   let app_header = format!("0x10000000: <image {}, entry point 0x1000000a>", app.display());
+   assert!(disasm.iter().any(|l| l.contains(&app_header)));
+
+   // booting the app takes its vector table and entry point
+   let (_, entry_point, _, mut sys) = crate::load_instruction_opcodes(&elfs, 1, &MemoryMap::default(), None).unwrap();
+   assert_eq!(entry_point, 0x1000000a);
+   sys.reset();
+   assert_eq!(sys.scs.vtor, 0x10000000);
+   assert_eq!(sys.registers.sp_main, 0x1078);
+
+   let overlap = crate::load_instruction_opcodes(&[bootloader.clone(), bootloader], 0, &MemoryMap::default(), None);
+   std::fs::remove_file(&app).unwrap();
+   assert_eq!(
+      overlap.err().unwrap().to_string(),
+      ".text of ./examples/fibonacci/fib_standalone.elf at 0x0 overlaps .text of ./examples/fibonacci/fib_standalone.elf at 0x0"
+   );
+}
+
+fn write_object(name: &str, src: &str)->PathBuf{
+   let assembly = crate::asm::assembler::assemble(src).unwrap();
+   let path = std::env::temp_dir().join(format!("armageddon_{}_{}.o",name,std::process::id()));
+   std::fs::write(&path, crate::elf::encoder::encode_relocatable(&assembly)).unwrap();
+   path
+}
+
+const LINKED_PROGRAM: &str = "
+.text
+.word 0x20001000
+.word _start
+.thumb_func
+.global _start
+_start:
+   ldr r0, =counter
+   ldr r1, [r0]
+   bl helper
+   str r1, [r0]
+   b _start
+
+.section .text.helper, \"ax\"
+.thumb_func
+helper:
+   adds r1, #1
+   bx lr
+
+.data
+counter:
+.word 41
+";
+
+#[test]
+fn should_link_relocatable_objects(){
+   use crate::system::simulator::Simulator;
+   let object = write_object("linked", LINKED_PROGRAM);
+   let (disasm, entry_point, symbols, mut sys) = crate::load_instruction_opcodes(std::slice::from_ref(&object), 0, &MemoryMap::default(), None).unwrap();
+   assert_eq!(entry_point, 0x9);
+   // .text.helper is packed after .text, .data goes to RAM
+   let helper = symbols.iter().find(|s| s.name == "helper").unwrap();
+   let counter = symbols.iter().find(|s| s.name == "counter").unwrap();
+   assert_eq!(helper.position, 0x19);
+   assert_eq!(counter.position, 0x20000000);
+   assert!(disasm.iter().any(|l| l.contains("BL (helper)")));
+   assert_eq!(sys.peek(0x14, 4), Some(0x20000000));
+
+   sys.reset();
+   assert_eq!(sys.registers.sp_main, 0x20001000);
+   for _ in 0 .. 14{
+      Simulator::step_or_signal_halt_type(&mut sys).unwrap();
+   }
+   assert_eq!(sys.peek(0x20000000, 4), Some(43));
+   assert_eq!(sys.registers.pc, 0x8);
+
+   let map = MemoryMap{code: 0x10000000, data: 0x20002000};
+   let (_, entry_point, _, sys) = crate::load_instruction_opcodes(std::slice::from_ref(&object), 0, &map, None).unwrap();
+   assert_eq!(entry_point, 0x10000009);
+   assert_eq!(sys.peek(0x10000004, 4), Some(0x10000009));
+   assert_eq!(sys.peek(0x10000014, 4), Some(0x20002000));
+   std::fs::remove_file(&object).unwrap();
+
+   let undefined = write_object("undefined", ".text\n.thumb_func\n_start:\n   bl missing\n");
+   assert_eq!(load_error(&undefined), "undefined symbol missing");
+}
+
+#[test]
+fn linking_should_reject_sections_past_the_address_space(){
+   let src = ".text\n.thumb_func\n_start:\n   nop\n.data\nvalue:\n.word 1\n";
+   let object = write_object("wrapping", src);
+   let map = MemoryMap{code: 0, data: 0xFFFFFFFE};
+   let err = crate::load_instruction_opcodes(std::slice::from_ref(&object), 0, &map, None).err().unwrap().to_string();
+   assert!(err.contains("past the end of the address space"), "{}",err);
+   let map = MemoryMap{code: 0xFFFFFFFF, data: 0x20000000};
+   let err = crate::load_instruction_opcodes(std::slice::from_ref(&object), 0, &map, None).err().unwrap().to_string();
+   assert!(err.starts_with("cannot align .text"), "{}",err);
+
+   // a crafted .data size that would wrap the cursor
+   let mut bytes = std::fs::read(&object).unwrap();
+   let half = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]) as usize;
+   let word = |bytes: &[u8], at: usize| u32::from_le_bytes(bytes[at .. at + 4].try_into().unwrap()) as usize;
+   let (shoff, shentsize, shnum, shstrndx) = (word(&bytes, 0x20), half(0x2E), half(0x30), half(0x32));
+   let strtab = word(&bytes, shoff + shstrndx * shentsize + 0x10);
+   let data = (0 .. shnum).map(|i| shoff + i * shentsize)
+      .find(|hdr| bytes[strtab + word(&bytes, *hdr) ..].starts_with(b".data\0"))
+      .unwrap();
+   bytes[data + 0x14 .. data + 0x18].copy_from_slice(&0xFFFFFFF0_u32.to_le_bytes());
+   std::fs::write(&object, bytes).unwrap();
+   let err = load_error(&object);
+   assert!(err.contains(".data") && err.contains("past the end of the address space"), "{}",err);
+}
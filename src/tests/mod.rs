@@ -4,3 +4,4 @@ pub mod dwarf;
 pub mod system;
 pub mod binutils;
 pub mod ui;
+pub mod dap;
@@ -88,6 +88,13 @@ fn allocator_test(){
    allocator.put::<2>(PAGE_SIZE as u32,[6,7]);
    assert_eq!(allocator.get_instr_32b(PAGE_SIZE as u32 - 2),[9,8,6,7],"can fetch 32 bit instructions ");
 
+   // sharing keeps every page written so far and both handles see later writes
+   let mut other = allocator.share();
+   assert_eq!(other.get::<4>(big_address_b),[11,22,33,44]);
+   other.put::<4>(8,[1,2,3,4]);
+   assert_eq!(allocator.get::<4>(8),[1,2,3,4]);
+   assert_eq!(allocator.pages(),other.pages());
+
    assert_eq!(allocator.view(PAGE_SIZE as u32 - 2, PAGE_SIZE as u32 + 1),vec![9,8,6,7]);
    assert_eq!(allocator.view(PAGE_SIZE as u32 - 2, PAGE_SIZE as u32 + 1),vec![9,8,6,7]);
 }
@@ -1,6 +1,7 @@
 pub mod instructions;
 pub mod memory;
 pub mod simulation;
+pub mod replay;
 
 use std::fs;
 use std::process::Command;
@@ -0,0 +1,209 @@
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+use crate::asm::assembler::assemble_at;
+use crate::binutils::from_arm_bytes;
+use crate::system::System;
+use super::simulation::{load_code_with_sections, copy_inital_state, step, are_states_equal};
+use super::{gdb_script, run_script_on_remote_cpu, parse_gdb_output, PROC_VARIABLES};
+
+// hardware runs are recorded once with the RP2040 attached and checked in,
+// after that the simulator is compared against the recording without any hardware
+
+pub const GDB_LOG_DIR: &str = "elf_samples/gdb_logs";
+
+const REGISTER_NAMES: [&str;PROC_VARIABLES] = [
+   "r0", "r1", "r2", "r3", "r4", "r5", "r6", "r7", "r8", "r9", "r10", "r11", "r12",
+   "msp", "psp", "lr", "pc", "xpsr"
+];
+
+// (log name, elf, start label, VTOR to spoof if the code relies on the pico SDK vector table)
+// fuzzy.elf is rebuilt by create_fuzzy_test, the recorder keeps a copy of the elf it traced next to the log
+const RECORDINGS: [(&str, &str, &str, Option<u32>);2] = [
+   ("linear_search", "elf_samples/linear_search.elf", "linear_search", None),
+   ("fuzzy_testsuite", "elf_samples/fuzzy/build/fuzzy.elf", "sim_testcase_init", Some(0x10000100))
+];
+
+#[derive(Debug)]
+pub struct Divergence{
+   pub step: usize,
+   pub hardware: [u32;PROC_VARIABLES],
+   pub simulator: [u32;PROC_VARIABLES],
+   // the source line gdb printed before the registers of this step
+   pub context: String
+}
+
+impl Divergence{
+   pub fn mismatched_registers(&self)->Vec<&'static str>{
+      (0 .. PROC_VARIABLES)
+         .filter(|i| self.hardware[*i] != self.simulator[*i])
+         .map(|i| REGISTER_NAMES[i])
+         .collect()
+   }
+}
+
+impl Display for Divergence{
+   fn fmt(&self, f: &mut Formatter<'_>)->std::fmt::Result{
+      writeln!(f, "state diverged at step {} (hardware pc = {:#010x})", self.step, self.hardware[super::PC])?;
+      if !self.context.is_empty(){
+         writeln!(f, "gdb: {}", self.context)?;
+      }
+      for i in (0 .. PROC_VARIABLES).filter(|i| self.hardware[*i] != self.simulator[*i]){
+         writeln!(f, "   {:<5}{:#010x}(hw) != {:#010x}(sim)", REGISTER_NAMES[i], self.hardware[i], self.simulator[i])?;
+      }
+      Ok(())
+   }
+}
+
+pub fn simulator_state(sys: &System)->[u32;PROC_VARIABLES]{
+   let mut state = [0;PROC_VARIABLES];
+   state[.. 13].copy_from_slice(&sys.registers.generic);
+   state[super::MSP] = sys.registers.sp_main;
+   state[super::PSP] = sys.registers.sp_process;
+   state[super::LR] = sys.registers.lr;
+   state[super::PC] = sys.registers.pc as u32;
+   state[super::XPSR] = from_arm_bytes(sys.xpsr);
+   return state;
+}
+
+fn log_path(name: &str)->PathBuf{
+   Path::new(GDB_LOG_DIR).join(format!("{}.log",name))
+}
+
+fn recorded_elf_path(name: &str)->PathBuf{
+   Path::new(GDB_LOG_DIR).join(format!("{}.elf",name))
+}
+
+// runs the dump_proc_state script on the attached RP2040 and keeps the raw gdb output
+pub fn record_gdb_log(name: &str, elf: &str, start_label: &str)->std::io::Result<PathBuf>{
+   let script_path = format!("dump_proc_state_{}",name);
+   std::fs::write(&script_path, gdb_script(&start_label.to_owned()))?;
+   let output = run_script_on_remote_cpu(&script_path, elf);
+   std::fs::remove_file(&script_path)?;
+   if !output.contains("<<FINISHED_PROC_LOG>>"){
+      return Err(std::io::Error::new(std::io::ErrorKind::Other, "gdb did not finish the register trace, is the RP2040 attached?"));
+   }
+   std::fs::create_dir_all(GDB_LOG_DIR)?;
+   std::fs::copy(elf, recorded_elf_path(name))?;
+   let path = log_path(name);
+   std::fs::write(&path, output)?;
+   return Ok(path);
+}
+
+// starts the simulator from the first recorded state and steps it alongside the log,
+// returns the number of states that matched or the first one that did not
+pub fn replay_gdb_log(sys: &mut System, log: &str)->Result<usize,Divergence>{
+   let states = parse_gdb_output(log);
+   let contexts: Vec<String> = log.split("<<-->>")
+      .map(|stage| stage.lines()
+         .map(|l| l.trim())
+         .find(|l| !l.is_empty() && !l.contains("$") && !l.starts_with("<<"))
+         .unwrap_or("")
+         .to_owned())
+      .collect();
+   copy_inital_state(sys, &states);
+   let mut matched = 0;
+   for (i, state) in states.chunks_exact(PROC_VARIABLES).enumerate(){
+      let hardware: [u32;PROC_VARIABLES] = state.try_into().expect("should be 18 registers");
+      if !are_states_equal(sys, &hardware){
+         let simulator = simulator_state(sys);
+         return Err(Divergence{step: i, hardware, simulator, context: contexts.get(i).cloned().unwrap_or_default()});
+      }
+      matched += 1;
+      step(sys);
+   }
+   return Ok(matched);
+}
+
+#[test] #[ignore]
+pub fn record_hardware_logs(){
+   for (name, elf, label, _) in RECORDINGS{
+      let path = record_gdb_log(name, elf, label).unwrap();
+      println!("recorded {}",path.display());
+   }
+}
+
+// ignored until the logs for RECORDINGS are recorded on hardware and checked in
+#[test] #[ignore]
+pub fn replay_recorded_gdb_logs(){
+   for (name, _, _, vtor) in RECORDINGS{
+      // a missing log is a failure, otherwise this test passes without comparing anything
+      let log = std::fs::read_to_string(log_path(name)).unwrap_or_else(|e| panic!(
+         "no recording for {} in {} ({}), run record_hardware_logs with the RP2040 attached and check the log in",
+         name,GDB_LOG_DIR,e
+      ));
+      let elf = recorded_elf_path(name);
+      let (mut sys, _) = load_code_with_sections(&elf).unwrap();
+      if let Some(v) = vtor{
+         sys.set_vtor(v);
+      }
+      match replay_gdb_log(&mut sys, &log){
+         Ok(n) => println!("{}: {} states matched",name,n),
+         Err(divergence) => panic!("{}: {}",name,divergence)
+      }
+   }
+}
+
+fn gdb_state(source_line: &str, registers: [u32;PROC_VARIABLES])->String{
+   let mut out = format!("{}\n",source_line);
+   for (i, v) in registers.iter().enumerate(){
+      match i{
+         super::MSP | super::PSP => out.push_str(&format!("{}: ${} = (void *) {:#x}\n",i + 1,REGISTER_NAMES[i],v)),
+         super::PC => out.push_str(&format!("{}: $pc = (void (*)()) {:#x} <main>\n",i + 1,v)),
+         super::XPSR => out.push_str(&format!("{}: $xPSR = {}\n",i + 1,v)),
+         _ => out.push_str(&format!("{}: ${} = {}\n",i + 1,REGISTER_NAMES[i],*v as i32))
+      }
+   }
+   out.push_str("<<-->>\n");
+   return out;
+}
+
+#[test]
+pub fn should_report_the_first_divergence_in_a_gdb_log(){
+   let code = "
+   .thumb
+   .text
+      MOVS r0, #4
+      MOVS r1, #0
+      ADDS r1, r1, r0
+   ";
+   let bytes = assemble_at(code, 0, &Vec::new()).unwrap();
+   let mut regs = [0_u32;PROC_VARIABLES];
+   regs[super::MSP] = 0x20041ff8;
+   regs[super::PSP] = 0xfffffffc;
+   regs[super::R8] = u32::MAX;
+   regs[super::XPSR] = 0x01000000;
+   let first = regs;
+   regs[super::R0] = 4;
+   regs[super::PC] = 2;
+   let second = regs;
+   regs[super::PC] = 4;
+   // MOVS sets Z
+   regs[super::XPSR] = 0x41000000;
+   let third = regs;
+   regs[super::R1] = 4;
+   regs[super::PC] = 6;
+   regs[super::XPSR] = 0x01000000;
+   let fourth = regs;
+
+   let log = |states: &[[u32;PROC_VARIABLES]]|{
+      let mut out = String::from("Loading section .text\n<<STARTING_PROC_LOG>>\n");
+      for (i, s) in states.iter().enumerate(){
+         out.push_str(&gdb_state(&format!("{}\tinstruction {}",i + 7,i), *s));
+      }
+      out.push_str("<<FINISHED_PROC_LOG>>\n");
+      out
+   };
+
+   let mut sys = System::fill_with(&bytes);
+   assert_eq!(replay_gdb_log(&mut sys, &log(&[first, second, third, fourth])).unwrap(), 4);
+
+   let mut wrong = fourth;
+   wrong[super::R1] = 5;
+   let mut sys = System::fill_with(&bytes);
+   let divergence = replay_gdb_log(&mut sys, &log(&[first, second, third, wrong])).unwrap_err();
+   assert_eq!(divergence.step, 3);
+   assert_eq!(divergence.mismatched_registers(), vec!["r1"]);
+   assert_eq!(divergence.context, "10\tinstruction 3");
+   assert!(divergence.to_string().contains("r1   0x00000005(hw) != 0x00000004(sim)"));
+}
@@ -8,11 +8,26 @@ use std::path::Path;
 use crate::binutils::{from_arm_bytes, from_arm_bytes_16b, u32_to_arm_bytes, into_arm_bytes};
 use crate::system::instructions::{zero_flag, negative_flag, carry_flag, overflow_flag};
 use crate::system::registers::{get_overflow_bit, get_carry_bit};
-use crate::tests::asm::{write_asm, asm_file_to_elf, asm_file_to_elf_armv6m};
-use crate::tests::elf::{write_asm_make_elf, link_elf};
+use crate::tests::asm::{write_asm, asm_file_to_elf};
+use crate::tests::elf::{write_asm_make_elf, link_object};
+use crate::elf::linker::MemoryMap;
 use crate::tests::system::{run_script_on_remote_cpu, parse_gdb_output, print_states};
-use crate::system::{ArmException, System, ExceptionStatus, Mode, SystemControlSpace};
-use crate::elf::decoder::{to_native_endianness_32b, ElfError, get_string_table_section_hdr, is_symbol_table_section_hdr, get_section_symbols, get_loadable_sections, load_sections, get_all_symbol_names, SymbolDefinition, LiteralPools};
+use crate::system::{ArmException, System, ExceptionStatus, Mode, SystemControlSpace, load_memory};
+use crate::system::registers::SpecialRegister;
+use crate::system::expression::{Condition, Comparison, Value, RegisterName};
+use crate::ui::Debug;
+use crate::system::simulator::{Machine, Simulator, HaltType, Throughput, Pacer, MIN_BATCH, MAX_BATCH};
+use crate::system::debug::{DebugHalt, DHCSR_DBGKEY, C_DEBUGEN, C_HALT, S_HALT, S_RETIRE_ST, S_RESET_ST, DFSR_HALTED, DFSR_BKPT, DFSR_VCATCH, DEMCR_VC_HARDERR};
+use crate::system::stimulus::{Schedule, Stimulus, Trigger, Action};
+use crate::system::rp2040::{Sio, SIO_BASE};
+use crate::system::profile::Profile;
+use crate::system::armv7m::{UFSR_UNDEFINSTR, UFSR_DIVBYZERO, HFSR_FORCED};
+use crate::system::mpu::{MPU_CTRL_ENABLE, MPU_RLAR_EN};
+use crate::system::observer::{SystemObserver, MemoryAccess};
+use crate::asm::decode::Opcode;
+use crate::asm::decode_operands::Operands;
+use crate::asm::assembler::{assemble, assemble_at};
+use crate::elf::decoder::{to_native_endianness_32b, ElfError, get_string_table_section_hdr, is_symbol_table_section_hdr, get_section_symbols, get_loadable_sections, load_sections, get_all_symbol_names, SymbolDefinition, SymbolType, LiteralPools};
 use crate::to_arm_bytes;
 use super::{gdb_script, PROC_VARIABLES};
 
@@ -76,48 +91,30 @@ fn run_assembly<
 }
 
 
+// links the object with .text at 0x0 as the loader would, then runs it from _entry_point
 fn run_elf<
    T: Any,
    F: Fn(usize,&[u8])->Result<T,ArmException>
 >(elf: &Path, interpreter: F)->Result<T,std::io::Error>{
-   let (elf_header,mut reader) = get_header(&elf).unwrap();
-
-   let maybe_hdr = get_all_section_headers(&mut reader, &elf_header);
-   if maybe_hdr.is_err(){
-      std::fs::remove_file(&elf)?;
-      return Err(Error::new(ErrorKind::Other, "could not read hdr"));
-   }
-
-   let section_headers = maybe_hdr.unwrap();
-   println!("sect_hdrs {:?}",section_headers);
-   assert!(!section_headers.is_empty());
-
-   let text_sect_hdr: Vec<SectionHeader> = section_headers.into_iter()
-      .filter(|hdr| is_text_section_hdr(&elf_header, hdr))
-      .collect();
-
-   println!("header {:?}",text_sect_hdr);
-   assert_eq!(text_sect_hdr.len(),1);
-   let sect_hdr = &text_sect_hdr[0];
-
-   let maybe_text_section = read_text_section(&mut reader, &elf_header, sect_hdr);
-   if maybe_text_section.is_err(){
-      std::fs::remove_file(&elf)?;
-      return Err(Error::new(ErrorKind::Other, "could not read text section"));
-   }
-
-   let text_section = maybe_text_section.unwrap();
+   let linked = link_object(elf, &MemoryMap::default());
+   let text_section = match linked.sections.iter().find(|(name,_,_)| name.eq(".text")){
+      Some((_,_,bytes)) => bytes,
+      None => {
+         std::fs::remove_file(elf)?;
+         return Err(Error::new(ErrorKind::Other, "could not read text section"));
+      }
+   };
    assert!(!text_section.is_empty());
 
-   let entry_point = to_native_endianness_32b(&elf_header, &elf_header._entry_point);
+   let entry_point = linked.symbols.iter().find(|s| s.name.eq("_entry_point")).unwrap().position;
 
-   let res = interpreter(entry_point as usize, &text_section[..]);
+   let res = interpreter(entry_point, &text_section[..]);
 
    if res.as_ref().is_err(){
       println!("failed execution exited due to: {:?}",res.as_ref().err());
    }
 
-   std::fs::remove_file(&elf)?;
+   std::fs::remove_file(elf)?;
    return Ok(res.unwrap());
 }
 
@@ -126,7 +123,7 @@ fn run_assembly_armv6m<
    F: Fn(usize,&[u8])->Result<T,ArmException>
 >(path: &Path,code: &[u8], interpreter: F)->Result<T,std::io::Error>{
    write_asm(path, code)?;
-   let elf = asm_file_to_elf_armv6m(path)?;
+   let elf = asm_file_to_elf(path)?;
 
    let (elf_header,mut reader) = get_header(&elf).unwrap();
 
@@ -644,21 +641,7 @@ pub fn support_exceptions_with_privelage_levels()->Result<(),std::io::Error>{
       ).as_bytes()
    )?;
 
-   let mut ld_script = File::create("./assembly_tests/priv_exceptions.ld")?;
-   ld_script.write_all(
-      format!(
-         "ENTRY(_entry_point);\n\
-         SECTIONS{{\n\
-            \t. = 0x0;\n\
-            \t.text : {{*(.text)}}\n\
-         }}\n"
-      ).as_bytes()
-   )?;
-
-   let linked = Path::new("./assembly_tests/priv_exceptions.out");
-   link_elf(&linked, &elf, Path::new("./assembly_tests/priv_exceptions.ld"));
-
-   run_elf(&linked, |entry_point, code|{
+   run_elf(&elf, |entry_point, code|{
       let mut sys = load_code_into_system(entry_point, code)?;
 
       sys.registers.sp_main = 0x100;
@@ -738,21 +721,7 @@ pub fn support_exceptions()->Result<(),std::io::Error>{
       ).as_bytes()
    )?;
 
-   let mut ld_script = File::create("./assembly_tests/basic_exc_load.ld")?;
-   ld_script.write_all(
-      format!(
-         "ENTRY(_entry_point);\n\
-         SECTIONS{{\n\
-            \t. = 0x0;\n\
-            \t.text : {{*(.text)}}\n\
-         }}\n"
-      ).as_bytes()
-   )?;
-
-   let linked = Path::new("./assembly_tests/exceptions.out");
-   link_elf(&linked, &elf, Path::new("./assembly_tests/load.ld"));
-
-   run_elf(&linked, |entry_point, code|{
+   run_elf(&elf, |entry_point, code|{
       let mut sys = load_code_into_system(entry_point, code)?;
       //let word: [u8;4] = sys.memory[..4].try_into().unwrap();
       let word = sys.alloc.get::<4>(0);
@@ -858,22 +827,7 @@ pub fn exception_preemption_test()->Result<(),std::io::Error>{
       ).as_bytes()
    )?;
 
-   let mut ld_script = File::create("./assembly_tests/exception_preemption.ld")?;
-   ld_script.write_all(
-      format!(
-         "ENTRY(_entry_point);\n\
-         SECTIONS{{\n\
-            \t. = 0x0;\n\
-            \t.text : {{*(.text)}}\n\
-         }}\n"
-      ).as_bytes()
-   )?;
-
-
-   let linked = Path::new("./assembly_tests/exc_preemption.out");
-   link_elf(&linked, &elf, Path::new("./assembly_tests/exception_preemption.ld"));
-
-   run_elf(&linked, |entry_point, code|{
+   run_elf(&elf, |entry_point, code|{
       let mut sys = load_code_into_system(entry_point, code)?;
       //let word: [u8;4] = sys.memory[..4].try_into().unwrap();
       let word = sys.alloc.get::<4>(0);
@@ -988,9 +942,9 @@ pub fn vtor_support()->Result<(),std::io::Error>{
          let i = sys.step()?;
          sys.offset_pc(i)?;
 
-         let i = sys.step()?;//writes to VTOR should be ignored
+         let i = sys.step()?;// only TBLOFF, bits [31:7], is writable
          sys.offset_pc(i)?;
-         assert_eq!(sys.scs.vtor,0);
+         assert_eq!(sys.scs.vtor,233 & 0xFFFFFF80);
 
          let i = sys.step()?;
          sys.offset_pc(i)?;
@@ -1001,7 +955,7 @@ pub fn vtor_support()->Result<(),std::io::Error>{
 
          let i = sys.step()?;
          sys.offset_pc(i)?;
-         assert_eq!(sys.registers.generic[3],0);
+         assert_eq!(sys.registers.generic[3],0x80);
          Ok(())
       }
    )?;
@@ -1719,7 +1673,7 @@ pub fn load_code_with_sections<P: AsRef<Path>>(elf: P)->Result<(System, Vec<Symb
    return Ok((System::with_sections(section_data),symbols));
 }
 
-fn copy_inital_state(sys: &mut System, states: &Vec<u32>){
+pub fn copy_inital_state(sys: &mut System, states: &Vec<u32>){
    let initial_state: [u32;PROC_VARIABLES] = states.chunks_exact(PROC_VARIABLES)
       .next()
       .unwrap()
@@ -1775,7 +1729,7 @@ macro_rules! fail_log {
     }
 }
 
-fn are_states_equal(sys: &System, state: &[u32; PROC_VARIABLES])->bool{
+pub fn are_states_equal(sys: &System, state: &[u32; PROC_VARIABLES])->bool{
 
    fail_log!(
       sys.registers.generic[0] == state[super::R0],
@@ -1890,7 +1844,7 @@ fn are_states_equal(sys: &System, state: &[u32; PROC_VARIABLES])->bool{
 }
 
 
-fn step(sys: &mut System ){
+pub fn step(sys: &mut System ){
    match sys.step(){
       Ok(offset) => {
          if sys.check_for_exceptions(offset).is_none(){
@@ -2178,3 +2132,1383 @@ fn create_fuzzy_test<P: AsRef<Path>>(bin_path: P)->Result<(),ElfError>{
    writer.write(&maybe_instruction)?;
    return Ok(());
 }
+
+#[test]
+pub fn should_validate_register_writes_from_the_debugger(){
+   let mut sys = System::create(0);
+   assert!(sys.write_core_register(3, 0xdeadbeef).is_ok());
+   assert_eq!(sys.registers.generic[3], 0xdeadbeef);
+   assert!(sys.write_core_register(13, 0x1002).is_err());
+   assert!(sys.write_core_register(15, 0x101).is_err());
+   assert!(sys.write_core_register(15, 0x100).is_ok());
+   assert_eq!(sys.registers.pc, 0x100);
+
+   assert!(sys.write_special_register(&SpecialRegister::PSP, 0x2001).is_err());
+   assert!(sys.write_special_register(&SpecialRegister::PSP, 0x2000).is_ok());
+   assert_eq!(sys.registers.sp_process, 0x2000);
+
+   // only the masked fields of xPSR change, here N and C plus IPSR
+   assert!(sys.write_special_register(&SpecialRegister::XPSR, 0xA100000B).is_ok());
+   assert_eq!(from_arm_bytes(sys.xpsr), 0xA100000B);
+   assert!(sys.write_special_register(&SpecialRegister::APSR, 0x40000000).is_ok());
+   assert_eq!(from_arm_bytes(sys.xpsr), 0x4100000B);
+
+   assert!(sys.write_special_register(&SpecialRegister::PRIMASK, 1).is_ok());
+   assert!(sys.primask());
+
+   // once nPRIV is set thread mode loses access to privileged registers
+   assert!(sys.write_special_register(&SpecialRegister::CONTROL, 3).is_ok());
+   assert_eq!(sys.get_sp(), 0x2000);
+   assert!(!sys.in_privileged_mode());
+   assert!(sys.write_special_register(&SpecialRegister::MSP, 0x3000).is_err());
+   assert!(sys.write_special_register(&SpecialRegister::APSR, 0).is_ok());
+
+   let mut handler = System::create(0);
+   handler.mode = Mode::Handler;
+   assert!(handler.write_special_register(&SpecialRegister::CONTROL, 2).is_err());
+   assert!(handler.write_special_register(&SpecialRegister::CONTROL, 1).is_ok());
+}
+
+#[test]
+pub fn should_launch_core1_through_the_sio_fifo(){
+   let code = "
+   .thumb
+   .text
+      MOVS r0, #0xD0
+      LSLS r0, r0, #24
+      LDR r5, [r0]
+      MOVS r1, #0
+      STR r1, [r0, #0x54]
+      STR r1, [r0, #0x54]
+      MOVS r1, #1
+      STR r1, [r0, #0x54]
+      MOVS r1, #0
+      STR r1, [r0, #0x54]
+      MOVS r1, #0x20
+      LSLS r1, r1, #24
+      STR r1, [r0, #0x54]
+      ADR r1, core1_entry
+      ADDS r1, #1
+      STR r1, [r0, #0x54]
+   wait:
+      LDR r2, [r0, #0x50]
+      MOVS r3, #1
+      TST r2, r3
+      BEQ wait
+      LDR r4, [r0, #0x58]
+      B wait
+   .align 2
+   core1_entry:
+      MOVS r0, #0xD0
+      LSLS r0, r0, #24
+      LDR r5, [r0]
+      MOVS r2, #42
+      STR r2, [r0, #0x54]
+   spin:
+      B spin
+   ";
+   let binary = assemble_at(code, 0, &Vec::new()).unwrap();
+   let machine = Machine::rp2040(System::fill_with(&binary));
+   for _ in 0 .. 200{
+      machine.step().unwrap();
+   }
+   let core0 = machine.cores[0].lock().unwrap();
+   let core1 = machine.cores[1].lock().unwrap();
+   assert_eq!(core0.registers.generic[5], 0);
+   assert_eq!(core1.registers.generic[5], 1);
+   assert_eq!(core1.registers.sp_main, 0x20000000);
+   assert_eq!(core0.registers.generic[4], 42);
+}
+
+#[test]
+pub fn sio_spinlocks_and_fifo_flags_should_follow_the_rp2040(){
+   let mut sio = Sio::create();
+   let lock3 = SIO_BASE + 0x100 + 12;
+   assert_eq!(sio.read(0, lock3), 1 << 3);
+   assert_eq!(sio.read(1, lock3), 0);
+   assert_eq!(sio.read(0, SIO_BASE + 0x5C), 1 << 3);
+   sio.write(1, lock3, 0);
+   assert_eq!(sio.read(1, lock3), 1 << 3);
+
+   // RDY with nothing to read, then ROE once core1 reads the empty FIFO
+   assert_eq!(sio.fifo_status(1), 2);
+   assert!(!sio.fifo_irq_asserted(1));
+   assert_eq!(sio.read(1, SIO_BASE + 0x58), 0);
+   assert_eq!(sio.fifo_status(1), 2 | 8);
+   assert!(sio.fifo_irq_asserted(1));
+   sio.write(1, SIO_BASE + 0x50, 0);
+   assert_eq!(sio.fifo_status(1), 2);
+
+   for i in 0 .. 9{
+      sio.write(0, SIO_BASE + 0x54, i);
+   }
+   assert_eq!(sio.fifo_status(0), 4);
+   assert_eq!(sio.fifo_status(1) & 1, 1);
+   assert_eq!(sio.read(1, SIO_BASE + 0x58), 0);
+   assert_eq!(sio.read(1, SIO_BASE + 0x58), 1);
+}
+
+#[test]
+pub fn debugger_reads_should_not_change_the_sio_state(){
+   let machine = Machine::rp2040(System::create(0));
+   let sio = machine.sio.clone().unwrap();
+   sio.lock().unwrap().write(0, SIO_BASE + 0x54, 42);
+   let core1 = machine.cores[1].lock().unwrap();
+   let fifo_rd = Value::Memory(SIO_BASE + 0x58, 4);
+   assert_eq!(fifo_rd.read(&core1), Some(42));
+   assert_eq!(fifo_rd.read(&core1), Some(42));
+   assert!(crate::ui::console::examine(&core1, SIO_BASE + 0x58, 1, 4, 'x').contains("0x0000002a"));
+   assert_eq!(Value::Memory(SIO_BASE + 0x100, 4).read(&core1), Some(1));
+   assert_eq!(Value::Memory(SIO_BASE, 4).read(&core1), Some(1));
+   let mut sio = sio.lock().unwrap();
+   // the word is still queued and spinlock 0 is still free
+   assert_eq!(sio.read(1, SIO_BASE + 0x58), 42);
+   assert_eq!(sio.read(1, SIO_BASE + 0x5C), 0);
+}
+
+#[test]
+pub fn conditional_breakpoints_should_only_halt_when_the_condition_holds(){
+   let mut sys = System::create(0);
+   sys.add_conditional_breakpoint(0x100, Condition{
+      lhs: Value::Register(RegisterName::Generic(0)),
+      cmp: Comparison::Ge,
+      rhs: Value::Literal(3)
+   });
+   sys.set_pc(0x100).unwrap();
+   sys.registers.generic[0] = 2;
+   assert!(!sys.on_breakpoint());
+   sys.registers.generic[0] = 3;
+   assert!(sys.on_breakpoint());
+   sys.remove_breakpoint(0x100);
+   assert!(!sys.on_breakpoint());
+   assert!(sys.breakpoint_conditions.is_empty());
+}
+
+#[test]
+pub fn should_parse_stimulus_schedules(){
+   let symbols = vec![
+      SymbolDefinition{position: 0x41, name: "handler_x".into(), section_index: 1, _type: SymbolType::Func}
+   ];
+   let schedule = Schedule::parse("
+      # comments and blank lines are skipped
+      pend IRQ 5 at instruction 10000
+      pend irq 3 every 2500 cycles
+      trigger NMI when PC == handler_x
+      set event register at step 0x10
+      pendsv when pc==0x100 # trailing comment
+   ", &symbols).unwrap();
+   assert_eq!(schedule.stimuli, vec![
+      Stimulus{action: Action::PendIrq(5), trigger: Trigger::At(10000)},
+      Stimulus{action: Action::PendIrq(3), trigger: Trigger::Every(2500)},
+      Stimulus{action: Action::Nmi, trigger: Trigger::PcEquals(0x40)},
+      Stimulus{action: Action::SetEvent, trigger: Trigger::At(0x10)},
+      Stimulus{action: Action::PendSV, trigger: Trigger::PcEquals(0x100)}
+   ]);
+   assert!(Schedule::parse_line("pend irq 32 at 5", &symbols).is_err());
+   assert!(Schedule::parse_line("pend irq 3 every 0 cycles", &symbols).is_err());
+   assert!(Schedule::parse_line("trigger nmi when pc == missing", &symbols).is_err());
+   assert!(Schedule::parse_line("pend usagefault at 5", &symbols).is_err());
+   assert_eq!(Schedule::parse("nmi at 1\nnmi sometime", &symbols).unwrap_err().starts_with("line 2:"), true);
+}
+
+fn stimulus_test_machine(schedule: &str)->Machine{
+   // interrupts are masked so pended exceptions stay visible, the NMI handler counts in r4
+   let code = "
+      .word 0x20001000
+      .word reset
+      .word nmi_handler
+      .thumb_func
+   reset:
+      CPSID i
+      MOVS r0, #0
+   loop:
+      ADDS r0, #1
+      B loop
+      .thumb_func
+   nmi_handler:
+      ADDS r4, #1
+      BX lr
+   ";
+   let binary = assemble_at(code, 0, &Vec::new()).unwrap();
+   let mut sys = System::fill_with(&binary);
+   sys.stimuli = Schedule::parse(schedule, &Vec::new()).unwrap();
+   sys.reset();
+   return Machine::single(sys);
+}
+
+#[test]
+pub fn scheduled_interrupts_should_pend_at_the_same_step_every_run(){
+   for _ in 0 .. 2{
+      let machine = stimulus_test_machine("pend irq 5 at instruction 10\npend irq 3 every 4 cycles");
+      machine.cores[0].lock().unwrap().scs.scr |= 0x10;
+      for _ in 0 .. 10{
+         machine.step().unwrap();
+      }
+      {
+         let sys = machine.cores[0].lock().unwrap();
+         assert!(matches!(sys.active_exceptions[16 + 5], ExceptionStatus::Inactive));
+         assert!(matches!(sys.active_exceptions[16 + 3], ExceptionStatus::Pending));
+      }
+      machine.cores[0].lock().unwrap().event_register = false;
+      machine.step().unwrap();
+      let sys = machine.cores[0].lock().unwrap();
+      assert!(matches!(sys.active_exceptions[16 + 5], ExceptionStatus::Pending));
+      // SEVONPEND is set so pending the interrupt also signals an event
+      assert!(sys.event_register);
+      assert_eq!(sys.stimuli.steps(), 11);
+   }
+}
+
+#[test]
+pub fn scheduled_nmi_should_fire_once_when_pc_arrives(){
+   let machine = stimulus_test_machine("trigger nmi when pc == 0x10\nset event at 1");
+   machine.step().unwrap();
+   assert!(!machine.cores[0].lock().unwrap().event_register);
+   machine.step().unwrap();
+   assert!(machine.cores[0].lock().unwrap().event_register);
+   for _ in 0 .. 40{
+      machine.step().unwrap();
+   }
+   let sys = machine.cores[0].lock().unwrap();
+   // the NMI ignores PRIMASK, it is taken every time the loop reaches its first instruction
+   assert!(sys.registers.generic[4] > 1);
+   assert!(sys.registers.generic[0] >= sys.registers.generic[4]);
+}
+
+#[test]
+pub fn reset_should_restart_the_stimulus_schedule(){
+   let machine = stimulus_test_machine("pend pendsv at 2");
+   for _ in 0 .. 3{
+      machine.step().unwrap();
+   }
+   assert!(matches!(machine.cores[0].lock().unwrap().active_exceptions[14], ExceptionStatus::Pending));
+   machine.reset();
+   assert!(matches!(machine.cores[0].lock().unwrap().active_exceptions[14], ExceptionStatus::Inactive));
+   for _ in 0 .. 3{
+      machine.step().unwrap();
+   }
+   assert!(matches!(machine.cores[0].lock().unwrap().active_exceptions[14], ExceptionStatus::Pending));
+}
+
+#[test]
+pub fn manual_steps_should_wait_for_every_core_to_be_free(){
+   let code = "
+   loop:
+      ADDS r0, #1
+      B loop
+   ";
+   let binary = assemble_at(code, 0, &Vec::new()).unwrap();
+   let machine = Machine::rp2040(System::fill_with(&binary));
+   let held = machine.cores[1].lock().unwrap();
+   assert!(machine.try_step().is_none());
+   drop(held);
+   assert_eq!(machine.cores[0].lock().unwrap().registers.generic[0], 0);
+   assert!(machine.try_step().unwrap().is_ok());
+   assert_eq!(machine.cores[0].lock().unwrap().registers.generic[0], 1);
+}
+
+#[test]
+pub fn sleeping_cores_should_fast_forward_to_the_next_stimulus(){
+   let code = "
+   loop:
+      WFE
+      ADDS r0, #1
+      B loop
+   ";
+   let binary = assemble_at(code, 0, &Vec::new()).unwrap();
+   let mut sys = System::fill_with(&binary);
+   sys.stimuli = Schedule::parse("set event at 100000", &Vec::new()).unwrap();
+   let machine = Machine::single(sys);
+   for _ in 0 .. 4{
+      machine.step().unwrap();
+   }
+   let sys = machine.cores[0].lock().unwrap();
+   assert_eq!(sys.registers.generic[0], 1);
+   assert!(sys.stimuli.steps() > 100000 && sys.stimuli.steps() < 100010);
+   assert!(sys.sleep.skipped >= 99990);
+   assert!(sys.sleep.asleep >= sys.sleep.skipped);
+}
+
+#[test]
+pub fn fast_forwarding_should_match_busy_stepping(){
+   // SysTick wakes the core every 1000 steps, its handler counts in r4, the main loop in r5 and PendSV in r6
+   let code = "
+      .word 0x20001000
+      .word reset
+      .word 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+      .word pendsv_handler
+      .word systick_handler
+      .thumb_func
+   reset:
+      LDR r0, =0xE000E014
+      LDR r1, =999
+      STR r1, [r0]
+      LDR r0, =0xE000E010
+      MOVS r1, #3
+      STR r1, [r0]
+   loop:
+      WFI
+      ADDS r5, #1
+      B loop
+      .thumb_func
+   systick_handler:
+      ADDS r4, #1
+      BX lr
+      .thumb_func
+   pendsv_handler:
+      ADDS r6, #1
+      BX lr
+      .pool
+   ";
+   let binary = assemble(code).unwrap().text().to_vec();
+   let boot = |binary: &[u8]|{
+      let mut sys = System::fill_with(binary);
+      sys.stimuli = Schedule::parse("pend pendsv at 2500", &Vec::new()).unwrap();
+      sys.reset();
+      sys
+   };
+   let fast = Machine::single(boot(&binary));
+   for _ in 0 .. 200{
+      fast.step().unwrap();
+   }
+   let fast = fast.cores[0].lock().unwrap();
+   assert!(fast.sleep.skipped > 0);
+
+   let mut busy = boot(&binary);
+   while busy.stimuli.steps() < fast.stimuli.steps(){
+      Simulator::step_or_signal_halt_type(&mut busy).unwrap();
+   }
+   assert!(busy.registers.generic[4] > 1);
+   assert_eq!(busy.registers.generic[6], 1);
+   assert_eq!(busy.registers.generic, fast.registers.generic);
+   assert_eq!(busy.registers.pc, fast.registers.pc);
+   assert_eq!(busy.scs.clock_value, fast.scs.clock_value);
+   assert_eq!(busy.sleep.asleep, fast.sleep.asleep);
+   assert_eq!(busy.sleep.skipped, 0);
+   for n in 0 .. busy.active_exceptions.len(){
+      assert_eq!(format!("{:?}",busy.active_exceptions[n]), format!("{:?}",fast.active_exceptions[n]));
+   }
+}
+
+#[test]
+pub fn vtor_writes_and_aircr_system_reset_should_take_effect(){
+   // the reset handler counts resets in r7, moves the vector table to table_b and pends PendSV,
+   // once table_b's handler has run it resets the system through AIRCR
+   let code = "
+      .word 0x20001000
+      .word reset
+      .word 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+      .word pendsv_a
+      .word 0
+      .thumb_func
+   reset:
+      ADDS r7, #1
+      LDR r0, =0xE000ED08
+      LDR r1, =table_b
+      STR r1, [r0]
+      LDR r3, [r0]
+      LDR r0, =0xE000ED04
+      LDR r1, =0x10000000
+      STR r1, [r0]
+   wait:
+      CMP r6, #0
+      BEQ wait
+      LDR r0, =0xE000ED0C
+      MOVS r1, #4
+      STR r1, [r0]
+      LDR r4, [r0]
+      LDR r1, =0x05FA0004
+      STR r1, [r0]
+      B .
+      .thumb_func
+   pendsv_a:
+      MOVS r5, #1
+      BX lr
+      .thumb_func
+   pendsv_b:
+      MOVS r6, #1
+      BX lr
+      .pool
+      .align 7
+   table_b:
+      .word 0x20001000
+      .word reset
+      .word 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+      .word pendsv_b
+      .word 0
+   ";
+   let asm = assemble(code).unwrap();
+   let symbol = |name: &str| asm.symbols.iter().find(|s| s.name.eq(name)).unwrap().value & !1;
+   let table_b = symbol("table_b");
+   let mut sys = System::fill_with(asm.text());
+   sys.reset();
+   let machine = Machine::single(sys);
+   let mut steps = 0;
+   while machine.cores[0].lock().unwrap().registers.generic[7] < 2{
+      machine.step().unwrap();
+      steps += 1;
+      assert!(steps < 200, "the system never reset");
+   }
+   let sys = machine.cores[0].lock().unwrap();
+   assert_eq!(sys.registers.generic[3], table_b);
+   assert_eq!(sys.registers.generic[5], 0);
+   assert_eq!(sys.registers.generic[6], 1);
+   // the write without VECTKEY was ignored and AIRCR reads back VECTKEYSTAT
+   assert_eq!(sys.registers.generic[4], 0xFA050000);
+   assert_eq!(sys.scs.vtor, 0);
+   assert_eq!(sys.registers.sp_main, 0x20001000);
+   assert_eq!(sys.registers.pc as u32, symbol("reset") + 2);
+   assert!(matches!(sys.active_exceptions[14], ExceptionStatus::Inactive));
+}
+
+#[test]
+pub fn aircr_vectclractive_should_clear_every_active_exception(){
+   let mut sys = System::create(0);
+   let aircr = Value::Memory(0xE000ED0C, 4);
+   sys.active_exceptions[14] = ExceptionStatus::Active;
+   sys.active_exceptions[11] = ExceptionStatus::ActiveAndPending;
+   sys.active_exceptions[16] = ExceptionStatus::Pending;
+   aircr.write(&mut sys, 0x2).unwrap();
+   assert!(matches!(sys.active_exceptions[14], ExceptionStatus::Active));
+   aircr.write(&mut sys, 0x05FA0002).unwrap();
+   assert!(matches!(sys.active_exceptions[14], ExceptionStatus::Inactive));
+   assert!(matches!(sys.active_exceptions[11], ExceptionStatus::Pending));
+   assert!(matches!(sys.active_exceptions[16], ExceptionStatus::Pending));
+   assert_eq!(sys.get_ipsr(), 0);
+   assert!(matches!(sys.mode, Mode::Thread));
+   assert_eq!(from_arm_bytes(load_memory::<4>(&sys, 0xE000ED0C).unwrap()), 0xFA050000);
+}
+
+#[test]
+pub fn dhcsr_halt_requests_should_stop_the_core_once_a_debugger_is_attached(){
+   let code = "
+      .word 0x20001000
+      .word reset
+      .thumb_func
+   reset:
+      LDR r0, =0xE000EDF0
+      LDR r1, =0xA05F0003
+      STR r1, [r0]
+      ADDS r5, #1
+      LDR r2, [r0]
+      LDR r3, [r0]
+      B .
+      .pool
+   ";
+   let mut sys = System::fill_with(assemble(code).unwrap().text());
+   sys.reset();
+   let machine = Machine::single(sys);
+
+   // without a debugger the write is ignored
+   for _ in 0 .. 5{
+      machine.step().unwrap();
+   }
+   assert_eq!(machine.cores[0].lock().unwrap().registers.generic[5], 1);
+   assert_eq!(machine.cores[0].lock().unwrap().debug.control, 0);
+
+   machine.reset();
+   machine.attach_debugger(true);
+   machine.step().unwrap();
+   machine.step().unwrap();
+   let halt = machine.step();
+   assert!(matches!(halt, Err(HaltType::debug(DebugHalt::Request))));
+   {
+      let sys = machine.cores[0].lock().unwrap();
+      // reset keeps r5, the ADDS after the store has not run yet
+      assert_eq!(sys.registers.generic[5], 1);
+      assert_eq!(sys.debug.dfsr, DFSR_HALTED);
+      assert!(sys.debug.halted);
+   }
+   // stepping resumes the core
+   for _ in 0 .. 3{
+      machine.step().unwrap();
+   }
+   let sys = machine.cores[0].lock().unwrap();
+   assert_eq!(sys.registers.generic[5], 2);
+   // the sticky bits are cleared by the first read, C_DEBUGEN stays set
+   assert_eq!(sys.registers.generic[2] & (S_HALT | S_RETIRE_ST | S_RESET_ST | C_HALT | C_DEBUGEN), S_RETIRE_ST | S_RESET_ST | C_DEBUGEN);
+   assert_eq!(sys.registers.generic[3] & (S_RETIRE_ST | S_RESET_ST), S_RETIRE_ST);
+}
+
+#[test]
+pub fn dhcsr_writes_should_need_the_debug_key(){
+   let mut sys = System::create(0);
+   sys.debug.set_enabled(true);
+   let dhcsr = Value::Memory(0xE000EDF0, 4);
+   dhcsr.write(&mut sys, C_HALT).unwrap();
+   assert_eq!(sys.debug.control, C_DEBUGEN);
+   // software cannot detach the debugger
+   dhcsr.write(&mut sys, (DHCSR_DBGKEY << 16) | C_HALT).unwrap();
+   assert_eq!(sys.debug.control, C_DEBUGEN | C_HALT);
+   assert_eq!(sys.debug.take_halt(), Some(DebugHalt::Request));
+}
+
+#[test]
+pub fn dcrsr_should_transfer_core_registers_through_dcrdr(){
+   let mut sys = System::create(0);
+   let dcrsr = Value::Memory(0xE000EDF4, 4);
+   let dcrdr = Value::Memory(0xE000EDF8, 4);
+   sys.registers.generic[4] = 0xCAFE;
+   dcrsr.write(&mut sys, 4).unwrap();
+   assert_eq!(sys.debug.dcrdr, 0xCAFE);
+   assert_eq!(from_arm_bytes(load_memory::<4>(&sys, 0xE000EDF8).unwrap()), 0xCAFE);
+
+   dcrdr.write(&mut sys, 0x1234).unwrap();
+   dcrsr.write(&mut sys, (1 << 16) | 14).unwrap();
+   assert_eq!(sys.registers.lr, 0x1234);
+
+   // REGSEL 20 packs CONTROL and PRIMASK
+   dcrdr.write(&mut sys, 1).unwrap();
+   dcrsr.write(&mut sys, (1 << 16) | 20).unwrap();
+   assert_eq!(RegisterName::PRIMASK.read(&sys), 1);
+   dcrsr.write(&mut sys, 20).unwrap();
+   assert_eq!(sys.debug.dcrdr, 1);
+}
+
+#[test]
+pub fn demcr_vector_catch_should_halt_on_hardfault_entry(){
+   let code = "
+      .word 0x20001000
+      .word reset
+      .word 0
+      .word hardfault
+      .thumb_func
+   reset:
+      LDR r0, =0xE000EDFC
+      LDR r1, =0x400
+      STR r1, [r0]
+      UDF #0
+      .thumb_func
+   hardfault:
+      MOVS r6, #1
+      B .
+      .pool
+   ";
+   let asm = assemble(code).unwrap();
+   let hardfault = asm.symbols.iter().find(|s| s.name.eq("hardfault")).unwrap().value & !1;
+   let mut sys = System::fill_with(asm.text());
+   sys.reset();
+   let machine = Machine::single(sys);
+   machine.attach_debugger(true);
+   let mut halt = Ok(());
+   for _ in 0 .. 10{
+      halt = machine.step();
+      if halt.is_err(){
+         break;
+      }
+   }
+   assert!(matches!(halt, Err(HaltType::debug(DebugHalt::VectorCatch(3)))));
+   let sys = machine.cores[0].lock().unwrap();
+   assert_eq!(sys.debug.demcr, DEMCR_VC_HARDERR);
+   assert_eq!(sys.debug.dfsr, DFSR_VCATCH);
+   // halted before the first instruction of the handler
+   assert_eq!(sys.registers.pc as u32, hardfault);
+   assert_eq!(sys.registers.generic[6], 0);
+}
+
+#[test]
+pub fn step_or_signal_halt_should_report_halts_as_debug_events(){
+   let code = "
+      MOVS r4, #1
+      BKPT #7
+      B .
+   ";
+   let mut sys = System::fill_with(assemble(code).unwrap().text());
+   sys.debug.set_enabled(true);
+   assert!(Simulator::step_or_signal_halt(&mut sys).is_ok());
+   assert_eq!(sys.registers.generic[4], 1);
+   assert!(matches!(Simulator::step_or_signal_halt(&mut sys), Err(Debug::Halt(HaltType::breakpoint(Some(7))))));
+}
+
+#[test]
+pub fn bkpt_should_halt_with_a_debugger_and_fault_without_one(){
+   let code = "
+      .word 0x20001000
+      .word reset
+      .word 0
+      .word hardfault
+      .thumb_func
+   reset:
+      MOVS r4, #1
+      BKPT #42
+      MOVS r5, #1
+      B .
+      .thumb_func
+   hardfault:
+      MOVS r6, #1
+      B .
+   ";
+   let asm = assemble(code).unwrap();
+   let symbol = |name: &str| asm.symbols.iter().find(|s| s.name.eq(name)).unwrap().value & !1;
+   let bkpt = symbol("reset") + 2;
+   let mut sys = System::fill_with(asm.text());
+   sys.reset();
+   let machine = Machine::single(sys);
+   machine.attach_debugger(true);
+
+   machine.step().unwrap();
+   assert!(matches!(machine.step(), Err(HaltType::breakpoint(Some(42)))));
+   {
+      let sys = machine.cores[0].lock().unwrap();
+      assert_eq!(sys.read_raw_ir(), bkpt);
+      assert_eq!(sys.debug.dfsr, DFSR_BKPT);
+      assert!(sys.debug.halted);
+   }
+   // resuming moves past the BKPT without executing anything else
+   machine.step().unwrap();
+   assert_eq!(machine.cores[0].lock().unwrap().read_raw_ir(), bkpt + 2);
+   assert_eq!(machine.cores[0].lock().unwrap().registers.generic[5], 0);
+   machine.step().unwrap();
+   assert_eq!(machine.cores[0].lock().unwrap().registers.generic[5], 1);
+
+   machine.reset();
+   machine.attach_debugger(false);
+   for _ in 0 .. 4{
+      machine.step().unwrap();
+   }
+   let sys = machine.cores[0].lock().unwrap();
+   assert_eq!(sys.registers.generic[6], 1);
+   assert!(matches!(sys.active_exceptions[3], ExceptionStatus::Active));
+}
+
+const THUMB2_PROGRAM: &str = "
+      .word 0x20001000
+      .word reset
+      .word 0
+      .word hardfault
+      .thumb_func
+   reset:
+      .hword 0xF245, 0x6078 @ MOVW r0, #0x5678
+      .hword 0xF2C1, 0x2034 @ MOVT r0, #0x1234
+      .hword 0xF04F, 0x0164 @ MOV.W r1, #100
+      .hword 0xF501, 0x427F @ ADD.W r2, r1, #0xFF00
+      MOVS r3, #7
+      .hword 0xFBB1, 0xF4F3 @ UDIV r4, r1, r3
+      .hword 0xFB04, 0x1503 @ MLA r5, r4, r3, r1
+      .hword 0xF3C0, 0x2607 @ UBFX r6, r0, #8, #8
+      .hword 0xF363, 0x260B @ BFI r6, r3, #8, #4
+      CMP r1, #100
+      .hword 0xBF0C @ ITE EQ
+      MOVS r7, #1
+      MOVS r7, #2
+      .hword 0xBF18 @ IT NE
+      MOVS r7, #3
+      MOVS r3, #0
+      .hword 0xB103 @ CBZ r3, skip
+      MOVS r7, #9
+   skip:
+      MOVS r3, #1
+      .hword 0xE8DF, 0xF003 @ TBB [pc, r3]
+      .hword 0x0201
+      B fail
+      .hword 0xF240, 0x1900 @ MOVW r9, #0x100
+      .hword 0xF2C2, 0x0900 @ MOVT r9, #0x2000
+      .hword 0xE859, 0xAF00 @ LDREX r10, [r9]
+      .hword 0xF10A, 0x0A05 @ ADD.W r10, r10, #5
+      .hword 0xE849, 0xAB00 @ STREX r11, r10, [r9]
+      MOV r8, r11
+      .hword 0xE849, 0xAB00 @ STREX r11, r10, [r9]
+      .hword 0xF859, 0xCB04 @ LDR.W r12, [r9], #4
+      .hword 0xFB84, 0x0105 @ SMULL r0, r1, r4, r5
+   done:
+      B .
+   fail:
+      MOVS r7, #0xFF
+      B .
+      .thumb_func
+   hardfault:
+      B .
+";
+
+#[test]
+pub fn armv7m_profile_should_execute_thumb2(){
+   let asm = assemble(THUMB2_PROGRAM).unwrap();
+   let done = asm.symbols.iter().find(|s| s.name.eq("done")).unwrap().value & !1;
+   let mut sys = System::fill_with(asm.text());
+   sys.profile = Profile::ArmV7M;
+   sys.reset();
+   let machine = Machine::single(sys);
+   for _ in 0 .. 100{
+      if machine.cores[0].lock().unwrap().read_raw_ir() == done{
+         break;
+      }
+      machine.step().unwrap();
+   }
+   let sys = machine.cores[0].lock().unwrap();
+   assert_eq!(sys.read_raw_ir(), done);
+   assert!(matches!(sys.active_exceptions[3], ExceptionStatus::Inactive));
+   let r = &sys.registers.generic;
+   assert_eq!(r[2], 100 + 0xFF00);
+   assert_eq!(r[4], 14);
+   assert_eq!(r[5], 198);
+   assert_eq!(r[6], 0x756);
+   // the MOVS inside the IT block did not clobber Z, so the IT NE was skipped
+   assert_eq!(r[7], 1);
+   assert_eq!(r[3], 1);
+   assert_eq!(r[10], 5);
+   // the first STREX held the monitor, the second one did not
+   assert_eq!(r[8], 0);
+   assert_eq!(r[11], 1);
+   assert_eq!(r[12], 5);
+   assert_eq!(r[9], 0x20000104);
+   assert_eq!((r[0], r[1]), (2772, 0));
+}
+
+#[test]
+pub fn armv6m_profile_should_fault_on_thumb2(){
+   let asm = assemble(THUMB2_PROGRAM).unwrap();
+   let mut sys = System::fill_with(asm.text());
+   sys.reset();
+   let machine = Machine::single(sys);
+   machine.step().unwrap();
+   let sys = machine.cores[0].lock().unwrap();
+   assert!(matches!(sys.active_exceptions[3], ExceptionStatus::Active));
+   assert_eq!(sys.registers.generic[0], 0);
+   // no configurable fault status on v6-M
+   assert_eq!(sys.scs.cfsr, 0);
+   assert_eq!(sys.scs.hfsr, 0);
+}
+
+#[test]
+pub fn armv7m_divide_by_zero_should_only_fault_when_trapped(){
+   let code = "
+      .word 0x20001000
+      .word reset
+      .word 0
+      .word hardfault
+      .thumb_func
+   reset:
+      MOVS r1, #9
+      MOVS r2, #0
+      MOVS r3, #1
+      .hword 0xFBB1, 0xF3F2 @ UDIV r3, r1, r2
+      MOV r4, r3
+      LDR r0, =0xE000ED14
+      LDR r5, [r0]
+      MOVS r6, #0x10
+      ORRS r5, r6
+      STR r5, [r0]
+      .hword 0xFBB1, 0xF3F2 @ UDIV r3, r1, r2
+      B .
+      .thumb_func
+   hardfault:
+      MOVS r7, #1
+      B .
+      .pool
+   ";
+   let asm = assemble(code).unwrap();
+   let mut sys = System::fill_with(asm.text());
+   sys.profile = Profile::ArmV7M;
+   sys.reset();
+   let machine = Machine::single(sys);
+   for _ in 0 .. 14{
+      machine.step().unwrap();
+   }
+   let sys = machine.cores[0].lock().unwrap();
+   assert_eq!(sys.registers.generic[4], 0);
+   assert_eq!(sys.registers.generic[7], 1);
+   assert!(matches!(sys.active_exceptions[3], ExceptionStatus::Active));
+   assert_eq!(sys.scs.cfsr & UFSR_DIVBYZERO, UFSR_DIVBYZERO);
+   assert_eq!(sys.scs.cfsr & UFSR_UNDEFINSTR, 0);
+   assert_eq!(sys.scs.hfsr, HFSR_FORCED);
+}
+
+#[test]
+pub fn armv8m_baseline_profile_should_execute_its_additions(){
+   let code = "
+      .word 0x20001000
+      .word reset
+      .word 0
+      .word hardfault
+      .thumb_func
+   reset:
+      .hword 0xF240, 0x1000 @ MOVW r0, #0x100
+      .hword 0xF2C2, 0x0000 @ MOVT r0, #0x2000
+      MOVS r1, #42
+      .hword 0xE8C0, 0x1FAF @ STL r1, [r0]
+      .hword 0xE8D0, 0x2FAF @ LDA r2, [r0]
+      .hword 0xE8D0, 0x3FEF @ LDAEX r3, [r0]
+      ADDS r3, #1
+      .hword 0xE8C0, 0x3FE4 @ STLEX r4, r3, [r0]
+      LDR r5, [r0]
+      MOVS r6, #7
+      .hword 0xFBB5, 0xF7F6 @ UDIV r7, r5, r6
+      MOVS r1, #0
+      .hword 0xB101 @ CBZ r1, skip
+      MOVS r7, #0xFF
+   skip:
+      .hword 0xF000, 0xB801 @ B.W done
+      MOVS r7, #0xFF
+   done:
+      B .
+      .thumb_func
+   hardfault:
+      B .
+   ";
+   let asm = assemble(code).unwrap();
+   let done = asm.symbols.iter().find(|s| s.name.eq("done")).unwrap().value & !1;
+   let mut sys = System::fill_with(asm.text());
+   sys.profile = Profile::ArmV8MBaseline;
+   sys.reset();
+   let machine = Machine::single(sys);
+   for _ in 0 .. 16{
+      machine.step().unwrap();
+   }
+   let sys = machine.cores[0].lock().unwrap();
+   assert_eq!(sys.read_raw_ir(), done);
+   let r = &sys.registers.generic;
+   assert_eq!(r[2], 42);
+   assert_eq!(r[4], 0);
+   assert_eq!(r[5], 43);
+   assert_eq!(r[7], 6);
+}
+
+#[test]
+pub fn profiles_should_reject_instructions_from_other_architectures(){
+   let code = "
+      .word 0x20001000
+      .word reset
+      .word 0
+      .word hardfault
+      .thumb_func
+   reset:
+      MOVS r0, #1
+      .hword 0xBF08 @ IT EQ
+      MOVS r1, #1
+      B .
+      .thumb_func
+   hardfault:
+      MOVS r6, #1
+      B .
+   ";
+   let asm = assemble(code).unwrap();
+   let mut sys = System::fill_with(asm.text());
+   sys.profile = Profile::ArmV8MBaseline;
+   sys.reset();
+   let machine = Machine::single(sys);
+   machine.step().unwrap();
+   machine.step().unwrap();
+   machine.step().unwrap();
+   assert_eq!(machine.cores[0].lock().unwrap().registers.generic[6], 1);
+
+   // the load-acquire/store-release instructions are not part of ARMv7-M
+   let code = "
+      .word 0x20001000
+      .word reset
+      .word 0
+      .word hardfault
+      .thumb_func
+   reset:
+      .hword 0xE8D0, 0x2FAF @ LDA r2, [r0]
+      B .
+      .thumb_func
+   hardfault:
+      MOVS r6, #1
+      B .
+   ";
+   let asm = assemble(code).unwrap();
+   let mut sys = System::fill_with(asm.text());
+   sys.profile = Profile::ArmV7M;
+   sys.reset();
+   let machine = Machine::single(sys);
+   machine.step().unwrap();
+   let sys = machine.cores[0].lock().unwrap();
+   assert!(matches!(sys.active_exceptions[3], ExceptionStatus::Active));
+   assert_eq!(sys.scs.cfsr, UFSR_UNDEFINSTR);
+}
+
+#[test]
+pub fn armv7m_priority_masks_should_hold_back_exceptions(){
+   let code = "
+      .word 0x20001000
+      .word reset
+      .word 0
+      .word hardfault
+      .word 0,0,0,0,0,0,0,0,0,0
+      .word pendsv
+      .thumb_func
+   reset:
+      LDR r0, =0xE000ED20
+      LDR r1, =0x00800000
+      STR r1, [r0]
+      MOVS r1, #0x40
+      MSR BASEPRI, r1
+      MOVS r1, #0xC0
+      MSR BASEPRI_MAX, r1
+      MRS r2, BASEPRI
+      LDR r0, =0xE000ED04
+      LDR r1, =0x10000000
+      STR r1, [r0]
+      MOVS r3, r6
+      CPSID f
+      MOVS r1, #0
+      MSR BASEPRI, r1
+      MOVS r4, r6
+      MRS r5, FAULTMASK
+      CPSIE f
+      MOVS r7, r6
+      MRS r1, FAULTMASK
+   done:
+      B .
+      .thumb_func
+   hardfault:
+      B .
+      .thumb_func
+   pendsv:
+      MOVS r6, #1
+      CPSID f
+      BX lr
+      .pool
+   ";
+   let asm = assemble(code).unwrap();
+   let done = asm.symbols.iter().find(|s| s.name.eq("done")).unwrap().value & !1;
+   let mut sys = System::fill_with(asm.text());
+   sys.profile = Profile::ArmV7M;
+   sys.reset();
+   let machine = Machine::single(sys);
+   for _ in 0 .. 40{
+      if machine.cores[0].lock().unwrap().read_raw_ir() == done{
+         break;
+      }
+      machine.step().unwrap();
+   }
+   let sys = machine.cores[0].lock().unwrap();
+   assert_eq!(sys.read_raw_ir(), done);
+   let r = &sys.registers.generic;
+   // BASEPRI_MAX only ever raises the mask
+   assert_eq!(r[2], 0x40);
+   // PendSV (priority 2) stays pending under BASEPRI 0x40 and under FAULTMASK
+   assert_eq!((r[3], r[4], r[5]), (0, 0, 1));
+   assert_eq!(r[7], 1);
+   // the CPSID f in the handler is undone by its exception return
+   assert_eq!(r[1], 0);
+   assert!(!sys.faultmask());
+   assert!(matches!(sys.active_exceptions[3], ExceptionStatus::Inactive));
+}
+
+#[test]
+pub fn armv6m_should_fault_on_the_armv7m_priority_masks(){
+   let asm = assemble(".thumb\nMSR BASEPRI, r0\n").unwrap();
+   let mut sys = System::fill_with(asm.text());
+   sys.reset();
+   let machine = Machine::single(sys);
+   machine.step().unwrap();
+   let mut sys = machine.cores[0].lock().unwrap();
+   assert!(matches!(sys.active_exceptions[3], ExceptionStatus::Active));
+   assert_eq!(sys.basepri(), 0);
+   assert!(sys.write_special_register(&SpecialRegister::FAULTMASK, 1).is_err());
+   drop(sys);
+
+   // SYSm 10 has no register behind it on either profile
+   let mut sys = System::fill_with(&[0x80, 0xF3, 0x0A, 0x88]);
+   sys.profile = Profile::ArmV7M;
+   sys.reset();
+   let machine = Machine::single(sys);
+   machine.step().unwrap();
+   let sys = machine.cores[0].lock().unwrap();
+   assert!(matches!(sys.active_exceptions[3], ExceptionStatus::Active));
+   assert_eq!(sys.scs.cfsr, UFSR_UNDEFINSTR);
+}
+
+#[test]
+pub fn armv8m_mpu_should_fault_outside_its_regions(){
+   let code = "
+      .word 0x20001000
+      .word reset
+      .word 0
+      .word hardfault
+      .thumb_func
+   reset:
+      LDR r7, =0xE000ED90
+      LDR r7, [r7]
+      LDR r0, =0xE000ED98
+      MOVS r1, #0
+      STR r1, [r0]
+      LDR r1, =0x20000003
+      STR r1, [r0, #4]
+      LDR r1, =0x20000FE1
+      STR r1, [r0, #8]
+      MOVS r1, #1
+      STR r1, [r0]
+      MOVS r1, #6
+      STR r1, [r0, #4]
+      LDR r1, =0x00000FE1
+      STR r1, [r0, #8]
+      SUBS r0, #4
+      MOVS r1, #1
+      STR r1, [r0]
+      LDR r2, =0x20000200
+      MOVS r3, #5
+      STR r3, [r2]
+      LDR r2, =0x20002000
+      STR r3, [r2]
+      B .
+      .thumb_func
+   hardfault:
+      MOVS r6, #1
+      B .
+      .pool
+   ";
+   let asm = assemble(code).unwrap();
+   let mut sys = System::fill_with(asm.text());
+   sys.profile = Profile::ArmV8MBaseline;
+   sys.reset();
+   let machine = Machine::single(sys);
+   for _ in 0 .. 25{
+      machine.step().unwrap();
+   }
+   let sys = machine.cores[0].lock().unwrap();
+   assert_eq!(sys.registers.generic[7], 0x800);
+   assert_eq!(sys.scs.mpu.ctrl, MPU_CTRL_ENABLE);
+   assert_eq!(sys.scs.mpu.rlar[0], 0x20000FE0 | MPU_RLAR_EN);
+   assert_eq!(from_arm_bytes(load_memory::<4>(&sys, 0x20000200).unwrap()), 5);
+   // the store outside every region faults, the handler runs on the default map
+   assert_eq!(sys.registers.generic[6], 1);
+   assert!(matches!(sys.active_exceptions[3], ExceptionStatus::Active));
+   assert_eq!(from_arm_bytes(load_memory::<4>(&sys, 0x20002000).unwrap()), 0);
+}
+
+#[test]
+pub fn be8_data_accesses_should_be_big_endian(){
+   let code = "
+      .word 0x20001000
+      .word reset
+      .word 0
+      .word hardfault
+      .thumb_func
+   reset:
+      MOVS r0, #1
+      LSLS r0, r0, #8
+      MOVS r1, #0x20
+      LSLS r1, r1, #24
+      ORRS r0, r1
+      ADR r2, data
+      LDR r3, [r2]
+      LDRH r4, [r2]
+      LDRB r5, [r2, #1]
+      STR r3, [r0]
+      STRH r4, [r0, #4]
+      PUSH {r3}
+      POP {r7}
+      B .
+      .thumb_func
+   hardfault:
+      B .
+      .align 2
+   data:
+      .byte 0x11, 0x22, 0x33, 0x44
+   ";
+   let asm = assemble(code).unwrap();
+   // a BE8 vector table is data, so it is stored big endian while the code stays little endian
+   let mut text = asm.text().to_vec();
+   for word in text[.. 16].chunks_mut(4){
+      word.reverse();
+   }
+   let mut sys = System::fill_with(&text);
+   sys.set_endianness(true);
+   sys.reset();
+   assert_eq!(sys.registers.sp_main, 0x20001000);
+   let machine = Machine::single(sys);
+   for _ in 0 .. 14{
+      machine.step().unwrap();
+   }
+   let sys = machine.cores[0].lock().unwrap();
+   assert!(matches!(sys.active_exceptions[3], ExceptionStatus::Inactive));
+   assert_eq!(sys.registers.generic[3], 0x11223344);
+   assert_eq!(sys.registers.generic[4], 0x1122);
+   assert_eq!(sys.registers.generic[5], 0x22);
+   assert_eq!(sys.registers.generic[7], 0x11223344);
+   assert_eq!(sys.alloc.view(0x20000100, 0x20000105), vec![0x11,0x22,0x33,0x44,0x11,0x22]);
+   assert_eq!(sys.peek(0x20000100, 4), Some(0x11223344));
+   // the PPB is always little endian
+   assert_eq!(from_arm_bytes(load_memory::<4>(&sys, 0xE000ED0C).unwrap()), 0xFA058000);
+}
+
+// records every event as a line so the order can be checked
+struct Recorder(std::sync::Arc<std::sync::Mutex<Vec<String>>>);
+
+impl SystemObserver for Recorder{
+   fn on_execute(&mut self, pc: u32, code: &Opcode, _operands: &Option<Operands>){
+      self.0.lock().unwrap().push(format!("execute {:#x} {}",pc,code));
+   }
+   fn on_retire(&mut self, pc: u32, code: &Opcode, _operands: &Option<Operands>){
+      self.0.lock().unwrap().push(format!("retire {:#x} {}",pc,code));
+   }
+   fn on_read(&mut self, access: &MemoryAccess){
+      self.0.lock().unwrap().push(format!("read {:#x}/{} = {:#x} {}",access.address,access.size,access.value,access.privileged));
+   }
+   fn on_write(&mut self, access: &MemoryAccess){
+      self.0.lock().unwrap().push(format!("write {:#x}/{} = {:#x} {}",access.address,access.size,access.value,access.privileged));
+   }
+   fn on_exception_entry(&mut self, exception: &ArmException, _priority: i32){
+      self.0.lock().unwrap().push(format!("enter {}",exception.number()));
+   }
+   fn on_exception_return(&mut self, exc_n: u32){
+      self.0.lock().unwrap().push(format!("return {}",exc_n));
+   }
+   fn on_reset(&mut self){
+      self.0.lock().unwrap().push(String::from("reset"));
+   }
+   fn on_lockup(&mut self, msg: &str){
+      self.0.lock().unwrap().push(format!("lockup {}",msg));
+   }
+   fn on_systick_reload(&mut self, reload: u32){
+      self.0.lock().unwrap().push(format!("systick {}",reload));
+   }
+}
+
+#[test]
+pub fn observers_should_see_every_execution_event(){
+   let code = "
+      .word 0x20001000
+      .word reset
+      .word 0
+      .word hardfault
+      .word 0, 0, 0, 0, 0, 0, 0
+      .word svc
+      .thumb_func
+   reset:
+      LDR r0, =0x20000000
+      MOVS r1, #7
+      STRB r1, [r0]
+      LDR r2, [r0]
+      LDR r3, =0xE000E010
+      MOVS r1, #2
+      STR r1, [r3, #4]
+      MOVS r1, #1
+      STR r1, [r3]
+      SVC #0
+      NOP
+      NOP
+      UDF #0
+      .thumb_func
+   svc:
+      BX lr
+      .thumb_func
+   hardfault:
+      UDF #0
+      .pool
+   ";
+   let asm = assemble(code).unwrap();
+   let symbol = |name: &str| asm.symbols.iter().find(|s| s.name.eq(name)).unwrap().value & !1;
+   let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+   let mut sys = System::fill_with(asm.text());
+   sys.add_observer(Box::new(Recorder(events.clone())));
+   sys.reset();
+   let machine = Machine::single(sys);
+   for _ in 0 .. 20{
+      let _ = machine.step();
+   }
+   assert!(machine.cores[0].lock().unwrap().is_locked_up());
+
+   let events = events.lock().unwrap();
+   let position = |event: &str| events.iter().position(|e| e.eq(event)).unwrap_or_else(|| panic!("no {} in {:#?}",event,events));
+   let reset = symbol("reset");
+   // the vector fetch on reset is a read like any other
+   assert_eq!(&events[0 .. 3], &["read 0x0/4 = 0x20001000 true", &format!("read 0x4/4 = {:#x} true",reset | 1), "reset"]);
+   // the literal load is seen between the LDR starting and retiring
+   assert!(events[3].starts_with(&format!("execute {:#x} LDR",reset)));
+   assert!(events[4].ends_with("/4 = 0x20000000 true"));
+   assert!(events[5].starts_with(&format!("retire {:#x} LDR",reset)));
+   assert!(events[8].starts_with(&format!("execute {:#x} STRB",reset + 4)));
+   assert_eq!(events[9], "write 0x20000000/1 = 0x7 true");
+   assert!(events[10].starts_with(&format!("retire {:#x} STRB",reset + 4)));
+   assert_eq!(events[12], "read 0x20000000/4 = 0x7 true");
+   assert!(position("enter 11") > position("write 0xe000e010/4 = 0x1 true"));
+   assert!(position("return 11") > position("enter 11"));
+   assert!(position("systick 2") > position("write 0xe000e010/4 = 0x1 true"));
+   // the exception frame is stacked through the bus
+   assert!(events.iter().any(|e| e.starts_with("write 0x20000fe0/4")));
+   assert!(position("enter 3") > position("return 11"));
+   assert!(events.last().unwrap().starts_with("lockup"));
+   // both UDFs are attempted but neither retires
+   assert_eq!(events.iter().filter(|e| e.starts_with("execute") && e.contains("UDF")).count(), 2);
+   assert!(!events.iter().any(|e| e.starts_with("retire") && e.contains("UDF")));
+}
+
+#[test]
+pub fn trace_should_show_the_instruction_that_faulted(){
+   let code = "
+      .word 0x20001000
+      .word reset
+      .word 0
+      .word hardfault
+      .thumb_func
+   reset:
+      MOVS r0, #1
+      UDF #0
+      .thumb_func
+   hardfault:
+      B hardfault
+   ";
+   let mut sys = System::fill_with(assemble(code).unwrap().text());
+   sys.trace_enabled = true;
+   sys.reset();
+   let machine = Machine::single(sys);
+   for _ in 0 .. 4{
+      let _ = machine.step();
+   }
+   let trace = machine.cores[0].lock().unwrap().trace.clone();
+   let udf = trace.find("UDF").unwrap_or_else(|| panic!("no UDF in {}",trace));
+   let entry = trace.find("initialising HardFault").unwrap_or_else(|| panic!("no HardFault entry in {}",trace));
+   assert!(udf < entry);
+   assert!(trace.contains("(priority: -1) exception"));
+}
+
+#[test]
+pub fn batched_runs_should_stop_exactly_on_breakpoints(){
+   let code = "
+      .word 0x20001000
+      .word reset
+      .thumb_func
+   reset:
+      MOVS r0, #0
+   loop:
+      ADDS r0, #1
+      CMP r0, #100
+      BNE loop
+   done:
+      B done
+   ";
+   let asm = assemble(code).unwrap();
+   let done = asm.symbols.iter().find(|s| s.name.eq("done")).unwrap().value;
+   let mut sys = System::fill_with(asm.text());
+   sys.reset();
+   let machine = Machine::single(sys);
+   machine.add_breakpoint(done);
+
+   let (steps, halt) = machine.run(10);
+   assert_eq!(steps, 10);
+   assert!(halt.is_ok());
+   let (steps, halt) = machine.run(MAX_BATCH);
+   // MOVS then 100 iterations of ADDS, CMP, BNE
+   assert_eq!(steps, 1 + 300 - 10);
+   assert!(matches!(halt, Err(HaltType::breakpoint(None))));
+   let sys = machine.cores[0].lock().unwrap();
+   assert_eq!(sys.registers.pc as u32, done);
+   assert_eq!(sys.registers.generic[0], 100);
+}
+
+#[test]
+pub fn batches_should_adapt_to_how_long_they_hold_the_core(){
+   use std::time::Duration;
+   let mut throughput = Throughput::start();
+   assert_eq!(throughput.batch, MIN_BATCH);
+   throughput.record(MIN_BATCH, Duration::from_millis(1));
+   assert_eq!(throughput.batch, 2 * MIN_BATCH);
+   // a batch cut short by a halt says nothing about its speed
+   throughput.record(3, Duration::from_millis(100));
+   assert_eq!(throughput.batch, 2 * MIN_BATCH);
+   throughput.record(2 * MIN_BATCH, Duration::from_millis(100));
+   assert_eq!(throughput.batch, MIN_BATCH);
+   throughput.record(MIN_BATCH, Duration::from_millis(100));
+   assert_eq!(throughput.batch, MIN_BATCH);
+   for _ in 0 .. 64{
+      let batch = throughput.batch;
+      throughput.record(batch, Duration::ZERO);
+   }
+   assert_eq!(throughput.batch, MAX_BATCH);
+   assert!(throughput.report() > 0);
+}
+
+#[test]
+pub fn throttled_runs_should_track_the_clock_rate(){
+   use std::{time::{Duration, Instant}, rc::Rc, cell::Cell};
+   let now = Rc::new(Cell::new(Instant::now()));
+   let clock = now.clone();
+   let mut pacer = Pacer::with_clock(None, move || clock.get());
+   assert_eq!(pacer.budget(MAX_BATCH), MAX_BATCH);
+   assert_eq!(pacer.delay(MAX_BATCH), Duration::ZERO);
+
+   // 10ms worth of cycles per batch, then nothing until the clock catches up
+   pacer.set_clock(Some(1000));
+   assert_eq!(pacer.budget(MAX_BATCH), 10);
+   now.set(now.get() + Duration::from_millis(4));
+   assert_eq!(pacer.delay(10), Duration::from_millis(6));
+   assert_eq!(pacer.budget(MAX_BATCH), 0);
+   now.set(now.get() + Duration::from_millis(6));
+   assert_eq!(pacer.budget(MAX_BATCH), 10);
+
+   // waits are capped so commands are still polled at slow clocks
+   pacer.set_clock(Some(10));
+   assert_eq!(pacer.budget(MAX_BATCH), 1);
+   assert_eq!(pacer.delay(1), Duration::from_millis(10));
+
+   // a machine that falls too far behind starts over instead of bursting to catch up
+   pacer.set_clock(Some(1000));
+   now.set(now.get() + Duration::from_millis(500));
+   assert_eq!(pacer.delay(10), Duration::ZERO);
+   assert_eq!(pacer.budget(MAX_BATCH), 10);
+   assert_eq!(pacer.delay(10), Duration::from_millis(10));
+
+   let code = "
+      .word 0x20001000
+      .word reset
+      .thumb_func
+   reset:
+      ADDS r0, #1
+      B reset
+   ";
+   let mut sys = System::fill_with(assemble(code).unwrap().text());
+   sys.reset();
+   let machine = Machine::single(sys);
+   let started = now.get();
+   let clock = now.clone();
+   let mut pacer = Pacer::with_clock(Some(2000), move || clock.get());
+   let mut total = 0;
+   while now.get() - started < Duration::from_millis(250){
+      let (steps, halt) = machine.run(pacer.budget(MAX_BATCH));
+      assert!(halt.is_ok());
+      total += steps;
+      let delay = pacer.delay(steps);
+      // the machine itself takes no time, only the waits move the clock
+      now.set(now.get() + delay.max(Duration::from_millis(1)));
+   }
+   // 500 steps in 250ms
+   assert_eq!(total, 500);
+}
+
+#[test]
+pub fn throttled_runs_should_charge_skipped_sleep_to_the_clock(){
+   use std::{time::{Duration, Instant}, rc::Rc, cell::Cell};
+   let code = "
+      .word 0x20001000
+      .word reset
+      .word 0,0,0,0,0,0,0,0,0,0,0,0,0
+      .word systick
+      .thumb_func
+   reset:
+      LDR r0, =0xE000E010
+      MOVS r2, #99
+      STR r2, [r0, #4]
+      MOVS r2, #3
+      STR r2, [r0]
+   sleep:
+      WFI
+      B sleep
+      .thumb_func
+   systick:
+      ADDS r4, #1
+      BX lr
+      .pool
+   ";
+   let mut sys = System::fill_with(assemble(code).unwrap().text());
+   sys.reset();
+   let machine = Machine::single(sys);
+   let now = Rc::new(Cell::new(Instant::now()));
+   let started = now.get();
+   let clock = now.clone();
+   let mut pacer = Pacer::with_clock(Some(2000), move || clock.get());
+   let mut total = 0;
+   while now.get() - started < Duration::from_millis(250){
+      let (steps, halt) = machine.run(pacer.budget(MAX_BATCH));
+      assert!(halt.is_ok());
+      total += steps;
+      now.set(now.get() + pacer.delay(steps).max(Duration::from_millis(1)));
+   }
+   assert_eq!(total, 500);
+   let sys = machine.cores[0].lock().unwrap();
+   // most of the 500 steps were skipped, SysTick still only reloaded once per 100 of them
+   assert!(sys.sleep.skipped > 400, "{:?}", sys.sleep);
+   assert!((3 ..= 5).contains(&sys.registers.generic[4]), "{} SysTick interrupts", sys.registers.generic[4]);
+}
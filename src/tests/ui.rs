@@ -1,5 +1,11 @@
 use crate::asm::interpreter::{build_lps_list, find_string, build_bin_lps_list, find_bin};
 use crate::ui::window::line_buffer;
+use crate::ui::{patch_disasm, memory_rows, parse_memory_write, parse_register_value, exception_rows, can_pend_from_ui, pend_from_ui, Cast, MemoryRow, EDITABLE_REGISTERS};
+use crate::system::{System, ArmException, ExceptionStatus};
+use crate::system::expression::{Value, RegisterName, Condition, Comparison};
+use crate::system::stimulus::{Stimulus, Action, Trigger};
+use crate::ui::console::{parse_command, examine, print_read, set_value, ConsoleCmd, Expr, MAX_EXAMINE_BYTES};
+use crate::elf::decoder::{SymbolDefinition, SymbolType};
 
 #[test]
 fn should_build_lps(){
@@ -80,3 +86,343 @@ fn should_bound_line_buffers(){
       "and for my next trick\na dance"
    );
 }
+
+#[test]
+fn should_patch_disassembly_in_place(){
+   let disasm = String::from(concat!(
+      "\n0x00000000: <_start>:\n",
+      "   0x00000000:   MOV Rd0, |8bit|#1\n",
+      "   0x00000002:   MOV Rd1, |8bit|#2\n",
+      "   0x00000004:   NOP\n"
+   ));
+   let patched = patch_disasm(&disasm, 2, &[0x00,0xbf]);
+   let lines: Vec<&str> = patched.lines().collect();
+   assert_eq!(lines[1], "0x00000000: <_start>:");
+   assert_eq!(lines[2], "   0x00000000:   MOV Rd0, |8bit|#1");
+   assert_eq!(lines[3], "   0x00000002:   NOP");
+   assert_eq!(lines[4], "   0x00000004:   NOP");
+   assert_eq!(lines.len(), 5);
+}
+
+#[test]
+fn should_dump_memory_as_hex_and_ascii(){
+   let data = b"Hello, world!\x00\x01\xffabc";
+   let rows = memory_rows(0x20000000, data, &Cast::HEXDUMP, &Vec::new(), false);
+   assert_eq!(rows.len(), 2);
+   assert_eq!(rows[0].address, 0x20000000);
+   assert_eq!(rows[0].cells.len(), 16);
+   assert_eq!(rows[0].cells[0], (0x20000000, "48".to_string()));
+   assert_eq!(rows[0].suffix, Some("|Hello, world!...|".to_string()));
+   assert_eq!(rows[1], MemoryRow{
+      label: None,
+      address: 0x20000010,
+      cells: vec![(0x20000010,"61".into()),(0x20000011,"62".into()),(0x20000012,"63".into())],
+      suffix: Some("|abc|".into())
+   });
+}
+
+#[test]
+fn should_cast_memory_to_strings_floats_and_arrays(){
+   let rows = memory_rows(0x100, b"one\x00two\n\x00\x00\x00\x00tail", &Cast::CSTRING, &Vec::new(), false);
+   let cells: Vec<(u32,String)> = rows.into_iter().map(|r| r.cells[0].clone()).collect();
+   assert_eq!(cells, vec![
+      (0x100, "\"one\"".to_string()),
+      (0x104, "\"two\\n\"".to_string()),
+      (0x109, "\"\"".to_string()),
+      (0x10c, "\"tail\"".to_string())
+   ]);
+
+   let rows = memory_rows(0, "päd\nline".as_bytes(), &Cast::UTF8, &Vec::new(), false);
+   assert_eq!(rows[0].cells[0], (0, "päd".to_string()));
+   assert_eq!(rows[1].cells[0], (5, "line".to_string()));
+
+   let rows = memory_rows(0, &1.5_f32.to_le_bytes(), &Cast::FLOAT, &Vec::new(), false);
+   assert_eq!(rows[0].cells[0].1, "1.5");
+
+   let words: Vec<u8> = (0..10_u32).flat_map(|w| w.to_le_bytes()).collect();
+   let rows = memory_rows(0x40, &words, &Cast::UWORDARR, &Vec::new(), false);
+   assert_eq!(rows.len(), 2);
+   assert_eq!(rows[0].cells.len(), 8);
+   assert_eq!(rows[1].cells, vec![(0x60, "0x00000008".to_string()), (0x64, "0x00000009".to_string())]);
+}
+
+#[test]
+fn should_parse_memory_writes_for_each_cast(){
+   assert_eq!(parse_memory_write(&Cast::UWORD, "0xdeadbeef", false), Some(vec![0xef,0xbe,0xad,0xde]));
+   assert_eq!(parse_memory_write(&Cast::UWORD, "16", false), Some(vec![16,0,0,0]));
+   assert_eq!(parse_memory_write(&Cast::IHALF, "-2", false), Some(vec![0xfe,0xff]));
+   assert_eq!(parse_memory_write(&Cast::HEXDUMP, "ff", false), Some(vec![0xff]));
+   assert_eq!(parse_memory_write(&Cast::FLOAT, "1.5", false), Some(1.5_f32.to_le_bytes().to_vec()));
+   assert_eq!(parse_memory_write(&Cast::CSTRING, "hi", false), Some(vec![b'h',b'i',0]));
+   assert_eq!(parse_memory_write(&Cast::UTF8, "hi", false), Some(vec![b'h',b'i']));
+   assert_eq!(parse_memory_write(&Cast::UBYTE, "256", false), None);
+   assert_eq!(parse_memory_write(&Cast::UWORD, "zz", false), None);
+}
+
+#[test]
+fn should_show_and_write_big_endian_memory(){
+   let data = [0x12,0x34,0x56,0x78];
+   let rows = memory_rows(0, &data, &Cast::UWORD, &Vec::new(), true);
+   assert_eq!(rows[0].cells[0].1, "305419896");
+   let rows = memory_rows(0, &data, &Cast::UHALFARR, &Vec::new(), true);
+   assert_eq!(rows[0].cells, vec![(0, "0x1234".to_string()), (2, "0x5678".to_string())]);
+   // bytes are never swapped
+   let rows = memory_rows(0, &data, &Cast::HEXDUMP, &Vec::new(), true);
+   assert_eq!(rows[0].cells[0].1, "12");
+
+   assert_eq!(parse_memory_write(&Cast::UWORD, "0xdeadbeef", true), Some(vec![0xde,0xad,0xbe,0xef]));
+   assert_eq!(parse_memory_write(&Cast::IHALF, "-2", true), Some(vec![0xff,0xfe]));
+   assert_eq!(parse_memory_write(&Cast::CSTRING, "hi", true), Some(vec![b'h',b'i',0]));
+   let float = parse_memory_write(&Cast::FLOAT, "1.5", true).unwrap();
+   assert_eq!(float, 1.5_f32.to_be_bytes().to_vec());
+   assert_eq!(memory_rows(0, &float, &Cast::FLOAT, &Vec::new(), true)[0].cells[0].1, "1.5");
+}
+
+#[test]
+fn should_parse_register_values(){
+   assert_eq!(parse_register_value("0x20001000"),Some(0x20001000));
+   assert_eq!(parse_register_value(" 42 "),Some(42));
+   assert_eq!(parse_register_value("-1"),Some(u32::MAX));
+   assert_eq!(parse_register_value("0xzz"),None);
+   assert_eq!(EDITABLE_REGISTERS[19],"CONTROL");
+}
+
+#[test]
+fn should_list_exceptions_with_priorities_and_nvic_state(){
+   let mut sys = System::create(0);
+   sys.scs.enabled_interrupts = 0b10;
+   sys.scs.shpr3 = 0x80C00000;
+   sys.scs.ipr[0] = 0x4000;
+   sys.set_exc_pending(ArmException::ExternInterrupt(17));
+   sys.set_exc_pending(ArmException::PendSV);
+
+   let rows = exception_rows(&sys);
+   // every one of the 48 exception numbers, reserved ones included
+   assert_eq!(rows.len(), 48);
+   assert!(rows.iter().enumerate().all(|(i,r)| r.number == i as u32));
+   assert_eq!(rows[7].name, "Reserved");
+   assert_eq!(rows[7].priority, None);
+   assert_eq!(rows[13].name, "Reserved");
+   let pendsv = rows.iter().find(|r| r.number == 14).unwrap();
+   assert_eq!(pendsv.priority, Some(3));
+   assert!(matches!(pendsv.status, ExceptionStatus::Pending));
+   assert_eq!(pendsv.enabled, None);
+   let systick = rows.iter().find(|r| r.number == 15).unwrap();
+   assert_eq!(systick.priority, Some(2));
+   let irq1 = rows.iter().find(|r| r.number == 17).unwrap();
+   assert_eq!(irq1.priority, Some(1));
+   assert_eq!(irq1.enabled, Some(true));
+   assert!(matches!(irq1.status, ExceptionStatus::Pending));
+   assert_eq!(rows.iter().find(|r| r.number == 18).unwrap().enabled, Some(false));
+
+   sys.clear_exc_pending(17);
+   assert!(matches!(sys.active_exceptions[17], ExceptionStatus::Inactive));
+   assert!(can_pend_from_ui(16) && can_pend_from_ui(2) && !can_pend_from_ui(3));
+}
+
+#[test]
+fn pending_from_the_ui_should_only_wake_wfi_for_exceptions_that_can_preempt(){
+   let mut sys = System::create(0);
+   sys.scs.wfi_wake_up = false;
+   pend_from_ui(&mut sys, 17);
+   assert!(matches!(sys.active_exceptions[17], ExceptionStatus::Pending));
+   assert!(!sys.scs.wfi_wake_up);
+
+   sys.clear_exc_pending(17);
+   sys.scs.enabled_interrupts = 0b10;
+   sys.scs.ipr[0] = 0x4000;
+   // an active SysTick at priority 0 masks the priority 1 interrupt
+   sys.active_exceptions[15] = ExceptionStatus::Active;
+   pend_from_ui(&mut sys, 17);
+   assert!(!sys.scs.wfi_wake_up);
+   pend_from_ui(&mut sys, 2);
+   assert!(sys.scs.wfi_wake_up);
+
+   sys.scs.wfi_wake_up = false;
+   sys.active_exceptions[15] = ExceptionStatus::Inactive;
+   sys.clear_exc_pending(17);
+   pend_from_ui(&mut sys, 17);
+   assert!(sys.scs.wfi_wake_up);
+}
+
+#[test]
+fn should_parse_console_commands(){
+   let symbols = vec![
+      SymbolDefinition{position: 0x41, name: "main".into(), section_index: 1, _type: SymbolType::Func},
+      SymbolDefinition{position: 0x20000000, name: "counter".into(), section_index: 2, _type: SymbolType::Object}
+   ];
+   assert_eq!(
+      parse_command("break main if $r0 == 5", &symbols),
+      Ok(ConsoleCmd::Break(0x41, Some(Condition{
+         lhs: Value::Register(RegisterName::Generic(0)),
+         cmp: Comparison::Eq,
+         rhs: Value::Literal(5)
+      })))
+   );
+   assert_eq!(parse_command("b 0x100", &symbols), Ok(ConsoleCmd::Break(0x100, None)));
+   assert_eq!(
+      parse_command("x/16wx 0x20000000", &symbols),
+      Ok(ConsoleCmd::Examine{addr: 0x20000000, count: 16, size: 4, format: 'x'})
+   );
+   assert_eq!(
+      parse_command("x/4bd counter", &symbols),
+      Ok(ConsoleCmd::Examine{addr: 0x20000000, count: 4, size: 1, format: 'd'})
+   );
+   let expr = |text: &str, value: Value| Expr{text: text.into(), value};
+   assert_eq!(parse_command("p $sp", &symbols), Ok(ConsoleCmd::Print(expr("$sp", Value::Register(RegisterName::SP)))));
+   assert_eq!(
+      parse_command("set $r1 = 5", &symbols),
+      Ok(ConsoleCmd::Set(expr("$r1", Value::Register(RegisterName::Generic(1))), expr("5", Value::Literal(5))))
+   );
+   assert_eq!(
+      parse_command("set *(u32*)0x20000000 = 1", &symbols),
+      Ok(ConsoleCmd::Set(expr("*(u32*)0x20000000", Value::Memory(0x20000000, 4)), expr("1", Value::Literal(1))))
+   );
+   assert_eq!(parse_command("step 3", &symbols), Ok(ConsoleCmd::Step(3)));
+   assert_eq!(parse_command("until main", &symbols), Ok(ConsoleCmd::Until(0x40)));
+   assert_eq!(parse_command("info symbols mai", &symbols), Ok(ConsoleCmd::InfoSymbols("mai".into())));
+   assert_eq!(parse_command("reset", &symbols), Ok(ConsoleCmd::Reset));
+   assert_eq!(parse_command("info stats", &symbols), Ok(ConsoleCmd::InfoStats));
+   assert_eq!(parse_command("schedule", &symbols), Ok(ConsoleCmd::ShowSchedule));
+   assert_eq!(parse_command("schedule clear", &symbols), Ok(ConsoleCmd::ClearSchedule));
+   assert_eq!(
+      parse_command("schedule trigger nmi when pc == main", &symbols),
+      Ok(ConsoleCmd::Schedule(Stimulus{action: Action::Nmi, trigger: Trigger::PcEquals(0x40)}))
+   );
+   assert!(parse_command("p $r13x", &symbols).is_err());
+   assert!(parse_command("set *(u16*)0x20000001 = 1", &symbols).is_err());
+   assert!(parse_command("x/0wx 0", &symbols).is_err());
+   assert!(parse_command("frobnicate", &symbols).is_err());
+}
+
+#[test]
+fn should_examine_and_write_memory_from_the_console(){
+   let mut sys = System::create(0);
+   let cell = Value::Memory(0x20000000, 4);
+   cell.write(&mut sys, 0xDEADBEEF).unwrap();
+   assert_eq!(cell.read(&sys), Some(0xDEADBEEF));
+   assert_eq!(Value::Memory(0x20000000, 2).read(&sys), Some(0xBEEF));
+   assert_eq!(examine(&sys, 0x20000000, 2, 4, 'x'), "0x20000000:\t0xdeadbeef\t0x00000000\n");
+   assert_eq!(examine(&sys, 0x20000000, 1, 1, 'd'), "0x20000000:\t-17\n");
+   assert!(Value::Literal(1).write(&mut sys, 2).is_err());
+
+   // ranges past the top of the address space are reported rather than wrapped
+   assert_eq!(examine(&sys, 0xFFFFFFFC, 1, 4, 'x'), "0xfffffffc:\t0x00000000\n");
+   assert!(examine(&sys, 0xFFFFFFFE, 1, 4, 'x').starts_with("cannot examine"));
+   // huge counts are cut off instead of copying the whole address space
+   let out = examine(&sys, 0, usize::MAX, 4, 'x');
+   assert_eq!(out.matches('\t').count(), MAX_EXAMINE_BYTES / 4);
+   assert!(out.ends_with(&format!("output truncated to {} of {} units ({} bytes)\n",MAX_EXAMINE_BYTES / 4,usize::MAX,MAX_EXAMINE_BYTES)));
+   assert!(!examine(&sys, 0, 1_000_000_000, 4, 'x').starts_with("cannot examine"));
+   assert_eq!(Value::Memory(0xFFFFFFFE, 4).read(&sys), None);
+   let typed = |text: &str, value: Value| Expr{text: text.into(), value};
+   assert_eq!(print_read(&sys, &typed("*(u32*)0x20000000", cell.clone())), "*(u32*)0x20000000 = 0xdeadbeef (-559038737)\n");
+   assert!(print_read(&sys, &typed("*(u16*)0xffffffff", Value::Memory(0xFFFFFFFF, 2))).starts_with("cannot read *(u16*)0xffffffff"));
+   assert!(!Condition{lhs: Value::Memory(0xFFFFFFFF, 4), cmp: Comparison::Eq, rhs: Value::Literal(0)}.holds(&sys));
+   assert!(set_value(&mut sys, &typed("*(u32*)0x20000000", cell.clone()), &typed("*0xfffffffd", Value::Memory(0xFFFFFFFD, 4))).starts_with("cannot read"));
+   assert_eq!(cell.read(&sys), Some(0xDEADBEEF));
+}
+
+#[test]
+fn console_writes_should_follow_the_data_endianness(){
+   let mut sys = System::create(0);
+   sys.set_endianness(true);
+   Value::Memory(0x20000000, 4).write(&mut sys, 0x11223344).unwrap();
+   Value::Memory(0x20000004, 2).write(&mut sys, 0x5566).unwrap();
+   assert_eq!(sys.alloc.view(0x20000000, 0x20000005), vec![0x11,0x22,0x33,0x44,0x55,0x66]);
+   assert_eq!(Value::Memory(0x20000000, 4).read(&sys), Some(0x11223344));
+   assert_eq!(examine(&sys, 0x20000004, 1, 2, 'x'), "0x20000004:\t0x5566\n");
+}
+
+#[test]
+fn should_drive_the_terminal_debugger(){
+   use crate::asm::assembler::assemble_at;
+   use crate::asm::interpreter::disasm_text;
+   use crate::system::simulator::Machine;
+   use crate::ui::tui::Tui;
+
+   let code = "
+   .thumb
+   .text
+      MOVS r0, #1
+      MOVS r1, #2
+      ADDS r2, r0, r1
+   spin:
+      B spin
+   ";
+   let bytes = assemble_at(code, 0, &Vec::new()).unwrap();
+   let symbols = vec![
+      SymbolDefinition{position: 0, name: "$t".into(), section_index: 1, _type: SymbolType::Notype},
+      SymbolDefinition{position: 6, name: "spin".into(), section_index: 1, _type: SymbolType::Notype}
+   ];
+   let disasm = disasm_text(&bytes, 0, &symbols).join("\n");
+   let mut tui = Tui::create(Machine::single(System::fill_with(&bytes)), symbols, disasm, None, None);
+   let (_sndr, interrupt) = std::sync::mpsc::channel();
+
+   assert_eq!(tui.execute("break 0x4", &interrupt).unwrap(), "breakpoint at 0x00000004\n");
+   let halted = tui.execute("continue", &interrupt).unwrap();
+   assert!(halted.starts_with("hit breakpoint\n"));
+   assert!(halted.lines().any(|l| l.starts_with("=>") && l.contains("0x00000004:")));
+   assert!(tui.execute("p $r1", &interrupt).unwrap().contains("0x00000002"));
+   tui.execute("set $r1 = 7", &interrupt).unwrap();
+   tui.execute("delete 0x4", &interrupt).unwrap();
+   let stepped = tui.execute("until spin", &interrupt).unwrap();
+   assert!(stepped.contains("r2  0x00000008"));
+   assert!(tui.execute("regs", &interrupt).unwrap().contains("pc  0x00000006"));
+   assert!(tui.execute("frobnicate", &interrupt).unwrap().starts_with("unknown command"));
+   assert!(tui.execute("quit", &interrupt).is_none());
+}
+
+#[test]
+fn should_dump_state_as_json_on_halt(){
+   use crate::asm::assembler::assemble_at;
+   use crate::asm::interpreter::disasm_text;
+   use crate::json::Json;
+   use crate::system::dump::{StateDump, halt_json, machine_state};
+   use crate::system::simulator::{HaltType, Machine};
+   use crate::ui::tui::Tui;
+
+   let code = "
+   .thumb
+   .text
+      MOVS r0, #0
+      MOVS r1, #0xAB
+      STR r1, [r2]
+   spin:
+      B spin
+   ";
+   let bytes = assemble_at(code, 0, &Vec::new()).unwrap();
+   let symbols = vec![
+      SymbolDefinition{position: 0, name: "$t".into(), section_index: 1, _type: SymbolType::Notype},
+      SymbolDefinition{position: 6, name: "spin".into(), section_index: 1, _type: SymbolType::Notype}
+   ];
+   let disasm = disasm_text(&bytes, 0, &symbols).join("\n");
+   let mut sys = System::fill_with(&bytes);
+   sys.registers.generic[2] = 0x20000000;
+   let machine = Machine::single(sys);
+   let path = std::env::temp_dir().join(format!("armageddon_dump_{}.json",std::process::id()));
+   let dump = StateDump{path: path.clone(), ranges: vec![(0x20000000, 0x20000003)]};
+   let mut tui = Tui::create(machine.share(), symbols, disasm, Some(dump), None);
+   let (_sndr, interrupt) = std::sync::mpsc::channel();
+   tui.execute("break spin", &interrupt).unwrap();
+   tui.execute("continue", &interrupt).unwrap();
+
+   let state = Json::parse(&std::fs::read_to_string(&path).unwrap()).unwrap();
+   std::fs::remove_file(&path).unwrap();
+   assert_eq!(state.get("halt").unwrap().get("type"), Some(&"breakpoint".into()));
+   let core = &state.get("cores").unwrap().as_array().unwrap()[0];
+   assert_eq!(core.get("registers").unwrap().get("r1"), Some(&Json::from(0xAB_u32)));
+   assert_eq!(core.get("registers").unwrap().get("pc"), Some(&Json::from(6_u32)));
+   assert_eq!(core.get("xpsr").unwrap().get("z"), Some(&false.into()));
+   assert_eq!(core.get("xpsr").unwrap().get("t"), Some(&true.into()));
+   assert_eq!(core.get("mode"), Some(&"Thread".into()));
+   assert_eq!(core.get("primask"), Some(&false.into()));
+   assert_eq!(core.get("exceptions").unwrap().as_array().unwrap().len(), 38);
+   let memory = &state.get("memory").unwrap().as_array().unwrap()[0];
+   assert_eq!(memory.get("bytes"), Some(&"ab000000".into()));
+
+   let fault = halt_json(Some(&HaltType::error(ArmException::HardFault("unaligned access".into()))));
+   assert_eq!(fault.get("exception"), Some(&"Hardfault".into()));
+   assert_eq!(fault.get("message"), Some(&"unaligned access".into()));
+   assert_eq!(machine_state(&machine, None, &[]).get("halt"), Some(&Json::Null));
+}
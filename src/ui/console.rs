@@ -0,0 +1,271 @@
+use crate::asm::interpreter::is_segment_mapping_symbol;
+use crate::elf::decoder::SymbolDefinition;
+use crate::system::System;
+use crate::system::expression::{Value, RegisterName, Condition, Comparison};
+use crate::system::stimulus::{Schedule, Stimulus};
+
+use super::{BkptInput, parse_register_value};
+
+// a small gdb flavoured command language for the console pane
+
+pub const HELP_MSG: &'static str = concat!(
+   "break <sym|addr> [if <cond>]   halt when PC reaches an address, optionally only when cond holds\n",
+   "x/<n><size><fmt> <sym|addr>    examine memory, size is b/h/w and fmt is x/d/u (x/16wx 0x20000000)\n",
+   "p <value>                      print a value ($r0, $sp, $xpsr, *(u32*)0x20000000, symbol)\n",
+   "set <value> = <value>          write a register or memory (set $r1 = 5, set *(u32*)0x20000000 = 1)\n",
+   "step [n]                       step n instructions\n",
+   "until <sym|addr>               continue until an address is reached\n",
+   "continue                       continue execution\n",
+   "info symbols [pattern]         list symbols containing pattern\n",
+   "info stats                     show how many steps were spent asleep in WFI/WFE\n",
+   "reset                          reset the system\n",
+   "schedule <stimulus>            inject an interrupt or event (schedule pend irq 3 every 2500 cycles)\n",
+   "schedule [clear]               list or remove the scheduled stimuli\n"
+);
+
+// x/ output is cut off after this many bytes, large counts would otherwise copy gigabytes out of the pages
+pub const MAX_EXAMINE_BYTES: usize = 4096;
+
+// a value together with the text it was parsed from, printed back the way the user typed it
+#[derive(Debug,PartialEq)]
+pub struct Expr{
+   pub text: String,
+   pub value: Value
+}
+
+#[derive(Debug,PartialEq)]
+pub enum ConsoleCmd{
+   Break(u32,Option<Condition>),
+   Examine{addr: u32, count: usize, size: usize, format: char},
+   Print(Expr),
+   Set(Expr,Expr),
+   Step(usize),
+   Until(u32),
+   Continue,
+   InfoSymbols(String),
+   InfoStats,
+   Reset,
+   Schedule(Stimulus),
+   ShowSchedule,
+   ClearSchedule,
+   Help
+}
+
+fn resolve_addr(input: &str, symbols: &Vec<SymbolDefinition>)->Result<u32,String>{
+   if let Some(v) = parse_register_value(input){
+      return Ok(v);
+   }
+   let input = BkptInput{pending_addr_or_symbol: input.trim().to_owned()};
+   return input.try_get_addr(symbols).ok_or(format!("no symbol or address matches '{}'",input.pending_addr_or_symbol));
+}
+
+pub fn parse_value(input: &str, symbols: &Vec<SymbolDefinition>)->Result<Value,String>{
+   let input = input.trim();
+   if let Some(name) = input.strip_prefix('$'){
+      return RegisterName::from_name(name)
+         .map(Value::Register)
+         .ok_or(format!("unknown register ${}",name));
+   }
+   if let Some(deref) = input.strip_prefix('*'){
+      let deref = deref.trim();
+      let (size, target) = match deref.strip_prefix('('){
+         Some(cast) => {
+            let (ty, rest) = cast.split_once(')').ok_or(format!("unterminated cast in '{}'",input))?;
+            let size = match ty.trim().trim_end_matches('*').trim(){
+               "u32" | "i32" | "int" => 4,
+               "u16" | "i16" | "short" => 2,
+               "u8" | "i8" | "char" => 1,
+               other => return Err(format!("cannot dereference as {}",other))
+            };
+            (size, rest)
+         },
+         None => (4, deref)
+      };
+      let addr = resolve_addr(target, symbols)?;
+      if addr % size as u32 != 0{
+         return Err(format!("{:#010x} is not {} byte aligned",addr,size));
+      }
+      return Ok(Value::Memory(addr, size));
+   }
+   return resolve_addr(input, symbols).map(Value::Literal);
+}
+
+pub fn parse_expr(input: &str, symbols: &Vec<SymbolDefinition>)->Result<Expr,String>{
+   Ok(Expr{text: input.trim().to_owned(), value: parse_value(input, symbols)?})
+}
+
+pub fn parse_condition(input: &str, symbols: &Vec<SymbolDefinition>)->Result<Condition,String>{
+   let operators = [
+      ("==", Comparison::Eq), ("!=", Comparison::Ne), ("<=", Comparison::Le),
+      (">=", Comparison::Ge), ("<", Comparison::Lt), (">", Comparison::Gt)
+   ];
+   for (op, cmp) in operators{
+      if let Some((lhs, rhs)) = input.split_once(op){
+         return Ok(Condition{lhs: parse_value(lhs, symbols)?, cmp, rhs: parse_value(rhs, symbols)?});
+      }
+   }
+   return Err(format!("'{}' is not a comparison",input.trim()));
+}
+
+fn parse_examine(spec: &str, target: &str, symbols: &Vec<SymbolDefinition>)->Result<ConsoleCmd,String>{
+   let digits: String = spec.chars().take_while(|c| c.is_ascii_digit()).collect();
+   let count = if digits.is_empty(){ 1 }else{ digits.parse::<usize>().map_err(|e| e.to_string())? };
+   if count == 0{
+      return Err("examine count must be at least 1".into());
+   }
+   let mut size = 4;
+   let mut format = 'x';
+   for c in spec[digits.len() ..].chars(){
+      match c{
+         'b' => size = 1,
+         'h' => size = 2,
+         'w' => size = 4,
+         'x' | 'd' | 'u' => format = c,
+         _ => return Err(format!("unknown examine modifier '{}'",c))
+      }
+   }
+   let addr = resolve_addr(target, symbols)?;
+   return Ok(ConsoleCmd::Examine{addr, count, size, format});
+}
+
+pub fn parse_command(line: &str, symbols: &Vec<SymbolDefinition>)->Result<ConsoleCmd,String>{
+   let line = line.trim();
+   let (cmd, args) = match line.split_once(char::is_whitespace){
+      Some((c, a)) => (c, a.trim()),
+      None => (line, "")
+   };
+   if let Some(spec) = cmd.strip_prefix("x/"){
+      return parse_examine(spec, args, symbols);
+   }
+   match cmd{
+      "b" | "break" => {
+         match args.split_once(" if "){
+            Some((target, cond)) => Ok(ConsoleCmd::Break(resolve_addr(target, symbols)?, Some(parse_condition(cond, symbols)?))),
+            None => Ok(ConsoleCmd::Break(resolve_addr(args, symbols)?, None))
+         }
+      },
+      "x" => parse_examine("", args, symbols),
+      "p" | "print" => Ok(ConsoleCmd::Print(parse_expr(args, symbols)?)),
+      "set" => {
+         let (lhs, rhs) = args.split_once('=').ok_or("expected set <value> = <value>".to_owned())?;
+         Ok(ConsoleCmd::Set(parse_expr(lhs, symbols)?, parse_expr(rhs, symbols)?))
+      },
+      "s" | "si" | "step" | "stepi" => {
+         if args.is_empty(){
+            Ok(ConsoleCmd::Step(1))
+         }else{
+            args.parse::<usize>().map(ConsoleCmd::Step).map_err(|_| format!("'{}' is not a step count",args))
+         }
+      },
+      "u" | "until" => Ok(ConsoleCmd::Until(resolve_addr(args, symbols)? & !1)),
+      "c" | "continue" => Ok(ConsoleCmd::Continue),
+      "info" => match args.split_once(char::is_whitespace).map(|(a,b)| (a, b.trim())).unwrap_or((args, "")){
+         ("symbols", pattern) => Ok(ConsoleCmd::InfoSymbols(pattern.to_owned())),
+         ("stats", _) => Ok(ConsoleCmd::InfoStats),
+         (other, _) => Err(format!("unknown info command '{}'",other))
+      },
+      "reset" => Ok(ConsoleCmd::Reset),
+      "schedule" => match args{
+         "" => Ok(ConsoleCmd::ShowSchedule),
+         "clear" => Ok(ConsoleCmd::ClearSchedule),
+         stimulus => Ok(ConsoleCmd::Schedule(Schedule::parse_line(stimulus, symbols)?))
+      },
+      "help" | "h" => Ok(ConsoleCmd::Help),
+      "" => Err("".into()),
+      other => Err(format!("unknown command '{}', try help",other))
+   }
+}
+
+pub fn examine(sys: &System, addr: u32, count: usize, size: usize, format: char)->String{
+   let per_line = if size == 4 { 4 } else { 8 };
+   let shown = count.min(MAX_EXAMINE_BYTES / size);
+   let end = shown.checked_mul(size)
+      .and_then(|n| u32::try_from(n).ok())
+      .and_then(|n| addr.checked_add(n - 1));
+   if end.is_none(){
+      return format!("cannot examine {} from {:#010x}, it runs past the end of memory\n",count,addr);
+   }
+   let mut out = String::new();
+   for i in 0 .. shown{
+      let unit = addr + (i * size) as u32;
+      if i % per_line == 0{
+         if i > 0{
+            out.push('\n');
+         }
+         out.push_str(&format!("{:#010x}:",unit));
+      }
+      // peek leaves the SIO FIFO and spinlocks alone
+      let v = sys.peek(unit, size).expect("the range was checked above");
+      let cell = match (format, size){
+         ('x', _) => format!("0x{:0width$x}", v, width = size * 2),
+         ('d', 1) => (v as u8 as i8).to_string(),
+         ('d', 2) => (v as u16 as i16).to_string(),
+         ('d', _) => (v as i32).to_string(),
+         _ => v.to_string()
+      };
+      out.push('\t');
+      out.push_str(&cell);
+   }
+   out.push('\n');
+   if shown < count{
+      out.push_str(&format!("output truncated to {} of {} units ({} bytes)\n",shown,count,MAX_EXAMINE_BYTES));
+   }
+   return out;
+}
+
+pub fn print_value(name: &str, v: u32)->String{
+   format!("{} = {:#010x} ({})\n",name.trim(),v,v as i32)
+}
+
+pub fn print_read(sys: &System, expr: &Expr)->String{
+   match expr.value.read(sys){
+      Some(v) => print_value(&expr.text, v),
+      None => format!("cannot read {}, it runs past the end of memory\n",expr.text)
+   }
+}
+
+// assigns value to target then prints target as it reads back
+pub fn set_value(sys: &mut System, target: &Expr, value: &Expr)->String{
+   let v = match value.value.read(sys){
+      Some(v) => v,
+      None => return print_read(sys, value)
+   };
+   match target.value.write(sys, v){
+      Ok(_) => print_read(sys, target),
+      Err(e) => format!("could not set {}: {:?}\n",target.text,e)
+   }
+}
+
+pub fn show_schedule(schedule: &Schedule)->String{
+   let mut out = format!("{} instructions stepped\n",schedule.steps());
+   for (i, stimulus) in schedule.stimuli.iter().enumerate(){
+      out.push_str(&format!("{:<3}{}\n",i,stimulus));
+   }
+   if schedule.stimuli.is_empty(){
+      out.push_str("nothing scheduled\n");
+   }
+   return out;
+}
+
+pub fn info_stats(sys: &System)->String{
+   let steps = sys.stimuli.steps();
+   let percent = if steps == 0 { 0.0 } else { 100.0 * sys.sleep.asleep as f64 / steps as f64 };
+   format!(
+      "{} steps since reset\n{} steps asleep in WFI/WFE ({:.1}%), {} of them fast-forwarded\n",
+      steps,
+      sys.sleep.asleep,
+      percent,
+      sys.sleep.skipped
+   )
+}
+
+pub fn info_symbols(pattern: &str, symbols: &Vec<SymbolDefinition>)->String{
+   let mut out = String::new();
+   for sym in symbols.iter().filter(|s| s.name.contains(pattern) && !s.name.is_empty() && !is_segment_mapping_symbol(&s.name)){
+      out.push_str(&format!("{:#010x}  {}\n",sym.position,sym.name));
+   }
+   if out.is_empty(){
+      out.push_str(&format!("no symbols matching '{}'\n",pattern));
+   }
+   return out;
+}
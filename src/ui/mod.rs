@@ -1,9 +1,9 @@
 use std::{fmt::Display, sync::{Mutex, Arc}, ops::{Deref, DerefMut}, path::{Path, PathBuf}, borrow::BorrowMut};
 
-use iced::{widget::{pane_grid, PaneGrid, text, column, container, scrollable, row, button, vertical_slider::StyleSheet, pick_list, image, tooltip, mouse_area, Row}, Application, Theme, executor, Command, Element, futures::StreamExt};
+use iced::{widget::{pane_grid, PaneGrid, text, column, container, scrollable, row, button, vertical_slider::StyleSheet, pick_list, image, tooltip, mouse_area, Row, checkbox}, Application, Theme, executor, Command, Element, futures::StreamExt};
 use iced::widget::text_input;
 
-use crate::{system::{System, ArmException, simulator::{HaltType, Simulator}, registers::Registers, self, write_memory}, asm::interpreter::{print_assembly, disasm_text, is_segment_mapping_symbol, TextPosition, SymbolTable}, binutils::{from_arm_bytes_16b, u32_to_arm_bytes}, elf::decoder::SymbolDefinition, to_arm_bytes};
+use crate::{system::{System, ArmException, simulator::{HaltType, Machine, Throughput, Pacer}, registers::{Registers, SpecialRegister}, self, write_memory, ExceptionStatus, exception_name}, asm::{interpreter::{print_assembly, disasm_text, is_segment_mapping_symbol, TextPosition, SymbolTable, INDENT}, assembler::assemble_at}, binutils::{u32_to_arm_bytes}, elf::decoder::SymbolDefinition};
 
 use crate::system::instructions::{
    negative_flag_u32,
@@ -13,13 +13,19 @@ use crate::system::instructions::{
 };
 
 use crate::dbg_ln;
-use crate::binutils::from_arm_bytes;
+use crate::binutils::{from_arm_bytes, from_data_bytes, into_data_bytes};
 const TEXT_SIZE: u16 = 11;
 
 pub mod searchbar;
 pub mod window;
+pub mod console;
+pub mod tui;
 
 use self::{searchbar::SearchBar, window::{Window, ExplorerMap, line_buffer}}; 
+use self::console::{ConsoleCmd, parse_command, examine, print_read, set_value, info_symbols, info_stats, show_schedule};
+use crate::system::expression::Condition;
+use crate::system::sleep::can_preempt;
+use crate::system::dump::{StateDump, DEFAULT_DUMP_PATH};
 
 pub struct App{
    _state: pane_grid::State<PaneType>,
@@ -36,15 +42,34 @@ pub struct App{
    symbols: Vec<SymbolDefinition>,
    sys_view: SystemView,
    sync_sys: Arc<Mutex<System>>,
+   machine: Machine,
+   active_core: usize,
    cmd_sender: Option<iced_mpsc::Sender<Event>>,
    breakpoints: Vec<u32>,
    view_error: Option<String>,
    trace_record: String,
    update_view: bool,
-   bkpt_input: BkptInput
+   bkpt_input: BkptInput,
+   patch_input: String,
+   register_inputs: [String;20],
+   register_status: Option<String>,
+   console_input: String,
+   console_log: String,
+   until_bkpt: Option<u32>,
+   state_dump: Option<StateDump>,
+   last_halt: Option<HaltType>,
+   // set from sending continue until the run thread reports its halt, the thread only holds the core
+   // mutexes a batch at a time so a free mutex does not mean it has stopped
+   running: bool,
+   ips: Option<u64>,
+   clock_input: String,
+   // the rate continue mode is throttled to unless max_speed is set
+   clock_hz: Option<u64>,
+   max_speed: bool
 }
 
 struct SystemView{
+   pub core: Option<u32>,
    pub mode: system::Mode,
    pub registers: Registers,
    pub privileged: bool,
@@ -52,12 +77,67 @@ struct SystemView{
    pub psp: u32,
    pub msp: u32,
    pub xpsr: u32,
-   pub raw_ir: u32
+   pub primask: bool,
+   pub control: u32,
+   pub raw_ir: u32,
+   pub exceptions: Vec<ExceptionRow>,
+   pub vec_active: u32,
+   pub vec_pending: u32,
+   pub enabled_interrupts: u32,
+   pub current_priority: i32,
+   pub stimuli: Vec<String>,
+   pub stimulus_steps: u64
+}
+
+pub struct ExceptionRow{
+   pub number: u32,
+   pub name: String,
+   pub status: ExceptionStatus,
+   // None for the reserved numbers, which have no handler
+   pub priority: Option<i32>,
+   // only external interrupts can be disabled through the NVIC
+   pub enabled: Option<bool>
+}
+
+// one row per exception number, the reserved ones are kept so the numbers line up with the vector table
+pub(crate) fn exception_rows(sys: &System)->Vec<ExceptionRow>{
+   let mut rows = Vec::new();
+   for n in 0 .. sys.active_exceptions.len() as u32{
+      let exc = ArmException::from_exception_number(n);
+      rows.push(ExceptionRow{
+         number: n,
+         name: match (n, &exc){
+            (0, _) => "Thread mode".into(),
+            (_, None) => "Reserved".into(),
+            _ => exception_name(n)
+         },
+         status: sys.active_exceptions[n as usize],
+         priority: exc.map(|exc| exc.priority_group(&sys.scs)),
+         enabled: if n >= 16 { Some(sys.scs.is_nvic_interrupt_enabled(n - 16)) } else { None }
+      });
+   }
+   return rows;
+}
+
+// only exceptions that software can pend through ICSR or the NVIC can be pended from the UI
+pub(crate) fn can_pend_from_ui(exc_n: u32)->bool{
+   exc_n == 2 || exc_n >= 14
+}
+
+// a disabled or lower priority exception stays pending without waking a core sleeping in WFI
+pub(crate) fn pend_from_ui(sys: &mut System, exc_n: u32){
+   if let Some(exc) = ArmException::from_exception_number(exc_n){
+      sys.set_exc_pending(exc);
+      if can_preempt(sys, exc_n){
+         sys.scs.wfi_wake_up = true;
+      }
+   }
 }
 
 impl From<&System> for SystemView{
    fn from(sys: &System) -> Self {
       Self{
+         core: sys.sio.as_ref().map(|link| link.core),
          mode: sys.mode.clone(),
          registers: sys.registers.clone(),
          sp: sys.get_sp(),
@@ -65,7 +145,16 @@ impl From<&System> for SystemView{
          psp: sys.registers.sp_process,
          msp: sys.registers.sp_main,
          xpsr: from_arm_bytes(sys.xpsr),
-         raw_ir: sys.read_raw_ir()
+         primask: sys.primask(),
+         control: from_arm_bytes(sys.control_register),
+         raw_ir: sys.read_raw_ir(),
+         exceptions: exception_rows(sys),
+         vec_active: sys.scs.vec_active(),
+         vec_pending: sys.scs.vec_pending(),
+         enabled_interrupts: sys.scs.enabled_interrupts,
+         current_priority: sys.current_priority(),
+         stimuli: sys.stimuli.stimuli.iter().map(|s| s.to_string()).collect(),
+         stimulus_steps: sys.stimuli.steps()
       }
    }
 }
@@ -95,15 +184,32 @@ fn inlay_button(label: String,on_click: Event, highlight: bool) -> iced::widget:
    }
 }
 
-fn adjustable_register(reg_num: u32,name: &str, value: u32,in_hex: bool) -> Row<Event>{
+fn adjustable_register<'a>(reg_num: u32,name: &str, value: u32,in_hex: bool, pending: &str) -> Row<'a,Event>{
    let label = if in_hex{
       format!("  {}: {:#x}",name,value)
    }else{
       format!("  {}: {}",name,value)
    };
    row![
-   inlay_button(label,Event::Ui(Gui::ToggleRegisterDisplay(reg_num)),false)
-   ]
+      inlay_button(label,Event::Ui(Gui::ToggleRegisterDisplay(reg_num)),false),
+      register_input(reg_num, pending)
+   ].spacing(10)
+}
+
+fn editable_register<'a>(reg_num: u32, label: String, pending: &str) -> Row<'a,Event>{
+   row![
+      text(label).size(TEXT_SIZE),
+      register_input(reg_num, pending)
+   ].spacing(10)
+}
+
+fn register_input<'a>(reg_num: u32, pending: &str) -> Element<'a,Event>{
+   text_input("set", pending)
+      .on_input(move |s| Event::Ui(Gui::SetRegisterInput(reg_num,s)))
+      .on_submit(Event::Ui(Gui::SubmitRegister(reg_num)))
+      .size(TEXT_SIZE)
+      .width(iced::Length::Fixed(110.0))
+      .into()
 }
 
 impl Display for SystemView{
@@ -158,19 +264,23 @@ struct BkptInput{
 
 impl BkptInput{
    pub fn try_get_addr(&self, symbols: &Vec<SymbolDefinition>)->Option<u32>{
-      match parse_hex(&self.pending_addr_or_symbol){
-         Some(addr) => Some(addr),
-         None => {
-            let treated = self.pending_addr_or_symbol.trim();
-            for symbol in symbols{
-               if symbol.name.eq(treated) && !is_segment_mapping_symbol(&symbol.name){
-                  return Some(symbol.position as u32);
-               }
+      return addr_or_symbol(&self.pending_addr_or_symbol, symbols);
+   }
+}
+
+fn addr_or_symbol(input: &str, symbols: &Vec<SymbolDefinition>)->Option<u32>{
+   match parse_hex(input){
+      Some(addr) => Some(addr),
+      None => {
+         let treated = input.trim();
+         for symbol in symbols{
+            if symbol.name.eq(treated) && !is_segment_mapping_symbol(&symbol.name){
+               return Some(symbol.position as u32);
             }
-            println!("could not identify symbol: {}",self.pending_addr_or_symbol);
-            return None;
-         },
-      }
+         }
+         println!("could not identify symbol: {}",input);
+         return None;
+      },
    }
 }
 
@@ -181,7 +291,26 @@ pub enum Cast{
    UHALF,
    IHALF,
    UBYTE,
-   IBYTE
+   IBYTE,
+   FLOAT,
+   HEXDUMP,
+   CSTRING,
+   UTF8,
+   UWORDARR,
+   UHALFARR,
+   UBYTEARR
+}
+
+impl Cast{
+   // bytes written for a single value entered in the memory explorer, None for strings
+   pub fn element_size(&self)->Option<usize>{
+      match self{
+         Cast::UWORD | Cast::IWORD | Cast::FLOAT | Cast::UWORDARR => Some(4),
+         Cast::UHALF | Cast::IHALF | Cast::UHALFARR => Some(2),
+         Cast::UBYTE | Cast::IBYTE | Cast::HEXDUMP | Cast::UBYTEARR => Some(1),
+         Cast::CSTRING | Cast::UTF8 => None
+      }
+   }
 }
 
 macro_rules! parse_hex_or_base10 {
@@ -200,71 +329,188 @@ macro_rules! parse_hex_or_base10 {
    }
 }
 
-fn stringify_slice(mut offset: u32, arr: &[u8], cast: Cast, symbols: &Vec<SymbolDefinition>)->String{
-   let mut display = String::new();
-   let mut table = SymbolTable::create(symbols);
-   let add_symbol = |tbl: &mut SymbolTable<'_>, address: u32,display: &mut String|{
-      if let Some(label) = tbl.lookup_ignore_functions(address as usize){
-         display.push('<');
-         display.push_str(label);
-         display.push_str(">:\n");
+// a line of the memory explorer, each cell can be clicked to select its address for writing
+#[derive(Debug,PartialEq)]
+pub struct MemoryRow{
+   pub label: Option<String>,
+   pub address: u32,
+   pub cells: Vec<(u32,String)>,
+   pub suffix: Option<String>
+}
+
+const HEXDUMP_WIDTH: usize = 16;
+const ARRAY_WIDTH: usize = 8;
+
+fn escape_bytes(bytes: &[u8])->String{
+   let mut escaped = String::new();
+   for c in String::from_utf8_lossy(bytes).chars(){
+      match c{
+         '\n' => escaped.push_str("\\n"),
+         '\r' => escaped.push_str("\\r"),
+         '\t' => escaped.push_str("\\t"),
+         '"' => escaped.push_str("\\\""),
+         c if c.is_control() => escaped.push_str(&format!("\\x{:02x}",c as u32)),
+         c => escaped.push(c)
       }
+   }
+   escaped
+}
+
+pub(crate) fn memory_rows(offset: u32, arr: &[u8], cast: &Cast, symbols: &Vec<SymbolDefinition>, big_endian: bool)->Vec<MemoryRow>{
+   let mut rows = Vec::new();
+   let mut table = SymbolTable::create(symbols);
+   let mut label_at = |address: u32|->Option<String>{
+      table.lookup_ignore_functions(address as usize).map(|l| l.clone())
    };
-   use crate::asm::interpreter::INDENT;
+
    match cast{
-      Cast::UWORD => {
-         for word in arr.chunks_exact(4){
-            let byte_pair: [u8;4] = word.try_into().expect("always 4 byte arr");
-            let native = from_arm_bytes(byte_pair);
-            add_symbol(&mut table, offset, &mut display);
-            display.push_str(&format!("{}{:#010X}: {}\n",INDENT,offset,native));
-            offset += 4;
+      Cast::HEXDUMP | Cast::UWORDARR | Cast::UHALFARR | Cast::UBYTEARR => {
+         let (size, per_row) = match cast{
+            Cast::UWORDARR => (4, ARRAY_WIDTH),
+            Cast::UHALFARR => (2, ARRAY_WIDTH),
+            Cast::UBYTEARR => (1, ARRAY_WIDTH),
+            _ => (1, HEXDUMP_WIDTH)
+         };
+         for (i,chunk) in arr.chunks(size * per_row).enumerate(){
+            let address = offset + (i * size * per_row) as u32;
+            let cells = chunk.chunks_exact(size).enumerate().map(|(j,v)|{
+               let cell_address = address + (j * size) as u32;
+               let value = match size{
+                  4 => format!("{:#010x}",from_data_bytes(v, big_endian)),
+                  2 => format!("{:#06x}",from_data_bytes(v, big_endian)),
+                  _ => if matches!(cast,Cast::HEXDUMP) { format!("{:02x}",v[0]) } else { format!("{:#04x}",v[0]) }
+               };
+               (cell_address,value)
+            }).collect();
+            let suffix = if matches!(cast,Cast::HEXDUMP){
+               let ascii: String = chunk.iter()
+                  .map(|b| if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '.' })
+                  .collect();
+               Some(format!("|{}|",ascii))
+            }else{
+               None
+            };
+            rows.push(MemoryRow{label: label_at(address), address, cells, suffix});
          }
       },
-      Cast::IWORD => {
-         for word in arr.chunks_exact(4){
-            let byte_pair: [u8;4] = word.try_into().expect("always 4 byte arr");
-            let native = from_arm_bytes(byte_pair);
-            add_symbol(&mut table, offset, &mut display);
-            display.push_str(&format!("{}{:#010X}: {}\n",INDENT,offset,native as i32));
-            offset += 4;
+      Cast::CSTRING => {
+         let mut start = 0;
+         while start < arr.len(){
+            let len = arr[start ..].iter().position(|b| *b == 0).unwrap_or(arr.len() - start);
+            let address = offset + start as u32;
+            rows.push(MemoryRow{
+               label: label_at(address),
+               address,
+               cells: vec![(address,format!("\"{}\"",escape_bytes(&arr[start .. start + len])))],
+               suffix: None
+            });
+            start += len + 1;
+            // a run of NUL bytes is padding, only the first empty string is shown
+            while start < arr.len() && arr[start] == 0 && len == 0{
+               start += 1;
+            }
          }
       },
-      Cast::UHALF => {
-         for hw in arr.chunks_exact(2){
-            let byte_pair: [u8;2] = hw.try_into().expect("always 2 byte arr");
-            let native = from_arm_bytes_16b(byte_pair);
-            add_symbol(&mut table, offset, &mut display);
-            display.push_str(&format!("{}{:#010X}: {}\n",INDENT,offset,native as u16));
-            offset += 2;
+      Cast::UTF8 => {
+         let mut start = 0;
+         for line in arr.split(|b| *b == b'\n'){
+            let address = offset + start as u32;
+            rows.push(MemoryRow{
+               label: label_at(address),
+               address,
+               cells: vec![(address,String::from_utf8_lossy(line).to_string())],
+               suffix: None
+            });
+            start += line.len() + 1;
          }
-     },
-      Cast::IHALF => for hw in arr.chunks_exact(2){
-         let byte_pair: [u8;2] = hw.try_into().expect("always 2 byte arr");
-         let native = from_arm_bytes_16b(byte_pair);
-            add_symbol(&mut table, offset, &mut display);
-            display.push_str(&format!("{}{:#010X}: {}\n",INDENT,offset,native as i16));
-            offset += 2;
       },
-      Cast::UBYTE => {
-         for byte in arr{
-            add_symbol(&mut table, offset, &mut display);
-            display.push_str(&format!("{}{:#010X}: {}\n",INDENT,offset,*byte));
-            offset += 1;
+      _ => {
+         let size = cast.element_size().expect("numeric casts have a size");
+         for (i,v) in arr.chunks_exact(size).enumerate(){
+            let address = offset + (i * size) as u32;
+            let raw = from_data_bytes(v, big_endian);
+            let value = match cast{
+               Cast::UWORD | Cast::UHALF | Cast::UBYTE => raw.to_string(),
+               Cast::IWORD => (raw as i32).to_string(),
+               Cast::FLOAT => f32::from_bits(raw).to_string(),
+               Cast::IHALF => (raw as u16 as i16).to_string(),
+               _ => (raw as u8 as i8).to_string()
+            };
+            rows.push(MemoryRow{label: label_at(address), address, cells: vec![(address,value)], suffix: None});
          }
       }
-      Cast::IBYTE => {
-         for byte in arr{
-            add_symbol(&mut table, offset, &mut display);
-            display.push_str(&format!("{}{:#010X}: {}\n",INDENT,offset,*byte as i8));
-            offset += 1;
-         }
+   }
+   rows
+}
+
+// turns user input into the bytes to store for the given cast, in memory order
+pub(crate) fn parse_memory_write(cast: &Cast, input: &str, big_endian: bool)->Option<Vec<u8>>{
+   let input = input.trim();
+   match cast{
+      Cast::CSTRING => {
+         let mut bytes = input.as_bytes().to_vec();
+         bytes.push(0);
+         Some(bytes)
       },
+      Cast::UTF8 => Some(input.as_bytes().to_vec()),
+      _ => {
+         let size = cast.element_size().expect("numeric casts have a size");
+         parse_memory_value(cast, input).map(|v| into_data_bytes(v, size, big_endian))
+      }
+   }
+}
+
+// the value a numeric cast stores, negative and float inputs as their bit patterns
+fn parse_memory_value(cast: &Cast, input: &str)->Option<u32>{
+   let is_hex = input.starts_with("0x") || matches!(cast,Cast::HEXDUMP);
+   let digits = input.trim_start_matches("0x");
+   match cast{
+      Cast::FLOAT => input.parse::<f32>().ok().map(f32::to_bits),
+      Cast::IWORD if input.starts_with('-') => input.parse::<i32>().ok().map(|v| v as u32),
+      Cast::IHALF if input.starts_with('-') => input.parse::<i16>().ok().map(|v| v as u16 as u32),
+      Cast::IBYTE if input.starts_with('-') => input.parse::<i8>().ok().map(|v| v as u8 as u32),
+      _ => match cast.element_size(){
+         Some(4) => parse_hex_or_base10!(u32,digits,is_hex),
+         Some(2) => parse_hex_or_base10!(u16,digits,is_hex).map(u32::from),
+         _ => parse_hex_or_base10!(u8,digits,is_hex).map(u32::from)
+      }
+   }
+}
+
+// names of the registers editable from the register pane, indexed as in Gui::SubmitRegister
+pub(crate) static EDITABLE_REGISTERS: [&str;20] = [
+   "r0", "r1", "r2", "r3", "r4", "r5", "r6", "r7", "r8", "r9", "r10", "r11", "r12",
+   "MSP", "PSP", "LR", "PC", "XPSR", "PRIMASK", "CONTROL"
+];
+
+// accepts 0x prefixed hex, unsigned or negative decimal values
+pub(crate) fn parse_register_value(input: &str)->Option<u32>{
+   let input = input.trim();
+   if input.starts_with('-'){
+      return input.parse::<i32>().ok().map(|v| v as u32);
+   }
+   let is_hex = input.starts_with("0x");
+   return parse_hex_or_base10!(u32,input.trim_start_matches("0x"),is_hex);
+}
+
+pub(crate) fn write_register(sys: &mut System, reg_num: u32, v: u32)->Result<(),ArmException>{
+   match reg_num{
+      0 ..=12 => sys.write_core_register(reg_num as u8, v),
+      13 => sys.write_special_register(&SpecialRegister::MSP, v),
+      14 => sys.write_special_register(&SpecialRegister::PSP, v),
+      15 => sys.write_core_register(14, v),
+      16 => sys.write_core_register(15, v),
+      17 => sys.write_special_register(&SpecialRegister::XPSR, v),
+      18 => sys.write_special_register(&SpecialRegister::PRIMASK, v),
+      19 => sys.write_special_register(&SpecialRegister::CONTROL, v),
+      _ => Err(ArmException::HardFault(format!("unknown register index {}",reg_num)))
    }
-   display
 }
 
-static CAST_OPTIONS: &[Cast] = &[Cast::UWORD, Cast::IWORD, Cast::UHALF, Cast::IHALF, Cast::UBYTE, Cast::IBYTE];
+static CAST_OPTIONS: &[Cast] = &[
+   Cast::UWORD, Cast::IWORD, Cast::UHALF, Cast::IHALF, Cast::UBYTE, Cast::IBYTE, Cast::FLOAT,
+   Cast::HEXDUMP, Cast::CSTRING, Cast::UTF8, Cast::UWORDARR, Cast::UHALFARR, Cast::UBYTEARR
+];
 
 impl Display for Cast{
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -275,6 +521,13 @@ impl Display for Cast{
           Cast::IHALF => "i16",
           Cast::UBYTE => "u8",
           Cast::IBYTE => "i8",
+          Cast::FLOAT => "f32",
+          Cast::HEXDUMP => "hex dump",
+          Cast::CSTRING => "C string",
+          Cast::UTF8 => "UTF-8",
+          Cast::UWORDARR => "u32[]",
+          Cast::UHALFARR => "u16[]",
+          Cast::UBYTEARR => "u8[]",
        };
        write!(f,"{}",rep)
     }
@@ -285,7 +538,10 @@ pub struct MemoryView{
    pub start: u32, 
    pub end: u32,
    pub view_cast: Cast,
-   //pub entry_cast: Cast,
+   pub selected: Option<u32>,
+   // write straight to the BlockAllocator, skipping permission and alignment checks
+   pub bypass: bool,
+   pub write_status: Option<String>
 }
 
 impl Default for MemoryView{
@@ -294,22 +550,36 @@ impl Default for MemoryView{
          start: 0,
          end: 0xFF,
          view_cast: Cast::UWORD,
-         //entry_cast: Cast::UBYTE,
+         selected: None,
+         bypass: false,
+         write_status: None
       }
    }
 }
 
-fn user_cmds<'a>(bkpt: &BkptInput)->Element<'a, Event>{
+fn user_cmds<'a>(bkpt: &BkptInput, patch: &str, clock: &str, max_speed: bool, ips: Option<u64>)->Element<'a, Event>{
    let bkpt_button = text_input("toggle breakpoint at address / symbol", &bkpt.pending_addr_or_symbol)
       .on_input(|s|Event::Ui(Gui::SetBkptInput(s)))
       .on_submit(Event::Ui(Gui::SubmitBkpt));
+   let patch_button = text_input("patch: <address / symbol> <instruction>; ...", patch)
+      .on_input(|s|Event::Ui(Gui::SetPatchInput(s)))
+      .on_submit(Event::Ui(Gui::SubmitPatch));
+   let clock_input = text_input("clock Hz", clock)
+      .on_input(|s|Event::Ui(Gui::SetClockInput(s)))
+      .on_submit(Event::Ui(Gui::SubmitClock))
+      .width(100);
    row![
       button(text("step").size(TEXT_SIZE)).on_press(Event::Dbg(Debug::Step)),
       button(text("continue").size(TEXT_SIZE)).on_press(Event::Dbg(Debug::Continue)),
       button(text("halt").size(TEXT_SIZE)).on_press(Event::Ui(Gui::SubmitHalt)),
       button(text("reset").size(TEXT_SIZE)).on_press(Event::Dbg(Debug::Reset)),
-      bkpt_button
-   ].spacing(5).into()
+      button(text("dump state").size(TEXT_SIZE)).on_press(Event::Ui(Gui::DumpState)),
+      bkpt_button,
+      patch_button,
+      clock_input,
+      checkbox("max speed", max_speed, |b| Event::Ui(Gui::SetMaxSpeed(b))).size(TEXT_SIZE),
+      text(ips.map(|n| format!("{} instr/s",n)).unwrap_or_default()).size(TEXT_SIZE)
+   ].spacing(5).align_items(iced::Alignment::Center).into()
 }
 
 fn img_button<'a>(msg: &str, signal: Event,alt_signal: Event, tip: &str) -> Element<'a,Event>{
@@ -329,7 +599,7 @@ macro_rules! split_pane_event {
     };
 }
 
-fn pane_cmds<'a>(n_panes: usize, pane: pane_grid::Pane,n_breakpoints: usize)->Element<'a, Event>{
+fn pane_cmds<'a>(n_panes: usize, pane: pane_grid::Pane,n_breakpoints: usize, n_cores: usize, active_core: usize)->Element<'a, Event>{
    use pane_grid::Axis::Vertical as Vertical;
    use pane_grid::Axis::Horizontal as Horizontal;
    let mut cmds = row![
@@ -351,6 +621,18 @@ fn pane_cmds<'a>(n_panes: usize, pane: pane_grid::Pane,n_breakpoints: usize)->El
          split_pane_event!(pane,PaneType::MemoryExplorer,Horizontal),
          "view a region of memory (right click to split horizontally)"
       ),
+      img_button(
+         "exceptions",
+         split_pane_event!(pane,PaneType::Exceptions,Vertical),
+         split_pane_event!(pane,PaneType::Exceptions,Horizontal),
+         "view exception and NVIC state (right click to split horizontally)"
+      ),
+      img_button(
+         "console",
+         split_pane_event!(pane,PaneType::Console,Vertical),
+         split_pane_event!(pane,PaneType::Console,Horizontal),
+         "debugger console, type help for commands (right click to split horizontally)"
+      ),
       img_button(
          "logs",
          split_pane_event!(pane,PaneType::Trace,Vertical),
@@ -367,6 +649,15 @@ fn pane_cmds<'a>(n_panes: usize, pane: pane_grid::Pane,n_breakpoints: usize)->El
       }
    ].spacing(5);
 
+   if n_cores > 1{
+      for core in 0 .. n_cores{
+         let label = text(format!("core {}",core));
+         cmds = cmds.push(
+            if core == active_core{ button(label) }else{ button(label).on_press(Event::Ui(Gui::SelectCore(core))) }
+         );
+      }
+   }
+
    if n_breakpoints > 0 {
       cmds = cmds.push(
          button(text("clear breakpoints")).on_press(Event::Ui(Gui::SubmitBkptClear))
@@ -625,111 +916,59 @@ fn pane_render<'a>(
 
       PaneType::SystemState => {
          let sview = &app.sys_view;
-         scrollable(column![
-            text(format!("  mode: {:?} ({})",sview.mode,if sview.privileged{"Privileged"}else{"Unprivileged"}))
-               .size(TEXT_SIZE)
-               .width(iced::Length::Fill),
-            adjustable_register(
-               0,
-               "r0",
-               sview.registers.generic[0],
-               app.register_hex_display[0]
-            ),
-            adjustable_register(
-               1,
-               "r1",
-               sview.registers.generic[1],
-               app.register_hex_display[1]
-            ),
-            adjustable_register(
-               2,
-               "r2",
-               sview.registers.generic[2],
-               app.register_hex_display[2]
-            ),
-            adjustable_register(
-               3,
-               "r3",
-               sview.registers.generic[3],
-               app.register_hex_display[3]
-            ),
-            adjustable_register(
-               4,
-               "r4",
-               sview.registers.generic[4],
-               app.register_hex_display[4]
-            ),
-            adjustable_register(
-               5,
-               "r5",
-               sview.registers.generic[5],
-               app.register_hex_display[5]
-            ),
-            adjustable_register(
-               6,
-               "r6",
-               sview.registers.generic[6],
-               app.register_hex_display[6]
-            ),
-            adjustable_register(
-               7,
-               "r7",
-               sview.registers.generic[7],
-               app.register_hex_display[7]
-            ),
-            adjustable_register(
-               8,
-               "r8",
-               sview.registers.generic[8],
-               app.register_hex_display[8]
-            ),
-            adjustable_register(
-               9,
-               "r9",
-               sview.registers.generic[9],
-               app.register_hex_display[9]
-            ),
-            adjustable_register(
-               10,
-               "r10",
-               sview.registers.generic[10],
-               app.register_hex_display[10]
-            ),
-            adjustable_register(
-               11,
-               "r11",
-               sview.registers.generic[11],
-               app.register_hex_display[11]
-            ),
-            adjustable_register(
-               12,
-               "r12",
-               sview.registers.generic[12],
-               app.register_hex_display[12]
-            ),
-            text(format!("  SP: {:#010x}    (MSP: {:#010x})    (PSP: {:#010x})",sview.sp,sview.msp,sview.psp))
-               .size(TEXT_SIZE)
-               .width(iced::Length::Fill),
-
-            text(format!("  LR: {:#010x}",sview.registers.lr))
-               .size(TEXT_SIZE)
-               .width(iced::Length::Fill),
-
-            text(format!("  PC: {:#010x}",sview.registers.pc))
-               .size(TEXT_SIZE)
-               .width(iced::Length::Fill),
-
-            text(format!(
-                  "  XPSR: {:#010x} (N:{} Z:{} C:{} V:{})",
-                  sview.xpsr,
-                  negative_flag_u32(sview.xpsr) as u32,
-                  zero_flag_u32(sview.xpsr) as u32,
-                  carry_flag_u32(sview.xpsr) as u32,
-                  overflow_flag_u32(sview.xpsr) as u32
+         let mut registers: Vec<Element<Event>> = vec![
+            text(format!("  {}mode: {:?} ({})",
+                  sview.core.map(|c| format!("core {}    ",c)).unwrap_or_default(),
+                  sview.mode,
+                  if sview.privileged{"Privileged"}else{"Unprivileged"}
             ))
                .size(TEXT_SIZE)
                .width(iced::Length::Fill)
-         ]).into()
+               .into()
+         ];
+         for i in 0 .. 13{
+            registers.push(adjustable_register(
+               i as u32,
+               EDITABLE_REGISTERS[i],
+               sview.registers.generic[i],
+               app.register_hex_display[i],
+               &app.register_inputs[i]
+            ).into());
+         }
+         registers.push(
+            text(format!("  SP: {:#010x}",sview.sp))
+               .size(TEXT_SIZE)
+               .width(iced::Length::Fill)
+               .into()
+         );
+         registers.push(editable_register(13, format!("  MSP: {:#010x}",sview.msp), &app.register_inputs[13]).into());
+         registers.push(editable_register(14, format!("  PSP: {:#010x}",sview.psp), &app.register_inputs[14]).into());
+         registers.push(editable_register(15, format!("  LR: {:#010x}",sview.registers.lr), &app.register_inputs[15]).into());
+         registers.push(editable_register(16, format!("  PC: {:#010x}",sview.registers.pc), &app.register_inputs[16]).into());
+         registers.push(editable_register(
+            17,
+            format!(
+               "  XPSR: {:#010x} (N:{} Z:{} C:{} V:{} T:{} IPSR:{})",
+               sview.xpsr,
+               negative_flag_u32(sview.xpsr) as u32,
+               zero_flag_u32(sview.xpsr) as u32,
+               carry_flag_u32(sview.xpsr) as u32,
+               overflow_flag_u32(sview.xpsr) as u32,
+               (sview.xpsr >> 24) & 1,
+               sview.xpsr & 0x3F
+            ),
+            &app.register_inputs[17]
+         ).into());
+         registers.push(editable_register(18, format!("  PRIMASK: {}",sview.primask as u32), &app.register_inputs[18]).into());
+         registers.push(editable_register(
+            19,
+            format!("  CONTROL: {:#x} (nPRIV:{} SPSEL:{})",sview.control,sview.control & 1,(sview.control >> 1) & 1),
+            &app.register_inputs[19]
+         ).into());
+         if let Some(status) = &app.register_status{
+            registers.push(text(format!("  {}",status)).size(TEXT_SIZE).width(iced::Length::Fill).into());
+         }
+         scrollable(column(registers).spacing(2)).into()
       },
 
       PaneType::MemoryExplorer => {
@@ -769,8 +1008,8 @@ fn pane_render<'a>(
 
                         //let data = &sys.memory[real_start ..= real_end];
                         let data = sys.alloc.view(real_start,real_end);
-                        let string_data = stringify_slice(real_start,&data, view.view_cast.clone(), &app.symbols);
-                        scrollable(text(&string_data).size(TEXT_SIZE).width(iced::Length::Fill))
+                        let rows = memory_rows(real_start,&data, &view.view_cast, &app.symbols, sys.is_big_endian());
+                        scrollable(memory_cells(rows, view.selected))
                            .id(app.memview_windows.id_of(id).unwrap().clone())
                      },
                      Err(_) => {
@@ -785,17 +1024,88 @@ fn pane_render<'a>(
                }
             };
 
-         /*let data_entry = row![
-            text_input(
-               "write data at this memory address",
-               &view.raw_entry_data
-            )
-         ];*/
+         let pend_write = app.explorer_map.get_write(wid).unwrap_or_else(|| "".into());
+         let write_hint = match view.selected{
+            Some(addr) => format!("write {} at {:#010x}",view.view_cast,addr),
+            None => "click a value to write to it".into()
+         };
+         let mut data_entry = row![
+            text_input(&write_hint, &pend_write)
+               .on_input(|s| Event::Ui(Gui::Exp(Explorer::SetWrite(s))))
+               .on_submit(Event::Ui(Gui::Exp(Explorer::SubmitWrite))),
+            checkbox("bypass permissions", view.bypass, |b| Event::Ui(Gui::Exp(Explorer::SetBypass(b))))
+               .size(TEXT_SIZE)
+               .text_size(TEXT_SIZE)
+         ].spacing(5);
+         if let Some(status) = &view.write_status{
+            data_entry = data_entry.push(text(status).size(TEXT_SIZE));
+         }
          container(
             column![
                inputs,
-               text_box,
-               //data_entry
+               data_entry,
+               text_box
+            ]
+         ).width(iced::Length::Fill).height(iced::Length::Fill).into()
+      }
+
+      PaneType::Exceptions=>{
+         let sview = &app.sys_view;
+         let mut lines: Vec<Element<Event>> = vec![
+            text(format!(
+                  "  mode: {:?}    execution priority: {}    VECTACTIVE: {}    VECTPENDING: {}",
+                  sview.mode,
+                  sview.current_priority,
+                  sview.vec_active,
+                  sview.vec_pending
+            )).size(TEXT_SIZE).into(),
+            text(format!("  NVIC ISER: {:#010x}",sview.enabled_interrupts)).size(TEXT_SIZE).into()
+         ];
+         for exc in sview.exceptions.iter(){
+            let enabled = match exc.enabled{
+               Some(true) => "enabled",
+               Some(false) => "disabled",
+               None => ""
+            };
+            let mut line = row![
+               text(format!("  {:>2} {:<20} {:<16} priority: {:>2} {}",
+                  exc.number,
+                  exc.name,
+                  format!("{:?}",exc.status),
+                  exc.priority.map(|p| p.to_string()).unwrap_or("-".into()),
+                  enabled
+               )).size(TEXT_SIZE)
+            ].spacing(5);
+            if can_pend_from_ui(exc.number){
+               line = line.push(
+                  button(text("pend").size(TEXT_SIZE)).padding(2).on_press(Event::Ui(Gui::PendException(exc.number)))
+               );
+               line = line.push(
+                  button(text("clear").size(TEXT_SIZE)).padding(2).on_press(Event::Ui(Gui::ClearException(exc.number)))
+               );
+            }
+            lines.push(line.into());
+         }
+         if !sview.stimuli.is_empty(){
+            lines.push(text(format!("  scheduled stimuli (step {}):",sview.stimulus_steps)).size(TEXT_SIZE).into());
+            for stimulus in sview.stimuli.iter(){
+               lines.push(text(format!("    {}",stimulus)).size(TEXT_SIZE).into());
+            }
+         }
+         container(
+            scrollable(column(lines).spacing(2).width(iced::Length::Fill))
+         ).width(iced::Length::Fill).height(iced::Length::Fill).into()
+      }
+
+      PaneType::Console=>{
+         container(
+            column![
+               scrollable(text(&app.console_log).size(TEXT_SIZE).width(iced::Length::Fill))
+                  .height(iced::Length::Fill),
+               text_input("command (type help)", &app.console_input)
+                  .on_input(|s| Event::Ui(Gui::SetConsoleInput(s)))
+                  .on_submit(Event::Ui(Gui::SubmitConsole))
+                  .size(TEXT_SIZE)
             ]
          ).width(iced::Length::Fill).height(iced::Length::Fill).into()
       }
@@ -811,6 +1121,30 @@ fn pane_render<'a>(
    }
 }
 
+fn memory_cells<'a>(rows: Vec<MemoryRow>, selected: Option<u32>)->Element<'a,Event>{
+   let mut lines = Vec::new();
+   for mem_row in rows{
+      if let Some(label) = mem_row.label{
+         lines.push(text(format!("<{}>:",label)).size(TEXT_SIZE).into());
+      }
+      let mut line = row![text(format!("{}{:#010X}:",INDENT,mem_row.address)).size(TEXT_SIZE)].spacing(6);
+      for (addr,value) in mem_row.cells{
+         let style = if selected == Some(addr) { iced::theme::Button::Primary } else { iced::theme::Button::Text };
+         line = line.push(
+            button(text(value).size(TEXT_SIZE))
+               .padding(0)
+               .style(style)
+               .on_press(Event::Ui(Gui::Exp(Explorer::Select(addr))))
+         );
+      }
+      if let Some(suffix) = mem_row.suffix{
+         line = line.push(text(suffix).size(TEXT_SIZE));
+      }
+      lines.push(line.into());
+   }
+   column(lines).width(iced::Length::Fill).into()
+}
+
 fn brkpt_theme(theme: &Theme)->container::Appearance{
    let palette = theme.extended_palette();
    container::Appearance{
@@ -842,8 +1176,126 @@ fn focused_pane(theme: &Theme)->container::Appearance{
    }
 }
 
+impl App{
+   // tells the run loop how fast to go, it keeps the last rate it was sent
+   fn send_clock(&mut self){
+      let hz = if self.max_speed { None } else { self.clock_hz };
+      if let Some(sndr) = self.cmd_sender.as_mut(){
+         let _ = sndr.try_send(Event::Dbg(Debug::SetClock(hz)));
+      }
+   }
+
+   // writes the state dump requested on the command line, the core mutexes must not be held
+   fn record_halt(&mut self, halt: HaltType){
+      if let Some(dump) = &self.state_dump{
+         if let Err(e) = dump.write(&self.machine, Some(&halt)){
+            println!("could not write {}: {}",dump.path.display(),e);
+         }
+      }
+      self.last_halt = Some(halt);
+   }
+
+   fn run_console_command(&mut self, console_cmd: ConsoleCmd)->Command<Event>{
+      let mut cmd = Command::none();
+      let output = match console_cmd{
+         ConsoleCmd::Help => console::HELP_MSG.to_owned(),
+         ConsoleCmd::InfoSymbols(pattern) => info_symbols(&pattern, &self.symbols),
+         ConsoleCmd::InfoStats => match self.sync_sys.try_lock(){
+            Ok(sys) => info_stats(&sys),
+            Err(_) => "cannot read statistics whilst DEBUG thread this running\n".to_owned()
+         },
+         ConsoleCmd::Break(addr, cond) => {
+            let addr = addr & !1;
+            match self.cmd_sender.as_mut(){
+               Some(sndr) => {
+                  let msg = match &cond{
+                     Some(c) => format!("breakpoint at {:#010x} if {:?}\n",addr,c),
+                     None => format!("breakpoint at {:#010x}\n",addr)
+                  };
+                  let _ = match cond{
+                     Some(c) => sndr.try_send(Event::Dbg(Debug::CreateConditionalBreakpoint(addr,c))),
+                     None => sndr.try_send(Event::Dbg(Debug::CreateBreakpoint(addr)))
+                  };
+                  if !self.breakpoints.contains(&addr){
+                     self.breakpoints.push(addr);
+                  }
+                  msg
+               },
+               None => "cannot interact with dbg session\n".to_owned()
+            }
+         },
+         ConsoleCmd::Examine{addr, count, size, format} => match self.sync_sys.try_lock(){
+            Ok(sys) => examine(&sys, addr, count, size, format),
+            Err(_) => "cannot read memory whilst DEBUG thread this running\n".to_owned()
+         },
+         ConsoleCmd::Print(value) => match self.sync_sys.try_lock(){
+            Ok(sys) => print_read(&sys, &value),
+            Err(_) => "cannot read state whilst DEBUG thread this running\n".to_owned()
+         },
+         ConsoleCmd::Set(target, value) => match self.sync_sys.try_lock(){
+            Ok(mut sys) => {
+               let result = set_value(&mut sys, &target, &value);
+               self.sys_view = sys.deref().into();
+               self.update_view = true;
+               result
+            },
+            Err(_) => "cannot write state whilst DEBUG thread this running\n".to_owned()
+         },
+         ConsoleCmd::Step(n) => {
+            for _ in 0 .. n{
+               cmd = self.update(Event::Dbg(Debug::Step));
+            }
+            format!("pc = {:#010x}\n",self.sys_view.registers.pc)
+         },
+         ConsoleCmd::Until(addr) => {
+            if !self.breakpoints.contains(&addr){
+               if let Some(sndr) = self.cmd_sender.as_mut(){
+                  let _ = sndr.try_send(Event::Dbg(Debug::CreateBreakpoint(addr)));
+                  self.until_bkpt = Some(addr);
+               }
+            }
+            cmd = self.update(Event::Dbg(Debug::Continue));
+            format!("running until {:#010x}\n",addr)
+         },
+         ConsoleCmd::Continue => {
+            cmd = self.update(Event::Dbg(Debug::Continue));
+            String::new()
+         },
+         ConsoleCmd::Reset => {
+            cmd = self.update(Event::Dbg(Debug::Reset));
+            "reset\n".to_owned()
+         },
+         ConsoleCmd::Schedule(stimulus) => match self.sync_sys.try_lock(){
+            Ok(mut sys) => {
+               let msg = format!("scheduled {}\n",stimulus);
+               sys.stimuli.stimuli.push(stimulus);
+               self.sys_view = sys.deref().into();
+               self.update_view = true;
+               msg
+            },
+            Err(_) => "cannot schedule stimuli whilst DEBUG thread this running\n".to_owned()
+         },
+         ConsoleCmd::ShowSchedule => match self.sync_sys.try_lock(){
+            Ok(sys) => show_schedule(&sys.stimuli),
+            Err(_) => "cannot read the schedule whilst DEBUG thread this running\n".to_owned()
+         },
+         ConsoleCmd::ClearSchedule => match self.sync_sys.try_lock(){
+            Ok(mut sys) => {
+               sys.stimuli.stimuli.clear();
+               self.sys_view = sys.deref().into();
+               self.update_view = true;
+               "schedule cleared\n".to_owned()
+            },
+            Err(_) => "cannot clear the schedule whilst DEBUG thread this running\n".to_owned()
+         }
+      };
+      self.console_log.push_str(&output);
+      return cmd;
+   }
+}
+
 impl Application for App{
-   type Flags = (System, usize, Vec<SymbolDefinition>,String);
+   type Flags = (Machine, usize, Vec<SymbolDefinition>, String, Option<StateDump>, Option<u64>);
    type Message = Event;
    type Theme = Theme;
    type Executor = executor::Default;
@@ -852,9 +1304,9 @@ impl Application for App{
       let (mut state,def) = pane_grid::State::new(PaneType::Disassembler);
       
       state.split(pane_grid::Axis::Vertical,&def,PaneType::SystemState);
-      let (sys,entry_point, symbols, disassembly) = args;
-      let starting_view: SystemView = (&sys).into();
-      let sync_sys_arc = Arc::new(Mutex::new(sys));
+      let (machine,entry_point, symbols, disassembly, state_dump, clock_hz) = args;
+      let sync_sys_arc = machine.cores[0].clone();
+      let starting_view: SystemView = sync_sys_arc.lock().unwrap().deref().into();
       let mut windows = Window::create();
       windows.add_pane(def.clone());
       let total_disasm_lines = disassembly.lines().count();
@@ -870,6 +1322,8 @@ impl Application for App{
          memview_windows: Window::create(),
          explorer_map: ExplorerMap::create(),
          sync_sys: sync_sys_arc,
+         machine,
+         active_core: 0,
          disasm: disassembly,
          total_disasm_lines,
          entry_point,
@@ -882,7 +1336,20 @@ impl Application for App{
          update_view: false,
          view_error: None,
          trace_record: String::new(),
-         bkpt_input: BkptInput { pending_addr_or_symbol: String::new() }
+         bkpt_input: BkptInput { pending_addr_or_symbol: String::new() },
+         patch_input: String::new(),
+         register_inputs: Default::default(),
+         register_status: None,
+         console_input: String::new(),
+         console_log: String::new(),
+         until_bkpt: None,
+         state_dump,
+         last_halt: None,
+         running: false,
+         ips: None,
+         clock_input: clock_hz.map(|hz| hz.to_string()).unwrap_or_default(),
+         clock_hz,
+         max_speed: clock_hz.is_none()
       },Command::none())
    }
 
@@ -958,18 +1425,20 @@ impl Application for App{
             _ => None
          }
       });
-      let async_copy = self.sync_sys.clone();
-      //assert_eq!(2,Arc::strong_count(&async_copy),"only one instance runs in continue mode, only one instance runs in step mode");
+      let machine = self.machine.share();
       let sim_runtime = iced::subscription::channel(0, 1, |mut output| async move {
          let (sndr, mut rcvr)  = iced_mpsc::channel(10);
          let _ = output.send(Event::Dbg(Debug::Connect(sndr))).await;
          let mut halt = None;
+         let mut clock_hz = None;
          loop{
             match rcvr.select_next_some().await{
                Event::Ui(e) => panic!("invalid cmd sent to simulator loop {:?}",e),
+               Event::Dbg(Debug::SetClock(hz)) => {
+                  clock_hz = hz;
+               },
                Event::Dbg(Debug::Reset)=>{
-                  let mut sys = async_copy.lock().unwrap();
-                  sys.reset();
+                  machine.reset();
                   halt = Some(HaltType::usercmd);
                }
 
@@ -978,18 +1447,19 @@ impl Application for App{
                },
 
                Event::Dbg(Debug::CreateBreakpoint(addr))=>{
-                  let mut sys = async_copy.lock().unwrap();
-                  sys.add_breakpoint(addr);
+                  machine.add_breakpoint(addr);
+               },
+
+               Event::Dbg(Debug::CreateConditionalBreakpoint(addr,cond))=>{
+                  machine.add_conditional_breakpoint(addr, &cond);
                },
 
                Event::Dbg(Debug::DeleteBreakpoint(addr))=>{
-                  let mut sys = async_copy.lock().unwrap();
-                  sys.remove_breakpoint(addr);
+                  machine.remove_breakpoint(addr);
                },
 
                Event::Dbg(Debug::ClearBreakpoints)=>{
-                  let mut sys = async_copy.lock().unwrap();
-                  sys.clear_breakpoints();
+                  machine.clear_breakpoints();
                }
 
                Event::Dbg(Debug::Disconnect) => {
@@ -998,56 +1468,72 @@ impl Application for App{
                   }
                },
                Event::Dbg(Debug::Continue) => {
+                  // the core is locked for a batch of instructions at a time and commands are polled between batches
                   let mut continue_mode = true;
+                  let mut throughput = Throughput::start();
+                  let mut pacer = Pacer::new(clock_hz);
                   while continue_mode{
-                     let mut sys = async_copy.lock().unwrap();
-                     match Simulator::step_or_signal_halt_type(&mut sys){
-                        Ok(_)=> {
-                           if sys.on_breakpoint(){
-                              continue_mode = false;
-                              halt = Some(HaltType::breakpoint);
-                           }
-                        },
-                        Err(e) => {continue_mode = false; halt = Some(e);}
+                     let started = std::time::Instant::now();
+                     let (steps, stepped) = machine.run(pacer.budget(throughput.batch));
+                     if let Err(e) = stepped{
+                        continue_mode = false;
+                        halt = Some(e);
                      }
-                     match rcvr.try_next(){
-                        Ok(event) => match event{
-                           Some(eve) => match eve{
-                              Event::Dbg(Debug::Halt(e)) => {
-                                 continue_mode = false;
-                                 halt = Some(e);
-                              },
-                              Event::Dbg(Debug::Disconnect) => {
-                                 continue_mode = false;
-                                 output.close_channel();
-                              },
-                              Event::Dbg(Debug::Reset)=>{
-                                 continue_mode = false;
-                                 sys.reset();
-                                 halt = Some(HaltType::usercmd);
-                              },
-
-                              Event::Dbg(Debug::Continue)=>{/*ignore this signal*/ },
-
-                              Event::Dbg(Debug::CreateBreakpoint(addr))=>{
-                                 sys.add_breakpoint(addr);
-                              },
-                              Event::Dbg(Debug::DeleteBreakpoint(addr))=>{
-                                 sys.remove_breakpoint(addr);
-                              },
-                              Event::Dbg(Debug::ClearBreakpoints)=>{
-                                 sys.clear_breakpoints();
-                              },
-                              Event::Dbg(e) => {
-                                 panic!("invalid cmd {:?} sent to simulator loop", e)
+                     if let Some(ips) = throughput.record(steps, started.elapsed()){
+                        let _ = output.try_send(Event::Dbg(Debug::Throughput(ips)));
+                     }
+                     if continue_mode{
+                        std::thread::sleep(pacer.delay(steps));
+                     }
+                     while continue_mode{
+                        match rcvr.try_next(){
+                           Ok(event) => match event{
+                              Some(eve) => match eve{
+                                 Event::Dbg(Debug::Halt(e)) => {
+                                    continue_mode = false;
+                                    halt = Some(e);
+                                 },
+                                 Event::Dbg(Debug::Disconnect) => {
+                                    continue_mode = false;
+                                    output.close_channel();
+                                 },
+                                 Event::Dbg(Debug::Reset)=>{
+                                    continue_mode = false;
+                                    machine.reset();
+                                    halt = Some(HaltType::usercmd);
+                                 },
+
+                                 Event::Dbg(Debug::Continue)=>{/*ignore this signal*/ },
+
+                                 Event::Dbg(Debug::SetClock(hz))=>{
+                                    clock_hz = hz;
+                                    pacer.set_clock(hz);
+                                 },
+
+                                 Event::Dbg(Debug::CreateBreakpoint(addr))=>{
+                                    machine.add_breakpoint(addr);
+                                 },
+                                 Event::Dbg(Debug::CreateConditionalBreakpoint(addr,cond))=>{
+                                    machine.add_conditional_breakpoint(addr, &cond);
+                                 },
+                                 Event::Dbg(Debug::DeleteBreakpoint(addr))=>{
+                                    machine.remove_breakpoint(addr);
+                                 },
+                                 Event::Dbg(Debug::ClearBreakpoints)=>{
+                                    machine.clear_breakpoints();
+                                 },
+                                 Event::Dbg(e) => {
+                                    panic!("invalid cmd {:?} sent to simulator loop", e)
+                                 },
+                                 Event::Ui(e) => {panic!("invalid cmd {:?} sent to sim loop", e)}
                               },
-                              Event::Ui(e) => {panic!("invalid cmd {:?} sent to sim loop", e)}
+                              None => todo!(),
                            },
-                           None => todo!(),
-                        },
-                        Err(_) => { },
+                           Err(_) => break,
+                        }
                      }
                   }
+                  let _ = output.try_send(Event::Dbg(Debug::Throughput(throughput.report())));
                },
                Event::Dbg(e) => panic!("invalid cmd sent to simulator loop {:?}", e),
             }
@@ -1304,10 +1790,166 @@ impl Application for App{
             }
          },
 
+         Event::Ui(Gui::Exp(Explorer::Select(addr))) => {
+            if let Some(st) = self.memview_windows.get_focused_pane(){
+               let working_id = st.clone();
+               let view = self.explorer_map.mut_view_entry(working_id).or_insert(MemoryView::default());
+               view.selected = Some(addr);
+               view.write_status = None;
+            }
+         },
+
+         Event::Ui(Gui::Exp(Explorer::SetWrite(w))) => {
+            if let Some(st) = self.memview_windows.get_focused_pane(){
+               let working_id = st.clone();
+               self.explorer_map.write_string(working_id, w);
+            }
+         },
+
+         Event::Ui(Gui::Exp(Explorer::SetBypass(b))) => {
+            if let Some(st) = self.memview_windows.get_focused_pane(){
+               let working_id = st.clone();
+               self.explorer_map.mut_view_entry(working_id).or_insert(MemoryView::default()).bypass = b;
+            }
+         },
+
+         Event::Ui(Gui::Exp(Explorer::SubmitWrite)) => {
+            if let Some(st) = self.memview_windows.get_focused_pane(){
+               let working_id = st.clone();
+               let pend_write = self.explorer_map.get_write(&working_id).unwrap_or_default();
+               if let Some(view) = self.explorer_map.mut_view_of(&working_id){
+                  view.write_status = match (view.selected, self.sync_sys.try_lock()){
+                     (None, _) => Some("select a value to write to first".into()),
+                     (_, Err(_)) => Some("cannot write to memory whilst DEBUG thread this running".into()),
+                     (Some(addr), Ok(mut sys)) => match parse_memory_write(&view.view_cast, &pend_write, sys.is_big_endian()){
+                        Some(bytes) => {
+                           let result = store_bytes(&mut sys, addr, &bytes, view.bypass);
+                           self.sys_view = sys.deref().into();
+                           match result{
+                              Ok(()) => None,
+                              Err(e) => Some(format!("write to {:#010x} failed: {:?}",addr,e))
+                           }
+                        },
+                        None => Some(format!("could not parse '{}' as {}",pend_write,view.view_cast))
+                     }
+                  };
+                  self.update_view = true;
+               }
+            }
+         },
+
          Event::Ui(Gui::ToggleRegisterDisplay(i))=>{
             self.register_hex_display[i as usize] = !self.register_hex_display[i as usize];
          },
 
+         Event::Ui(Gui::SetRegisterInput(i,input))=>{
+            self.register_inputs[i as usize] = input;
+         },
+
+         Event::Ui(Gui::SelectCore(n))=>{
+            self.active_core = n;
+            self.sync_sys = self.machine.cores[n].clone();
+            match self.sync_sys.try_lock(){
+               Ok(sys) => {
+                  self.trace_record = sys.trace.clone();
+                  self.sys_view = sys.deref().into();
+               },
+               Err(_) => {
+                  println!("cannot read core {} whilst DEBUG thread this running",n);
+                  return cmd;
+               }
+            }
+            if let Some(ir_ln) = get_pc_text_position(&self.disasm,self.sys_view.raw_ir){
+               if let Some(c) = centre_disassembler(&mut self.diasm_windows, ir_ln, self.total_disasm_lines){
+                  cmd = c;
+               }
+            }
+         },
+
+         Event::Ui(Gui::SetConsoleInput(input))=>{
+            self.console_input = input;
+         },
+
+         Event::Ui(Gui::SubmitConsole)=>{
+            let line = std::mem::take(&mut self.console_input);
+            self.console_log.push_str(&format!("> {}\n",line));
+            match parse_command(&line, &self.symbols){
+               Ok(console_cmd) => {
+                  cmd = self.run_console_command(console_cmd);
+               },
+               Err(e) => {
+                  if !e.is_empty(){
+                     self.console_log.push_str(&e);
+                     self.console_log.push('\n');
+                  }
+               }
+            }
+         },
+
+         Event::Ui(Gui::DumpState)=>{
+            let dump = self.state_dump.clone().unwrap_or(StateDump{path: DEFAULT_DUMP_PATH.into(), ranges: Vec::new()});
+            match self.sync_sys.try_lock(){
+               Ok(_) => {},
+               Err(_) => {
+                  println!("cannot dump state whilst DEBUG thread this running");
+                  return cmd;
+               }
+            }
+            match dump.write(&self.machine, self.last_halt.as_ref()){
+               Ok(_) => println!("state written to {}",dump.path.display()),
+               Err(e) => println!("could not write {}: {}",dump.path.display(),e)
+            }
+         },
+
+         Event::Ui(Gui::PendException(n))=>{
+            match self.sync_sys.try_lock(){
+               Ok(mut sys)=>{
+                  pend_from_ui(&mut sys, n);
+                  self.sys_view = sys.deref().into();
+               },
+               Err(_)=>{
+                  println!("cannot pend exceptions whilst DEBUG thread this running");
+               }
+            }
+         },
+
+         Event::Ui(Gui::ClearException(n))=>{
+            match self.sync_sys.try_lock(){
+               Ok(mut sys)=>{
+                  sys.clear_exc_pending(n);
+                  self.sys_view = sys.deref().into();
+               },
+               Err(_)=>{
+                  println!("cannot clear exceptions whilst DEBUG thread this running");
+               }
+            }
+         },
+
+         Event::Ui(Gui::SubmitRegister(i))=>{
+            let name = EDITABLE_REGISTERS[i as usize];
+            let value = match parse_register_value(&self.register_inputs[i as usize]){
+               Some(v) => v,
+               None => {
+                  self.register_status = Some(format!("could not parse {} as a value for {}",self.register_inputs[i as usize],name));
+                  return cmd;
+               }
+            };
+            self.register_status = match self.sync_sys.try_lock(){
+               Ok(mut sys)=>{
+                  let status = match write_register(&mut sys, i, value){
+                     Ok(_) => None,
+                     Err(e) => Some(format!("could not set {}: {:?}",name,e))
+                  };
+                  self.sys_view = sys.deref().into();
+                  status
+               },
+               Err(_)=> Some("cannot edit registers whilst DEBUG thread this running".into())
+            };
+            if self.register_status.is_none(){
+               self.register_inputs[i as usize].clear();
+            }
+         },
+
          //TODO revome cursor_position
          Event::Ui(Gui::MouseHover(point))=>{
             self.diasm_windows.cursor_position = Some(point);
@@ -1439,6 +2081,72 @@ impl Application for App{
             }
          },
 
+         Event::Ui(Gui::SetPatchInput(input)) => {
+            self.patch_input = input;
+         },
+
+         Event::Ui(Gui::SetClockInput(input)) => {
+            self.clock_input = input;
+         },
+
+         Event::Ui(Gui::SubmitClock) => {
+            match self.clock_input.trim().parse::<u64>(){
+               Ok(hz) if hz > 0 => {
+                  self.clock_hz = Some(hz);
+                  self.max_speed = false;
+                  self.send_clock();
+               },
+               _ => println!("could not parse {} as a clock rate in Hz",self.clock_input)
+            }
+         },
+
+         Event::Ui(Gui::SetMaxSpeed(max_speed)) => {
+            self.max_speed = max_speed;
+            self.send_clock();
+         },
+
+         Event::Ui(Gui::SubmitPatch) => {
+            let (target, src) = match self.patch_input.trim().split_once(char::is_whitespace){
+               Some(split) => split,
+               None => {
+                  println!("expected patch as <address / symbol> <instruction>");
+                  return cmd;
+               }
+            };
+            let addr = match addr_or_symbol(target, &self.symbols){
+               Some(addr) => addr & !1,
+               None => {
+                  println!("could not parse {} as address",target);
+                  return cmd;
+               }
+            };
+            let bytes = match assemble_at(src, addr, &self.symbols){
+               Ok(bytes) => bytes,
+               Err(e) => {
+                  println!("could not assemble patch: {}",e);
+                  return cmd;
+               }
+            };
+            match self.sync_sys.try_lock(){
+               Ok(mut sys)=>{
+                  for (i,hw) in bytes.chunks_exact(2).enumerate(){
+                     if let Err(e) = write_memory(&mut sys, addr + 2 * i as u32, [hw[0],hw[1]]){
+                        println!("could not patch {:#010x}: {:?}",addr + 2 * i as u32,e);
+                        return cmd;
+                     }
+                  }
+                  self.sys_view = sys.deref().into();
+               },
+               Err(_)=>{
+                  println!("cannot patch instructions whilst DEBUG thread this running");
+                  return cmd;
+               }
+            }
+            self.disasm = patch_disasm(&self.disasm, addr, &bytes);
+            self.total_disasm_lines = self.disasm.lines().count();
+            self.patch_input.clear();
+         },
+
          Event::Ui(Gui::SubmitGuiBkpt(addr))=>{
             if self.breakpoints.contains(&addr){
                match self.cmd_sender{
@@ -1460,37 +2168,44 @@ impl Application for App{
          },
 
          Event::Dbg(Debug::Step) => {
-            use std::sync::TryLockError;
-            match self.sync_sys.try_lock(){
-               Ok(mut sys)=>{
-                  match Simulator::step_or_signal_halt(&mut sys){
-                     Ok(_)=> {},
-                     Err(Debug::Halt(HaltType::lockup)) => {
-                        println!("Simulator reached a lockup error condition");
-                        println!("The Simulator needs to be reset before it can resume  normal execution");
-                     },
-                     Err(e) => { println!("Halted due to {:?}",e); }
-                  }
-                  self.trace_record = sys.trace.clone();
-                  self.sys_view = sys.deref().into();
-               },
-               Err(e)=>{
-                  match e{
-                     TryLockError::Poisoned(_)=>{
-                        panic!("unexpected error occured in DEBUG thread, this is definately a problem! ");
-                     },
-                     TryLockError::WouldBlock=>{
-                        println!("cannot use manual step whilst DEBUG thread this running");
+            let mut halted = None;
+            if self.running{
+               println!("cannot use manual step whilst DEBUG thread this running");
+            }else{
+               // the run thread can still hold the core briefly for a command such as reset
+               match self.machine.try_step(){
+                  Some(stepped)=>{
+                     match stepped{
+                        Ok(_)=> {},
+                        Err(HaltType::lockup) => {
+                           println!("Simulator reached a lockup error condition");
+                           println!("The Simulator needs to be reset before it can resume  normal execution");
+                           halted = Some(HaltType::lockup);
+                        },
+                        Err(e) => {
+                           println!("Halted due to {:?}",e);
+                           halted = Some(e);
+                        }
                      }
+                     let sys = self.sync_sys.lock().unwrap();
+                     self.trace_record = sys.trace.clone();
+                     self.sys_view = sys.deref().into();
+                  },
+                  None=>{
+                     println!("cannot use manual step whilst DEBUG thread this running");
                   }
                }
             }
+            if let Some(halt) = halted{
+               self.record_halt(halt);
+            }
 
          },
 
          Event::Dbg(Debug::Connect(sender)) => {
             self.cmd_sender = Some(sender);
             println!("connected with dbg thread");
+            self.send_clock();
          },
 
          Event::Dbg(Debug::Continue) => {
@@ -1498,6 +2213,7 @@ impl Application for App{
             match self.cmd_sender.as_mut(){
                Some(sndr) => {
                   sndr.try_send(Event::Dbg(Debug::Continue)).unwrap();
+                  self.running = true;
                },
                None => {},
             }
@@ -1509,14 +2225,23 @@ impl Application for App{
                },
                None =>{
                   println!("WARN: cannot interact with debug session");
-                  let mut sys = self.sync_sys.try_lock().unwrap();
-                  sys.reset();
-                  self.sys_view = sys.deref().into();
+                  self.machine.reset();
+                  self.sys_view = self.sync_sys.try_lock().unwrap().deref().into();
                }
             }
          },
+         Event::Dbg(Debug::Throughput(ips))=>{
+            self.ips = Some(ips);
+         },
          Event::Dbg(Debug::Halt(_type))=>{
             println!("dbg session halted due to {:?}",_type);
+            self.running = false;
+            self.record_halt(_type);
+            if let Some(addr) = self.until_bkpt.take(){
+               if let Some(sndr) = self.cmd_sender.as_mut(){
+                  let _ = sndr.try_send(Event::Dbg(Debug::DeleteBreakpoint(addr)));
+               }
+            }
             let sys = self.sync_sys.try_lock().unwrap();
             self.trace_record = sys.trace.clone();
             self.sys_view = sys.deref().into();
@@ -1534,7 +2259,7 @@ impl Application for App{
 
 
    fn view(&self) -> iced::Element<'_, Self::Message, iced::Renderer> {
-      let pane_buttons = pane_cmds(self.n_panes,self.focused_pane.clone(),self.breakpoints.len());
+      let pane_buttons = pane_cmds(self.n_panes,self.focused_pane.clone(),self.breakpoints.len(),self.machine.n_cores(),self.active_core);
       let layout = PaneGrid::new(&self._state, |id, pane, _maximised|{
          let is_focused = id == self.focused_pane;
          let title_bar = pane_titles(pane,is_focused);
@@ -1547,14 +2272,14 @@ impl Application for App{
 
       if self.searchbar.is_some(){
          column![
-            user_cmds(&self.bkpt_input),
+            user_cmds(&self.bkpt_input,&self.patch_input,&self.clock_input,self.max_speed,self.ips),
             pane_buttons,
             searchbar(&self.searchbar.as_ref().unwrap()),
             layout
          ].into()
       }else{
          column![
-            user_cmds(&self.bkpt_input),
+            user_cmds(&self.bkpt_input,&self.patch_input,&self.clock_input,self.max_speed,self.ips),
             pane_buttons,
             layout
          ].into()
@@ -1586,6 +2311,14 @@ fn pane_titles(kind: &PaneType, focused: bool) -> pane_grid::TitleBar<Event> {
          pane_grid::TitleBar::new("Armageddon (memory viewer)")
             .padding(10).style(if focused{focused_pane}else{normal_pane})
       },
+      PaneType::Console => {
+         pane_grid::TitleBar::new("Armageddon (console)")
+            .padding(10).style(if focused{focused_pane}else{normal_pane})
+      },
+      PaneType::Exceptions => {
+         pane_grid::TitleBar::new("Armageddon (exceptions)")
+            .padding(10).style(if focused{focused_pane}else{normal_pane})
+      },
       PaneType::Trace => {
          pane_grid::TitleBar::new("Armageddon (execution trace)")
             .padding(10).style(if focused{focused_pane}else{normal_pane})
@@ -1593,6 +2326,60 @@ fn pane_titles(kind: &PaneType, focused: bool) -> pane_grid::TitleBar<Event> {
    }
 }
 
+// stores bytes given in memory order, writes go through write_memory as the core would perform them
+// unless bypass is set, which puts them straight into the allocator
+fn store_bytes(sys: &mut System, addr: u32, bytes: &[u8], bypass: bool)->Result<(),ArmException>{
+   if bypass{
+      for (i,b) in bytes.iter().enumerate(){
+         sys.alloc.put(addr + i as u32, [*b]);
+      }
+      return Ok(());
+   }
+   // the bytes are in memory order, write_memory expects a value and swaps it again on a big endian core
+   let mut value = bytes.to_vec();
+   if sys.is_big_endian(){
+      value.reverse();
+   }
+   match bytes.len(){
+      4 => write_memory(sys, addr, [value[0],value[1],value[2],value[3]]),
+      2 => write_memory(sys, addr, [value[0],value[1]]),
+      _ => {
+         for (i,b) in bytes.iter().enumerate(){
+            write_memory(sys, addr + i as u32, [*b])?;
+         }
+         Ok(())
+      }
+   }
+}
+
+// swaps the disassembled lines covering a patched range of memory, labels are kept
+pub(crate) fn patch_disasm(disasm: &String, addr: u32, bytes: &[u8])->String{
+   let end = addr + bytes.len() as u32;
+   let mut patched = String::new();
+   let mut inserted = false;
+   for line in disasm.lines(){
+      let is_code_line = line.starts_with(INDENT);
+      let line_addr = line.split(":").next()
+         .and_then(|a| u32::from_str_radix(a.trim().trim_start_matches("0x"), 16).ok());
+      match line_addr{
+         Some(a) if is_code_line && a >= addr && a < end => {
+            if !inserted{
+               for new_line in disasm_text(bytes, addr as usize, &Vec::new()){
+                  patched.push_str(new_line.trim_start_matches('\n'));
+                  patched.push('\n');
+               }
+               inserted = true;
+            }
+         },
+         _ => {
+            patched.push_str(line);
+            patched.push('\n');
+         }
+      }
+   }
+   return patched;
+}
+
 fn get_pc_text_position(disasm: &String, ir: u32)->Option<usize>{
    let mut line_number: usize = 0;
 
@@ -1665,9 +2452,14 @@ pub enum Debug{
    Disconnect,
    Reset,
    CreateBreakpoint(u32),
+   CreateConditionalBreakpoint(u32,Condition),
    DeleteBreakpoint(u32),
    ClearBreakpoints,
-   Connect(iced_mpsc::Sender<Event>)
+   Connect(iced_mpsc::Sender<Event>),
+   // instructions per second while continuing
+   Throughput(u64),
+   // throttles continue mode to a clock rate, None runs at full speed
+   SetClock(Option<u64>)
 }
 
 #[derive(Debug,Clone)]
@@ -1681,6 +2473,11 @@ pub enum Gui{
    SetBkptInput(String),
    SubmitBkpt,
    SubmitGuiBkpt(u32),
+   SetPatchInput(String),
+   SubmitPatch,
+   SetClockInput(String),
+   SubmitClock,
+   SetMaxSpeed(bool),
    SubmitHalt,
    SubmitBkptClear,
    OpenSearchBar,
@@ -1690,6 +2487,14 @@ pub enum Gui{
    SetSearchInput(String),
    CentreDisassembler,
    ToggleRegisterDisplay(u32),
+   SetRegisterInput(u32,String),
+   SubmitRegister(u32),
+   SelectCore(usize),
+   SetConsoleInput(String),
+   DumpState,
+   SubmitConsole,
+   PendException(u32),
+   ClearException(u32),
    ScrollEvent(iced::widget::scrollable::Id,f32,f32),
    MouseEntered,
    MouseHover(iced::Point),
@@ -1704,6 +2509,10 @@ pub enum Explorer{
    SetEnd(String),
    SetViewCast(Cast),
    Update,
+   Select(u32),
+   SetWrite(String),
+   SetBypass(bool),
+   SubmitWrite
 }
 
 #[derive(Debug,Clone)]
@@ -1717,6 +2526,8 @@ pub enum PaneType{
    Disassembler,
    SystemState,
    MemoryExplorer,
+   Exceptions,
+   Console,
    Trace
 }
 
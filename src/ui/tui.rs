@@ -0,0 +1,312 @@
+use std::io::{BufRead, Write};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+
+use crate::binutils::from_arm_bytes;
+use crate::elf::decoder::SymbolDefinition;
+use crate::system::System;
+use crate::system::simulator::{HaltType, Machine, Pacer};
+use crate::system::debug::debug_halt_msg;
+use crate::system::dump::{StateDump, DEFAULT_DUMP_PATH};
+
+use super::console::{self, ConsoleCmd, parse_command, examine, print_read, set_value, info_symbols, info_stats, show_schedule};
+use super::get_pc_text_position;
+
+// a line oriented front end for when no window can be opened, e.g. over ssh
+
+const PROMPT: &'static str = "(armageddon) ";
+const CONTEXT_LINES: usize = 5;
+// how many steps to run between checks for user input while continuing
+const POLL_INTERVAL: u64 = 1024;
+
+pub const TUI_HELP_MSG: &'static str = concat!(
+   "regs                           print the registers of the selected core\n",
+   "list                           print the disassembly around PC\n",
+   "delete <sym|addr>              remove a breakpoint, delete with no argument removes them all\n",
+   "core <n>                       select the core shown by regs/list/x/p/set\n",
+   "dump [file]                    write the machine state as JSON\n",
+   "quit                           exit the simulator\n",
+   "press enter while the simulator is running to halt it\n"
+);
+
+pub struct Tui{
+   machine: Machine,
+   active_core: usize,
+   symbols: Vec<SymbolDefinition>,
+   disasm: String,
+   breakpoints: Vec<u32>,
+   state_dump: Option<StateDump>,
+   last_halt: Option<HaltType>,
+   clock_hz: Option<u64>
+}
+
+impl Tui{
+   pub fn create(
+         machine: Machine,
+         symbols: Vec<SymbolDefinition>,
+         disasm: String,
+         state_dump: Option<StateDump>,
+         clock_hz: Option<u64>
+      )->Self{
+      Self{machine, active_core: 0, symbols, disasm, breakpoints: Vec::new(), state_dump, last_halt: None, clock_hz}
+   }
+
+   pub fn run(&mut self){
+      // stdin is read on its own thread so that a running simulation can be interrupted
+      let (sndr, rcvr) = channel();
+      std::thread::spawn(move ||{
+         for line in std::io::stdin().lock().lines(){
+            match line{
+               Ok(l) => if sndr.send(l).is_err(){ return; },
+               Err(_) => return
+            }
+         }
+      });
+
+      println!("{}",self.context());
+      loop{
+         print!("{}",PROMPT);
+         let _ = std::io::stdout().flush();
+         let line = match rcvr.recv(){
+            Ok(l) => l,
+            Err(_) => return
+         };
+         match self.execute(&line, &rcvr){
+            Some(output) => print!("{}",output),
+            None => return
+         }
+      }
+   }
+
+   // returns None when the user asks to quit
+   pub fn execute(&mut self, line: &str, interrupt: &Receiver<String>)->Option<String>{
+      let line = line.trim();
+      let (cmd, args) = match line.split_once(char::is_whitespace){
+         Some((c, a)) => (c, a.trim()),
+         None => (line, "")
+      };
+      let output = match cmd{
+         "q" | "quit" | "exit" => return None,
+         "regs" | "r" => registers(&self.sys()),
+         "l" | "list" => self.disassembly(),
+         "info" if args == "registers" => registers(&self.sys()),
+         "help" | "h" => format!("{}{}",console::HELP_MSG,TUI_HELP_MSG),
+         "d" | "delete" => self.delete(args),
+         "dump" => {
+            let mut dump = self.state_dump.clone().unwrap_or(StateDump{path: DEFAULT_DUMP_PATH.into(), ranges: Vec::new()});
+            if !args.is_empty(){
+               dump.path = args.into();
+            }
+            match dump.write(&self.machine, self.last_halt.as_ref()){
+               Ok(_) => format!("state written to {}\n",dump.path.display()),
+               Err(e) => format!("could not write {}: {}\n",dump.path.display(),e)
+            }
+         },
+         "core" => match args.parse::<usize>(){
+            Ok(n) if n < self.machine.n_cores() => {
+               self.active_core = n;
+               self.context()
+            },
+            _ => format!("there is no core '{}'\n",args)
+         },
+         _ => match parse_command(line, &self.symbols){
+            Ok(console_cmd) => self.run_console_command(console_cmd, interrupt),
+            Err(e) if e.is_empty() => String::new(),
+            Err(e) => format!("{}\n",e)
+         }
+      };
+      return Some(output);
+   }
+
+   fn sys(&self)->std::sync::MutexGuard<'_,System>{
+      self.machine.cores[self.active_core].lock().unwrap()
+   }
+
+   fn delete(&mut self, args: &str)->String{
+      if args.is_empty(){
+         self.machine.clear_breakpoints();
+         self.breakpoints.clear();
+         return "all breakpoints removed\n".into();
+      }
+      match parse_command(&format!("break {}",args), &self.symbols){
+         Ok(ConsoleCmd::Break(addr, _)) => {
+            let addr = addr & !1;
+            self.machine.remove_breakpoint(addr);
+            self.breakpoints.retain(|b| *b != addr);
+            format!("removed breakpoint at {:#010x}\n",addr)
+         },
+         Ok(_) => unreachable!(),
+         Err(e) => format!("{}\n",e)
+      }
+   }
+
+   fn run_console_command(&mut self, console_cmd: ConsoleCmd, interrupt: &Receiver<String>)->String{
+      match console_cmd{
+         ConsoleCmd::Help => format!("{}{}",console::HELP_MSG,TUI_HELP_MSG),
+         ConsoleCmd::InfoSymbols(pattern) => info_symbols(&pattern, &self.symbols),
+         ConsoleCmd::InfoStats => info_stats(&self.sys()),
+         ConsoleCmd::Break(addr, cond) => {
+            let addr = addr & !1;
+            let msg = match &cond{
+               Some(c) => {
+                  self.machine.add_conditional_breakpoint(addr, c);
+                  format!("breakpoint at {:#010x} if {:?}\n",addr,c)
+               },
+               None => {
+                  self.machine.add_breakpoint(addr);
+                  format!("breakpoint at {:#010x}\n",addr)
+               }
+            };
+            if !self.breakpoints.contains(&addr){
+               self.breakpoints.push(addr);
+            }
+            msg
+         },
+         ConsoleCmd::Examine{addr, count, size, format} => examine(&self.sys(), addr, count, size, format),
+         ConsoleCmd::Print(value) => print_read(&self.sys(), &value),
+         ConsoleCmd::Set(target, value) => set_value(&mut self.sys(), &target, &value),
+         ConsoleCmd::Step(n) => {
+            let mut out = String::new();
+            for _ in 0 .. n{
+               if let Err(e) = self.machine.step(){
+                  out.push_str(&self.on_halt(e));
+                  break;
+               }
+            }
+            out.push_str(&self.context());
+            out
+         },
+         ConsoleCmd::Until(addr) => {
+            let temporary = !self.breakpoints.contains(&addr);
+            if temporary{
+               self.machine.add_breakpoint(addr);
+            }
+            let out = self.continue_until_halt(interrupt);
+            if temporary{
+               self.machine.remove_breakpoint(addr);
+            }
+            out
+         },
+         ConsoleCmd::Continue => self.continue_until_halt(interrupt),
+         ConsoleCmd::Reset => {
+            self.machine.reset();
+            format!("reset\n{}",self.context())
+         },
+         ConsoleCmd::Schedule(stimulus) => {
+            let msg = format!("scheduled {}\n",stimulus);
+            self.sys().stimuli.stimuli.push(stimulus);
+            msg
+         },
+         ConsoleCmd::ShowSchedule => show_schedule(&self.sys().stimuli),
+         ConsoleCmd::ClearSchedule => {
+            self.sys().stimuli.stimuli.clear();
+            "schedule cleared\n".into()
+         }
+      }
+   }
+
+   fn continue_until_halt(&mut self, interrupt: &Receiver<String>)->String{
+      // drop anything typed before the continue so it does not halt immediately,
+      // once stdin is closed (e.g. a piped script) the run can only end at a halt
+      while interrupt.try_recv().is_ok(){}
+      println!("running, press enter to halt");
+      let mut pacer = Pacer::new(self.clock_hz);
+      let halt = loop{
+         let (steps, stepped) = self.machine.run(pacer.budget(POLL_INTERVAL));
+         if let Err(e) = stepped{
+            break e;
+         }
+         match interrupt.try_recv(){
+            Ok(_) => break HaltType::usercmd,
+            Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => {}
+         }
+         std::thread::sleep(pacer.delay(steps));
+      };
+      format!("{}{}",self.on_halt(halt),self.context())
+   }
+
+   fn on_halt(&mut self, halt: HaltType)->String{
+      let mut out = halt_msg(&halt);
+      if let Some(dump) = &self.state_dump{
+         if let Err(e) = dump.write(&self.machine, Some(&halt)){
+            out.push_str(&format!("could not write {}: {}\n",dump.path.display(),e));
+         }
+      }
+      self.last_halt = Some(halt);
+      return out;
+   }
+
+   fn disassembly(&self)->String{
+      let pc = self.sys().read_raw_ir();
+      let lines: Vec<&str> = self.disasm.lines().collect();
+      let mut out = String::new();
+      match get_pc_text_position(&self.disasm, pc){
+         Some(ln) => {
+            let start = ln.saturating_sub(CONTEXT_LINES);
+            let end = (ln + CONTEXT_LINES + 1).min(lines.len());
+            for (i, line) in lines[start .. end].iter().enumerate(){
+               let addr = line_address(line);
+               let marker = if start + i == ln { "=>" }
+                  else if addr.is_some_and(|a| self.breakpoints.contains(&a)) { " *" }
+                  else { "  " };
+               out.push_str(&format!("{} {}\n",marker,line));
+            }
+         },
+         None => out.push_str(&format!("no disassembly for PC = {:#010x}\n",pc))
+      }
+      return out;
+   }
+
+   fn context(&self)->String{
+      let mut out = String::new();
+      if self.machine.n_cores() > 1{
+         out.push_str(&format!("core {}\n",self.active_core));
+      }
+      out.push_str(&self.disassembly());
+      out.push_str(&registers(&self.sys()));
+      return out;
+   }
+}
+
+fn line_address(line: &str)->Option<u32>{
+   let (addr, _) = line.split_once(':')?;
+   u32::from_str_radix(addr.trim().trim_start_matches("0x"), 16).ok()
+}
+
+fn halt_msg(halt: &HaltType)->String{
+   match halt{
+      HaltType::breakpoint(None) => "hit breakpoint\n".into(),
+      HaltType::breakpoint(Some(imm)) => format!("hit BKPT #{}, continue or step to resume after it\n",imm),
+      HaltType::usercmd => "halted\n".into(),
+      HaltType::lockup => "the simulator reached a lockup, reset it before resuming execution\n".into(),
+      HaltType::error(e) => format!("halted due to {:?}\n",e),
+      HaltType::debug(reason) => format!("debug halt: {}\n",debug_halt_msg(reason))
+   }
+}
+
+pub(crate) fn registers(sys: &System)->String{
+   let mut out = String::new();
+   for (i, v) in sys.registers.generic.iter().enumerate(){
+      out.push_str(&format!("r{:<3}{:#010x} ",i,v));
+      if i % 4 == 3{
+         out.push('\n');
+      }
+   }
+   if sys.registers.generic.len() % 4 != 0{
+      out.push('\n');
+   }
+   let xpsr = from_arm_bytes(sys.xpsr);
+   out.push_str(&format!("sp  {:#010x} lr  {:#010x} pc  {:#010x}\n",sys.get_sp(),sys.registers.lr,sys.registers.pc));
+   out.push_str(&format!(
+      "msp {:#010x} psp {:#010x} xpsr {:#010x} [{}{}{}{}] {:?} {}\n",
+      sys.registers.sp_main,
+      sys.registers.sp_process,
+      xpsr,
+      if xpsr & (1 << 31) > 0 { 'N' } else { '-' },
+      if xpsr & (1 << 30) > 0 { 'Z' } else { '-' },
+      if xpsr & (1 << 29) > 0 { 'C' } else { '-' },
+      if xpsr & (1 << 28) > 0 { 'V' } else { '-' },
+      sys.mode,
+      if sys.in_privileged_mode() { "privileged" } else { "unprivileged" }
+   ));
+   return out;
+}
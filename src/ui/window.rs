@@ -129,7 +129,8 @@ impl Window{
 pub struct ExplorerMap{
    mem_view: HashMap<Id,MemoryView>,
    pending_start: HashMap<Id,String>,
-   pending_end: HashMap<Id,String>
+   pending_end: HashMap<Id,String>,
+   pending_write: HashMap<Id,String>
 }
 
 impl ExplorerMap{
@@ -138,6 +139,7 @@ impl ExplorerMap{
          mem_view: HashMap::new(),
          pending_start: HashMap::new(),
          pending_end: HashMap::new(),
+         pending_write: HashMap::new(),
       }
    }
 
@@ -180,9 +182,18 @@ impl ExplorerMap{
       }
    }
 
+   pub fn write_string(&mut self, id: Id,val: String){
+      self.pending_write.insert(id,val);
+   }
+
+   pub fn get_write(&self, id: &Id)->Option<String>{
+      self.pending_write.get(id).cloned()
+   }
+
    pub fn remove(&mut self,id: &Id){
       self.mem_view.remove(id);
       self.pending_start.remove(id);
       self.pending_end.remove(id);
+      self.pending_write.remove(id);
    }
 }